@@ -0,0 +1,15 @@
+use std::process::Command;
+
+/// Records the commit being built as `RELAY_CODE_GIT_HASH`, for `version`.
+/// Builds from outside a git checkout simply go without.
+fn main() {
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+    let output = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output();
+    if let Some(output) = output.ok().filter(|output| output.status.success()) {
+        let hash = String::from_utf8_lossy(&output.stdout);
+        println!("cargo:rustc-env=RELAY_CODE_GIT_HASH={}", hash.trim());
+    }
+}
@@ -1,59 +1,376 @@
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::collections::BTreeMap;
+use std::fmt::Display;
 
-use crate::error::Result;
-use crate::serde::{serialize, Deserialize, Field, FieldReader, Serialize};
+use crate::error::{Error, Result};
+use crate::handlers::{handler, ActionHandler, Context};
+use crate::impl_enum_field;
+use crate::outcome::ActionOutcome;
+use crate::resolve::resolve;
+use crate::serde::{Deserialize, FieldReader, FieldWriter, Serialize, TaggedEnum, HEADER_LEN};
+use crate::timestamp::Timestamp;
 use crate::Entity;
 
-fn start() -> Result<u128> {
-    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
-    Ok(now)
-}
-
-#[repr(u8)]
+/// What an action does, written as a one-byte id. Ids without a built-in
+/// kind are kept as `Custom`, so a session using kinds this binary does not
+/// know about still loads and saves unchanged.
 #[derive(Debug, Eq, PartialEq, Clone, Copy)]
 pub enum ActionKind {
     Fight,
     Love,
     Neutral,
+    Attack,
+    Defend,
+    Trade,
+    Rest,
+    Inspect,
+    Say,
+    Give,
+    Drop,
+    Use,
+    Transfer,
+    /// Moves the target `steps` squares in `direction` across the map.
+    Move,
+    /// Puts on the target's `item`, into the slot the item is made for.
+    Equip,
+    /// Takes off whatever the target has in `slot`.
+    Unequip,
+    /// Carried out by the session's script named by the `script` parameter.
+    Script,
+    /// Any other id, for kinds registered by extensions. Ids from 64 up
+    /// are left for them; lower ones are reserved for built-in kinds.
+    Custom(u8),
+}
+
+impl ActionKind {
+    pub const ALL: [ActionKind; 17] = [
+        Self::Fight,
+        Self::Love,
+        Self::Neutral,
+        Self::Attack,
+        Self::Defend,
+        Self::Trade,
+        Self::Rest,
+        Self::Inspect,
+        Self::Say,
+        Self::Script,
+        Self::Give,
+        Self::Drop,
+        Self::Use,
+        Self::Transfer,
+        Self::Move,
+        Self::Equip,
+        Self::Unequip,
+    ];
+}
+
+impl ActionKind {
+    pub fn id(self) -> u8 {
+        match self {
+            Self::Fight => 0,
+            Self::Love => 1,
+            Self::Neutral => 2,
+            Self::Attack => 3,
+            Self::Defend => 4,
+            Self::Trade => 5,
+            Self::Rest => 6,
+            Self::Inspect => 7,
+            Self::Say => 8,
+            Self::Script => 9,
+            Self::Give => 10,
+            Self::Drop => 11,
+            Self::Use => 12,
+            Self::Transfer => 13,
+            Self::Move => 14,
+            Self::Equip => 15,
+            Self::Unequip => 16,
+            Self::Custom(id) => id,
+        }
+    }
+}
+
+/// Ordered by id, the order they are written in.
+impl Ord for ActionKind {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.id().cmp(&other.id())
+    }
+}
+
+impl PartialOrd for ActionKind {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Custom kinds show the name their handler registered, or else their id.
+impl Display for ActionKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Fight => "fight",
+            Self::Love => "love",
+            Self::Neutral => "neutral",
+            Self::Attack => "attack",
+            Self::Defend => "defend",
+            Self::Trade => "trade",
+            Self::Rest => "rest",
+            Self::Inspect => "inspect",
+            Self::Say => "say",
+            Self::Script => "script",
+            Self::Give => "give",
+            Self::Drop => "drop",
+            Self::Use => "use",
+            Self::Transfer => "transfer",
+            Self::Move => "move",
+            Self::Equip => "equip",
+            Self::Unequip => "unequip",
+            Self::Custom(id) => {
+                let handler = handler(*self);
+                return match handler.as_deref().and_then(ActionHandler::name) {
+                    Some(name) => write!(f, "{name}"),
+                    None => write!(f, "kind{id}"),
+                };
+            }
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl From<u8> for ActionKind {
+    fn from(id: u8) -> Self {
+        match id {
+            0 => Self::Fight,
+            1 => Self::Love,
+            2 => Self::Neutral,
+            3 => Self::Attack,
+            4 => Self::Defend,
+            5 => Self::Trade,
+            6 => Self::Rest,
+            7 => Self::Inspect,
+            8 => Self::Say,
+            9 => Self::Script,
+            10 => Self::Give,
+            11 => Self::Drop,
+            12 => Self::Use,
+            13 => Self::Transfer,
+            14 => Self::Move,
+            15 => Self::Equip,
+            16 => Self::Unequip,
+            id => Self::Custom(id),
+        }
+    }
+}
+
+/// The value of a named action parameter, such as `steps=2` or `item=rope`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Param {
+    Int(i64),
+    Text(String),
+}
+
+impl Param {
+    /// Anything that reads as a whole number is one.
+    pub fn parse(value: &str) -> Self {
+        match value.parse() {
+            Ok(n) => Self::Int(n),
+            Err(_) => Self::Text(value.to_string()),
+        }
+    }
+
+    /// Encoded size of the parameter as a field, header included.
+    fn field_len(&self) -> usize {
+        let payload = match self {
+            Self::Int(_) => HEADER_LEN + 8,
+            Self::Text(text) => HEADER_LEN + text.len(),
+        };
+        HEADER_LEN + 1 + payload
+    }
+}
+
+impl Display for Param {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Int(n) => write!(f, "{n}"),
+            Self::Text(text) => write!(f, "{text}"),
+        }
+    }
+}
+
+impl TaggedEnum for Param {
+    fn discriminant(&self) -> u8 {
+        match self {
+            Self::Int(_) => 0,
+            Self::Text(_) => 1,
+        }
+    }
+
+    fn serialize_variant(&self, writer: &mut FieldWriter<'_>) {
+        match self {
+            Self::Int(n) => writer.write_i64(*n),
+            Self::Text(text) => writer.write_str(text),
+        }
+    }
+
+    fn deserialize_variant(discriminant: u8, reader: &mut FieldReader<'_>) -> Result<Self> {
+        let param = match discriminant {
+            0 => Self::Int(reader.read_field()?),
+            1 => Self::Text(reader.read_field()?),
+            _ => return Err(Error::InvalidVariant(discriminant)),
+        };
+        reader.finish()?;
+        Ok(param)
+    }
 }
 
+impl_enum_field!(Param);
+
+/// Named parameters of an action, in addition to its kind and target.
+pub type Params = BTreeMap<String, Param>;
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Action {
-    start: u128,
+    start: Timestamp,
     target: String,
     kind: ActionKind,
+    params: Params,
 }
 
 impl Action {
     pub fn new(kind: ActionKind, target: String) -> Result<Self> {
         let inst = Self {
-            start: start()?,
+            start: Timestamp::now()?,
             kind,
             target,
+            params: Params::new(),
         };
         Ok(inst)
     }
 
-    pub fn exec(&mut self, entity: &mut Entity) {
-        let action: String = String::from("fight");
-        eprintln!("Action is : {action:?} and Entity is {0:?}", entity.name);
+    /// An action started at `start` rather than now, for actions the
+    /// session makes up itself and must make up the same on every replay.
+    pub(crate) fn at(kind: ActionKind, target: String, start: Timestamp) -> Self {
+        Self {
+            start,
+            kind,
+            target,
+            params: Params::new(),
+        }
+    }
+
+    pub fn with_params(mut self, params: Params) -> Self {
+        self.params = params;
+        self
+    }
+
+    pub fn params(&self) -> &Params {
+        &self.params
+    }
+
+    /// The name of the script carrying out a `Script` action.
+    pub fn script(&self) -> Option<String> {
         match self.kind {
-            ActionKind::Fight => {
-                eprintln!("You're fighting {0}", entity.name);
+            ActionKind::Script => self.params.get("script").map(Param::to_string),
+            _ => None,
+        }
+    }
+
+    /// Replaces the target and the `with` and `by` entities by the full
+    /// names of the entities they resolve to, so the log never holds an id
+    /// or prefix.
+    pub(crate) fn resolved(mut self, entities: &[Entity]) -> Result<Self> {
+        self.target = resolve(entities, &self.target)?.name.clone();
+        for key in ["with", "by"] {
+            if let Some(Param::Text(name)) = self.params.get_mut(key) {
+                *name = resolve(entities, name)?.name.clone();
+            }
+        }
+        Ok(self)
+    }
+
+    /// The parameter `key` as an amount from 1 to 255, 1 when not given.
+    pub fn amount(&self, key: &str) -> Result<u8> {
+        match self.params.get(key) {
+            None => Ok(1),
+            Some(Param::Int(n)) => u8::try_from(*n)
+                .ok()
+                .filter(|&n| n > 0)
+                .ok_or_else(|| Error::IllegalAction(format!("{key} must be from 1 to 255"))),
+            Some(Param::Text(text)) => Err(Error::IllegalAction(format!(
+                "{key} must be a number, not {text:?}"
+            ))),
+        }
+    }
+
+    /// The parameter `key` as text, whatever its type.
+    pub fn text(&self, key: &str) -> Result<String> {
+        self.params
+            .get(key)
+            .map(Param::to_string)
+            .ok_or_else(|| Error::IllegalAction(format!("{} needs {key}=<value>", self.kind)))
+    }
+
+    pub fn start(&self) -> Timestamp {
+        self.start
+    }
+
+    pub fn kind(&self) -> ActionKind {
+        self.kind
+    }
+
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    /// Carries the action out on `entities` with the handler registered
+    /// for its kind. Legality is checked beforehand by a `Validator`, which
+    /// refuses kinds without a handler; ones already in the log are
+    /// replayed as having had no effect.
+    pub fn exec(
+        &self,
+        entities: &mut [Entity],
+        scripts: &BTreeMap<String, String>,
+    ) -> Result<ActionOutcome> {
+        match handler(self.kind) {
+            Some(handler) => handler.apply(self, Context { entities, scripts }),
+            None => {
+                let mut outcome = ActionOutcome::default();
+                outcome.message(format!("{} is not known here; skipped", self.kind));
+                Ok(outcome)
+            }
+        }
+    }
+}
+
+/// As shown in the log: `kind target key=value... at start`, where the kind
+/// of a scripted action is the name of its script.
+impl Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.script() {
+            Some(name) => write!(f, "{name} {}", self.target)?,
+            None => write!(f, "{} {}", self.kind, self.target)?,
+        }
+        for (key, value) in &self.params {
+            if self.kind != ActionKind::Script || key != "script" {
+                write!(f, " {key}={value}")?;
             }
-            _ => eprintln!("Typed unknown action you have"),
         }
+        write!(f, " at {}", self.start)
     }
 }
 
 impl Serialize for Action {
-    fn serialize(&self) -> Vec<u8> {
-        let mut bytes = vec![];
-        serialize(&mut bytes, Field::U128(self.start));
-        serialize(&mut bytes, Field::ActionKind(self.kind));
-        serialize(&mut bytes, Field::Str(&self.target));
-        eprintln!("BUFFER: {bytes:?}");
-        bytes
+    fn serialize_to(&self, writer: &mut FieldWriter<'_>) {
+        writer.write(&self.start);
+        writer.write(&self.kind);
+        writer.write_str(&self.target);
+        writer.write(&self.params);
+    }
+
+    fn size_hint(&self) -> usize {
+        let params: usize = self
+            .params
+            .iter()
+            .map(|(key, value)| HEADER_LEN + key.len() + value.field_len())
+            .sum();
+        HEADER_LEN + 16 + HEADER_LEN + 1 + HEADER_LEN + self.target.len() + HEADER_LEN + params
     }
 }
 
@@ -66,6 +383,7 @@ impl Deserialize for Action {
             start: reader.read_field()?,
             kind: reader.read_field()?,
             target: reader.read_field()?,
+            params: reader.read_field()?,
         };
 
         Ok(action)
@@ -0,0 +1,153 @@
+use crate::actions::{Action, ActionKind, Param};
+use crate::components::Behavior;
+use crate::map::Direction;
+use crate::outcome::ActionOutcome;
+use crate::rng::Rng;
+use crate::session::Session;
+use crate::timestamp::Timestamp;
+use crate::Entity;
+
+/// The actions the computer-played entities of `session` take this turn,
+/// in the order the entities were added, for a resolve at `at`. Choices are
+/// drawn from `rng`, which is seeded from the log, so every machine
+/// replaying it plans the same. `outcome` says what each chose.
+pub(crate) fn plan(
+    session: &Session,
+    rng: &mut Rng,
+    at: Timestamp,
+    outcome: &mut ActionOutcome,
+) -> Vec<Action> {
+    let mut actions = vec![];
+    for npc in session.entities() {
+        let Some(ai) = &npc.ai else {
+            continue;
+        };
+        if npc.stats.hp == 0 {
+            continue;
+        }
+        let action = match ai.behavior {
+            Behavior::Aggressive => attack(session, npc, rng, at),
+            Behavior::Defensive => Some(defend(npc, at)),
+            Behavior::Wander => wander(session, npc, rng, at),
+        };
+        if let Some(action) = action {
+            outcome.message(format!(
+                "{} ({}) chose {} {}",
+                npc.name,
+                ai.behavior,
+                action.kind(),
+                action.target()
+            ));
+            actions.push(action);
+        }
+    }
+    actions
+}
+
+/// One of `items` chosen by `rng`, or `None` if there are none.
+fn choose<T>(rng: &mut Rng, mut items: Vec<T>) -> Option<T> {
+    if items.is_empty() {
+        return None;
+    }
+    let index = rng.next_u64() % items.len() as u64;
+    Some(items.swap_remove(index as usize))
+}
+
+/// Attacks an enemy or a member of a hostile faction still standing, with
+/// the npc's attack stat.
+fn attack(session: &Session, npc: &Entity, rng: &mut Rng, at: Timestamp) -> Option<Action> {
+    let enemies: Vec<_> = session
+        .entities()
+        .iter()
+        .filter(|entity| entity.stats.hp > 0 && session.hostile(npc, entity))
+        .collect();
+    let enemy = choose(rng, enemies)?;
+    let damage = i64::from(npc.stats.attack.max(1));
+    let params = [
+        ("damage".to_string(), Param::Int(damage)),
+        ("by".to_string(), Param::Text(npc.name.clone())),
+    ];
+    Some(
+        Action::at(ActionKind::Attack, enemy.name.clone(), at)
+            .with_params(params.into_iter().collect()),
+    )
+}
+
+fn defend(npc: &Entity, at: Timestamp) -> Action {
+    let kind = match npc.guarding {
+        true => ActionKind::Rest,
+        false => ActionKind::Defend,
+    };
+    Action::at(kind, npc.name.clone(), at)
+}
+
+/// Steps one square in a direction that stays on the map.
+fn wander(session: &Session, npc: &Entity, rng: &mut Rng, at: Timestamp) -> Option<Action> {
+    let from = npc.position?;
+    let open: Vec<_> = Direction::ALL
+        .into_iter()
+        .filter(|direction| {
+            direction
+                .step(from, 1)
+                .is_some_and(|to| session.map().is_none_or(|map| map.contains(to)))
+        })
+        .collect();
+    let direction = choose(rng, open)?;
+    let params = [("direction".to_string(), Param::Text(direction.to_string()))];
+    Some(
+        Action::at(ActionKind::Move, npc.name.clone(), at)
+            .with_params(params.into_iter().collect()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::components::{Ai, Behavior, Position};
+    use crate::map::Map;
+    use crate::relations::Relation;
+    use crate::serde::{Deserialize, FieldReader, Serialize};
+    use crate::session::Session;
+    use crate::Entity;
+
+    #[test]
+    fn npcs_take_the_same_turns_on_replay() {
+        let mut session = Session::new("game".to_string()).unwrap();
+        let npc = |name: &str, behavior| {
+            let mut entity = Entity::new(name.to_string());
+            entity.stats.hp = 5;
+            entity.stats.attack = 2;
+            entity.position = Some(Position { x: 1, y: 1 });
+            entity.ai = Some(Ai { behavior });
+            entity
+        };
+        let mut hero = Entity::new("hero".to_string());
+        hero.stats.hp = 9;
+        session.add_entity(hero).unwrap();
+        session
+            .add_entity(npc("goblin", Behavior::Aggressive))
+            .unwrap();
+        session
+            .add_entity(npc("guard", Behavior::Defensive))
+            .unwrap();
+        session.add_entity(npc("rat", Behavior::Wander)).unwrap();
+        session
+            .set_map(Map {
+                width: 3,
+                height: 3,
+            })
+            .unwrap();
+        session.relate("goblin", Relation::Enemy, "hero").unwrap();
+
+        let outcome = session.resolve().unwrap().to_string();
+        assert!(outcome.contains("goblin (aggressive) chose attack hero"));
+        assert!(outcome.contains("guard (defensive) chose defend guard"));
+        assert!(outcome.contains("rat (wander) chose move rat"));
+        session.resolve().unwrap();
+        assert_eq!(session.entity("hero").unwrap().stats.hp, 5);
+        assert!(session.entity("guard").unwrap().guarding);
+
+        let bytes = session.serialize();
+        let copy = Session::deserialize(&mut FieldReader::new(&bytes)).unwrap();
+        assert_eq!(copy.entities(), session.entities());
+    }
+}
@@ -0,0 +1,136 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use uuid::Uuid;
+
+use crate::atomic;
+use crate::error::{Error, Result};
+use crate::metadata::FORMAT_VERSION;
+use crate::serde::{FieldReader, FieldWriter, Serialize, HEADER_LEN};
+use crate::session::validate_name;
+use crate::Entity;
+
+/// Kept in `<data-dir>/archetypes`, one `<archetype>.entity` file each.
+const DIR: &str = "archetypes";
+const EXTENSION: &str = "entity";
+
+/// First field of every archetype file, so stray files are rejected up front.
+const MAGIC: &str = "relay_code entity";
+
+/// Encodes `entity` as an archetype, the entity that spawned copies of a
+/// goblin or merchant start out as. The file is written in the session
+/// format so it can be shared like one:
+///
+/// ```text
+/// Str(MAGIC) U16(FORMAT_VERSION) Entity
+/// ```
+pub fn to_bytes(entity: &Entity) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(3 * HEADER_LEN + MAGIC.len() + 2 + entity.size_hint());
+    let mut writer = FieldWriter::new(&mut bytes);
+    writer.write_str(MAGIC);
+    writer.write_u16(FORMAT_VERSION);
+    writer.write(entity);
+    bytes
+}
+
+pub fn from_bytes(bytes: &[u8]) -> Result<Entity> {
+    let mut reader = FieldReader::new(bytes);
+    match reader.read_field::<&str>() {
+        Ok(MAGIC) => {}
+        _ => return Err(Error::NotAnArchetype),
+    }
+    let version: u16 = reader.read_field()?;
+    if version != FORMAT_VERSION {
+        return Err(Error::UnsupportedVersion(version));
+    }
+    let entity = reader.read_field()?;
+    reader.finish()?;
+    Ok(entity)
+}
+
+fn path(dir: &Path, name: &str) -> Result<PathBuf> {
+    validate_name(name)?;
+    Ok(dir.join(DIR).join(format!("{name}.{EXTENSION}")))
+}
+
+pub fn load(dir: &Path, name: &str) -> Result<Entity> {
+    match fs::read(path(dir, name)?) {
+        Ok(bytes) => from_bytes(&bytes),
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            Err(Error::ArchetypeNotFound(name.to_string()))
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Saves `entity` as the archetype `name`, replacing any already saved.
+pub fn save(dir: &Path, name: &str, entity: &Entity) -> Result<()> {
+    let path = path(dir, name)?;
+    fs::create_dir_all(dir.join(DIR))?;
+    atomic::write(&path, &to_bytes(entity))
+}
+
+/// The names of the saved archetypes, sorted.
+pub fn list(dir: &Path) -> Result<Vec<String>> {
+    let entries = match fs::read_dir(dir.join(DIR)) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err.into()),
+    };
+    let mut names = vec![];
+    for entry in entries {
+        let path = entry?.path();
+        if path
+            .extension()
+            .is_some_and(|extension| extension == EXTENSION)
+        {
+            if let Some(name) = path.file_stem().and_then(|name| name.to_str()) {
+                names.push(name.to_string());
+            }
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+impl Entity {
+    /// A copy of this entity under a fresh id, named `name`.
+    pub fn spawn(&self, name: String) -> Entity {
+        Entity {
+            id: Uuid::new_v4(),
+            name,
+            ..self.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::inventory::Item;
+    use crate::session::Session;
+    use crate::temp_dir::TempDir;
+    use crate::Entity;
+
+    #[test]
+    fn archetypes_spawn_fresh_entities() {
+        let dir = TempDir::new("archetypes");
+        let mut goblin = Entity::new("goblin".to_string());
+        goblin.stats.hp = 4;
+        goblin.stow(Item::new("club".to_string(), 1));
+        super::save(&dir, "goblin", &goblin).unwrap();
+        assert_eq!(super::list(&dir).unwrap(), ["goblin"]);
+        let archetype = super::load(&dir, "goblin").unwrap();
+        assert_eq!(archetype, goblin);
+        assert!(super::load(&dir, "merchant").is_err());
+
+        let mut session = Session::new("game".to_string()).unwrap();
+        let first = session.spawn(&archetype, None).unwrap();
+        let second = session.spawn(&archetype, None).unwrap();
+        assert_eq!([first.as_str(), second.as_str()], ["goblin", "goblin-2"]);
+        let [a, b] = [&session.entities()[0], &session.entities()[1]];
+        assert_ne!(a.id, b.id);
+        assert_ne!(a.id, goblin.id);
+        assert_eq!((a.stats, a.count("club")), (goblin.stats, 1));
+    }
+}
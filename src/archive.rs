@@ -0,0 +1,126 @@
+use crate::error::{Error, Result};
+use crate::serde::{from_bytes, Deserialize, FieldReader, FieldWriter, Serialize, HEADER_LEN};
+use crate::session::Session;
+use crate::signing::Keyring;
+use crate::timestamp::Timestamp;
+
+/// First field of every archive, so stray files are rejected up front.
+const MAGIC: &str = "relay_code archive";
+
+/// Bumped whenever the layout of `Contents` changes.
+pub const FORMAT_VERSION: u16 = 1;
+
+/// A session packed into one self-contained `.relay` file for sharing
+/// between machines. The file is three fields:
+///
+/// ```text
+/// Str(MAGIC) U16(FORMAT_VERSION) Bytes(sealed contents)
+/// ```
+///
+/// The contents are sealed with the exporter's keyring, so an archive is
+/// verified on import exactly like a session file.
+#[derive(Debug, PartialEq)]
+pub struct Archive {
+    pub exported: Timestamp,
+    pub session: Session,
+}
+
+impl Archive {
+    pub fn new(session: Session) -> Result<Self> {
+        Ok(Self {
+            exported: Timestamp::now()?,
+            session,
+        })
+    }
+
+    pub fn to_bytes(&self, keyring: &Keyring) -> Vec<u8> {
+        let sealed = keyring.seal(&self.serialize());
+        let mut bytes = Vec::with_capacity(3 * HEADER_LEN + MAGIC.len() + 2 + sealed.len());
+        let mut writer = FieldWriter::new(&mut bytes);
+        writer.write_str(MAGIC);
+        writer.write_u16(FORMAT_VERSION);
+        writer.write_bytes(&sealed);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8], keyring: &Keyring) -> Result<Self> {
+        let mut reader = FieldReader::new(bytes);
+        match reader.read_field::<&str>() {
+            Ok(MAGIC) => {}
+            _ => return Err(Error::NotAnArchive),
+        }
+        let version: u16 = reader.read_field()?;
+        if version != FORMAT_VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+        let sealed: &[u8] = reader.read_field()?;
+        reader.finish()?;
+        from_bytes(keyring.open(sealed)?)
+    }
+}
+
+impl Serialize for Archive {
+    fn serialize_to(&self, writer: &mut FieldWriter<'_>) {
+        writer.write(&self.exported);
+        writer.write(&self.session);
+    }
+
+    fn size_hint(&self) -> usize {
+        HEADER_LEN + 16 + HEADER_LEN + self.session.size_hint()
+    }
+}
+
+impl Deserialize for Archive {
+    fn deserialize(reader: &mut FieldReader<'_>) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        Ok(Self {
+            exported: reader.read_field()?,
+            session: reader.read_field()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Archive;
+    use crate::error::Error;
+    use crate::serde::FieldWriter;
+    use crate::session::Session;
+    use crate::signing::Keyring;
+    use crate::Entity;
+
+    #[test]
+    fn archive_round_trip() {
+        let mut alice = Keyring::default();
+        let public = alice.generate("alice".to_string()).unwrap();
+        let mut bob = Keyring::default();
+        bob.trust("alice".to_string(), &public).unwrap();
+
+        let mut session = Session::new("game".to_string()).unwrap();
+        session.add_entity(Entity::new("hero".to_string())).unwrap();
+        let archive = Archive::new(session).unwrap();
+
+        let bytes = archive.to_bytes(&alice);
+        assert_eq!(Archive::from_bytes(&bytes, &bob).unwrap(), archive);
+    }
+
+    #[test]
+    fn foreign_files_and_versions_are_rejected() {
+        let keyring = Keyring::default();
+        assert!(matches!(
+            Archive::from_bytes(b"not an archive", &keyring),
+            Err(Error::NotAnArchive)
+        ));
+
+        let mut bytes = vec![];
+        let mut writer = FieldWriter::new(&mut bytes);
+        writer.write_str(super::MAGIC);
+        writer.write_u16(99);
+        assert!(matches!(
+            Archive::from_bytes(&bytes, &keyring),
+            Err(Error::UnsupportedVersion(99))
+        ));
+    }
+}
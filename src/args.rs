@@ -1,16 +1,344 @@
+use std::collections::VecDeque;
 use std::env::args;
+use std::path::PathBuf;
 
 use crate::{
-    actions::ActionKind,
+    actions::{ActionKind, Param, Params},
+    attributes::AttributeValue,
+    completions::{Candidates, Shell},
+    components::{Behavior, Position},
+    config::Config,
+    effects::{Effect, EffectKind},
     error::{Error, Result},
+    factions::Stance,
+    handlers, history,
+    macros::Macros,
+    mailbox::MAX_CHAT_LEN,
+    paths,
+    relations::Relation,
+    resources::Resources,
+    rules::{Prerequisite, STANDARD},
+    script, suggest, usage,
+    verbosity::Level,
+    Stats,
 };
 
+/// Flags that apply to every subcommand.
+#[derive(Debug, Default)]
+pub struct Options {
+    pub data_dir: Option<PathBuf>,
+    /// Use the SQLite store rather than one file per session.
+    pub sqlite: bool,
+    /// Always prompt for passphrases instead of using the OS keychain.
+    pub no_keyring: bool,
+    /// Print JSON rather than text, errors included.
+    pub json: bool,
+    /// Never color output, as if `NO_COLOR` were set.
+    pub no_color: bool,
+    /// Set by `-q`, `-v` and `-vv`.
+    pub verbosity: Level,
+    /// Answer yes to every confirmation prompt, for scripts.
+    pub yes: bool,
+    /// The server to act on sessions through, rather than the data
+    /// directory.
+    pub remote: Option<String>,
+    /// What to authenticate to the server with, if the server asks.
+    pub token: Option<String>,
+    /// The certificate authorities to trust a TLS server's certificate
+    /// from, rather than the system's.
+    pub ca: Option<PathBuf>,
+    /// The config file read, which the options above already account for.
+    pub config: Config,
+}
+
+#[derive(Debug)]
+pub enum EntityCommand {
+    /// Session name, the name of the new entity, its stats and, for one
+    /// played by the computer, its behavior.
+    Add(String, String, Stats, Option<Behavior>),
+    Remove(String, String),
+    /// Session name and optionally a single entity to show.
+    Show(String, Option<String>),
+    /// Session name, entity and the attributes to set; those without a
+    /// value are removed.
+    Set(String, String, Vec<(String, Option<AttributeValue>)>),
+    /// Session name, entity and optionally the one attribute to show.
+    Get(String, String, Option<String>),
+    /// Session name, entity and where to put it on the map.
+    Place(String, String, Position),
+    /// Session name, entity and the player it belongs to, or none.
+    Own(String, String, Option<String>),
+}
+
+#[derive(Debug)]
+pub enum RelCommand {
+    /// Session name and the entity whose relationships to show.
+    Show(String, String),
+    /// Session name and the relationship to add, as `a relation b`.
+    Add(String, String, Relation, String),
+    Remove(String, String, Relation, String),
+}
+
+#[derive(Debug)]
+pub enum FactionCommand {
+    /// Session name; shows its factions and how they stand.
+    Show(String),
+    /// Session name and the faction to define.
+    Add(String, String),
+    /// Session name, entity and the faction it joins, or none to leave.
+    Join(String, String, Option<String>),
+    /// Session name, two factions and how they now stand.
+    Stance(String, String, String, Stance),
+}
+
+#[derive(Debug)]
+pub enum PlayerCommand {
+    /// Session name; lists its players.
+    List(String),
+    /// Session name, the player to add and their public key, if known.
+    Add(String, String, Option<String>),
+    /// Session name and the player to remove.
+    Remove(String, String),
+}
+
+#[derive(Debug)]
+pub enum ReplayCommand {
+    /// Session name and the file to write its replay to.
+    Export(String, Option<PathBuf>),
+    /// The replay to play, and whether to wait for Enter after each turn.
+    Play(PathBuf, bool),
+}
+
+#[derive(Debug)]
+pub enum LobbyCommand {
+    /// Session name, the rule set to play by and how many players to wait
+    /// for.
+    Host(String, String, u8),
+    List,
+    /// The game to take a slot in.
+    Join(String),
+}
+
+#[derive(Debug)]
+pub enum TurnCommand {
+    /// Session name and the file to write its new events to.
+    Export(String, Option<PathBuf>),
+    /// The turn file to apply to the session it names.
+    Apply(PathBuf),
+}
+
 #[derive(Debug)]
 pub enum Args {
-    Action(ActionKind, String),
-    New(String),
-    Load(String),
-    Help,
+    /// Session names left out default to the most recently used session.
+    /// The flag is `--dry-run`: show the outcome without saving it.
+    /// `--force` is passed on as the `force` parameter.
+    Action(Option<String>, ActionKind, String, Params, bool),
+    /// `action <name> -`: session name, then `--force` and `--dry-run` for
+    /// every action read from stdin.
+    Actions(Option<String>, bool, bool),
+    /// Session name and optionally the template to start from and the
+    /// rule set to play by.
+    New(String, Option<String>, Option<String>),
+    /// Session name and optionally how many logged events to replay.
+    Load(Option<String>, Option<usize>),
+    /// Session name and optionally the one entity to show in detail.
+    Show(Option<String>, Option<String>),
+    Log(String),
+    History(String, history::Filter),
+    Stats(String),
+    Compact(String),
+    /// Session name, the archive to write, `<name>.relay` by default, and
+    /// the player to redact it for, if any.
+    Export(String, Option<PathBuf>, Option<String>),
+    Import(PathBuf),
+    /// Base, mine and theirs; the result replaces mine.
+    Merge(String, String, String),
+    Turn(TurnCommand),
+    Replay(ReplayCommand),
+    /// Damaged file and where to write what could be recovered.
+    Doctor(PathBuf, Option<PathBuf>),
+    /// Only sessions carrying every one of these tags.
+    List(Vec<String>),
+    /// Session name and tags to add.
+    Tag(String, Vec<String>),
+    Untag(String, Vec<String>),
+    Encrypt(String),
+    Decrypt(String),
+    Delete(String),
+    Rename(String, String),
+    Copy(String, String),
+    /// Session name and which backup to restore, 1 being the most recent.
+    Restore(String, u32),
+    Entity(EntityCommand),
+    Rel(RelCommand),
+    Faction(FactionCommand),
+    Player(PlayerCommand),
+    Undo(String),
+    Redo(String),
+    /// Session name and the actions to queue, each a kind, target and
+    /// parameters.
+    Queue(String, Vec<(ActionKind, String, Params)>),
+    Resolve(String),
+    /// Session name, the new limit on actions per turn and new cooldowns.
+    /// With neither the rules are shown.
+    Rules(String, Option<u32>, Vec<(ActionKind, u32)>),
+    /// Session name, the action kind and everything it now requires;
+    /// nothing lifts its requirements.
+    Require(String, ActionKind, Vec<Prerequisite>),
+    /// Session name, the action kind and its new cost; nothing makes it free.
+    Cost(String, ActionKind, Resources),
+    /// Session name, the action kind and the xp its target earns; 0 stops
+    /// it earning any.
+    Xp(String, ActionKind, u32),
+    /// Session name, the xp between levels and the stats gained on each.
+    Leveling(String, u32, Stats),
+    /// Session name and the map's width and height.
+    Map(String, u32, u32),
+    /// Session name, the entity and the effect it comes under.
+    Afflict(String, String, Effect),
+    /// Session name, the entity and the resources to add to its pool.
+    Grant(String, String, Resources),
+    /// Show the macros defined in the data directory.
+    Macros,
+    /// Session name, the archetype to spawn and the name of the new entity.
+    Spawn(String, String, Option<String>),
+    /// Session name, the entity to save and the archetype to save it as,
+    /// by default its own name.
+    SaveArchetype(String, String, Option<String>),
+    /// Show the archetypes saved in the data directory.
+    Archetypes,
+    /// Session name, the action kind to define and the script defining it.
+    Script(String, String, PathBuf),
+    /// Print the completion script for a shell.
+    Completions(Shell),
+    /// List what completion scripts offer at a point of the command line.
+    Complete(Candidates),
+    /// The player to make a key for, if not the configured one.
+    KeyGen(Option<String>),
+    Trust(String, String),
+    /// The address to accept clients on, one to accept them on over
+    /// WebSocket, and the certificate and key files to encrypt both with.
+    Serve(String, Option<String>, Option<(PathBuf, PathBuf)>),
+    /// The player to issue a server token to, and whether they may only
+    /// watch.
+    GenToken(String, bool),
+    /// The session and what to tell its other players.
+    Chat(Option<String>, String),
+    /// The session whose turn to end.
+    Pass(Option<String>),
+    /// The session and the players to take turns in it, if they are to be
+    /// set; `--none` sets none.
+    Players(String, Option<Vec<String>>),
+    /// The session, how many seconds each turn may last, if limited, and
+    /// whether players who run out of time forfeit.
+    Deadline(String, Option<u32>, bool),
+    CheckDeadlines,
+    /// The session and the address of the server to sync it with.
+    Sync(String, String),
+    /// The address to accept clients on and that of the server to relay
+    /// them to.
+    Proxy(String, String),
+    Mail,
+    Ping,
+    /// The session to follow as a spectator.
+    Watch(Option<String>),
+    Lobby(LobbyCommand),
+    /// The subcommand to show help for, or none for all of them.
+    Help(Option<String>),
+    Version,
+}
+
+impl Args {
+    /// The session a command names explicitly, which becomes the most
+    /// recently used one once the command succeeds.
+    pub fn session(&self) -> Option<&str> {
+        match self {
+            Args::Action(name, ..)
+            | Args::Actions(name, ..)
+            | Args::Load(name, _)
+            | Args::Show(name, _) => name.as_deref(),
+            Args::New(name, ..)
+            | Args::Log(name)
+            | Args::History(name, _)
+            | Args::Stats(name)
+            | Args::Compact(name)
+            | Args::Export(name, ..)
+            | Args::Tag(name, _)
+            | Args::Untag(name, _)
+            | Args::Encrypt(name)
+            | Args::Decrypt(name)
+            | Args::Restore(name, _)
+            | Args::Undo(name)
+            | Args::Redo(name)
+            | Args::Queue(name, _)
+            | Args::Resolve(name)
+            | Args::Rules(name, ..)
+            | Args::Require(name, ..)
+            | Args::Cost(name, ..)
+            | Args::Xp(name, ..)
+            | Args::Leveling(name, ..)
+            | Args::Grant(name, ..)
+            | Args::Afflict(name, ..)
+            | Args::Map(name, ..)
+            | Args::Script(name, ..)
+            | Args::Spawn(name, ..)
+            | Args::SaveArchetype(name, ..)
+            | Args::Rename(_, name)
+            | Args::Players(name, _)
+            | Args::Deadline(name, ..)
+            | Args::Merge(_, name, _)
+            | Args::Turn(TurnCommand::Export(name, _))
+            | Args::Replay(ReplayCommand::Export(name, _))
+            | Args::Sync(name, _) => Some(name),
+            Args::Entity(
+                EntityCommand::Add(name, ..)
+                | EntityCommand::Remove(name, _)
+                | EntityCommand::Show(name, _)
+                | EntityCommand::Set(name, ..)
+                | EntityCommand::Get(name, ..)
+                | EntityCommand::Place(name, ..)
+                | EntityCommand::Own(name, ..),
+            ) => Some(name),
+            Args::Rel(
+                RelCommand::Show(name, _)
+                | RelCommand::Add(name, ..)
+                | RelCommand::Remove(name, ..),
+            ) => Some(name),
+            Args::Faction(
+                FactionCommand::Show(name)
+                | FactionCommand::Add(name, _)
+                | FactionCommand::Join(name, ..)
+                | FactionCommand::Stance(name, ..),
+            ) => Some(name),
+            Args::Player(
+                PlayerCommand::List(name)
+                | PlayerCommand::Add(name, ..)
+                | PlayerCommand::Remove(name, _),
+            ) => Some(name),
+            _ => None,
+        }
+    }
+}
+
+/// Seconds in `90s`, `10m`, `2h` or `3d`; plain numbers are seconds.
+fn parse_time(text: &str) -> Result<u32> {
+    let (count, unit) = match text.find(|c: char| !c.is_ascii_digit()) {
+        Some(at) => text.split_at(at),
+        None => (text, "s"),
+    };
+    let unit = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86_400,
+        _ => return Err(Error::InvalidArgs),
+    };
+    count
+        .parse::<u32>()
+        .ok()
+        .and_then(|count| count.checked_mul(unit))
+        .filter(|secs| *secs > 0)
+        .ok_or(Error::InvalidArgs)
 }
 
 fn parse_action_kind<S: AsRef<str>>(action: S) -> Result<ActionKind> {
@@ -18,37 +346,1060 @@ fn parse_action_kind<S: AsRef<str>>(action: S) -> Result<ActionKind> {
         "fight" => Ok(ActionKind::Fight),
         "love" => Ok(ActionKind::Love),
         "neutral" => Ok(ActionKind::Neutral),
-        _ => Err(Error::InvalidActionType),
+        "attack" => Ok(ActionKind::Attack),
+        "defend" => Ok(ActionKind::Defend),
+        "trade" => Ok(ActionKind::Trade),
+        "rest" => Ok(ActionKind::Rest),
+        "inspect" => Ok(ActionKind::Inspect),
+        "say" => Ok(ActionKind::Say),
+        "give" => Ok(ActionKind::Give),
+        "drop" => Ok(ActionKind::Drop),
+        "use" => Ok(ActionKind::Use),
+        "transfer" => Ok(ActionKind::Transfer),
+        "move" => Ok(ActionKind::Move),
+        "equip" => Ok(ActionKind::Equip),
+        "unequip" => Ok(ActionKind::Unequip),
+        name => handlers::find(name).ok_or(Error::InvalidActionType),
+    }
+}
+
+/// One of `hp=<n>`, `guarding`, `unguarded`, `exists=<entity>` or
+/// `after=<action>`.
+fn parse_prerequisite(arg: &str) -> Result<Prerequisite> {
+    match arg.split_once('=') {
+        None if arg == "guarding" => Ok(Prerequisite::Guarding(true)),
+        None if arg == "unguarded" => Ok(Prerequisite::Guarding(false)),
+        Some(("hp", n)) => Ok(Prerequisite::MinHp(
+            n.parse().map_err(|_| Error::InvalidArgs)?,
+        )),
+        Some(("exists", name)) => Ok(Prerequisite::Exists(name.to_string())),
+        Some(("after", kind)) => Ok(Prerequisite::After(parse_action_kind(kind)?)),
+        _ => Err(Error::InvalidArgs),
+    }
+}
+
+/// Any of `hp=<n>`, `attack=<n>`, `defense=<n>` and `speed=<n>`; those
+/// left out are 0.
+fn parse_stats(args: impl Iterator<Item = String>) -> Result<Stats> {
+    let mut stats = Stats::default();
+    for arg in args {
+        let (key, value) = arg.split_once('=').ok_or(Error::InvalidArgs)?;
+        let (_, stat) = stats
+            .named_mut()
+            .into_iter()
+            .find(|(name, _)| *name == key)
+            .ok_or(Error::InvalidArgs)?;
+        *stat = value.parse().map_err(|_| Error::InvalidArgs)?;
+    }
+    Ok(stats)
+}
+
+/// Any of `ap=<n>`, `mana=<n>` and `gold=<n>`; those left out are 0.
+fn parse_resources(args: impl Iterator<Item = String>) -> Result<Resources> {
+    let mut resources = Resources::default();
+    for arg in args {
+        let (key, value) = arg.split_once('=').ok_or(Error::InvalidArgs)?;
+        match key {
+            "ap" => resources.action_points = value.parse().map_err(|_| Error::InvalidArgs)?,
+            "mana" => resources.mana = value.parse().map_err(|_| Error::InvalidArgs)?,
+            "gold" => resources.gold = value.parse().map_err(|_| Error::InvalidArgs)?,
+            _ => return Err(Error::InvalidArgs),
+        }
+    }
+    Ok(resources)
+}
+
+/// Separates `key=value` args from positional ones. The target may be given
+/// as `target=<entity>`, in which case it becomes the last positional arg.
+fn split_params(args: impl Iterator<Item = String>) -> (Vec<String>, Params) {
+    let (mut rest, mut params) = (vec![], Params::new());
+    for arg in args {
+        match arg.split_once('=') {
+            Some((key, value)) if !key.is_empty() => {
+                params.insert(key.to_string(), Param::parse(value));
+            }
+            _ => rest.push(arg),
+        }
+    }
+    if let Some(target) = params.remove("target") {
+        rest.push(target.to_string());
+    }
+    (rest, params)
+}
+
+/// Where `serve` listens unless told otherwise: only on this machine.
+pub const DEFAULT_BIND: &str = "127.0.0.1:7777";
+
+/// Where `proxy` listens unless told otherwise.
+pub const DEFAULT_PROXY: &str = "127.0.0.1:7778";
+
+/// Parses actions as `queue` and `action -` take them: `<kind> <target>
+/// [<key>=<value>]...`, separated by commas or lines. A lone word is the
+/// name of a macro standing for several. Blank lines are skipped.
+pub fn parse_actions(text: &str, macros: &Macros) -> Result<Vec<(ActionKind, String, Params)>> {
+    let mut expanded = vec![];
+    for action in text.split([',', '\n']).map(str::trim) {
+        match macros.get(action) {
+            Some(body) => expanded.extend(body.split(',')),
+            None => expanded.push(action),
+        }
+    }
+    let mut actions = vec![];
+    for action in expanded
+        .into_iter()
+        .filter(|action| !action.trim().is_empty())
+    {
+        let (rest, mut params) = split_params(action.split_whitespace().map(String::from));
+        let [kind, target] = <[String; 2]>::try_from(rest).map_err(|_| Error::InvalidArgs)?;
+        actions.push((parse_kind_or_script(kind, &mut params)?, target, params));
+    }
+    Ok(actions)
+}
+
+/// `UnknownCommand`, suggesting the subcommand closest to `command`.
+fn unknown_command(command: &str) -> Error {
+    let commands = usage::COMMANDS.iter().map(|usage| usage.command);
+    suggest::did_you_mean(
+        Error::UnknownCommand(command.to_string()),
+        command,
+        commands,
+    )
+}
+
+/// Any name that is not a built-in kind is taken to be a scripted one,
+/// named by the `script` parameter.
+fn parse_kind_or_script(kind: String, params: &mut Params) -> Result<ActionKind> {
+    match parse_action_kind(&kind) {
+        Ok(kind) => Ok(kind),
+        Err(err) if script::validate_name(&kind).is_err() => Err(err),
+        Err(_) => {
+            params.insert("script".to_string(), Param::Text(kind));
+            Ok(ActionKind::Script)
+        }
+    }
+}
+
+/// Whether `arg` reads as a flag, such as `--at` or `-o`, rather than an
+/// operand. Negative numbers and `-` alone are operands.
+fn is_flag(arg: &str) -> bool {
+    match arg.strip_prefix("--") {
+        Some(name) => !name.is_empty(),
+        None => arg.len() == 2 && arg.starts_with('-') && arg.as_bytes()[1].is_ascii_alphabetic(),
+    }
+}
+
+/// The args of one subcommand while its flags are taken out of them. Every
+/// subcommand takes `--session <name>` in place of its session operand.
+struct Flags {
+    command: &'static str,
+    args: Vec<String>,
+}
+
+impl Flags {
+    fn new(command: &'static str, args: impl Iterator<Item = String>) -> Self {
+        Self {
+            command,
+            args: args.collect(),
+        }
+    }
+
+    /// Takes out `flag` and returns whether it was given.
+    fn flag(&mut self, names: &[&str]) -> bool {
+        let before = self.args.len();
+        self.args.retain(|arg| !names.contains(&arg.as_str()));
+        self.args.len() < before
+    }
+
+    /// Takes out every `--flag <value>` or `--flag=<value>`, in order.
+    fn values(&mut self, names: &[&str]) -> Result<Vec<String>> {
+        let mut values = vec![];
+        let mut i = 0;
+        while i < self.args.len() {
+            let arg = &self.args[i];
+            if names.contains(&arg.as_str()) {
+                if i + 1 == self.args.len() {
+                    let what = format!("a value after {arg}");
+                    return Err(Error::MissingArg(self.command.to_string(), what));
+                }
+                self.args.remove(i);
+                values.push(self.args.remove(i));
+                continue;
+            }
+            let inline = names.iter().find_map(|name| {
+                arg.strip_prefix(name)
+                    .and_then(|rest| rest.strip_prefix('='))
+                    .filter(|_| name.starts_with("--"))
+            });
+            match inline {
+                Some(value) => {
+                    values.push(value.to_string());
+                    self.args.remove(i);
+                }
+                None => i += 1,
+            }
+        }
+        Ok(values)
+    }
+
+    /// Takes out `--flag <value>`; the last one given wins.
+    fn value(&mut self, names: &[&str]) -> Result<Option<String>> {
+        Ok(self.values(names)?.pop())
+    }
+
+    /// Takes out `--flag <n>`, which must be a number.
+    fn number<T: std::str::FromStr>(&mut self, names: &[&str]) -> Result<Option<T>> {
+        self.value(names)?
+            .map(|n| n.parse().map_err(|_| Error::InvalidArgs))
+            .transpose()
+    }
+
+    /// What is left once every flag the subcommand takes is out, each of
+    /// them an operand. A flag still among them is one it does not take.
+    fn operands(mut self) -> Result<Operands> {
+        let session = self.value(&["--session"])?;
+        if let Some(flag) = self.args.iter().find(|arg| is_flag(arg)) {
+            return Err(Error::UnknownFlag(self.command.to_string(), flag.clone()));
+        }
+        Ok(Operands {
+            command: self.command,
+            session,
+            args: self.args.into(),
+        })
+    }
+}
+
+/// The operands of a subcommand, taken in order.
+struct Operands {
+    command: &'static str,
+    /// The session given by `--session`, if any.
+    session: Option<String>,
+    args: VecDeque<String>,
+}
+
+impl Operands {
+    /// The next operand, described by `what` if it is missing.
+    fn next(&mut self, what: &str) -> Result<String> {
+        self.args
+            .pop_front()
+            .ok_or_else(|| Error::MissingArg(self.command.to_string(), what.to_string()))
+    }
+
+    fn optional(&mut self) -> Option<String> {
+        self.args.pop_front()
+    }
+
+    /// The session named by `--session`, or else the next operand.
+    fn session(&mut self) -> Result<String> {
+        match self.session.take() {
+            Some(name) => Ok(name),
+            None => self.next("a session <name>"),
+        }
+    }
+
+    /// The session named by `--session`, or else the next operand if it is
+    /// not one of the `operands` that must follow.
+    fn optional_session(&mut self, operands: usize) -> Option<String> {
+        match self.session.take() {
+            Some(name) => Some(name),
+            None if self.args.len() > operands => self.args.pop_front(),
+            None => None,
+        }
+    }
+
+    /// The next operand as a number.
+    fn number<T: std::str::FromStr>(&mut self, what: &str) -> Result<T> {
+        self.next(what)?.parse().map_err(|_| Error::InvalidArgs)
+    }
+
+    /// Every operand left.
+    fn rest(&mut self) -> std::collections::vec_deque::IntoIter<String> {
+        std::mem::take(&mut self.args).into_iter()
+    }
+
+    /// Checks that every operand was taken.
+    fn finish(self) -> Result<()> {
+        match self.args.into_iter().next() {
+            Some(arg) => Err(Error::UnexpectedArg(self.command.to_string(), arg)),
+            None => Ok(()),
+        }
     }
 }
 
 impl Args {
-    pub fn parse() -> Result<Args> {
+    /// Parses the command line. The data directory is resolved first, so
+    /// the macros kept there can be expanded; it is returned in `Options`.
+    /// The options are returned even if the command is wrong, so that the
+    /// error can be printed the way they ask. Flags left out are taken from
+    /// the config file.
+    pub fn parse() -> (Options, Result<Args>) {
+        let mut options = Options::default();
+        let mut rest = vec![];
+        let mut config = None;
+        let mut player = None;
+        let mut missing_value = false;
         let mut args = args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--data-dir" => match args.next() {
+                    Some(dir) => options.data_dir = Some(dir.into()),
+                    None => missing_value = true,
+                },
+                "--config" => match args.next() {
+                    Some(path) => config = Some(PathBuf::from(path)),
+                    None => missing_value = true,
+                },
+                "--remote" => match args.next() {
+                    Some(address) => options.remote = Some(address),
+                    None => missing_value = true,
+                },
+                "--token" => match args.next() {
+                    Some(token) => options.token = Some(token),
+                    None => missing_value = true,
+                },
+                "--ca" => match args.next() {
+                    Some(path) => options.ca = Some(path.into()),
+                    None => missing_value = true,
+                },
+                "--as" => match args.next() {
+                    Some(name) => player = Some(name),
+                    None => missing_value = true,
+                },
+                "--sqlite" => options.sqlite = true,
+                "--no-keyring" => options.no_keyring = true,
+                "--json" => options.json = true,
+                "--no-color" => options.no_color = true,
+                "-y" | "--yes" => options.yes = true,
+                "-q" | "--quiet" => options.verbosity = Level::Quiet,
+                "-v" | "--verbose" => options.verbosity = options.verbosity.louder(),
+                "-vv" => options.verbosity = Level::Trace,
+                _ => {
+                    if let Some(dir) = arg.strip_prefix("--data-dir=") {
+                        options.data_dir = Some(dir.into());
+                    } else if let Some(path) = arg.strip_prefix("--config=") {
+                        config = Some(path.into());
+                    } else if let Some(address) = arg.strip_prefix("--remote=") {
+                        options.remote = Some(address.to_string());
+                    } else if let Some(token) = arg.strip_prefix("--token=") {
+                        options.token = Some(token.to_string());
+                    } else if let Some(path) = arg.strip_prefix("--ca=") {
+                        options.ca = Some(path.into());
+                    } else {
+                        rest.push(arg);
+                    }
+                }
+            }
+        }
+        if missing_value {
+            return (options, Err(Error::InvalidArgs));
+        }
+        let args = Config::load(config.as_deref()).and_then(|mut config| {
+            config.player = player.or(config.player);
+            options.sqlite |= config.sqlite;
+            options.no_keyring |= config.no_keyring;
+            let dir = paths::data_dir(options.data_dir.take(), config.data_dir.clone())?;
+            options.config = config;
+            let macros = Macros::load(&dir)?;
+            options.data_dir = Some(dir);
+            Self::parse_command(rest.into_iter(), &macros)
+        });
+        (options, args)
+    }
 
+    fn parse_command(mut args: impl Iterator<Item = String>, macros: &Macros) -> Result<Args> {
         let next_arg = match args.next() {
-            None => return Ok(Args::Help),
+            None => return Ok(Args::Help(None)),
             Some(arg) => arg,
         };
+        let command = match next_arg.as_str() {
+            "--help" | "-h" => return Ok(Args::Help(None)),
+            "--version" | "-V" => return Ok(Args::Version),
+            "help" => {
+                let command = args.next();
+                if let Some(command) = command.as_deref().filter(|c| usage::usage(c).is_none()) {
+                    return Err(unknown_command(command));
+                }
+                return Ok(Args::Help(command));
+            }
+            command => match usage::usage(command) {
+                Some(usage) => usage.command,
+                None => return Err(unknown_command(command)),
+            },
+        };
+        let mut flags = Flags::new(command, args);
+        if flags.flag(&["--help", "-h"]) {
+            return Ok(Args::Help(Some(command.to_string())));
+        }
 
-        match next_arg.as_str() {
+        let args = match command {
             "new" => {
-                let name = args.next().ok_or(Error::InvalidArgs)?;
-                Ok(Args::New(name))
+                let template = flags.value(&["--template"])?;
+                let rules = flags.value(&["--rules"])?;
+                let mut args = flags.operands()?;
+                let name = args.session()?;
+                args.finish()?;
+                Args::New(name, template, rules)
+            }
+            "undo" | "redo" | "log" | "stats" | "compact" | "encrypt" | "decrypt" | "resolve" => {
+                let mut args = flags.operands()?;
+                let name = args.session()?;
+                args.finish()?;
+                match command {
+                    "undo" => Args::Undo(name),
+                    "redo" => Args::Redo(name),
+                    "log" => Args::Log(name),
+                    "stats" => Args::Stats(name),
+                    "compact" => Args::Compact(name),
+                    "encrypt" => Args::Encrypt(name),
+                    "decrypt" => Args::Decrypt(name),
+                    _ => Args::Resolve(name),
+                }
             }
             "load" => {
-                let name = args.next().ok_or(Error::InvalidArgs)?;
-                Ok(Args::Load(name))
+                let at = flags.number(&["--at"])?;
+                let mut args = flags.operands()?;
+                let name = args.optional_session(0);
+                args.finish()?;
+                Args::Load(name, at)
+            }
+            "show" => {
+                let entity = flags.value(&["--entity"])?;
+                let mut args = flags.operands()?;
+                let name = args.optional_session(0);
+                args.finish()?;
+                Args::Show(name, entity)
+            }
+            "export" => {
+                let output = flags.value(&["-o", "--output"])?;
+                let player = flags.value(&["--for"])?;
+                let mut args = flags.operands()?;
+                let name = args.session()?;
+                args.finish()?;
+                Args::Export(name, output.map(PathBuf::from), player)
+            }
+            "turn" => {
+                let output = flags.value(&["-o", "--output"])?;
+                let mut args = flags.operands()?;
+                let verb = args.next("export or apply")?;
+                let command = match verb.as_str() {
+                    "export" => TurnCommand::Export(args.session()?, output.map(PathBuf::from)),
+                    "apply" if output.is_none() => {
+                        TurnCommand::Apply(args.next("a <file>")?.into())
+                    }
+                    "apply" => {
+                        return Err(Error::UnexpectedArg(command.to_string(), "-o".to_string()))
+                    }
+                    _ => return Err(Error::UnexpectedArg(command.to_string(), verb)),
+                };
+                args.finish()?;
+                Args::Turn(command)
+            }
+            "replay" => {
+                let output = flags.value(&["-o", "--output"])?;
+                let step = flags.flag(&["--step"]);
+                let mut args = flags.operands()?;
+                let verb = args.next("export or play")?;
+                let command = match verb.as_str() {
+                    "export" if !step => {
+                        ReplayCommand::Export(args.session()?, output.map(PathBuf::from))
+                    }
+                    "play" if output.is_none() => {
+                        ReplayCommand::Play(args.next("a <file>")?.into(), step)
+                    }
+                    "export" => {
+                        return Err(Error::UnexpectedArg(
+                            command.to_string(),
+                            "--step".to_string(),
+                        ))
+                    }
+                    "play" => {
+                        return Err(Error::UnexpectedArg(command.to_string(), "-o".to_string()))
+                    }
+                    _ => return Err(Error::UnexpectedArg(command.to_string(), verb)),
+                };
+                args.finish()?;
+                Args::Replay(command)
+            }
+            "doctor" => {
+                let output = flags.value(&["-o", "--output"])?;
+                let mut args = flags.operands()?;
+                let file = args.next("a session <file>")?;
+                args.finish()?;
+                Args::Doctor(file.into(), output.map(PathBuf::from))
+            }
+            "merge" => {
+                let mut args = flags.operands()?;
+                let base = args.next("<base>")?;
+                let mine = args.next("<mine>")?;
+                let theirs = args.next("<theirs>")?;
+                args.finish()?;
+                Args::Merge(base, mine, theirs)
+            }
+            "import" => {
+                let mut args = flags.operands()?;
+                let path = args.next("a .relay <file>")?;
+                args.finish()?;
+                Args::Import(path.into())
+            }
+            "history" => {
+                let mut filter = history::Filter::default();
+                if let Some(kind) = flags.value(&["--kind"])? {
+                    filter.kind = Some(parse_action_kind(kind)?);
+                }
+                filter.entity = flags.value(&["--entity"])?;
+                filter.last = flags.number(&["--last"])?;
+                let mut args = flags.operands()?;
+                let name = args.session()?;
+                args.finish()?;
+                Args::History(name, filter)
+            }
+            "serve" => {
+                if let Some(player) = flags.value(&["--gen-token"])? {
+                    let spectator = flags.flag(&["--spectator"]);
+                    flags.operands()?.finish()?;
+                    return Ok(Args::GenToken(player, spectator));
+                }
+                let bind = flags.value(&["--bind"])?;
+                let ws = flags.value(&["--ws"])?;
+                let tls = match (flags.value(&["--cert"])?, flags.value(&["--key"])?) {
+                    (Some(cert), Some(key)) => Some((cert.into(), key.into())),
+                    (None, None) => None,
+                    (cert, _) => {
+                        let what = match cert {
+                            Some(_) => "--key with --cert",
+                            None => "--cert with --key",
+                        };
+                        return Err(Error::MissingArg(command.to_string(), what.to_string()));
+                    }
+                };
+                flags.operands()?.finish()?;
+                Args::Serve(bind.unwrap_or_else(|| DEFAULT_BIND.to_string()), ws, tls)
+            }
+            "chat" => {
+                let mut args = flags.operands()?;
+                let name = args.optional_session(1);
+                let text = args.next("a <message>")?;
+                args.finish()?;
+                if text.len() > MAX_CHAT_LEN {
+                    return Err(Error::ChatTooLong(text.len()));
+                }
+                Args::Chat(name, text)
+            }
+            "sync" => {
+                let peer = flags.value(&["--peer"])?;
+                let mut args = flags.operands()?;
+                let name = args.session()?;
+                args.finish()?;
+                let peer = peer.ok_or_else(|| {
+                    Error::MissingArg(command.to_string(), "--peer <host:port>".to_string())
+                })?;
+                Args::Sync(name, peer)
+            }
+            "proxy" => {
+                let listen = flags.value(&["--listen"])?;
+                let upstream = flags.value(&["--upstream"])?;
+                flags.operands()?.finish()?;
+                let upstream = upstream.ok_or_else(|| {
+                    Error::MissingArg(command.to_string(), "--upstream <host:port>".to_string())
+                })?;
+                let listen = match listen {
+                    // As in ":7778", on this machine only.
+                    Some(listen) if listen.starts_with(':') => format!("127.0.0.1{listen}"),
+                    listen => listen.unwrap_or_else(|| DEFAULT_PROXY.to_string()),
+                };
+                Args::Proxy(listen, upstream)
+            }
+            "mail" => {
+                flags.operands()?.finish()?;
+                Args::Mail
+            }
+            "lobby" => {
+                let rules = flags.value(&["--rules"])?;
+                let slots = flags.number(&["--slots"])?;
+                let mut args = flags.operands()?;
+                let verb = args.next("host, list or join")?;
+                let command = match verb.as_str() {
+                    "host" => LobbyCommand::Host(
+                        args.session()?,
+                        rules.unwrap_or_else(|| STANDARD.to_string()),
+                        slots.unwrap_or(2),
+                    ),
+                    "list" | "join" if rules.is_some() || slots.is_some() => {
+                        let flag = if rules.is_some() {
+                            "--rules"
+                        } else {
+                            "--slots"
+                        };
+                        return Err(Error::UnexpectedArg(command.to_string(), flag.to_string()));
+                    }
+                    "list" => LobbyCommand::List,
+                    "join" => LobbyCommand::Join(args.session()?),
+                    _ => return Err(Error::UnexpectedArg(command.to_string(), verb)),
+                };
+                args.finish()?;
+                Args::Lobby(command)
+            }
+            "ping" => {
+                flags.operands()?.finish()?;
+                Args::Ping
+            }
+            "pass" => {
+                let mut args = flags.operands()?;
+                let name = args.optional_session(0);
+                args.finish()?;
+                Args::Pass(name)
+            }
+            "players" => {
+                let none = flags.flag(&["--none"]);
+                let mut args = flags.operands()?;
+                let name = args.session()?;
+                let players: Vec<_> = args.rest().collect();
+                match (none, players.is_empty()) {
+                    (true, false) => return Err(Error::InvalidArgs),
+                    (true, true) => Args::Players(name, Some(vec![])),
+                    (false, true) => Args::Players(name, None),
+                    (false, false) => Args::Players(name, Some(players)),
+                }
+            }
+            "deadline" => {
+                let forfeit = flags.flag(&["--forfeit"]);
+                let mut args = flags.operands()?;
+                let name = args.session()?;
+                let time = match args.next("a <time>")?.as_str() {
+                    "none" if forfeit => return Err(Error::InvalidArgs),
+                    "none" => None,
+                    time => Some(parse_time(time)?),
+                };
+                args.finish()?;
+                Args::Deadline(name, time, forfeit)
+            }
+            "check-deadlines" => {
+                flags.operands()?.finish()?;
+                Args::CheckDeadlines
+            }
+            "watch" => {
+                let mut args = flags.operands()?;
+                let name = args.optional_session(0);
+                args.finish()?;
+                Args::Watch(name)
+            }
+            "version" => {
+                flags.operands()?.finish()?;
+                Args::Version
+            }
+            "completions" => {
+                let mut args = flags.operands()?;
+                let shell = Shell::parse(&args.next("a shell")?)?;
+                args.finish()?;
+                Args::Completions(shell)
+            }
+            "complete" => {
+                let mut args = flags.operands()?;
+                let candidates = Candidates::parse(&args.next("what to list")?)?;
+                args.finish()?;
+                Args::Complete(candidates)
+            }
+            "keygen" => {
+                let mut args = flags.operands()?;
+                let player = args.optional();
+                args.finish()?;
+                Args::KeyGen(player)
+            }
+            "trust" => {
+                let mut args = flags.operands()?;
+                let player = args.next("a <player>")?;
+                let public_key = args.next("their <public-key>")?;
+                args.finish()?;
+                Args::Trust(player, public_key)
+            }
+            "list" => {
+                let tags = flags.values(&["--tag"])?;
+                flags.operands()?.finish()?;
+                Args::List(tags)
+            }
+            "tag" | "untag" => {
+                let mut args = flags.operands()?;
+                let name = args.session()?;
+                let tags: Vec<_> = args.rest().collect();
+                if tags.is_empty() {
+                    return Err(Error::MissingArg(
+                        command.to_string(),
+                        "a <tag>".to_string(),
+                    ));
+                }
+                match command {
+                    "tag" => Args::Tag(name, tags),
+                    _ => Args::Untag(name, tags),
+                }
+            }
+            "delete" => {
+                let mut args = flags.operands()?;
+                let name = args.session()?;
+                args.finish()?;
+                Args::Delete(name)
+            }
+            "rename" | "copy" => {
+                let mut args = flags.operands()?;
+                let src = args.session()?;
+                let dst = args.next("a new <name>")?;
+                args.finish()?;
+                match command {
+                    "rename" => Args::Rename(src, dst),
+                    _ => Args::Copy(src, dst),
+                }
+            }
+            "restore" => {
+                let backup = flags.number(&["--backup"])?.unwrap_or(1);
+                let mut args = flags.operands()?;
+                let name = args.session()?;
+                args.finish()?;
+                Args::Restore(name, backup)
+            }
+            "entity" => {
+                let mut args = flags.operands()?;
+                let verb = args.next("add, remove, show, set, get, place or own")?;
+                let session = args.session()?;
+                let command = match verb.as_str() {
+                    "add" => {
+                        let entity = args.next("an <entity>")?;
+                        let (ai, stats): (Vec<_>, Vec<_>) =
+                            args.rest().partition(|arg| arg.starts_with("ai="));
+                        let behavior = match &ai[..] {
+                            [] => None,
+                            [ai] => Some(Behavior::parse(&ai["ai=".len()..])?),
+                            _ => return Err(Error::InvalidArgs),
+                        };
+                        let stats = parse_stats(stats.into_iter())?;
+                        EntityCommand::Add(session, entity, stats, behavior)
+                    }
+                    "remove" => EntityCommand::Remove(session, args.next("an <entity>")?),
+                    "show" => EntityCommand::Show(session, args.optional()),
+                    "set" => {
+                        let entity = args.next("an <entity>")?;
+                        let attributes = args
+                            .rest()
+                            .map(|arg| {
+                                let (key, value) = arg.split_once('=').ok_or(Error::InvalidArgs)?;
+                                let value = Some(value)
+                                    .filter(|value| !value.is_empty())
+                                    .map(AttributeValue::parse);
+                                Ok((key.to_string(), value))
+                            })
+                            .collect::<Result<Vec<_>>>()?;
+                        if attributes.is_empty() {
+                            let what = "a <key>=<value>".to_string();
+                            return Err(Error::MissingArg(command.to_string(), what));
+                        }
+                        EntityCommand::Set(session, entity, attributes)
+                    }
+                    "get" => {
+                        let entity = args.next("an <entity>")?;
+                        EntityCommand::Get(session, entity, args.optional())
+                    }
+                    "place" => {
+                        let entity = args.next("an <entity>")?;
+                        let x = args.number("<x>")?;
+                        let y = args.number("<y>")?;
+                        EntityCommand::Place(session, entity, Position { x, y })
+                    }
+                    "own" => {
+                        let entity = args.next("an <entity>")?;
+                        let player = args.next("a <player> or none")?;
+                        let player = Some(player).filter(|player| player != "none");
+                        EntityCommand::Own(session, entity, player)
+                    }
+                    _ => return Err(Error::UnexpectedArg(command.to_string(), verb)),
+                };
+                args.finish()?;
+                Args::Entity(command)
+            }
+            "rel" => {
+                let mut args = flags.operands()?;
+                let session = args.session()?;
+                let verb = args.next("show, add or remove")?;
+                let command = match verb.as_str() {
+                    "show" => RelCommand::Show(session, args.next("an <entity>")?),
+                    "add" | "remove" => {
+                        let from = args.next("an <entity>")?;
+                        let relation = Relation::parse(&args.next("a <relation>")?)?;
+                        let to = args.next("another <entity>")?;
+                        match verb.as_str() {
+                            "add" => RelCommand::Add(session, from, relation, to),
+                            _ => RelCommand::Remove(session, from, relation, to),
+                        }
+                    }
+                    _ => return Err(Error::UnexpectedArg(command.to_string(), verb)),
+                };
+                args.finish()?;
+                Args::Rel(command)
+            }
+            "faction" => {
+                let mut args = flags.operands()?;
+                let session = args.session()?;
+                let verb = args.next("show, add, join or stance")?;
+                let command = match verb.as_str() {
+                    "show" => FactionCommand::Show(session),
+                    "add" => FactionCommand::Add(session, args.next("a <faction>")?),
+                    "join" => {
+                        let entity = args.next("an <entity>")?;
+                        let faction = args.next("a <faction> or none")?;
+                        let faction = Some(faction).filter(|faction| faction != "none");
+                        FactionCommand::Join(session, entity, faction)
+                    }
+                    "stance" => {
+                        let a = args.next("a <faction>")?;
+                        let b = args.next("another <faction>")?;
+                        let stance = Stance::parse(&args.next("hostile, neutral or allied")?)?;
+                        FactionCommand::Stance(session, a, b, stance)
+                    }
+                    _ => return Err(Error::UnexpectedArg(command.to_string(), verb)),
+                };
+                args.finish()?;
+                Args::Faction(command)
+            }
+            "player" => {
+                let mut args = flags.operands()?;
+                let verb = args.next("list, add or remove")?;
+                let session = args.session()?;
+                let command = match verb.as_str() {
+                    "list" => PlayerCommand::List(session),
+                    "add" => {
+                        let player = args.next("a <player>")?;
+                        PlayerCommand::Add(session, player, args.optional())
+                    }
+                    "remove" => PlayerCommand::Remove(session, args.next("a <player>")?),
+                    _ => return Err(Error::UnexpectedArg(command.to_string(), verb)),
+                };
+                args.finish()?;
+                Args::Player(command)
+            }
+            "queue" => {
+                let mut args = flags.operands()?;
+                let name = args.session()?;
+                let actions = args.rest().collect::<Vec<_>>().join(" ");
+                Args::Queue(name, parse_actions(&actions, macros)?)
+            }
+            "rules" => {
+                let mut args = flags.operands()?;
+                let name = args.session()?;
+                let (mut max_actions, mut cooldowns) = (None, vec![]);
+                for arg in args.rest() {
+                    let (key, value) = arg.split_once('=').ok_or(Error::InvalidArgs)?;
+                    let value = value.parse().map_err(|_| Error::InvalidArgs)?;
+                    match key {
+                        "max" => max_actions = Some(value),
+                        kind => cooldowns.push((parse_action_kind(kind)?, value)),
+                    }
+                }
+                Args::Rules(name, max_actions, cooldowns)
+            }
+            "require" => {
+                let mut args = flags.operands()?;
+                let name = args.session()?;
+                let kind = parse_action_kind(args.next("an <action>")?)?;
+                let prerequisites = args
+                    .rest()
+                    .map(|arg| parse_prerequisite(&arg))
+                    .collect::<Result<_>>()?;
+                Args::Require(name, kind, prerequisites)
+            }
+            "cost" => {
+                let mut args = flags.operands()?;
+                let name = args.session()?;
+                let kind = parse_action_kind(args.next("an <action>")?)?;
+                Args::Cost(name, kind, parse_resources(args.rest())?)
+            }
+            "xp" => {
+                let mut args = flags.operands()?;
+                let name = args.session()?;
+                let kind = parse_action_kind(args.next("an <action>")?)?;
+                let xp = args.number("the xp <n>")?;
+                args.finish()?;
+                Args::Xp(name, kind, xp)
+            }
+            "leveling" => {
+                let mut args = flags.operands()?;
+                let name = args.session()?;
+                let xp = args.number("the <xp> per level")?;
+                Args::Leveling(name, xp, parse_stats(args.rest())?)
+            }
+            "map" => {
+                let mut args = flags.operands()?;
+                let name = args.session()?;
+                let width = args.number("a <width>")?;
+                let height = args.number("a <height>")?;
+                args.finish()?;
+                Args::Map(name, width, height)
+            }
+            "afflict" => {
+                let mut args = flags.operands()?;
+                let name = args.session()?;
+                let entity = args.next("an <entity>")?;
+                let kind = EffectKind::parse(&args.next("an effect")?)?;
+                let turns = args.number("the <turns> it lasts")?;
+                args.finish()?;
+                Args::Afflict(name, entity, Effect { kind, turns })
+            }
+            "grant" => {
+                let mut args = flags.operands()?;
+                let name = args.session()?;
+                let entity = args.next("an <entity>")?;
+                Args::Grant(name, entity, parse_resources(args.rest())?)
             }
             "action" => {
-                let action_arg = args.next().ok_or(Error::InvalidArgs)?;
-                let target_arg = args.next().ok_or(Error::InvalidArgs)?;
-                let action_arg = parse_action_kind(action_arg)?;
-                eprintln!("Action arg is {action_arg:?} where target_arg is {target_arg}");
-                Ok(Args::Action(action_arg, target_arg))
+                let dry_run = flags.flag(&["--dry-run"]);
+                let force = flags.flag(&["--force"]);
+                let mut args = flags.operands()?;
+                let session = args.session.take();
+                let (mut rest, mut params) = split_params(args.rest());
+                if rest.last().is_some_and(|arg| arg == "-") && rest.len() + params.len() <= 2 {
+                    let name = session.or_else(|| rest.into_iter().next().filter(|arg| arg != "-"));
+                    return Ok(Args::Actions(name, force, dry_run));
+                }
+                if force {
+                    params.insert("force".to_string(), Param::Int(1));
+                }
+                if rest.len() == 2 || session.is_some() {
+                    rest.insert(0, session.unwrap_or_default());
+                }
+                let mut args = Operands {
+                    command,
+                    session: None,
+                    args: rest.into(),
+                };
+                let name = Some(args.next("a session <name>")?).filter(|name| !name.is_empty());
+                let action_arg = args.next("an <action>")?;
+                let target_arg = args.next("a <target>")?;
+                args.finish()?;
+                let action_arg = parse_kind_or_script(action_arg, &mut params)?;
+                crate::trace!("parsed action {action_arg:?} on {target_arg:?}");
+                Args::Action(name, action_arg, target_arg, params, dry_run)
             }
-            "--help" | "-h" => Ok(Args::Help),
-            _ => Ok(Args::Help),
-        }
+            "macros" => {
+                flags.operands()?.finish()?;
+                Args::Macros
+            }
+            "spawn" => {
+                let mut args = flags.operands()?;
+                let name = args.session()?;
+                let archetype = args.next("an <archetype>")?;
+                let entity = args.optional();
+                args.finish()?;
+                Args::Spawn(name, archetype, entity)
+            }
+            "archetype" => {
+                let mut args = flags.operands()?;
+                let verb = args.next("save or list")?;
+                let command = match verb.as_str() {
+                    "list" => Args::Archetypes,
+                    "save" => {
+                        let name = args.session()?;
+                        let entity = args.next("an <entity>")?;
+                        Args::SaveArchetype(name, entity, args.optional())
+                    }
+                    _ => return Err(Error::UnexpectedArg(command.to_string(), verb)),
+                };
+                args.finish()?;
+                command
+            }
+            "script" => {
+                let mut args = flags.operands()?;
+                let name = args.session()?;
+                let kind = args.next("a <kind>")?;
+                let path = args.next("a script <file>")?;
+                args.finish()?;
+                Args::Script(name, kind, path.into())
+            }
+            _ => return Err(Error::UnknownCommand(command.to_string())),
+        };
+        Ok(args)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_actions, Args};
+    use crate::actions::Param;
+    use crate::error::Error;
+    use crate::macros::Macros;
+
+    fn parse(line: &str) -> crate::error::Result<Args> {
+        let args = line.split_whitespace().map(String::from);
+        Args::parse_command(args.collect::<Vec<_>>().into_iter(), &Macros::default())
+    }
+
+    #[test]
+    fn subcommands_take_their_own_flags() {
+        assert!(
+            matches!(parse("load game --at 3"), Ok(Args::Load(Some(name), Some(3))) if name == "game")
+        );
+        assert!(matches!(
+            parse("load --at=3"),
+            Ok(Args::Load(None, Some(3)))
+        ));
+        assert!(matches!(parse("stats --session game"), Ok(Args::Stats(name)) if name == "game"));
+        assert!(
+            matches!(parse("action --session game attack orc --dry-run"), Ok(Args::Action(Some(name), _, target, _, true)) if name == "game" && target == "orc")
+        );
+        assert!(matches!(
+            parse("entity place game orc -3 4"),
+            Ok(Args::Entity(_))
+        ));
+        assert!(matches!(
+            parse("action game - --dry-run"),
+            Ok(Args::Actions(Some(name), false, true)) if name == "game"
+        ));
+        assert!(matches!(
+            parse("action -"),
+            Ok(Args::Actions(None, false, false))
+        ));
+        assert!(
+            matches!(parse("serve"), Ok(Args::Serve(bind, None, None)) if bind == super::DEFAULT_BIND)
+        );
+        assert!(matches!(
+            parse("serve --bind 0.0.0.0:7777 --ws 0.0.0.0:7778"),
+            Ok(Args::Serve(bind, Some(ws), None)) if bind == "0.0.0.0:7777" && ws == "0.0.0.0:7778"
+        ));
+        assert!(matches!(
+            parse("serve --cert cert.pem --key key.pem"),
+            Ok(Args::Serve(_, None, Some(_)))
+        ));
+        assert!(parse("serve --cert cert.pem").is_err());
+        assert!(matches!(
+            parse("serve --gen-token alice"),
+            Ok(Args::GenToken(player, false)) if player == "alice"
+        ));
+        assert!(parse("serve --gen-token alice --bind 0.0.0.0:7777").is_err());
+        let actions = parse_actions(
+            "attack goblin damage=2\n\nrest hero, defend hero",
+            &Macros::default(),
+        )
+        .unwrap();
+        assert_eq!(actions.len(), 3);
+        assert_eq!(actions[0].2["damage"], Param::Int(2));
+        assert!(
+            matches!(parse("show --entity orc"), Ok(Args::Show(None, Some(entity))) if entity == "orc")
+        );
+        assert!(
+            matches!(parse("delete game --help"), Ok(Args::Help(Some(command))) if command == "delete")
+        );
+
+        let err = |line| parse(line).unwrap_err().to_string();
+        assert_eq!(
+            err("load game --template x"),
+            "load does not take --template; see relay_code load --help"
+        );
+        assert_eq!(
+            err("rename game"),
+            "rename needs a new <name>; see relay_code rename --help"
+        );
+        assert_eq!(
+            err("undo game again"),
+            "undo does not take \"again\"; see relay_code undo --help"
+        );
+        assert!(matches!(parse("frobnicate"), Err(Error::UnknownCommand(_))));
+        assert_eq!(
+            parse("shwo game").unwrap_err().to_string(),
+            "no command \"shwo\"; see relay_code --help; did you mean \"show\"?"
+        );
     }
 }
@@ -0,0 +1,73 @@
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use crate::error::Result;
+
+/// Replaces the file at `path` with `bytes` such that a crash at any point
+/// leaves either the old contents or the new ones, never a mix.
+///
+/// The data goes to a temporary file in the same directory (so the rename
+/// cannot cross filesystems), is flushed to disk, and is then renamed over
+/// the target.
+pub fn write(path: &Path, bytes: &[u8]) -> Result<()> {
+    let tmp = temp_path(path);
+    let result = write_synced(&tmp, bytes).and_then(|()| Ok(fs::rename(&tmp, path)?));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+        return result;
+    }
+    sync_parent(path)
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{file_name}.{}.tmp", process::id()))
+}
+
+fn write_synced(path: &Path, bytes: &[u8]) -> Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    Ok(())
+}
+
+/// Makes the rename itself durable. Directories cannot be opened for syncing
+/// on Windows, where the rename is already durable once it returns.
+#[cfg(unix)]
+fn sync_parent(path: &Path) -> Result<()> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    File::open(parent)?.sync_all()?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn sync_parent(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::temp_dir::TempDir;
+
+    #[test]
+    fn replaces_contents_without_leaving_temp_files() {
+        let dir = TempDir::new("atomic");
+        let path = dir.join("game.session");
+
+        super::write(&path, b"old").unwrap();
+        super::write(&path, b"new").unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    }
+}
@@ -0,0 +1,162 @@
+use std::fmt::Display;
+
+use crate::error::{Error, Result};
+use crate::impl_enum_field;
+use crate::serde::{FieldReader, FieldWriter, TaggedEnum, HEADER_LEN};
+
+/// The value of a custom attribute a GM attached to an entity. Floats are
+/// written as their bits, and equal when their bits are, so that a value
+/// always reads back as itself.
+#[derive(Debug, Clone)]
+pub enum AttributeValue {
+    Str(String),
+    Int(i64),
+    Bool(bool),
+    Float(f64),
+}
+
+impl AttributeValue {
+    /// `true` and `false` are bools, whole numbers ints and other finite
+    /// numbers floats. Anything else is a string.
+    pub fn parse(value: &str) -> Self {
+        if let Ok(b) = value.parse() {
+            return Self::Bool(b);
+        }
+        if let Ok(n) = value.parse() {
+            return Self::Int(n);
+        }
+        match value.parse::<f64>() {
+            Ok(x) if x.is_finite() => Self::Float(x),
+            _ => Self::Str(value.to_string()),
+        }
+    }
+
+    /// Encoded size of the value as a field, header included.
+    pub(crate) fn field_len(&self) -> usize {
+        let payload = match self {
+            Self::Str(s) => HEADER_LEN + s.len(),
+            Self::Int(_) | Self::Float(_) => HEADER_LEN + 8,
+            Self::Bool(_) => HEADER_LEN + 1,
+        };
+        HEADER_LEN + 1 + payload
+    }
+}
+
+impl PartialEq for AttributeValue {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Str(a), Self::Str(b)) => a == b,
+            (Self::Int(a), Self::Int(b)) => a == b,
+            (Self::Bool(a), Self::Bool(b)) => a == b,
+            (Self::Float(a), Self::Float(b)) => a.to_bits() == b.to_bits(),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for AttributeValue {}
+
+impl Display for AttributeValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Str(s) => write!(f, "{s}"),
+            Self::Int(n) => write!(f, "{n}"),
+            Self::Bool(b) => write!(f, "{b}"),
+            Self::Float(x) => write!(f, "{x:?}"),
+        }
+    }
+}
+
+impl TaggedEnum for AttributeValue {
+    fn discriminant(&self) -> u8 {
+        match self {
+            Self::Str(_) => 0,
+            Self::Int(_) => 1,
+            Self::Bool(_) => 2,
+            Self::Float(_) => 3,
+        }
+    }
+
+    fn serialize_variant(&self, writer: &mut FieldWriter<'_>) {
+        match self {
+            Self::Str(s) => writer.write_str(s),
+            Self::Int(n) => writer.write_i64(*n),
+            Self::Bool(b) => writer.write_bool(*b),
+            Self::Float(x) => writer.write_u64(x.to_bits()),
+        }
+    }
+
+    fn deserialize_variant(discriminant: u8, reader: &mut FieldReader<'_>) -> Result<Self> {
+        let value = match discriminant {
+            0 => Self::Str(reader.read_field()?),
+            1 => Self::Int(reader.read_field()?),
+            2 => Self::Bool(reader.read_field()?),
+            3 => Self::Float(f64::from_bits(reader.read_field()?)),
+            _ => return Err(Error::InvalidVariant(discriminant)),
+        };
+        reader.finish()?;
+        Ok(value)
+    }
+}
+
+impl_enum_field!(AttributeValue);
+
+/// Checks the name of an attribute, which is given on the command line as
+/// `name=value`.
+pub fn validate_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-' || c == '.');
+    match valid {
+        true => Ok(()),
+        false => Err(Error::InvalidAttribute(name.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AttributeValue;
+    use crate::error::Error;
+    use crate::serde::{Deserialize, FieldReader, Serialize};
+    use crate::session::Session;
+    use crate::Entity;
+
+    #[test]
+    fn attributes_are_typed_and_logged() {
+        let mut session = Session::new("campaign".to_string()).unwrap();
+        session.add_entity(Entity::new("hero".to_string())).unwrap();
+        for (key, value) in [
+            ("title", "Baron"),
+            ("age", "41"),
+            ("cursed", "true"),
+            ("luck", "0.1"),
+            ("oddity", "nan"),
+        ] {
+            let value = AttributeValue::parse(value);
+            session.set_attribute("he", key.to_string(), value).unwrap();
+        }
+        session.unset_attribute("hero", "age".to_string()).unwrap();
+        assert!(matches!(
+            session.unset_attribute("hero", "age".to_string()),
+            Err(Error::AttributeNotFound(..))
+        ));
+        assert!(session
+            .set_attribute("hero", "a b".to_string(), AttributeValue::Int(1))
+            .is_err());
+
+        let bytes = session.serialize();
+        let copy = Session::deserialize(&mut FieldReader::new(&bytes)).unwrap();
+        let attributes = &copy.entity("hero").unwrap().attributes;
+        assert_eq!(attributes, &session.entity("hero").unwrap().attributes);
+        let shown: Vec<_> = attributes
+            .iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect();
+        assert_eq!(
+            shown,
+            ["cursed=true", "luck=0.1", "oddity=nan", "title=Baron"]
+        );
+        assert_eq!(attributes["luck"], AttributeValue::Float(0.1));
+    }
+}
@@ -0,0 +1,433 @@
+use std::cell::{Cell, RefCell};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use rand_core::{OsRng, RngCore};
+
+use crate::actions::Action;
+use crate::error::{Error, Result};
+use crate::lobby::OpenGame;
+use crate::mailbox::Letter;
+use crate::outcome::ActionOutcome;
+#[cfg(feature = "websocket")]
+use crate::protocol::WebSocketTransport;
+use crate::protocol::{Hello, Message, TcpTransport, Transport, Versions, PROTOCOL_VERSIONS};
+#[cfg(feature = "tls")]
+use crate::protocol::{TlsClient, TlsTransport};
+use crate::provider::SessionProvider;
+use crate::session::Session;
+use crate::turnfile::{Merged, TurnFile};
+
+/// How long to wait for the server to answer before taking the connection
+/// for dead.
+const ANSWER_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How long a connection may sit idle before it is pinged ahead of the
+/// next request, in case the server has since closed it.
+const KEEPALIVE: Duration = Duration::from_secs(60);
+
+/// A connection to `serve`, for `--remote`. Requests are answered one at a
+/// time, in order. A connection found dead before a request is made again.
+pub struct Client {
+    transport: RefCell<Box<dyn Transport>>,
+    /// The versions the server picked.
+    agreed: Hello,
+    /// Who the server knows this client as, if it presented a token.
+    player: Option<String>,
+    /// Where it connected, and with what, to connect again.
+    address: String,
+    token: Option<String>,
+    ca: Option<PathBuf>,
+    /// When the server last answered.
+    answered: Cell<Instant>,
+    /// What to resume the connection with, once the server issued it.
+    resume: RefCell<Option<String>>,
+    /// Whether the client asked to only watch.
+    watching: Cell<bool>,
+}
+
+impl Client {
+    /// Connects to a server at `address`, such as `example.com:7777`,
+    /// `tls://example.com:7777` over TLS, or `ws://example.com:7778` or
+    /// `wss://example.com:7778` over WebSocket, agrees with it on the
+    /// versions to speak and presents `token`, if given. Over TLS, the
+    /// server must have a certificate from an authority in `ca`, or else
+    /// one the system trusts.
+    pub fn connect(address: &str, token: Option<&str>, ca: Option<&Path>) -> Result<Self> {
+        let (transport, agreed, player) = greet(address, token, ca)?;
+        Ok(Self {
+            transport: RefCell::new(transport),
+            agreed,
+            player,
+            address: address.to_string(),
+            token: token.map(str::to_string),
+            ca: ca.map(Path::to_path_buf),
+            answered: Cell::new(Instant::now()),
+            resume: RefCell::default(),
+            watching: Cell::new(false),
+        })
+    }
+
+    /// The player the server knows this client as.
+    pub fn player(&self) -> Option<&str> {
+        self.player.as_deref()
+    }
+
+    /// The protocol version the server and this client agreed on.
+    pub fn protocol(&self) -> u16 {
+        self.agreed.protocol.max
+    }
+
+    /// Sends `request` and waits for the answer. An error the server
+    /// answers with becomes `Error::Remote`.
+    pub fn request(&self, request: &Message) -> Result<Message> {
+        if self.answered.get().elapsed() >= KEEPALIVE {
+            self.revive()?;
+        }
+        let response = exchange(&mut **self.transport.borrow_mut(), request);
+        if let Ok(_) | Err(Error::Remote(..)) = response {
+            self.answered.set(Instant::now());
+        }
+        response
+    }
+
+    /// Pings the server, returning how long it took to answer.
+    pub fn ping(&self) -> Result<Duration> {
+        self.require(5)?;
+        let nonce = OsRng.next_u64();
+        let sent = Instant::now();
+        match exchange(&mut **self.transport.borrow_mut(), &Message::Ping(nonce))? {
+            Message::Pong(echoed) if echoed == nonce => {
+                self.answered.set(Instant::now());
+                Ok(sent.elapsed())
+            }
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+
+    /// Connects again if the server no longer answers pings, as when it
+    /// closed the connection for being idle. Servers too old to answer
+    /// them are left to fail the next request if they have.
+    fn revive(&self) -> Result<()> {
+        if self.protocol() < 5 || self.ping().is_ok() {
+            return Ok(());
+        }
+        crate::verbose!("lost the connection to {}; reconnecting", self.address);
+        let (mut transport, ..) = greet(&self.address, self.token.as_deref(), self.ca.as_deref())?;
+        // Carry on the old connection's record of what it was sent, if the
+        // server still has it.
+        let token = self.resume.borrow().clone();
+        if let Some(token) = token {
+            if exchange(&mut *transport, &Message::Resume(token)).is_err() {
+                *self.resume.borrow_mut() = None;
+            }
+        }
+        if self.watching.get() {
+            exchange(&mut *transport, &Message::Spectate)?;
+        }
+        *self.transport.borrow_mut() = transport;
+        self.answered.set(Instant::now());
+        Ok(())
+    }
+
+    /// Fails unless the server speaks at least protocol `version`.
+    fn require(&self, version: u16) -> Result<()> {
+        if self.protocol() >= version {
+            return Ok(());
+        }
+        let needed = Versions {
+            min: version,
+            max: PROTOCOL_VERSIONS.max,
+        };
+        Err(Error::IncompatibleVersions(
+            "protocol",
+            self.agreed.protocol,
+            needed,
+        ))
+    }
+
+    /// Makes the connection read-only, so that the server refuses to let
+    /// it change anything, as it does for a spectator's token.
+    pub fn spectate(&self) -> Result<()> {
+        self.require(7)?;
+        match self.request(&Message::Spectate)? {
+            Message::Done(_) => {
+                self.watching.set(true);
+                Ok(())
+            }
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+
+    /// A token to carry on from this connection with, by `resume` on a new
+    /// one if it is lost, fetching only the events missed since.
+    pub fn resume_token(&self) -> Result<String> {
+        if let Some(token) = &*self.resume.borrow() {
+            return Ok(token.clone());
+        }
+        self.require(6)?;
+        match self.request(&Message::Resume(String::new()))? {
+            Message::Resumed(token, _) => {
+                *self.resume.borrow_mut() = Some(token.clone());
+                Ok(token)
+            }
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+
+    /// Carries on from the connection `token` was issued on, by the same
+    /// player, returning the events it missed of each session it was sent.
+    /// Letters are kept until acknowledged, so `mail` has those it missed.
+    pub fn resume(&self, token: &str) -> Result<Vec<TurnFile>> {
+        self.require(6)?;
+        let seen = match self.request(&Message::Resume(token.to_string()))? {
+            Message::Resumed(token, seen) => {
+                *self.resume.borrow_mut() = Some(token);
+                seen
+            }
+            _ => return Err(Error::UnexpectedResponse),
+        };
+        let mut missed = vec![];
+        for (name, events) in seen {
+            match self.events(&name, events as usize) {
+                Ok(turn) if turn.events.is_empty() => {}
+                Ok(turn) => missed.push(turn),
+                // Deleted since.
+                Err(Error::Remote(_, 2)) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(missed)
+    }
+
+    /// The oldest of this player's letters the server has not been told
+    /// were read, which servers too old to keep mail have none of.
+    pub fn mail(&self) -> Result<Vec<Letter>> {
+        if self.protocol() < 3 {
+            return Ok(vec![]);
+        }
+        match self.request(&Message::FetchMail)? {
+            Message::Mail(letters) => Ok(letters),
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+
+    /// Tells the server this player has read every letter up to and
+    /// including the one with id `last`.
+    pub fn acknowledge(&self, last: u64) -> Result<()> {
+        match self.request(&Message::Acknowledge(last))? {
+            Message::Done(_) => Ok(()),
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+
+    /// Sends `text` to the other players of session `name`, returning what
+    /// the server said of it.
+    pub fn chat(&self, name: &str, text: &str) -> Result<String> {
+        self.require(3)?;
+        match self.request(&Message::Chat(name.to_string(), text.to_string()))? {
+            Message::Done(said) => Ok(said),
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+
+    /// Opens game `name` in the server's lobby, played by `rule_set`, for
+    /// `slots` players including this one, returning what the server said
+    /// of it.
+    pub fn host(&self, name: &str, rule_set: &str, slots: u8) -> Result<String> {
+        self.require(9)?;
+        let host = Message::Host(name.to_string(), rule_set.to_string(), slots);
+        match self.request(&host)? {
+            Message::Done(said) => Ok(said),
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+
+    /// The games in the server's lobby waiting for players.
+    pub fn games(&self) -> Result<Vec<OpenGame>> {
+        self.require(9)?;
+        match self.request(&Message::ListGames)? {
+            Message::Games(games) => Ok(games),
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+
+    /// Takes a slot in game `name` in the server's lobby, returning what
+    /// the server said of it, which is whether it has started.
+    pub fn join(&self, name: &str) -> Result<String> {
+        self.require(9)?;
+        match self.request(&Message::Join(name.to_string()))? {
+            Message::Done(said) => Ok(said),
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+}
+
+/// A new connection to `address`, the versions agreed on it and the
+/// player `token` is issued to; see `Client::connect`.
+fn greet(
+    address: &str,
+    token: Option<&str>,
+    ca: Option<&Path>,
+) -> Result<(Box<dyn Transport>, Hello, Option<String>)> {
+    let mut transport = open(address, ca)?;
+    let hello = Hello::current();
+    let server = match exchange(&mut *transport, &Message::Hello(hello.clone()))? {
+        Message::Hello(server) => server,
+        _ => return Err(Error::UnexpectedResponse),
+    };
+    // The server picked from what it was offered, unless it misbehaves.
+    let agreed = server.answer(&hello)?;
+    crate::verbose!(
+        "connected to {} at {address}, speaking protocol {}",
+        server.software,
+        agreed.protocol
+    );
+    let player = match token {
+        Some(token) => {
+            match exchange(&mut *transport, &Message::Authenticate(token.to_string()))? {
+                Message::Authenticated(player) => Some(player),
+                _ => return Err(Error::UnexpectedResponse),
+            }
+        }
+        None => None,
+    };
+    if let Some(player) = &player {
+        crate::verbose!("acting as {player}");
+    }
+    Ok((transport, agreed, player))
+}
+
+#[cfg_attr(not(feature = "tls"), allow(unused_variables))]
+fn open(address: &str, ca: Option<&Path>) -> Result<Box<dyn Transport>> {
+    let (scheme, rest) = address.split_once("://").unwrap_or(("", address));
+    let authority = rest.split('/').next().unwrap_or(rest);
+    match scheme {
+        "ws" | "wss" if !cfg!(feature = "websocket") => Err(Error::WebSocketUnsupported),
+        "tls" | "wss" if !cfg!(feature = "tls") => Err(Error::TlsUnsupported),
+        #[cfg(feature = "tls")]
+        "tls" => Ok(Box::new(TlsTransport::new(secure(authority, None, ca)?))),
+        #[cfg(feature = "websocket")]
+        "ws" => {
+            let (_, stream) = dial(authority, Some(80))?;
+            Ok(Box::new(WebSocketTransport::handshake(address, stream)?))
+        }
+        #[cfg(all(feature = "tls", feature = "websocket"))]
+        "wss" => {
+            let stream = secure(authority, Some(443), ca)?;
+            Ok(Box::new(WebSocketTransport::handshake(address, stream)?))
+        }
+        _ => Ok(Box::new(TcpTransport::new(dial(authority, None)?.1)?)),
+    }
+}
+
+/// A connection to `authority`, such as `example.com:7777`, on `port` if
+/// it names none, and the host it names. Reads give up on a server that
+/// takes longer than `ANSWER_TIMEOUT` to answer.
+fn dial(authority: &str, port: Option<u16>) -> Result<(&str, TcpStream)> {
+    fn unbracketed(host: &str) -> &str {
+        host.trim_start_matches('[').trim_end_matches(']')
+    }
+    // An IPv6 address without a port ends in a bracket.
+    let (host, stream) = match authority.rsplit_once(':') {
+        Some((host, _)) if !authority.ends_with(']') => {
+            (unbracketed(host), TcpStream::connect(authority)?)
+        }
+        _ => {
+            let host = unbracketed(authority);
+            let port = port.ok_or(Error::InvalidArgs)?;
+            (host, TcpStream::connect((host, port))?)
+        }
+    };
+    stream.set_read_timeout(Some(ANSWER_TIMEOUT))?;
+    Ok((host, stream))
+}
+
+/// An encrypted connection to `authority`, as `dial` makes.
+#[cfg(feature = "tls")]
+fn secure(
+    authority: &str,
+    port: Option<u16>,
+    ca: Option<&Path>,
+) -> Result<rustls::StreamOwned<rustls::ClientConnection, TcpStream>> {
+    let (host, stream) = dial(authority, port)?;
+    TlsClient::new(ca)?.connect(host, stream)
+}
+
+fn exchange(transport: &mut dyn Transport, request: &Message) -> Result<Message> {
+    transport.send(request)?;
+    match transport.receive()?.ok_or(Error::UnexpectedEof)? {
+        Message::Error(message, code) => Err(Error::Remote(message, code)),
+        response => Ok(response),
+    }
+}
+
+impl SessionProvider for Client {
+    fn create(&self, name: &str) -> Result<()> {
+        match self.request(&Message::CreateSession(name.to_string()))? {
+            Message::Done(_) => Ok(()),
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+
+    fn load(&self, name: &str) -> Result<Session> {
+        match self.request(&Message::FetchState(name.to_string()))? {
+            Message::StateResponse(session) => Ok(*session),
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+
+    fn apply(&self, name: &str, action: Action, dry_run: bool) -> Result<ActionOutcome> {
+        match self.request(&Message::SubmitAction(name.to_string(), action, dry_run))? {
+            Message::Outcome(outcome) => Ok(outcome),
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+
+    fn list(&self) -> Result<Vec<String>> {
+        match self.request(&Message::ListSessions)? {
+            Message::Sessions(names) => Ok(names),
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+
+    fn events(&self, name: &str, from: usize) -> Result<TurnFile> {
+        self.require(4)?;
+        let from = u32::try_from(from).unwrap_or(u32::MAX);
+        match self.request(&Message::FetchEvents(name.to_string(), from))? {
+            Message::Events(turn) => Ok(*turn),
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+
+    fn receive(&self, turn: &TurnFile) -> Result<Merged> {
+        self.require(4)?;
+        // Sent whole, so the turn is cloned rather than borrowed.
+        let push = Message::PushEvents(Box::new(turn.clone()));
+        match self.request(&push)? {
+            Message::Merged(merged) => Ok(merged),
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+
+    /// Clients build sessions up by request; only a server's own lobby
+    /// saves them whole.
+    fn save_new(&self, _session: Session) -> Result<()> {
+        Err(Error::RemoteUnsupported(
+            "saving a session whole".to_string(),
+        ))
+    }
+
+    fn say(&self, name: &str, text: &str) -> Result<()> {
+        self.chat(name, text).map(drop)
+    }
+
+    fn pass(&self, name: &str) -> Result<String> {
+        self.require(8)?;
+        match self.request(&Message::Pass(name.to_string()))? {
+            Message::Done(next) => Ok(next),
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+}
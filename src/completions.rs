@@ -0,0 +1,231 @@
+use std::fmt::Display;
+
+use crate::actions::ActionKind;
+use crate::error::{Error, Result};
+use crate::usage::{Usage, COMMANDS};
+
+/// A shell `completions` writes a script for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    PowerShell,
+}
+
+impl Shell {
+    pub const ALL: [Shell; 4] = [Self::Bash, Self::Zsh, Self::Fish, Self::PowerShell];
+
+    pub fn parse(name: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|shell| shell.to_string().eq_ignore_ascii_case(name))
+            .ok_or(Error::InvalidArgs)
+    }
+}
+
+impl Display for Shell {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Bash => write!(f, "bash"),
+            Self::Zsh => write!(f, "zsh"),
+            Self::Fish => write!(f, "fish"),
+            Self::PowerShell => write!(f, "powershell"),
+        }
+    }
+}
+
+/// What the completion scripts ask `relay_code complete` for while the
+/// user types, so that they offer what there is at the time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Candidates {
+    Commands,
+    /// The sessions saved in the data directory.
+    Sessions,
+    Actions,
+}
+
+impl Candidates {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name {
+            "commands" => Ok(Self::Commands),
+            "sessions" => Ok(Self::Sessions),
+            "actions" => Ok(Self::Actions),
+            _ => Err(Error::InvalidArgs),
+        }
+    }
+}
+
+/// Every subcommand, or every built-in action kind, by name. Sessions are
+/// listed from the store instead.
+pub fn candidates(candidates: Candidates) -> Vec<String> {
+    match candidates {
+        Candidates::Commands => COMMANDS
+            .iter()
+            .map(|usage| usage.command.to_string())
+            .collect(),
+        Candidates::Actions => ActionKind::ALL.iter().map(ToString::to_string).collect(),
+        Candidates::Sessions => vec![],
+    }
+}
+
+/// Whether the first operand of `usage` is a session name.
+fn takes_session(usage: &Usage) -> bool {
+    usage.synopsis.iter().any(|synopsis| {
+        let operand = synopsis.split_whitespace().nth(1);
+        matches!(operand, Some("<name>" | "[<name>]" | "<old>" | "<src>"))
+    })
+}
+
+/// The subcommands whose first operand is a session name, and those that
+/// take an action kind after one, separated by spaces.
+fn command_lists() -> (String, String, String) {
+    let names = |usages: &mut dyn Iterator<Item = &Usage>| {
+        usages
+            .map(|usage| usage.command)
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    (
+        names(&mut COMMANDS.iter()),
+        names(&mut COMMANDS.iter().filter(|usage| takes_session(usage))),
+        "action queue".to_string(),
+    )
+}
+
+/// A script that completes `relay_code` commands in `shell`, to be
+/// sourced from the shell's startup file.
+pub fn script(shell: Shell) -> String {
+    let (commands, sessions, actions) = command_lists();
+    let script = match shell {
+        Shell::Bash => BASH,
+        Shell::Zsh => ZSH,
+        Shell::Fish => FISH,
+        Shell::PowerShell => POWERSHELL,
+    };
+    let shells: Vec<_> = Shell::ALL.iter().map(ToString::to_string).collect();
+    script
+        .replace("@COMMANDS@", &commands)
+        .replace("@SESSION_COMMANDS@", &sessions)
+        .replace("@ACTION_COMMANDS@", &actions)
+        .replace("@SHELLS@", &shells.join(" "))
+        .replace("@SESSION_PATTERN@", &sessions.replace(' ', "|"))
+        .replace("@ACTION_PATTERN@", &actions.replace(' ', "|"))
+        .replace("@SESSION_ARRAY@", &quoted(&sessions))
+        .replace("@ACTION_ARRAY@", &quoted(&actions))
+        .replace("@COMMAND_ARRAY@", &quoted(&commands))
+}
+
+/// `a b` as `'a', 'b'`, for PowerShell.
+fn quoted(words: &str) -> String {
+    let words: Vec<_> = words.split(' ').map(|word| format!("'{word}'")).collect();
+    words.join(", ")
+}
+
+const BASH: &str = r#"# relay_code completions for bash; source from ~/.bashrc.
+_relay_code() {
+    local cur=${COMP_WORDS[COMP_CWORD]} command=${COMP_WORDS[1]} words=
+    if [ "$COMP_CWORD" -eq 1 ]; then
+        words="@COMMANDS@"
+    elif [ "$command" = completions ]; then
+        words="@SHELLS@"
+    elif [ "$COMP_CWORD" -eq 2 ]; then
+        case "$command" in
+            @ACTION_PATTERN@) words="$(relay_code complete sessions 2>/dev/null) $(relay_code complete actions 2>/dev/null)" ;;
+            @SESSION_PATTERN@) words=$(relay_code complete sessions 2>/dev/null) ;;
+            *) return ;;
+        esac
+    elif [ "$COMP_CWORD" -eq 3 ]; then
+        case "$command" in
+            @ACTION_PATTERN@) words=$(relay_code complete actions 2>/dev/null) ;;
+            *) return ;;
+        esac
+    else
+        return
+    fi
+    COMPREPLY=($(compgen -W "$words" -- "$cur"))
+}
+complete -o default -F _relay_code relay_code
+"#;
+
+const ZSH: &str = r#"#compdef relay_code
+# relay_code completions for zsh; put on $fpath as _relay_code.
+_relay_code() {
+    if (( CURRENT == 2 )); then
+        compadd -- @COMMANDS@
+        return
+    fi
+    case $words[2] in
+        completions) (( CURRENT == 3 )) && compadd -- @SHELLS@ ;;
+        @ACTION_PATTERN@)
+            (( CURRENT == 3 )) && compadd -- ${(f)"$(relay_code complete sessions 2>/dev/null)"}
+            (( CURRENT <= 4 )) && compadd -- ${(f)"$(relay_code complete actions 2>/dev/null)"}
+            ;;
+        @SESSION_PATTERN@)
+            (( CURRENT == 3 )) && compadd -- ${(f)"$(relay_code complete sessions 2>/dev/null)"}
+            ;;
+        *) _files ;;
+    esac
+}
+compdef _relay_code relay_code
+"#;
+
+const FISH: &str = r#"# relay_code completions for fish; save as
+# ~/.config/fish/completions/relay_code.fish.
+complete -c relay_code -n __fish_use_subcommand -f -a '@COMMANDS@'
+complete -c relay_code -n '__fish_seen_subcommand_from completions' -f -a '@SHELLS@'
+complete -c relay_code -n '__fish_seen_subcommand_from @SESSION_COMMANDS@; and test (count (commandline -opc)) -eq 2' -f -a '(relay_code complete sessions 2>/dev/null)'
+complete -c relay_code -n '__fish_seen_subcommand_from @ACTION_COMMANDS@; and test (count (commandline -opc)) -le 3' -f -a '(relay_code complete actions 2>/dev/null)'
+"#;
+
+const POWERSHELL: &str = r#"# relay_code completions for PowerShell; dot-source from $PROFILE.
+Register-ArgumentCompleter -Native -CommandName relay_code -ScriptBlock {
+    param($wordToComplete, $commandAst, $cursorPosition)
+    $words = @($commandAst.CommandElements | ForEach-Object { $_.ToString() })
+    $position = $words.Count
+    if ($wordToComplete -ne '') { $position -= 1 }
+    $command = if ($words.Count -gt 1) { $words[1] } else { '' }
+    $candidates = @()
+    if ($position -eq 1) {
+        $candidates = @(@COMMAND_ARRAY@)
+    } elseif ($command -eq 'completions') {
+        $candidates = '@SHELLS@' -split ' '
+    } elseif ($position -eq 2 -and @(@SESSION_ARRAY@) -contains $command) {
+        $candidates = @(relay_code complete sessions 2>$null)
+    }
+    if ($position -in 2, 3 -and @(@ACTION_ARRAY@) -contains $command) {
+        $candidates += @(relay_code complete actions 2>$null)
+    }
+    $candidates | Where-Object { $_ -like "$wordToComplete*" } | ForEach-Object {
+        [System.Management.Automation.CompletionResult]::new($_, $_, 'ParameterValue', $_)
+    }
+}
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::{script, Shell};
+
+    #[test]
+    fn scripts_complete_sessions_for_commands_taking_them() {
+        let bash = script(Shell::Bash);
+        assert!(bash.contains("words=\"new show load"));
+        let sessions = bash
+            .lines()
+            .find(|line| line.contains(") words=$(relay_code complete sessions"))
+            .unwrap();
+        assert!(sessions.contains("|show|"));
+        assert!(sessions.contains("|rename|"));
+        assert!(!sessions.contains("entity"));
+        assert!(!sessions.contains("import"));
+        for shell in Shell::ALL {
+            let script = script(shell);
+            let placeholder = script
+                .split('@')
+                .skip(1)
+                .any(|rest| rest.starts_with(|c: char| c.is_ascii_uppercase()));
+            assert!(!placeholder, "{shell} left a placeholder");
+            assert_eq!(Shell::parse(&shell.to_string()).unwrap(), shell);
+        }
+    }
+}
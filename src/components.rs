@@ -0,0 +1,309 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+
+use crate::error::{Error, Result};
+use crate::serde::{Field, FieldReader, FieldWriter, SerializeField, HEADER_LEN};
+use crate::Entity;
+
+/// Data attached to an entity under its own one-byte tag. Each component is
+/// written as a field of its own, so one this binary does not know is kept
+/// as it was read and written back unchanged.
+///
+/// The components every entity may have are fields of `Entity`, under tags
+/// below 64. Rules and plugins add theirs with tags from 64 up, through
+/// `Entity::component` and `Entity::set_component`, without touching it.
+pub trait Component: SerializeField + for<'a> TryFrom<Field<'a>, Error = Error> {
+    const TAG: u8;
+}
+
+/// Tags of the components that are fields of `Entity`.
+pub(crate) mod tag {
+    pub const STATS: u8 = 0;
+    pub const GUARDING: u8 = 1;
+    pub const RESOURCES: u8 = 2;
+    pub const INVENTORY: u8 = 3;
+    pub const ATTRIBUTES: u8 = 4;
+    pub const POSITION: u8 = 5;
+    pub const AI: u8 = 6;
+    pub const EXPERIENCE: u8 = 7;
+    pub const EFFECTS: u8 = 8;
+    pub const FACTION: u8 = 9;
+    pub const EQUIPMENT: u8 = 10;
+    pub const OWNER: u8 = 11;
+}
+
+/// Where an entity is on the map.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Position {
+    pub(crate) const FIELD_LEN: usize = 3 * HEADER_LEN + 4 + 4;
+
+    /// Squares between the two, a diagonal step counting as one.
+    pub fn distance(self, other: Self) -> u32 {
+        self.x.abs_diff(other.x).max(self.y.abs_diff(other.y))
+    }
+}
+
+impl Display for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "({}, {})", self.x, self.y)
+    }
+}
+
+impl SerializeField for Position {
+    fn serialize_field(&self, writer: &mut FieldWriter<'_>) {
+        writer.write(&(self.x, self.y));
+    }
+}
+
+impl TryFrom<Field<'_>> for Position {
+    type Error = Error;
+
+    fn try_from(value: Field<'_>) -> Result<Self> {
+        let (x, y) = value.try_into()?;
+        Ok(Self { x, y })
+    }
+}
+
+/// How a computer-played entity picks its action each turn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Behavior {
+    /// Attacks one of its enemies.
+    Aggressive,
+    /// Guards, or rests once it is guarding.
+    Defensive,
+    /// Moves a square in a random direction.
+    Wander,
+}
+
+impl Behavior {
+    pub const ALL: [Behavior; 3] = [Self::Aggressive, Self::Defensive, Self::Wander];
+
+    pub fn parse(name: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|behavior| behavior.to_string().eq_ignore_ascii_case(name))
+            .ok_or(Error::InvalidArgs)
+    }
+
+    fn id(&self) -> u8 {
+        match self {
+            Self::Aggressive => 0,
+            Self::Defensive => 1,
+            Self::Wander => 2,
+        }
+    }
+}
+
+impl Display for Behavior {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Aggressive => write!(f, "aggressive"),
+            Self::Defensive => write!(f, "defensive"),
+            Self::Wander => write!(f, "wander"),
+        }
+    }
+}
+
+/// Marks an entity as played by the computer, following `behavior`. Written
+/// as `U8(behavior)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ai {
+    pub behavior: Behavior,
+}
+
+impl SerializeField for Ai {
+    fn serialize_field(&self, writer: &mut FieldWriter<'_>) {
+        writer.write_u8(self.behavior.id());
+    }
+}
+
+impl TryFrom<Field<'_>> for Ai {
+    type Error = Error;
+
+    fn try_from(value: Field<'_>) -> Result<Self> {
+        let id: u8 = value.try_into()?;
+        let behavior = Behavior::ALL
+            .into_iter()
+            .find(|behavior| behavior.id() == id)
+            .ok_or(Error::InvalidVariant(id))?;
+        Ok(Self { behavior })
+    }
+}
+
+/// An encoded component, written as `Bytes`.
+pub(crate) struct Raw<'a>(pub &'a [u8]);
+
+impl SerializeField for Raw<'_> {
+    fn serialize_field(&self, writer: &mut FieldWriter<'_>) {
+        writer.write_bytes(self.0);
+    }
+}
+
+/// The field `value` is written as.
+pub(crate) fn encode(value: &(impl SerializeField + ?Sized)) -> Vec<u8> {
+    let mut bytes = vec![];
+    FieldWriter::new(&mut bytes).write(value);
+    bytes
+}
+
+/// Reads a component written by `encode`.
+pub(crate) fn decode<T>(bytes: &[u8]) -> Result<T>
+where
+    T: for<'a> TryFrom<Field<'a>, Error = Error>,
+{
+    let mut reader = FieldReader::new(bytes);
+    let value = reader.read_field()?;
+    reader.finish()?;
+    Ok(value)
+}
+
+impl Entity {
+    /// The entity's component `T`, if it has one.
+    pub fn component<T: Component>(&self) -> Result<Option<T>> {
+        self.components
+            .get(&T::TAG)
+            .map(|bytes| decode(bytes))
+            .transpose()
+    }
+
+    /// Gives the entity `value`, replacing any component with its tag.
+    pub fn set_component<T: Component>(&mut self, value: &T) {
+        self.components.insert(T::TAG, encode(value));
+    }
+
+    pub fn remove_component<T: Component>(&mut self) -> bool {
+        self.components.remove(&T::TAG).is_some()
+    }
+
+    /// Every component the entity has, encoded and by tag. Those left at
+    /// their defaults are left out.
+    pub(crate) fn encode_components(&self) -> BTreeMap<u8, Vec<u8>> {
+        let mut components = self.components.clone();
+        if self.stats != Default::default() {
+            components.insert(tag::STATS, encode(&self.stats));
+        }
+        if self.experience != Default::default() {
+            components.insert(tag::EXPERIENCE, encode(&self.experience));
+        }
+        if self.guarding {
+            components.insert(tag::GUARDING, encode(&self.guarding));
+        }
+        if !self.effects.is_empty() {
+            components.insert(tag::EFFECTS, encode(&self.effects));
+        }
+        if !self.resources.is_empty() {
+            components.insert(tag::RESOURCES, encode(&self.resources));
+        }
+        if !self.inventory.is_empty() {
+            components.insert(tag::INVENTORY, encode(&self.inventory));
+        }
+        if !self.equipment.is_empty() {
+            components.insert(tag::EQUIPMENT, encode(&self.equipment));
+        }
+        if !self.attributes.is_empty() {
+            components.insert(tag::ATTRIBUTES, encode(&self.attributes));
+        }
+        if let Some(position) = &self.position {
+            components.insert(tag::POSITION, encode(position));
+        }
+        if let Some(ai) = &self.ai {
+            components.insert(tag::AI, encode(ai));
+        }
+        if let Some(faction) = &self.faction {
+            components.insert(tag::FACTION, encode(faction));
+        }
+        if let Some(owner) = &self.owner {
+            components.insert(tag::OWNER, encode(owner));
+        }
+        components
+    }
+
+    /// Moves the components that are fields of `Entity` out of
+    /// `components` into them, keeping the rest as they are.
+    pub(crate) fn decode_components(
+        &mut self,
+        mut components: BTreeMap<u8, Vec<u8>>,
+    ) -> Result<()> {
+        fn take<T>(components: &mut BTreeMap<u8, Vec<u8>>, tag: u8) -> Result<Option<T>>
+        where
+            T: for<'a> TryFrom<Field<'a>, Error = Error>,
+        {
+            components
+                .remove(&tag)
+                .map(|bytes| decode(&bytes))
+                .transpose()
+        }
+
+        let c = &mut components;
+        self.stats = take(c, tag::STATS)?.unwrap_or_default();
+        self.experience = take(c, tag::EXPERIENCE)?.unwrap_or_default();
+        self.guarding = take(c, tag::GUARDING)?.unwrap_or_default();
+        self.effects = take(c, tag::EFFECTS)?.unwrap_or_default();
+        self.resources = take(c, tag::RESOURCES)?.unwrap_or_default();
+        self.inventory = take(c, tag::INVENTORY)?.unwrap_or_default();
+        self.equipment = take(c, tag::EQUIPMENT)?.unwrap_or_default();
+        self.attributes = take(c, tag::ATTRIBUTES)?.unwrap_or_default();
+        self.position = take(c, tag::POSITION)?;
+        self.ai = take(c, tag::AI)?;
+        self.faction = take(c, tag::FACTION)?;
+        self.owner = take(c, tag::OWNER)?;
+        self.components = components;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Component, Position};
+    use crate::error::{Error, Result};
+    use crate::serde::{Deserialize, Field, FieldReader, FieldWriter, Serialize, SerializeField};
+    use crate::Entity;
+
+    /// As a plugin would add.
+    #[derive(Debug, PartialEq)]
+    struct Mood(String);
+
+    impl SerializeField for Mood {
+        fn serialize_field(&self, writer: &mut FieldWriter<'_>) {
+            writer.write_str(&self.0);
+        }
+    }
+
+    impl TryFrom<Field<'_>> for Mood {
+        type Error = Error;
+
+        fn try_from(value: Field<'_>) -> Result<Self> {
+            Ok(Self(value.try_into()?))
+        }
+    }
+
+    impl Component for Mood {
+        const TAG: u8 = 200;
+    }
+
+    #[test]
+    fn unknown_components_round_trip() {
+        let mut entity = Entity::new("troll".to_string());
+        entity.position = Some(Position { x: 1, y: -1 });
+        entity.set_component(&Mood("grumpy".to_string()));
+        let bytes = entity.serialize();
+        assert_eq!(entity.size_hint(), bytes.len());
+
+        // Read back without knowing about moods, as another binary would.
+        let mut copy = Entity::deserialize(&mut FieldReader::new(&bytes)).unwrap();
+        assert_eq!(copy.serialize(), bytes);
+        assert_eq!(copy.position, entity.position);
+        assert_eq!(
+            copy.component::<Mood>().unwrap(),
+            Some(Mood("grumpy".to_string()))
+        );
+
+        assert!(copy.remove_component::<Mood>());
+        assert_eq!(copy.component::<Mood>().unwrap(), None);
+    }
+}
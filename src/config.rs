@@ -0,0 +1,212 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use crate::error::{Error, Result};
+use crate::server::Limits;
+use crate::webhook::Webhook;
+
+/// Defaults for flags and arguments left out, read from
+/// `~/.config/relay_code/config.toml` or the file given with `--config`.
+/// Flags override the config file, which overrides the built-in defaults.
+/// Only plain `key = value` lines of TOML are understood:
+///
+/// ```text
+/// # Comments and blank lines are ignored.
+/// data_dir = "/srv/relay_code"
+/// session = "campaign"
+/// player = "alice"
+/// color = false
+/// sqlite = false
+/// keyring = true
+/// encrypt = true
+/// webhook = "https://hooks.slack.com/services/..."
+/// rate_limit = 20
+/// player_rate_limit = 40
+/// max_message = 4194304
+/// max_sessions = 100
+/// idle_timeout = 300
+/// ```
+///
+/// `webhook` may be given more than once. The limits are what `serve`
+/// allows each client; see `Limits`. 0 is no limit.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Config {
+    /// Used unless `--data-dir` or `RELAY_CODE_DATA_DIR` is given.
+    pub data_dir: Option<PathBuf>,
+    /// The session commands that may leave it out act on, instead of the
+    /// last one used.
+    pub session: Option<String>,
+    /// Who `keygen` makes a key for when not told.
+    pub player: Option<String>,
+    /// Whether to color output, when neither flags nor the environment say.
+    pub color: Option<bool>,
+    /// Keep sessions in a SQLite database, as `--sqlite` does.
+    pub sqlite: bool,
+    /// Set by `keyring = false`, as `--no-keyring` is.
+    pub no_keyring: bool,
+    /// Encrypt new sessions with a passphrase.
+    pub encrypt: bool,
+    /// Told when a turn begins after `resolve`, and by `serve` of every
+    /// action.
+    pub webhooks: Vec<Webhook>,
+    pub limits: Limits,
+}
+
+impl Config {
+    /// Reads the config file at `path`, or else at the default location.
+    /// The default file may be missing; one given with `--config` may not.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let (path, required) = match path {
+            Some(path) => (path.to_path_buf(), true),
+            None => match crate::paths::config_file() {
+                Some(path) => (path, false),
+                None => return Ok(Self::default()),
+            },
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => Self::parse(&text),
+            Err(err) if err.kind() == ErrorKind::NotFound && !required => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut config = Self::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = |why: &str| Error::InvalidConfig(i + 1, why.to_string());
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| invalid("expected key = value"))?;
+            let value = Value::parse(value.trim()).ok_or_else(|| invalid("invalid value"))?;
+            match (key.trim(), value) {
+                ("data_dir", Value::Str(dir)) => config.data_dir = Some(dir.into()),
+                ("session", Value::Str(name)) => config.session = Some(name),
+                ("player", Value::Str(player)) => config.player = Some(player),
+                ("color", Value::Bool(color)) => config.color = Some(color),
+                ("sqlite", Value::Bool(sqlite)) => config.sqlite = sqlite,
+                ("keyring", Value::Bool(keyring)) => config.no_keyring = !keyring,
+                ("encrypt", Value::Bool(encrypt)) => config.encrypt = encrypt,
+                ("webhook", Value::Str(url)) => config.webhooks.push(
+                    Webhook::parse(&url)
+                        .ok_or_else(|| invalid("expected an http:// or https:// URL"))?,
+                ),
+                ("rate_limit", Value::Number(rate)) => config.limits.rate = rate,
+                ("player_rate_limit", Value::Number(rate)) => config.limits.player_rate = rate,
+                ("max_message", Value::Number(len)) => config.limits.max_message = len,
+                ("max_sessions", Value::Number(max)) => config.limits.max_sessions = max,
+                ("idle_timeout", Value::Number(secs)) => config.limits.idle_timeout = secs,
+                ("data_dir" | "session" | "player" | "webhook", _) => {
+                    return Err(invalid("expected a string"))
+                }
+                ("color" | "sqlite" | "keyring" | "encrypt", _) => {
+                    return Err(invalid("expected true or false"))
+                }
+                (
+                    "rate_limit" | "player_rate_limit" | "max_message" | "max_sessions"
+                    | "idle_timeout",
+                    _,
+                ) => return Err(invalid("expected a number")),
+                (key, _) => return Err(invalid(&format!("unknown key {key:?}"))),
+            }
+        }
+        Ok(config)
+    }
+}
+
+enum Value {
+    Str(String),
+    Bool(bool),
+    Number(u32),
+}
+
+impl Value {
+    /// A basic `"string"` with `\"` and `\\` escapes, a literal `'string'`,
+    /// a boolean or a whole number, optionally followed by a comment.
+    fn parse(text: &str) -> Option<Self> {
+        let (value, rest) = match text.chars().next()? {
+            '"' => {
+                let mut value = String::new();
+                let mut chars = text.char_indices().skip(1);
+                loop {
+                    match chars.next()? {
+                        (i, '"') => break (Self::Str(value), &text[i + 1..]),
+                        (_, '\\') => match chars.next()?.1 {
+                            c @ ('"' | '\\') => value.push(c),
+                            'n' => value.push('\n'),
+                            't' => value.push('\t'),
+                            _ => return None,
+                        },
+                        (_, c) => value.push(c),
+                    }
+                }
+            }
+            '\'' => {
+                let (value, rest) = text[1..].split_once('\'')?;
+                (Self::Str(value.to_string()), rest)
+            }
+            _ => {
+                let end = text.find([' ', '\t', '#']).unwrap_or(text.len());
+                let value = match &text[..end] {
+                    "true" => Self::Bool(true),
+                    "false" => Self::Bool(false),
+                    number => Self::Number(number.parse().ok()?),
+                };
+                (value, &text[end..])
+            }
+        };
+        let rest = rest.trim_start();
+        (rest.is_empty() || rest.starts_with('#')).then_some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Config;
+
+    #[test]
+    fn config_sets_what_it_names() {
+        let config = Config::parse(
+            "# defaults\n\
+             data_dir = \"/srv/relay \\\"code\\\"\"\n\
+             session = 'campaign' # the usual\n\
+             keyring = false\n\
+             encrypt = true\n\
+             webhook = \"https://example.com/hook\"\n\
+             webhook = \"http://127.0.0.1:8080\"\n\
+             rate_limit = 5 # a second\n\
+             max_sessions = 0\n",
+        )
+        .unwrap();
+        assert_eq!(
+            config.data_dir.unwrap().to_str(),
+            Some("/srv/relay \"code\"")
+        );
+        assert_eq!(config.session.as_deref(), Some("campaign"));
+        assert_eq!(config.player, None);
+        assert!(config.no_keyring && config.encrypt && !config.sqlite);
+        assert_eq!(config.webhooks.len(), 2);
+        assert_eq!((config.limits.rate, config.limits.max_sessions), (5, 0));
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+
+        let err = |text| Config::parse(text).unwrap_err().to_string();
+        assert_eq!(
+            err("\nsqlite = \"yes\""),
+            "config line 2: expected true or false"
+        );
+        assert_eq!(
+            err("colour = true"),
+            "config line 1: unknown key \"colour\""
+        );
+        assert_eq!(err("session = \"open"), "config line 1: invalid value");
+        assert_eq!(err("rate_limit = -1"), "config line 1: invalid value");
+        assert_eq!(
+            err("webhook = \"hooks.example.com\""),
+            "config line 1: expected an http:// or https:// URL"
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use std::collections::BTreeSet;
+
+use crate::actions::Action;
+use crate::handlers::handler;
+use crate::outcome::ActionOutcome;
+use crate::rng::Rng;
+use crate::Entity;
+
+/// What `action` claims for itself alone, as its handler decides.
+fn claim(action: &Action) -> Option<String> {
+    handler(action.kind()).and_then(|handler| handler.claim(action))
+}
+
+/// The initiative of the entity acting, which is its speed with what its
+/// equipment adds.
+fn initiative(action: &Action, entities: &[Entity]) -> u8 {
+    entities
+        .iter()
+        .find(|entity| entity.name == action.target())
+        .map_or(0, |entity| entity.effective_stats().speed)
+}
+
+/// The resolution phase of a turn: of the queued actions claiming the same
+/// thing, only the one whose target has the most initiative is kept, with
+/// ties broken by a roll of `rng`. The rest are dropped, and `outcome` says
+/// why. What is kept stays in the order it was queued.
+pub(crate) fn settle(
+    queue: Vec<Action>,
+    entities: &[Entity],
+    rng: &mut Rng,
+    outcome: &mut ActionOutcome,
+) -> Vec<Action> {
+    let claims: Vec<_> = queue.iter().map(claim).collect();
+    let mut lost = vec![None; queue.len()];
+    let mut settled = BTreeSet::new();
+    for thing in claims.iter().flatten() {
+        if !settled.insert(thing) {
+            continue;
+        }
+        let contenders: Vec<_> = (0..queue.len())
+            .filter(|&i| claims[i].as_ref() == Some(thing))
+            .collect();
+        if contenders.len() < 2 {
+            continue;
+        }
+        let best = contenders
+            .iter()
+            .map(|&i| initiative(&queue[i], entities))
+            .max()
+            .unwrap_or(0);
+        let tied: Vec<_> = contenders
+            .iter()
+            .copied()
+            .filter(|&i| initiative(&queue[i], entities) == best)
+            .collect();
+        let (winner, how) = match tied[..] {
+            [winner] => (winner, "on initiative"),
+            _ => {
+                let roll = rng.roll(u8::try_from(tied.len()).unwrap_or(u8::MAX));
+                outcome.rolls.push(roll);
+                (tied[usize::from(roll.value) - 1], "by roll")
+            }
+        };
+        for &i in contenders.iter().filter(|&&i| i != winner) {
+            lost[i] = Some(format!("{thing} went to {} {how}", queue[winner].target()));
+        }
+    }
+    queue
+        .into_iter()
+        .zip(lost)
+        .filter_map(|(action, lost)| match lost {
+            None => Some(action),
+            Some(reason) => {
+                outcome.message(format!(
+                    "skipped {} {}: {reason}",
+                    action.kind(),
+                    action.target()
+                ));
+                None
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::actions::{Action, ActionKind, Param};
+    use crate::serde::{Deserialize, FieldReader, Serialize};
+    use crate::session::Session;
+    use crate::Entity;
+
+    #[test]
+    fn conflicting_actions_resolve_the_same_everywhere() {
+        let mut session = Session::new("game".to_string()).unwrap();
+        for (name, speed) in [("bob", 0), ("carol", 2), ("dave", 0)] {
+            let mut entity = Entity::new(name.to_string());
+            entity.stats.speed = speed;
+            session.add_entity(entity).unwrap();
+        }
+        let rest = |target: &str, bed: &str| {
+            let params = [("claim".to_string(), Param::parse(bed))].into();
+            Action::new(ActionKind::Rest, target.to_string())
+                .unwrap()
+                .with_params(params)
+        };
+        session.queue(rest("bob", "bed")).unwrap();
+        session.queue(rest("carol", "bed")).unwrap();
+        session.queue(rest("bob", "cot")).unwrap();
+        session.queue(rest("dave", "cot")).unwrap();
+
+        let outcome = session.resolve().unwrap();
+        assert_eq!(
+            outcome.messages[0],
+            "skipped rest bob: bed went to carol on initiative"
+        );
+        assert_eq!(outcome.rolls.len(), 1);
+        assert_eq!(session.entity("carol").unwrap().stats.hp, 1);
+        let hp = |session: &Session, name| session.entity(name).unwrap().stats.hp;
+        assert_eq!(hp(&session, "bob") + hp(&session, "dave"), 1);
+
+        let bytes = session.serialize();
+        let copy = Session::deserialize(&mut FieldReader::new(&bytes)).unwrap();
+        assert_eq!(copy.entities(), session.entities());
+    }
+}
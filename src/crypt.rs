@@ -0,0 +1,92 @@
+use argon2::Argon2;
+use chacha20poly1305::aead::Aead;
+use chacha20poly1305::{ChaCha20Poly1305, Key, KeyInit, Nonce};
+use rand_core::{OsRng, RngCore};
+
+use crate::error::{Error, Result};
+use crate::serde::{FieldReader, FieldWriter, HEADER_LEN};
+
+/// First field of every encrypted file, so they can be told apart from
+/// plain ones without a passphrase.
+const MAGIC: &str = "relay_code encrypted";
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// Encrypts `plaintext` with a key derived from `passphrase`. The result is
+/// four fields:
+///
+/// ```text
+/// Str(MAGIC) Bytes(salt) Bytes(nonce) Bytes(ciphertext)
+/// ```
+///
+/// A fresh salt and nonce are drawn every time, so encrypting the same
+/// bytes twice gives different output.
+pub fn encrypt(passphrase: &str, plaintext: &[u8]) -> Vec<u8> {
+    let mut salt = [0; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let mut nonce = [0; NONCE_LEN];
+    OsRng.fill_bytes(&mut nonce);
+    let ciphertext = cipher(passphrase, &salt)
+        .encrypt(Nonce::from_slice(&nonce), plaintext)
+        .expect("plaintext fits in a field");
+
+    let mut bytes =
+        Vec::with_capacity(4 * HEADER_LEN + MAGIC.len() + SALT_LEN + NONCE_LEN + ciphertext.len());
+    let mut writer = FieldWriter::new(&mut bytes);
+    writer.write_str(MAGIC);
+    writer.write_bytes(&salt);
+    writer.write_bytes(&nonce);
+    writer.write_bytes(&ciphertext);
+    bytes
+}
+
+/// Reverses `encrypt`. A wrong passphrase and tampered bytes look the same.
+pub fn decrypt(passphrase: &str, bytes: &[u8]) -> Result<Vec<u8>> {
+    let mut reader = FieldReader::new(bytes);
+    if !matches!(reader.read_field::<&str>(), Ok(MAGIC)) {
+        return Err(Error::InvalidFieldType);
+    }
+    let salt: &[u8] = reader.read_field()?;
+    let nonce: &[u8] = reader.read_field()?;
+    let ciphertext: &[u8] = reader.read_field()?;
+    reader.finish()?;
+    if salt.len() != SALT_LEN || nonce.len() != NONCE_LEN {
+        return Err(Error::WrongPassphrase);
+    }
+    cipher(passphrase, salt)
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| Error::WrongPassphrase)
+}
+
+pub fn is_encrypted(bytes: &[u8]) -> bool {
+    matches!(FieldReader::new(bytes).read_field::<&str>(), Ok(MAGIC))
+}
+
+fn cipher(passphrase: &str, salt: &[u8]) -> ChaCha20Poly1305 {
+    let mut key = Key::default();
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .expect("salt and key lengths are valid");
+    ChaCha20Poly1305::new(&key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decrypt, encrypt, is_encrypted};
+    use crate::error::Error;
+
+    #[test]
+    fn only_the_right_passphrase_decrypts() {
+        let bytes = encrypt("hunter2", b"secret orders");
+        assert!(is_encrypted(&bytes));
+        assert!(!is_encrypted(b"secret orders"));
+        assert!(!bytes.windows(6).any(|window| window == b"secret"));
+
+        assert_eq!(decrypt("hunter2", &bytes).unwrap(), b"secret orders");
+        assert!(matches!(
+            decrypt("hunter3", &bytes),
+            Err(Error::WrongPassphrase)
+        ));
+    }
+}
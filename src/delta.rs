@@ -0,0 +1,66 @@
+use crate::error::Result;
+use crate::log::Event;
+use crate::serde::{Deserialize, FieldReader, FieldWriter, Serialize, HEADER_LEN};
+use crate::timestamp::Timestamp;
+
+/// The changes made to a session by one save, for appending to a journal
+/// instead of rewriting the whole session.
+///
+/// Undo shortens the log, so a delta first truncates the log to `base`
+/// events and then appends `events`. The redo history and tags are small
+/// and carried whole.
+#[derive(Debug, PartialEq)]
+pub struct Delta {
+    /// Modification time of the snapshot the journal builds on. A journal
+    /// left behind by an interrupted rewrite does not match the new
+    /// snapshot and is ignored.
+    pub(crate) snapshot: Timestamp,
+    pub(crate) modified: Timestamp,
+    pub(crate) base: u32,
+    pub(crate) events: Vec<Event>,
+    pub(crate) redo: Vec<Event>,
+    pub(crate) tags: Vec<String>,
+}
+
+impl Serialize for Delta {
+    fn serialize_to(&self, writer: &mut FieldWriter<'_>) {
+        writer.write(&self.snapshot);
+        writer.write(&self.modified);
+        writer.write_u32(self.base);
+        writer.write(&self.events);
+        writer.write(&self.redo);
+        writer.write(&self.tags);
+    }
+
+    fn size_hint(&self) -> usize {
+        let events =
+            |events: &[Event]| HEADER_LEN + events.iter().map(Event::field_len).sum::<usize>();
+        2 * (HEADER_LEN + 16)
+            + HEADER_LEN
+            + 4
+            + events(&self.events)
+            + events(&self.redo)
+            + HEADER_LEN
+            + self
+                .tags
+                .iter()
+                .map(|tag| HEADER_LEN + tag.len())
+                .sum::<usize>()
+    }
+}
+
+impl Deserialize for Delta {
+    fn deserialize(reader: &mut FieldReader<'_>) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        Ok(Self {
+            snapshot: reader.read_field()?,
+            modified: reader.read_field()?,
+            base: reader.read_field()?,
+            events: reader.read_field()?,
+            redo: reader.read_field()?,
+            tags: reader.read_field()?,
+        })
+    }
+}
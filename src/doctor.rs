@@ -0,0 +1,157 @@
+use crate::error::Result;
+use crate::log::Event;
+use crate::metadata::Metadata;
+use crate::serde::{FieldReader, FieldType, HEADER_LEN, LONG, LONG_HEADER_LEN};
+use crate::session::Session;
+use crate::signing::Keyring;
+
+/// What `examine` found wrong with a session file and what it could save.
+#[derive(Debug)]
+pub struct Diagnosis {
+    pub problems: Vec<String>,
+    /// The session rebuilt from every event up to the first damaged one,
+    /// or `None` if not even the header could be read.
+    pub recovered: Option<Session>,
+}
+
+/// Walks a session file field by field instead of decoding it in one go, so
+/// a single bad byte costs the events after it rather than the whole file.
+/// Any redo history is dropped from the recovered session.
+pub fn examine(bytes: &[u8], keyring: &Keyring) -> Diagnosis {
+    let mut problems = vec![];
+    let payload = match FieldReader::new(bytes).read_field::<&[u8]>() {
+        Ok(payload) => {
+            if let Err(err) = keyring.open(bytes) {
+                problems.push(format!("envelope: {err}"));
+            }
+            payload
+        }
+        Err(err) => {
+            problems.push(format!("envelope: {err}"));
+            contents(bytes, FieldType::Bytes).unwrap_or(bytes)
+        }
+    };
+    let recovered = match recover(payload, &mut problems) {
+        Ok(session) => Some(session),
+        Err(err) => {
+            problems.push(format!("header: {err}"));
+            None
+        }
+    };
+    Diagnosis {
+        problems,
+        recovered,
+    }
+}
+
+fn recover(payload: &[u8], problems: &mut Vec<String>) -> Result<Session> {
+    let mut reader = FieldReader::new(payload);
+    let metadata = Metadata::read(&mut reader)?;
+    let mut session = Session::from_parts(metadata.name, metadata.created, metadata.modified);
+    for tag in metadata.tags {
+        session.add_tag(tag)?;
+    }
+
+    let list = reader.unread();
+    let mut events = match reader.read_list() {
+        Ok(events) => events,
+        Err(err) => {
+            problems.push(format!("log: {err}"));
+            match contents(list, FieldType::List) {
+                Some(list) => FieldReader::new(list),
+                None => return Ok(session),
+            }
+        }
+    };
+    let mut n = 0;
+    while !events.is_empty() {
+        n += 1;
+        let event = match events.read_field::<Event>() {
+            Ok(event) => event,
+            Err(err) => {
+                problems.push(format!("event {n}: {err}"));
+                return Ok(session);
+            }
+        };
+        if let Err(err) = session.record(event) {
+            problems.push(format!("event {n} does not replay: {err}"));
+            return Ok(session);
+        }
+    }
+
+    if let Err(err) = reader.read_list() {
+        problems.push(format!("redo history: {err}"));
+        return Ok(session);
+    }
+    if let Err(err) = reader.finish() {
+        problems.push(format!("{err}"));
+    }
+    Ok(session)
+}
+
+/// Whatever follows the header of a `field_type` field at the start of
+/// `bytes`. A truncated file leaves headers claiming more bytes than there
+/// are, but the fields that did survive still follow them.
+fn contents(bytes: &[u8], field_type: FieldType) -> Option<&[u8]> {
+    let header = match *bytes.first()? {
+        byte if byte == field_type as u8 => HEADER_LEN,
+        byte if byte == field_type as u8 | LONG => LONG_HEADER_LEN,
+        _ => return None,
+    };
+    bytes.get(header..)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::examine;
+    use crate::serde::{Serialize, HEADER_LEN};
+    use crate::session::Session;
+    use crate::signing::Keyring;
+    use crate::Entity;
+
+    fn damaged_session() -> (Session, Vec<u8>) {
+        let mut session = Session::new("game".to_string()).unwrap();
+        for name in ["a", "b", "c"] {
+            session.add_entity(Entity::new(name.to_string())).unwrap();
+        }
+        let bytes = Keyring::default().seal(&session.serialize());
+        (session, bytes)
+    }
+
+    #[test]
+    fn healthy_files_have_no_problems() {
+        let (session, bytes) = damaged_session();
+        let diagnosis = examine(&bytes, &Keyring::default());
+        assert!(diagnosis.problems.is_empty(), "{:?}", diagnosis.problems);
+        assert_eq!(diagnosis.recovered.unwrap(), session);
+    }
+
+    #[test]
+    fn truncated_files_keep_their_intact_events() {
+        let (_, mut bytes) = damaged_session();
+        // Drops the redo list and most of the last event.
+        bytes.truncate(bytes.len() - 8);
+
+        let diagnosis = examine(&bytes, &Keyring::default());
+        assert!(!diagnosis.problems.is_empty());
+        let recovered = diagnosis.recovered.unwrap();
+        let names: Vec<_> = recovered.entities().iter().map(|e| &e.name).collect();
+        assert_eq!(names, ["a", "b"]);
+    }
+
+    #[test]
+    fn corrupt_field_types_stop_recovery_at_that_event() {
+        let (session, mut bytes) = damaged_session();
+        // The log list is followed only by the empty redo list.
+        let log_start = bytes.len() - HEADER_LEN - session_log_len(&session) - HEADER_LEN;
+        bytes[log_start + HEADER_LEN] = 0xee;
+
+        let diagnosis = examine(&bytes, &Keyring::default());
+        assert!(diagnosis.problems.iter().any(|p| p.starts_with("event 1")));
+        assert!(diagnosis.recovered.unwrap().entities().is_empty());
+    }
+
+    fn session_log_len(session: &Session) -> usize {
+        session.log().iter().map(|event| event.field_len()).sum()
+    }
+}
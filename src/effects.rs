@@ -0,0 +1,206 @@
+use std::fmt::Display;
+
+use crate::actions::ActionKind;
+use crate::error::{Error, Result};
+use crate::outcome::ActionOutcome;
+use crate::serde::{Field, FieldWriter, SerializeField, HEADER_LEN};
+use crate::Entity;
+
+/// Hp a poisoned entity loses at the end of each turn, per poisoning.
+pub const POISON_DAMAGE: u8 = 1;
+
+/// A temporary condition on an entity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EffectKind {
+    /// Loses `POISON_DAMAGE` hp at the end of every turn.
+    Poisoned,
+    /// Takes no damage from attacks.
+    Shielded,
+    /// Cannot take actions of its own; see `EffectKind::blocks`.
+    Stunned,
+}
+
+impl EffectKind {
+    pub const ALL: [EffectKind; 3] = [Self::Poisoned, Self::Shielded, Self::Stunned];
+
+    pub fn parse(name: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.to_string().eq_ignore_ascii_case(name))
+            .ok_or(Error::InvalidArgs)
+    }
+
+    /// Whether an entity under this effect is kept from being the target of
+    /// an action of `kind`. Stunned entities may still be attacked, inspected,
+    /// spoken to and given things, which are done to them rather than by them.
+    pub fn blocks(&self, kind: ActionKind) -> bool {
+        let done_to = [
+            ActionKind::Attack,
+            ActionKind::Inspect,
+            ActionKind::Say,
+            ActionKind::Give,
+        ];
+        *self == Self::Stunned && !done_to.contains(&kind)
+    }
+
+    fn id(&self) -> u8 {
+        match self {
+            Self::Poisoned => 0,
+            Self::Shielded => 1,
+            Self::Stunned => 2,
+        }
+    }
+}
+
+impl Display for EffectKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Poisoned => write!(f, "poisoned"),
+            Self::Shielded => write!(f, "shielded"),
+            Self::Stunned => write!(f, "stunned"),
+        }
+    }
+}
+
+impl SerializeField for EffectKind {
+    fn serialize_field(&self, writer: &mut FieldWriter<'_>) {
+        writer.write_u8(self.id());
+    }
+}
+
+impl TryFrom<Field<'_>> for EffectKind {
+    type Error = Error;
+
+    fn try_from(value: Field<'_>) -> Result<Self> {
+        let id: u8 = value.try_into()?;
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.id() == id)
+            .ok_or(Error::InvalidVariant(id))
+    }
+}
+
+/// An effect and the turns it has left. An entity may be under several,
+/// including several of one kind, which stack. Written as
+/// `List(U8(kind) U8(turns))`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Effect {
+    pub kind: EffectKind,
+    /// Turn ends the effect lasts through; it wears off when this reaches 0.
+    pub turns: u8,
+}
+
+impl Effect {
+    pub(crate) const FIELD_LEN: usize = 3 * HEADER_LEN + 1 + 1;
+}
+
+impl Display for Effect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = if self.turns == 1 { "" } else { "s" };
+        write!(f, "{} for {} turn{s}", self.kind, self.turns)
+    }
+}
+
+impl SerializeField for Effect {
+    fn serialize_field(&self, writer: &mut FieldWriter<'_>) {
+        writer.write(&(self.kind, self.turns));
+    }
+}
+
+impl TryFrom<Field<'_>> for Effect {
+    type Error = Error;
+
+    fn try_from(value: Field<'_>) -> Result<Self> {
+        let (kind, turns) = value.try_into()?;
+        Ok(Self { kind, turns })
+    }
+}
+
+impl Entity {
+    pub fn is(&self, kind: EffectKind) -> bool {
+        self.effects.iter().any(|effect| effect.kind == kind)
+    }
+
+    /// Runs the entity's effects for the end of a turn: poison does its
+    /// damage, then every effect has a turn less and those at 0 wear off.
+    pub(crate) fn tick_effects(&mut self) -> ActionOutcome {
+        let mut outcome = ActionOutcome::default();
+        if self.effects.is_empty() {
+            return outcome;
+        }
+        let poisonings = self
+            .effects
+            .iter()
+            .filter(|effect| effect.kind == EffectKind::Poisoned)
+            .count();
+        if poisonings > 0 {
+            let damage = u8::try_from(poisonings)
+                .unwrap_or(u8::MAX)
+                .saturating_mul(POISON_DAMAGE);
+            let before = self.stats.hp;
+            self.stats.hp = before.saturating_sub(damage);
+            outcome.message(format!("{} took {damage} poison damage", self.name));
+            outcome.change(&self.name, "hp", before, self.stats.hp);
+        }
+        for effect in &mut self.effects {
+            effect.turns = effect.turns.saturating_sub(1);
+        }
+        let (worn, kept): (Vec<_>, Vec<_>) =
+            self.effects.iter().partition(|effect| effect.turns == 0);
+        self.effects = kept;
+        for effect in worn {
+            if !self.is(effect.kind) {
+                outcome.message(format!("{} is no longer {}", self.name, effect.kind));
+            }
+        }
+        outcome
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Effect, EffectKind};
+    use crate::actions::{Action, ActionKind, Param};
+    use crate::session::Session;
+    use crate::Entity;
+
+    #[test]
+    fn effects_stack_and_wear_off() {
+        let mut session = Session::new("game".to_string()).unwrap();
+        let mut hero = Entity::new("hero".to_string());
+        hero.stats.hp = 10;
+        session.add_entity(hero).unwrap();
+        let afflict = |session: &mut Session, kind, turns| {
+            session.afflict("hero", Effect { kind, turns }).unwrap();
+        };
+        afflict(&mut session, EffectKind::Poisoned, 2);
+        afflict(&mut session, EffectKind::Poisoned, 1);
+        afflict(&mut session, EffectKind::Stunned, 1);
+        afflict(&mut session, EffectKind::Shielded, 1);
+
+        let rest = Action::new(ActionKind::Rest, "hero".to_string()).unwrap();
+        assert_eq!(
+            session.apply(rest.clone()).unwrap_err().to_string(),
+            "hero is stunned"
+        );
+        let attack = Action::new(ActionKind::Attack, "hero".to_string())
+            .unwrap()
+            .with_params(
+                [("damage".to_string(), Param::Int(5))]
+                    .into_iter()
+                    .collect(),
+            );
+        session.apply(attack).unwrap();
+        assert_eq!(session.entity("hero").unwrap().stats.hp, 10);
+
+        let outcome = session.resolve().unwrap().to_string();
+        assert!(outcome.contains("hero took 2 poison damage"));
+        assert!(outcome.contains("hero is no longer stunned"));
+        assert!(!outcome.contains("no longer poisoned"));
+        session.apply(rest).unwrap();
+        session.resolve().unwrap();
+        let hero = session.entity("hero").unwrap();
+        assert!(hero.effects.is_empty());
+        assert_eq!(hero.stats.hp, 8);
+    }
+}
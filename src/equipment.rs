@@ -0,0 +1,195 @@
+use std::fmt::Display;
+
+use crate::error::{Error, Result};
+use crate::inventory::Item;
+use crate::outcome::ActionOutcome;
+use crate::serde::{Field, FieldWriter, SerializeField};
+use crate::{Entity, Stats};
+
+/// Where on an entity an item is equipped. An entity has one of each.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Slot {
+    Weapon,
+    Armor,
+    Trinket,
+}
+
+impl Slot {
+    pub const ALL: [Slot; 3] = [Self::Weapon, Self::Armor, Self::Trinket];
+
+    pub fn parse(name: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|slot| slot.to_string().eq_ignore_ascii_case(name))
+            .ok_or(Error::InvalidArgs)
+    }
+
+    /// Ids start at 1, so that items can write 0 for no slot.
+    pub(crate) fn id(&self) -> u8 {
+        match self {
+            Self::Weapon => 1,
+            Self::Armor => 2,
+            Self::Trinket => 3,
+        }
+    }
+
+    pub(crate) fn from_id(id: u8) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|slot| slot.id() == id)
+            .ok_or(Error::InvalidVariant(id))
+    }
+}
+
+impl Display for Slot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Weapon => write!(f, "weapon"),
+            Self::Armor => write!(f, "armor"),
+            Self::Trinket => write!(f, "trinket"),
+        }
+    }
+}
+
+impl SerializeField for Slot {
+    fn serialize_field(&self, writer: &mut FieldWriter<'_>) {
+        writer.write_u8(self.id());
+    }
+}
+
+impl TryFrom<Field<'_>> for Slot {
+    type Error = Error;
+
+    fn try_from(value: Field<'_>) -> Result<Self> {
+        Self::from_id(value.try_into()?)
+    }
+}
+
+/// Stat bonuses such as `+2 attack, +1 speed`, leaving out those of 0.
+pub fn describe(modifiers: &Stats) -> String {
+    let bonuses: Vec<_> = modifiers
+        .named()
+        .into_iter()
+        .filter(|(_, bonus)| *bonus > 0)
+        .map(|(name, bonus)| format!("+{bonus} {name}"))
+        .collect();
+    bonuses.join(", ")
+}
+
+impl Entity {
+    /// What the entity's equipped items add to its stats, all together.
+    pub fn modifiers(&self) -> Stats {
+        let mut total = Stats::default();
+        for item in self.equipment.values() {
+            for ((_, stat), (_, bonus)) in total.named_mut().into_iter().zip(item.modifiers.named())
+            {
+                *stat = stat.saturating_add(bonus);
+            }
+        }
+        total
+    }
+
+    /// The entity's stats with what its equipment adds. They are worked out
+    /// whenever an action needs them rather than stored, so taking an item
+    /// off takes its bonus with it.
+    pub fn effective_stats(&self) -> Stats {
+        let mut stats = self.stats;
+        for ((_, stat), (_, bonus)) in stats.named_mut().into_iter().zip(self.modifiers().named()) {
+            *stat = stat.saturating_add(bonus);
+        }
+        stats
+    }
+
+    /// Takes one `item` out of the inventory and into its slot, putting
+    /// back whatever was there.
+    pub(crate) fn equip(&mut self, item: &str) -> ActionOutcome {
+        let mut outcome = ActionOutcome::default();
+        let Some(stack) = self.inventory.iter().find(|stack| stack.name == item) else {
+            return outcome;
+        };
+        let Some(slot) = stack.slot else {
+            return outcome;
+        };
+        let equipped = Item {
+            count: 1,
+            ..stack.clone()
+        };
+        let before = self.count(item);
+        self.remove_item(item, 1);
+        let old = self.unequip(slot).unwrap_or_else(|| "nothing".to_string());
+        outcome.message(format!("{} equipped {item} as {slot}", self.name));
+        outcome.change(&self.name, item, before, self.count(item));
+        outcome.change(&self.name, &slot.to_string(), old, item.to_string());
+        self.equipment.insert(slot, equipped);
+        outcome
+    }
+
+    /// Puts the item in `slot`, if any, back in the inventory, returning
+    /// its name.
+    pub(crate) fn unequip(&mut self, slot: Slot) -> Option<String> {
+        let item = self.equipment.remove(&slot)?;
+        let name = item.name.clone();
+        self.stow(item);
+        Some(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::actions::{Action, ActionKind, Param};
+    use crate::serde::{Deserialize, FieldReader, Serialize};
+    use crate::session::Session;
+    use crate::Entity;
+
+    #[test]
+    fn equipment_adds_to_stats_while_worn() {
+        let mut session = Session::new("game".to_string()).unwrap();
+        for name in ["knight", "orc"] {
+            let mut entity = Entity::new(name.to_string());
+            entity.stats.hp = 20;
+            session.add_entity(entity).unwrap();
+        }
+        let mut act = |kind, target: &str, params: &[(&str, &str)]| {
+            let params = params
+                .iter()
+                .map(|(key, value)| (key.to_string(), Param::parse(value)))
+                .collect();
+            let action = Action::new(kind, target.to_string()).unwrap();
+            session.apply(action.with_params(params))
+        };
+        act(
+            ActionKind::Give,
+            "knight",
+            &[("item", "sword"), ("slot", "weapon"), ("attack", "3")],
+        )
+        .unwrap();
+        act(
+            ActionKind::Give,
+            "orc",
+            &[("item", "hide"), ("slot", "armor"), ("defense", "1")],
+        )
+        .unwrap();
+        act(ActionKind::Give, "orc", &[("item", "bone")]).unwrap();
+        let err = act(ActionKind::Equip, "orc", &[("item", "bone")]).unwrap_err();
+        assert_eq!(err.to_string(), "bone cannot be equipped");
+        act(ActionKind::Equip, "knight", &[("item", "sword")]).unwrap();
+        act(ActionKind::Equip, "orc", &[("item", "hide")]).unwrap();
+
+        let attack = [("damage", "2"), ("by", "knight")];
+        let outcome = act(ActionKind::Attack, "orc", &attack).unwrap();
+        assert_eq!(outcome.messages, ["attacked orc for 4"]);
+        act(ActionKind::Unequip, "knight", &[("slot", "weapon")]).unwrap();
+        let outcome = act(ActionKind::Attack, "orc", &attack).unwrap();
+        assert_eq!(outcome.messages, ["attacked orc for 1"]);
+        let err = act(ActionKind::Unequip, "knight", &[("slot", "weapon")]).unwrap_err();
+        assert_eq!(err.to_string(), "knight has no weapon equipped");
+
+        let knight = session.entity("knight").unwrap();
+        assert!(knight.equipment.is_empty());
+        assert_eq!(knight.inventory[0].to_string(), "sword (weapon, +3 attack)");
+        assert_eq!(session.entity("orc").unwrap().effective_stats().defense, 1);
+        let bytes = session.serialize();
+        let copy = Session::deserialize(&mut FieldReader::new(&bytes)).unwrap();
+        assert_eq!(copy.entities(), session.entities());
+    }
+}
@@ -1,37 +1,520 @@
 use std::fmt::Display;
-use std::io::Error as IoErr;
+use std::io::{Error as IoErr, ErrorKind};
+use std::path::PathBuf;
 use std::str::Utf8Error;
 use std::time::SystemTimeError;
 
+use crate::lobby::{MAX_HOSTED, MAX_SLOTS};
+use crate::mailbox::MAX_CHAT_LEN;
+use crate::protocol::Versions;
+use crate::serde::FieldType;
+
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Debug)]
 pub enum Error {
     InvalidArgs,
+    UnknownCommand(String),
+    /// A subcommand and a flag it does not take.
+    UnknownFlag(String, String),
+    /// A subcommand and what it was not given.
+    MissingArg(String, String),
+    /// A subcommand and an argument left over once it had all it takes.
+    UnexpectedArg(String, String),
     InvalidActionType,
+    /// An action kind with no handler registered.
+    UnknownActionKind(u8),
     InvalidFieldType,
     MissingFieldLen,
     MissingFieldType,
+    MissingDiscriminant,
+    InvalidVariant(u8),
+    InvalidFieldLen(FieldType, usize),
+    UnexpectedEof,
+    TrailingBytes(usize),
+    NonCanonical,
+    TimestampOutOfRange,
     NoEntity,
+    InvalidSessionName(String),
+    SessionNotFound(String),
+    SessionExists(String),
+    SessionLocked(String),
+    BackupNotFound(String, u32),
+    NoDataDir,
+    EntityExists(String),
+    EntityNotFound(String),
+    /// A name prefix shared by several entities, and their names.
+    AmbiguousEntity(String, Vec<String>),
+    NothingToUndo,
+    NothingToRedo,
+    EventNotFound(usize),
+    /// An action not allowed in the current state, and why.
+    IllegalAction(String),
+    /// Whose turn it is, when someone else tried to act.
+    NotYourTurn(String),
+    /// A session passed in that is not played in turns.
+    NoTurnOrder(String),
+    InvalidPlayer(String),
+    UnknownRuleSet(String),
+    /// How the game ended, when an action was taken after.
+    GameOver(String),
+    PlayerExists(String),
+    PlayerNotFound(String),
+    /// A session with players, when an action was taken by none of them.
+    Unattributed(String),
+    NotAnArchive,
+    NotAReplay,
+    Diverged(String),
+    NotATurnFile,
+    /// A session with fewer events than a turn file follows on from, and
+    /// how many that is.
+    TurnOutOfOrder(String, usize),
+    /// A session with nothing new since it was last exchanged.
+    NothingToExchange(String),
+    TemplateNotFound(String),
+    ArchetypeNotFound(String),
+    NotAnArchetype,
+    InvalidTag(String),
+    InvalidAttribute(String),
+    InvalidFaction(String),
+    FactionNotFound(String),
+    /// An entity and the attribute it does not have.
+    AttributeNotFound(String, String),
+    /// A line of the macros file that does not define a macro.
+    InvalidMacro(String),
+    /// A line number of the config file and what is wrong with it.
+    InvalidConfig(usize, String),
+    InvalidScriptName(String),
+    ScriptNotFound(String),
+    /// A script that failed other than by rejecting its action.
+    Script(String),
+    ScriptingUnsupported,
+    NoRecentSession,
+    UnsupportedVersion(u16),
+    /// An error raised while decoding the field at the given byte offset.
+    At(usize, Box<Error>),
+    /// A name not found and the known name closest to it.
+    DidYouMean(Box<Error>, String),
+    InvalidKey,
+    InvalidSignature,
+    UnknownSigner(String),
+    Unsigned,
+    /// A session is encrypted but no passphrases were available.
+    Encrypted(String),
+    WrongPassphrase,
+    EncryptionUnsupported,
+    /// An error a server answered with, and the exit code it stands for.
+    Remote(String, u8),
+    /// A server answered with something other than what was asked for.
+    UnexpectedResponse,
+    /// A client sent a server something other than a request.
+    UnexpectedRequest,
+    /// What is being versioned, and the versions a server and a client
+    /// support, which have none in common.
+    IncompatibleVersions(&'static str, Versions, Versions),
+    /// A client sent a request before saying hello.
+    NoHello,
+    /// A message framed with a length that is zero or over the limit.
+    InvalidFrameLen(u64),
+    /// The length of a message over a server's limit, and the limit.
+    MessageTooLarge(u32, u32),
+    /// The messages a second a client went over.
+    RateLimited(u32),
+    /// The sessions a client may create, all of which it has.
+    TooManySessions(u32),
+    /// A command that only works on the local data directory.
+    RemoteUnsupported(String),
+    /// A command that only works with `--remote`.
+    RemoteOnly(String),
+    WebSocketUnsupported,
+    TlsUnsupported,
+    SqliteUnsupported,
+    /// What a PEM file was expected to hold, and the file.
+    InvalidPem(&'static str, PathBuf),
+    /// The system trusts no certificate authority rustls can use.
+    NoTrustedCertificates,
+    InvalidToken,
+    /// A client made a request of a server that has issued tokens before
+    /// presenting one.
+    Unauthenticated,
+    /// A spectator asked to change something.
+    Spectating,
+    /// A client asked for the log of a session that hides things from
+    /// its players.
+    Hidden(String),
+    /// The player who sent events and one they may not send: someone
+    /// else's, or one only whoever keeps the session may add.
+    NotSentBy(String, String),
+    /// A player signed with a key other than the session's roster has.
+    WrongKey(String),
+    /// The length of a chat message over `MAX_CHAT_LEN`.
+    ChatTooLong(usize),
+    /// A game in a server's lobby was opened for fewer than two players or
+    /// more than `MAX_SLOTS`.
+    InvalidSlots(u8),
+    /// No game by that name is waiting in the lobby for players.
+    GameNotOpen(String),
+    /// The player and the game in the lobby they have already joined.
+    AlreadyJoined(String, String),
+    /// A player with `MAX_HOSTED` games already waiting in the lobby.
+    TooManyGames(String),
+    /// The host of a webhook that answered with other than success, and
+    /// the status, or 0 for none.
+    WebhookRefused(String, u16),
     Io(IoErr),
     Utf8(Utf8Error),
     SystemTime(SystemTimeError),
+    #[cfg(feature = "sqlite")]
+    Sqlite(rusqlite::Error),
+    #[cfg(feature = "websocket")]
+    WebSocket(Box<tungstenite::Error>),
+    #[cfg(feature = "tls")]
+    Tls(rustls::Error),
 }
 
 impl Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::InvalidArgs => write!(f, "invalid argument"),
+            Self::UnknownCommand(command) => {
+                write!(f, "no command {command:?}; see relay_code --help")
+            }
+            Self::UnknownFlag(command, flag) => write!(
+                f,
+                "{command} does not take {flag}; see relay_code {command} --help"
+            ),
+            Self::MissingArg(command, what) => {
+                write!(f, "{command} needs {what}; see relay_code {command} --help")
+            }
+            Self::UnexpectedArg(command, arg) => write!(
+                f,
+                "{command} does not take {arg:?}; see relay_code {command} --help"
+            ),
             Self::InvalidActionType => write!(f, "invalid action type"),
+            Self::UnknownActionKind(id) => write!(f, "no handler for action kind {id}"),
             Self::InvalidFieldType => write!(f, "invalid field type"),
             Self::MissingFieldLen => write!(f, "missing field length"),
             Self::MissingFieldType => write!(f, "missing field type"),
+            Self::MissingDiscriminant => write!(f, "missing enum discriminant"),
+            Self::InvalidVariant(discriminant) => write!(f, "invalid enum variant {discriminant}"),
+            Self::InvalidFieldLen(field_type, len) => {
+                write!(f, "invalid length {len} for {field_type:?} field")
+            }
+            Self::UnexpectedEof => write!(f, "unexpected end of input"),
+            Self::TrailingBytes(count) => write!(f, "{count} unexpected trailing bytes"),
+            Self::NonCanonical => write!(f, "non-canonical encoding"),
+            Self::TimestampOutOfRange => write!(f, "timestamp out of range"),
             Self::NoEntity => write!(f, "no entity"),
+            Self::InvalidSessionName(name) => write!(f, "invalid session name {name:?}"),
+            Self::SessionNotFound(name) => write!(f, "session {name:?} not found"),
+            Self::SessionExists(name) => write!(f, "session {name:?} already exists"),
+            Self::SessionLocked(name) => {
+                write!(f, "session {name:?} is in use by another process")
+            }
+            Self::BackupNotFound(name, n) => write!(f, "session {name:?} has no backup {n}"),
+            Self::EntityExists(name) => write!(f, "entity {name:?} already exists"),
+            Self::EntityNotFound(name) => write!(f, "no entity named {name:?}"),
+            Self::AmbiguousEntity(query, names) => {
+                write!(f, "{query:?} matches several entities; did you mean ")?;
+                for (i, name) in names.iter().enumerate() {
+                    match i {
+                        0 => write!(f, "{name:?}")?,
+                        i if i + 1 == names.len() => write!(f, " or {name:?}")?,
+                        _ => write!(f, ", {name:?}")?,
+                    }
+                }
+                write!(f, "?")
+            }
+            Self::NothingToUndo => write!(f, "nothing to undo"),
+            Self::NothingToRedo => write!(f, "nothing to redo"),
+            Self::EventNotFound(n) => write!(f, "the log has no event {n}"),
+            Self::IllegalAction(reason) => write!(f, "{reason}"),
+            Self::NotYourTurn(player) => write!(f, "it is {player}'s turn"),
+            Self::NoTurnOrder(name) => write!(
+                f,
+                "session {name:?} is not played in turns; set its players first"
+            ),
+            Self::InvalidPlayer(name) => write!(f, "invalid player name {name:?}"),
+            Self::UnknownRuleSet(name) => write!(f, "no rule set named {name:?}"),
+            Self::GameOver(how) => write!(f, "the game is over; {how}"),
+            Self::PlayerExists(name) => write!(f, "player {name:?} already exists"),
+            Self::PlayerNotFound(name) => write!(f, "no player named {name:?}"),
+            Self::Unattributed(name) => write!(f, "session {name:?} has players; act as one"),
+            Self::NoRecentSession => write!(f, "no session name given and none used recently"),
+            Self::InvalidTag(tag) => write!(f, "invalid tag {tag:?}"),
+            Self::InvalidAttribute(name) => write!(f, "invalid attribute name {name:?}"),
+            Self::InvalidFaction(name) => write!(f, "invalid faction name {name:?}"),
+            Self::FactionNotFound(name) => write!(f, "no faction named {name:?}"),
+            Self::AttributeNotFound(entity, name) => {
+                write!(f, "{entity} has no attribute {name:?}")
+            }
+            Self::InvalidMacro(line) => write!(f, "invalid macro definition {line:?}"),
+            Self::InvalidConfig(line, why) => write!(f, "config line {line}: {why}"),
+            Self::InvalidScriptName(name) => write!(f, "invalid script name {name:?}"),
+            Self::ScriptNotFound(name) => write!(f, "no action or script named {name:?}"),
+            Self::Script(err) => write!(f, "script failed: {err}"),
+            Self::ScriptingUnsupported => write!(
+                f,
+                "this build cannot run scripts; rebuild with --features scripting"
+            ),
+            Self::TemplateNotFound(name) => write!(f, "template {name:?} not found"),
+            Self::ArchetypeNotFound(name) => write!(f, "archetype {name:?} not found"),
+            Self::NotAnArchetype => write!(f, "not a relay_code entity archetype"),
+            Self::Diverged(name) => write!(f, "session {name:?} does not descend from the base"),
+            Self::NotAnArchive => write!(f, "not a relay_code archive"),
+            Self::NotAReplay => write!(f, "not a relay_code replay"),
+            Self::NotATurnFile => write!(f, "not a relay_code turn file"),
+            Self::TurnOutOfOrder(name, base) => write!(
+                f,
+                "this turn follows event {base} of session {name:?}, which has fewer; \
+                 apply the turns before it first"
+            ),
+            Self::NothingToExchange(name) => {
+                write!(
+                    f,
+                    "nothing new in session {name:?} since it was last exchanged"
+                )
+            }
+            Self::UnsupportedVersion(version) => {
+                write!(f, "unsupported format version {version}")
+            }
+            Self::NoDataDir => write!(
+                f,
+                "no data directory found; pass --data-dir or set RELAY_CODE_DATA_DIR"
+            ),
+            Self::InvalidKey => write!(f, "invalid key"),
+            Self::InvalidSignature => write!(f, "signature verification failed"),
+            Self::UnknownSigner(player) => write!(f, "no trusted key for signer {player:?}"),
+            Self::Unsigned => write!(f, "file is not signed"),
+            Self::Encrypted(name) => write!(f, "session {name:?} is encrypted"),
+            Self::WrongPassphrase => write!(f, "wrong passphrase"),
+            Self::EncryptionUnsupported => write!(f, "this store cannot encrypt sessions"),
+            Self::Remote(message, _) => write!(f, "{message}"),
+            Self::UnexpectedResponse => write!(f, "unexpected response from the server"),
+            Self::UnexpectedRequest => write!(f, "expected a request"),
+            Self::IncompatibleVersions(what, server, client) if server.min > client.max => {
+                write!(
+                    f,
+                    "server requires {what} {server}, client supports {client}"
+                )
+            }
+            Self::IncompatibleVersions(what, server, client) => {
+                write!(
+                    f,
+                    "server supports {what} {server}, client requires {client}"
+                )
+            }
+            Self::NoHello => write!(f, "expected a hello before any request"),
+            Self::InvalidFrameLen(len) => write!(f, "invalid message length {len}"),
+            Self::MessageTooLarge(len, max) => write!(
+                f,
+                "message of {len} bytes is over the server's limit of {max}"
+            ),
+            Self::RateLimited(rate) => write!(
+                f,
+                "over the server's limit of {rate} messages a second; slow down"
+            ),
+            Self::TooManySessions(max) => {
+                write!(
+                    f,
+                    "the server lets each player create {max} sessions at most"
+                )
+            }
+            Self::RemoteUnsupported(command) => {
+                write!(f, "{command} cannot be used with --remote")
+            }
+            Self::RemoteOnly(command) => write!(f, "{command} only works with --remote"),
+            Self::WebSocketUnsupported => write!(
+                f,
+                "this build cannot use WebSocket; rebuild with --features websocket"
+            ),
+            Self::TlsUnsupported => {
+                write!(f, "this build cannot use TLS; rebuild with --features tls")
+            }
+            Self::SqliteUnsupported => write!(
+                f,
+                "this build cannot use SQLite; rebuild with --features sqlite"
+            ),
+            Self::InvalidPem(what, path) => {
+                write!(f, "{} does not hold {what} in PEM", path.display())
+            }
+            Self::NoTrustedCertificates => {
+                write!(f, "no trusted certificate authorities found; pass --ca")
+            }
+            Self::InvalidToken => write!(f, "invalid token"),
+            Self::Unauthenticated => {
+                write!(f, "this server requires a token; pass --token")
+            }
+            Self::Spectating => write!(f, "spectators can only watch"),
+            Self::Hidden(name) => write!(
+                f,
+                "session {name:?} hides things from its players; only its state is sent"
+            ),
+            Self::NotSentBy(player, event) => write!(f, "{player} may not send \"{event}\""),
+            Self::WrongKey(player) => write!(
+                f,
+                "{player} signed with a key other than the session's roster has for them"
+            ),
+            Self::ChatTooLong(len) => write!(
+                f,
+                "chat messages are limited to {MAX_CHAT_LEN} bytes, not {len}"
+            ),
+            Self::InvalidSlots(slots) => {
+                write!(f, "a game takes 2 to {MAX_SLOTS} players, not {slots}")
+            }
+            Self::GameNotOpen(name) => write!(f, "no game named {name:?} is waiting for players"),
+            Self::AlreadyJoined(player, name) => write!(f, "{player} has already joined {name:?}"),
+            Self::TooManyGames(host) => write!(
+                f,
+                "{host} already has {MAX_HOSTED} games waiting for players"
+            ),
+            Self::WebhookRefused(host, 0) => write!(f, "webhook at {host} did not answer HTTP"),
+            Self::WebhookRefused(host, status) => {
+                write!(f, "webhook at {host} answered {status}")
+            }
+            Self::At(offset, err) => write!(f, "{err} (at byte {offset})"),
+            Self::DidYouMean(err, name) => write!(f, "{err}; did you mean {name:?}?"),
             Self::Io(err) => write!(f, "{err}"),
             Self::Utf8(err) => write!(f, "{err}"),
             Self::SystemTime(err) => write!(f, "{err}"),
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite(err) => write!(f, "{err}"),
+            #[cfg(feature = "websocket")]
+            Self::WebSocket(err) => write!(f, "{err}"),
+            #[cfg(feature = "tls")]
+            Self::Tls(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl Error {
+    /// Attaches the offset of the field being decoded. Offsets already
+    /// attached by a nested reader are relative to `base` and get rebased.
+    pub(crate) fn at(self, start: usize, base: usize) -> Self {
+        match self {
+            Self::At(offset, err) => Self::At(base + offset, err),
+            err => Self::At(start, Box::new(err)),
         }
     }
+
+    /// What the process exits with on this error, so that scripts can tell
+    /// kinds of failure apart:
+    ///
+    /// - 1: anything not listed below, such as an illegal action
+    /// - 2: a session, entity or other named thing was not found
+    /// - 3: the command line, config file or a name in it is malformed
+    /// - 4: a file is corrupt, unsigned or of an unsupported version, or a
+    ///   server speaks no version the client does
+    /// - 5: the session is locked by another process
+    /// - 6: a network connection failed
+    /// - 7: a server refused the client's token, or it gave none
+    ///
+    /// Errors a server answered with exit as they would have locally.
+    pub fn exit_code(&self) -> u8 {
+        match self.cause() {
+            Self::SessionNotFound(_)
+            | Self::BackupNotFound(..)
+            | Self::EntityNotFound(_)
+            | Self::EventNotFound(_)
+            | Self::TemplateNotFound(_)
+            | Self::ArchetypeNotFound(_)
+            | Self::FactionNotFound(_)
+            | Self::PlayerNotFound(_)
+            | Self::UnknownRuleSet(_)
+            | Self::AttributeNotFound(..)
+            | Self::ScriptNotFound(_)
+            | Self::GameNotOpen(_)
+            | Self::NoRecentSession
+            | Self::NoEntity => 2,
+            Self::InvalidArgs
+            | Self::UnknownCommand(_)
+            | Self::UnknownFlag(..)
+            | Self::MissingArg(..)
+            | Self::UnexpectedArg(..)
+            | Self::InvalidActionType
+            | Self::InvalidSessionName(_)
+            | Self::AmbiguousEntity(..)
+            | Self::InvalidTag(_)
+            | Self::InvalidAttribute(_)
+            | Self::InvalidFaction(_)
+            | Self::InvalidPlayer(_)
+            | Self::InvalidMacro(_)
+            | Self::InvalidConfig(..)
+            | Self::InvalidScriptName(_)
+            | Self::RemoteUnsupported(_)
+            | Self::RemoteOnly(_)
+            | Self::ChatTooLong(_)
+            | Self::InvalidSlots(_)
+            | Self::InvalidKey => 3,
+            Self::UnknownActionKind(_)
+            | Self::InvalidFieldType
+            | Self::MissingFieldLen
+            | Self::MissingFieldType
+            | Self::MissingDiscriminant
+            | Self::InvalidVariant(_)
+            | Self::InvalidFieldLen(..)
+            | Self::UnexpectedEof
+            | Self::TrailingBytes(_)
+            | Self::NonCanonical
+            | Self::TimestampOutOfRange
+            | Self::UnsupportedVersion(_)
+            | Self::IncompatibleVersions(..)
+            | Self::NotAnArchive
+            | Self::NotAReplay
+            | Self::NotATurnFile
+            | Self::NotAnArchetype
+            | Self::InvalidSignature
+            | Self::UnknownSigner(_)
+            | Self::Unsigned
+            | Self::InvalidPem(..)
+            | Self::Utf8(_) => 4,
+            Self::SessionLocked(_) => 5,
+            Self::InvalidToken
+            | Self::Unauthenticated
+            | Self::Spectating
+            | Self::Hidden(_)
+            | Self::NotSentBy(..)
+            | Self::WrongKey(_) => 7,
+            Self::MessageTooLarge(..)
+            | Self::RateLimited(_)
+            | Self::TooManySessions(_)
+            | Self::TooManyGames(_) => 8,
+            Self::Remote(_, code) => *code,
+            Self::UnexpectedResponse
+            | Self::UnexpectedRequest
+            | Self::NoHello
+            | Self::InvalidFrameLen(_)
+            | Self::WebhookRefused(..) => 6,
+            Self::Io(err) if is_network(err.kind()) => 6,
+            #[cfg(feature = "websocket")]
+            Self::WebSocket(_) => 6,
+            #[cfg(feature = "tls")]
+            Self::Tls(_) => 6,
+            _ => 1,
+        }
+    }
+
+    /// The underlying error, without any position or suggestion attached.
+    pub fn cause(&self) -> &Self {
+        match self {
+            Self::At(_, err) | Self::DidYouMean(err, _) => err.cause(),
+            err => err,
+        }
+    }
+}
+
+fn is_network(kind: ErrorKind) -> bool {
+    matches!(
+        kind,
+        ErrorKind::ConnectionRefused
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::NotConnected
+            | ErrorKind::AddrInUse
+            | ErrorKind::AddrNotAvailable
+            | ErrorKind::BrokenPipe
+            | ErrorKind::TimedOut
+    )
 }
 
 impl std::error::Error for Error {}
@@ -53,3 +536,45 @@ impl From<SystemTimeError> for Error {
         Self::SystemTime(err)
     }
 }
+
+#[cfg(feature = "sqlite")]
+impl From<rusqlite::Error> for Error {
+    fn from(err: rusqlite::Error) -> Self {
+        Self::Sqlite(err)
+    }
+}
+
+#[cfg(feature = "websocket")]
+impl From<tungstenite::Error> for Error {
+    fn from(err: tungstenite::Error) -> Self {
+        match err {
+            tungstenite::Error::Io(err) => Self::Io(err),
+            err => Self::WebSocket(Box::new(err)),
+        }
+    }
+}
+
+#[cfg(feature = "tls")]
+impl From<rustls::Error> for Error {
+    fn from(err: rustls::Error) -> Self {
+        Self::Tls(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Error as IoErr, ErrorKind};
+
+    use super::Error;
+
+    #[test]
+    fn failures_exit_by_kind() {
+        assert_eq!(Error::SessionNotFound("game".into()).exit_code(), 2);
+        assert_eq!(Error::UnknownCommand("x".into()).exit_code(), 3);
+        assert_eq!(Error::UnexpectedEof.at(4, 0).exit_code(), 4);
+        assert_eq!(Error::SessionLocked("game".into()).exit_code(), 5);
+        let refused = IoErr::from(ErrorKind::ConnectionRefused);
+        assert_eq!(Error::Io(refused).exit_code(), 6);
+        assert_eq!(Error::NothingToUndo.exit_code(), 1);
+    }
+}
@@ -0,0 +1,207 @@
+use std::fmt::Display;
+
+use crate::error::{Error, Result};
+use crate::outcome::ActionOutcome;
+use crate::serde::{Field, FieldWriter, SerializeField, HEADER_LEN};
+use crate::{Entity, Stats};
+
+/// How far an entity has come. Written as `List(U32(xp) U8(level))`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Experience {
+    pub xp: u32,
+    /// Starts at 1 and only goes up, even if leveling is made slower.
+    pub level: u8,
+}
+
+impl Default for Experience {
+    fn default() -> Self {
+        Self { xp: 0, level: 1 }
+    }
+}
+
+impl Display for Experience {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "level {}, {} xp", self.level, self.xp)
+    }
+}
+
+impl SerializeField for Experience {
+    fn serialize_field(&self, writer: &mut FieldWriter<'_>) {
+        writer.write(&(self.xp, self.level));
+    }
+}
+
+impl TryFrom<Field<'_>> for Experience {
+    type Error = Error;
+
+    fn try_from(value: Field<'_>) -> Result<Self> {
+        let (xp, level) = value.try_into()?;
+        Ok(Self { xp, level })
+    }
+}
+
+/// How entities level up as they earn xp. Written as:
+///
+/// ```text
+/// List(U32(xp_per_level) Stats(gains))
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Leveling {
+    /// Xp between one level and the next, or 0 to never level up.
+    pub xp_per_level: u32,
+    /// Added to an entity's stats on every level up.
+    pub gains: Stats,
+}
+
+impl Leveling {
+    pub(crate) const FIELD_LEN: usize = 2 * HEADER_LEN + 4 + HEADER_LEN + 4 * (HEADER_LEN + 1);
+
+    /// The level `xp` is worth.
+    pub fn level(&self, xp: u32) -> u8 {
+        match self.xp_per_level {
+            0 => 1,
+            per_level => u8::try_from((xp / per_level).saturating_add(1)).unwrap_or(u8::MAX),
+        }
+    }
+}
+
+impl Default for Leveling {
+    fn default() -> Self {
+        Self {
+            xp_per_level: 100,
+            gains: Stats {
+                hp: 2,
+                attack: 1,
+                defense: 1,
+                speed: 0,
+            },
+        }
+    }
+}
+
+/// Such as `a level every 100 xp, gaining 2 hp, 1 attack`.
+impl Display for Leveling {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.xp_per_level == 0 {
+            return write!(f, "no leveling");
+        }
+        write!(f, "a level every {} xp, gaining", self.xp_per_level)?;
+        let gains: Vec<_> = self
+            .gains
+            .named()
+            .into_iter()
+            .filter(|(_, gain)| *gain > 0)
+            .map(|(name, gain)| format!(" {gain} {name}"))
+            .collect();
+        match gains.is_empty() {
+            true => write!(f, " nothing"),
+            false => write!(f, "{}", gains.join(",")),
+        }
+    }
+}
+
+impl SerializeField for Leveling {
+    fn serialize_field(&self, writer: &mut FieldWriter<'_>) {
+        writer.write(&(self.xp_per_level, self.gains));
+    }
+}
+
+impl TryFrom<Field<'_>> for Leveling {
+    type Error = Error;
+
+    fn try_from(value: Field<'_>) -> Result<Self> {
+        let (xp_per_level, gains) = value.try_into()?;
+        Ok(Self {
+            xp_per_level,
+            gains,
+        })
+    }
+}
+
+impl Entity {
+    /// Adds `xp`, then levels up as many times as it is now worth.
+    pub(crate) fn earn(&mut self, xp: u32, leveling: &Leveling) -> ActionOutcome {
+        let mut outcome = ActionOutcome::default();
+        let before = self.experience;
+        self.experience.xp = before.xp.saturating_add(xp);
+        outcome.change(&self.name, "xp", before.xp, self.experience.xp);
+        let level = leveling.level(self.experience.xp);
+        if level <= before.level {
+            return outcome;
+        }
+        let stats = self.stats;
+        for _ in before.level..level {
+            for ((_, stat), (_, gain)) in self
+                .stats
+                .named_mut()
+                .into_iter()
+                .zip(leveling.gains.named())
+            {
+                *stat = stat.saturating_add(gain);
+            }
+        }
+        self.experience.level = level;
+        outcome.message(format!("{} reached level {level}", self.name));
+        outcome.change(&self.name, "level", before.level, level);
+        for ((name, before), (_, after)) in stats.named().into_iter().zip(self.stats.named()) {
+            outcome.change(&self.name, name, before, after);
+        }
+        outcome
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Leveling;
+    use crate::actions::{Action, ActionKind};
+    use crate::rules::Rules;
+    use crate::serde::{Deserialize, FieldReader, Serialize};
+    use crate::session::Session;
+    use crate::Entity;
+
+    #[test]
+    fn actions_earn_xp_and_levels() {
+        let mut session = Session::new("game".to_string()).unwrap();
+        session.add_entity(Entity::new("hero".to_string())).unwrap();
+        let mut rules = Rules::default();
+        rules.xp.insert(ActionKind::Inspect, 60);
+        session.set_rules(rules).unwrap();
+        let inspect = || Action::new(ActionKind::Inspect, "hero".to_string()).unwrap();
+
+        let outcome = session.apply(inspect()).unwrap();
+        assert_eq!(
+            outcome.changes.last().unwrap().to_string(),
+            "hero xp: 0 -> 60"
+        );
+        let outcome = session.apply(inspect()).unwrap();
+        assert!(outcome.to_string().contains("hero reached level 2"));
+        let hero = session.entity("hero").unwrap();
+        assert_eq!(
+            (hero.experience.level, hero.stats.hp, hero.stats.attack),
+            (2, 2, 1)
+        );
+
+        let bytes = session.serialize();
+        let copy = Session::deserialize(&mut FieldReader::new(&bytes)).unwrap();
+        assert_eq!(copy.entities(), session.entities());
+        assert_eq!(copy.rules(), session.rules());
+    }
+
+    #[test]
+    fn levels_cap_at_the_highest() {
+        let leveling = Leveling {
+            xp_per_level: 1,
+            ..Leveling::default()
+        };
+        assert_eq!(leveling.level(0), 1);
+        assert_eq!(leveling.level(254), u8::MAX);
+        assert_eq!(leveling.level(u32::MAX), u8::MAX);
+        let mut hero = Entity::new("hero".to_string());
+        hero.earn(u32::MAX, &leveling);
+        hero.earn(1, &leveling);
+        assert_eq!(
+            (hero.experience.xp, hero.experience.level),
+            (u32::MAX, u8::MAX)
+        );
+    }
+}
@@ -0,0 +1,140 @@
+use std::fmt::Display;
+
+use crate::error::{Error, Result};
+use crate::serde::{Field, FieldWriter, SerializeField};
+
+/// How the members of two factions stand towards each other. Members of
+/// one faction are allied, and factions are neutral until set otherwise.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Stance {
+    Hostile,
+    #[default]
+    Neutral,
+    Allied,
+}
+
+impl Stance {
+    pub const ALL: [Stance; 3] = [Self::Hostile, Self::Neutral, Self::Allied];
+
+    pub fn parse(name: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|stance| stance.to_string().eq_ignore_ascii_case(name))
+            .ok_or(Error::InvalidArgs)
+    }
+
+    fn id(&self) -> u8 {
+        match self {
+            Self::Hostile => 0,
+            Self::Neutral => 1,
+            Self::Allied => 2,
+        }
+    }
+}
+
+impl Display for Stance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Hostile => write!(f, "hostile"),
+            Self::Neutral => write!(f, "neutral"),
+            Self::Allied => write!(f, "allied"),
+        }
+    }
+}
+
+impl SerializeField for Stance {
+    fn serialize_field(&self, writer: &mut FieldWriter<'_>) {
+        writer.write_u8(self.id());
+    }
+}
+
+impl TryFrom<Field<'_>> for Stance {
+    type Error = Error;
+
+    fn try_from(value: Field<'_>) -> Result<Self> {
+        let id: u8 = value.try_into()?;
+        Self::ALL
+            .into_iter()
+            .find(|stance| stance.id() == id)
+            .ok_or(Error::InvalidVariant(id))
+    }
+}
+
+/// Faction names follow the rules for attribute names.
+pub fn validate_name(name: &str) -> Result<()> {
+    crate::attributes::validate_name(name).map_err(|_| Error::InvalidFaction(name.to_string()))
+}
+
+/// The two factions of a stance in the order it is stored under, so that
+/// it is the same whichever way round it is given.
+pub(crate) fn pair(a: &str, b: &str) -> (String, String) {
+    match a <= b {
+        true => (a.to_string(), b.to_string()),
+        false => (b.to_string(), a.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Stance;
+    use crate::actions::{Action, ActionKind, Param};
+    use crate::serde::{Deserialize, FieldReader, Serialize};
+    use crate::session::Session;
+    use crate::Entity;
+
+    #[test]
+    fn allies_need_force_to_attack() {
+        let mut session = Session::new("game".to_string()).unwrap();
+        for name in ["knight", "squire", "orc"] {
+            let mut entity = Entity::new(name.to_string());
+            entity.stats.hp = 5;
+            session.add_entity(entity).unwrap();
+        }
+        assert!(session
+            .join_faction("knight", Some("crown".to_string()))
+            .is_err());
+        for faction in ["crown", "horde"] {
+            session.define_faction(faction.to_string()).unwrap();
+        }
+        session
+            .join_faction("knight", Some("crown".to_string()))
+            .unwrap();
+        session
+            .join_faction("squire", Some("crown".to_string()))
+            .unwrap();
+        session
+            .join_faction("orc", Some("horde".to_string()))
+            .unwrap();
+        session
+            .set_stance("horde", "crown", Stance::Hostile)
+            .unwrap();
+
+        let attack = |target: &str, force: bool| {
+            let mut params: crate::actions::Params =
+                [("by".to_string(), Param::Text("kni".to_string()))].into();
+            if force {
+                params.insert("force".to_string(), Param::Int(1));
+            }
+            Action::new(ActionKind::Attack, target.to_string())
+                .unwrap()
+                .with_params(params)
+        };
+        assert_eq!(
+            session
+                .apply(attack("squire", false))
+                .unwrap_err()
+                .to_string(),
+            "knight and squire are allied; force the attack to make it anyway"
+        );
+        session.apply(attack("squire", true)).unwrap();
+        session.apply(attack("orc", false)).unwrap();
+        let knight = session.entity("knight").unwrap();
+        let orc = session.entity("orc").unwrap();
+        assert!(session.hostile(knight, orc));
+
+        let bytes = session.serialize();
+        let copy = Session::deserialize(&mut FieldReader::new(&bytes)).unwrap();
+        assert_eq!(copy.stance("crown", "horde"), Stance::Hostile);
+        assert_eq!(copy.entities(), session.entities());
+    }
+}
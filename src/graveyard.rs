@@ -0,0 +1,107 @@
+use std::collections::BTreeSet;
+use std::fmt::Display;
+
+use uuid::Uuid;
+
+use crate::outcome::ActionOutcome;
+use crate::session::Session;
+use crate::Entity;
+
+/// An entity that died, as it was when it did. Graves are rebuilt by
+/// replaying the log, like the rest of the session's state.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Grave {
+    pub entity: Entity,
+    /// The turn it died on.
+    pub turn: u32,
+}
+
+/// `goblin, died on turn 3`.
+impl Display for Grave {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}, died on turn {}", self.entity.name, self.turn)
+    }
+}
+
+impl Session {
+    /// The ids of the entities with hp left, which die if a resolve takes
+    /// it all. Entities that never had any, such as props, cannot die.
+    pub(crate) fn living(&self) -> BTreeSet<Uuid> {
+        self.entities()
+            .iter()
+            .filter(|entity| entity.stats.hp > 0)
+            .map(|entity| entity.id)
+            .collect()
+    }
+
+    /// The grave of the entity named `name`, if it is dead.
+    pub fn grave(&self, name: &str) -> Option<&Grave> {
+        self.graveyard()
+            .iter()
+            .rev()
+            .find(|grave| grave.entity.name == name)
+    }
+}
+
+/// Takes the entities among `living` that are out of hp out of `entities`,
+/// saying in `outcome` that they died.
+pub(crate) fn bury(
+    entities: &mut Vec<Entity>,
+    living: &BTreeSet<Uuid>,
+    outcome: &mut ActionOutcome,
+) -> Vec<Entity> {
+    let (dead, alive) = std::mem::take(entities)
+        .into_iter()
+        .partition(|entity: &Entity| entity.stats.hp == 0 && living.contains(&entity.id));
+    *entities = alive;
+    for entity in &dead {
+        outcome.message(format!("{} died", entity.name));
+    }
+    dead
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::actions::{Action, ActionKind, Param};
+    use crate::serde::{Deserialize, FieldReader, Serialize};
+    use crate::session::Session;
+    use crate::Entity;
+
+    #[test]
+    fn the_dead_leave_for_the_graveyard() {
+        let mut session = Session::new("game".to_string()).unwrap();
+        let mut hero = Entity::new("hero".to_string());
+        hero.stats.hp = 3;
+        session.add_entity(hero).unwrap();
+        session.add_entity(Entity::new("door".to_string())).unwrap();
+        let attack = |target: &str| {
+            let params = [("damage".to_string(), Param::Int(3))];
+            Action::new(ActionKind::Attack, target.to_string())
+                .unwrap()
+                .with_params(params.into_iter().collect())
+        };
+        session.queue(attack("hero")).unwrap();
+        session.queue(attack("hero")).unwrap();
+        session
+            .queue(Action::new(ActionKind::Inspect, "door".to_string()).unwrap())
+            .unwrap();
+
+        let outcome = session.resolve().unwrap().to_string();
+        assert!(outcome.contains("hero died"));
+        assert!(outcome.contains("skipped attack hero: hero is dead"));
+        assert!(!outcome.contains("door died"));
+        assert_eq!(session.entities().len(), 1);
+        assert_eq!(
+            session.grave("hero").unwrap().to_string(),
+            "hero, died on turn 0"
+        );
+        assert_eq!(
+            session.apply(attack("hero")).unwrap_err().to_string(),
+            "no entity named \"hero\""
+        );
+
+        let bytes = session.serialize();
+        let copy = Session::deserialize(&mut FieldReader::new(&bytes)).unwrap();
+        assert_eq!(copy.graveyard(), session.graveyard());
+    }
+}
@@ -0,0 +1,747 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, OnceLock, RwLock};
+
+use crate::actions::{Action, ActionKind};
+use crate::effects::EffectKind;
+use crate::equipment::Slot;
+use crate::error::{Error, Result};
+use crate::factions::Stance;
+use crate::inventory::Item;
+use crate::map::Direction;
+use crate::outcome::ActionOutcome;
+use crate::script;
+use crate::session::Session;
+use crate::suggest;
+use crate::{Entity, Stats};
+
+/// Carries out one kind of action. Handlers are looked up in a process-wide
+/// registry, so a new kind of behavior can live in its own module or crate:
+/// register it with `register` before any session is loaded.
+pub trait ActionHandler: Send + Sync {
+    fn kind(&self) -> ActionKind;
+
+    /// The name a `Custom` kind is shown and typed as. Built-in kinds have
+    /// their own.
+    fn name(&self) -> Option<&str> {
+        None
+    }
+
+    /// What `action` takes for itself alone, such as a tile or an item.
+    /// Queued actions claiming the same thing conflict, and only one of
+    /// them is resolved. By default this is the `claim` parameter.
+    fn claim(&self, action: &Action) -> Option<String> {
+        action.params().get("claim").map(ToString::to_string)
+    }
+
+    /// Checks that `action` is legal in `session`. Its target is known to
+    /// exist and the session's rules to allow it.
+    fn validate(&self, action: &Action, session: &Session) -> Result<()>;
+
+    /// Carries out `action`, which has been validated. This runs again
+    /// whenever the log is replayed, so it must only depend on the action
+    /// and `context`; amounts should saturate rather than fail.
+    fn apply(&self, action: &Action, context: Context<'_>) -> Result<ActionOutcome>;
+}
+
+/// What a handler may see and change while applying an action.
+pub struct Context<'a> {
+    pub entities: &'a mut [Entity],
+    /// Source of each scripted action kind, by name.
+    pub scripts: &'a BTreeMap<String, String>,
+}
+
+impl Context<'_> {
+    /// The index of the entity called `name`.
+    pub fn position(&self, name: &str) -> Result<usize> {
+        self.entities
+            .iter()
+            .position(|entity| entity.name == name)
+            .ok_or_else(|| Error::EntityNotFound(name.to_string()))
+    }
+}
+
+type Registry = RwLock<BTreeMap<ActionKind, Arc<dyn ActionHandler>>>;
+
+fn registry() -> &'static Registry {
+    static REGISTRY: OnceLock<Registry> = OnceLock::new();
+    REGISTRY.get_or_init(|| {
+        let builtin: [Arc<dyn ActionHandler>; 17] = [
+            Arc::new(Points(ActionKind::Fight)),
+            Arc::new(Love),
+            Arc::new(Neutral),
+            Arc::new(Attack),
+            Arc::new(Defend),
+            Arc::new(Trade),
+            Arc::new(Points(ActionKind::Rest)),
+            Arc::new(Inspect),
+            Arc::new(Say),
+            Arc::new(Script),
+            Arc::new(Give),
+            Arc::new(Items(ActionKind::Drop)),
+            Arc::new(Items(ActionKind::Use)),
+            Arc::new(Transfer),
+            Arc::new(Move),
+            Arc::new(Equip),
+            Arc::new(Unequip),
+        ];
+        RwLock::new(
+            builtin
+                .into_iter()
+                .map(|handler| (handler.kind(), handler))
+                .collect(),
+        )
+    })
+}
+
+/// Makes `handler` carry out every action of its kind from now on, in
+/// place of the handler it replaces.
+pub fn register(handler: impl ActionHandler + 'static) {
+    registry()
+        .write()
+        .unwrap_or_else(|err| err.into_inner())
+        .insert(handler.kind(), Arc::new(handler));
+}
+
+/// The handler for actions of `kind`, which only custom kinds may lack.
+pub fn handler(kind: ActionKind) -> Option<Arc<dyn ActionHandler>> {
+    let handlers = registry().read().unwrap_or_else(|err| err.into_inner());
+    handlers.get(&kind).cloned()
+}
+
+/// The custom kind registered under `name`.
+pub fn find(name: &str) -> Option<ActionKind> {
+    let handlers = registry().read().unwrap_or_else(|err| err.into_inner());
+    handlers
+        .values()
+        .find(|handler| handler.name() == Some(name))
+        .map(|handler| handler.kind())
+}
+
+fn illegal(reason: String) -> Result<()> {
+    Err(Error::IllegalAction(reason))
+}
+
+fn target<'a>(action: &Action, session: &'a Session) -> Result<&'a Entity> {
+    session
+        .entity(action.target())
+        .ok_or_else(|| Error::EntityNotFound(action.target().to_string()))
+}
+
+/// Fighting and resting both add a hit point, up to 255.
+struct Points(ActionKind);
+
+impl ActionHandler for Points {
+    fn kind(&self) -> ActionKind {
+        self.0
+    }
+
+    fn validate(&self, action: &Action, session: &Session) -> Result<()> {
+        let target = target(action, session)?;
+        if self.0 == ActionKind::Rest && target.stats.hp == u8::MAX {
+            return illegal(format!("{} is already fully rested", target.name));
+        }
+        Ok(())
+    }
+
+    fn apply(&self, action: &Action, context: Context<'_>) -> Result<ActionOutcome> {
+        let entity = &mut context.entities[context.position(action.target())?];
+        let before = entity.stats.hp;
+        entity.stats.hp = before.saturating_add(1);
+        let verb = match self.0 {
+            ActionKind::Fight => "fought",
+            _ => "rested",
+        };
+        let mut outcome = ActionOutcome::default();
+        outcome.message(format!("{verb} {}", entity.name));
+        outcome.change(&entity.name, "hp", before, entity.stats.hp);
+        Ok(outcome)
+    }
+}
+
+struct Love;
+
+impl ActionHandler for Love {
+    fn kind(&self) -> ActionKind {
+        ActionKind::Love
+    }
+
+    fn validate(&self, _action: &Action, _session: &Session) -> Result<()> {
+        Ok(())
+    }
+
+    fn apply(&self, action: &Action, context: Context<'_>) -> Result<ActionOutcome> {
+        let entity = &mut context.entities[context.position(action.target())?];
+        let before = entity.guarding;
+        entity.guarding = true;
+        let mut outcome = ActionOutcome::default();
+        outcome.message(format!("loved {}", entity.name));
+        outcome.change(&entity.name, "guarding", before, entity.guarding);
+        Ok(outcome)
+    }
+}
+
+struct Neutral;
+
+impl ActionHandler for Neutral {
+    fn kind(&self) -> ActionKind {
+        ActionKind::Neutral
+    }
+
+    fn validate(&self, _action: &Action, _session: &Session) -> Result<()> {
+        Ok(())
+    }
+
+    fn apply(&self, action: &Action, _context: Context<'_>) -> Result<ActionOutcome> {
+        let mut outcome = ActionOutcome::default();
+        outcome.message(format!("nothing happened to {}", action.target()));
+        Ok(outcome)
+    }
+}
+
+/// Takes `damage` hp, half as much from a guarding target, which drops
+/// its guard. The weapon of the attacker `by`, if given, adds to the
+/// damage and the target's armor takes from it.
+struct Attack;
+
+impl ActionHandler for Attack {
+    fn kind(&self) -> ActionKind {
+        ActionKind::Attack
+    }
+
+    fn validate(&self, action: &Action, session: &Session) -> Result<()> {
+        action.amount("damage")?;
+        let target = target(action, session)?;
+        if target.stats.hp == 0 {
+            return illegal(format!("{} has nothing left to lose", target.name));
+        }
+        if let Some(by) = action.params().get("by").map(ToString::to_string) {
+            let attacker = session.entity(&by).ok_or(Error::EntityNotFound(by))?;
+            let forced = action.params().contains_key("force");
+            if session.allegiance(attacker, target) == Some(Stance::Allied) && !forced {
+                return illegal(format!(
+                    "{} and {} are allied; force the attack to make it anyway",
+                    attacker.name, target.name
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn apply(&self, action: &Action, context: Context<'_>) -> Result<ActionOutcome> {
+        let mut damage = action.amount("damage")?;
+        if let Some(by) = action.params().get("by") {
+            let attacker = &context.entities[context.position(&by.to_string())?];
+            damage = damage.saturating_add(attacker.modifiers().attack);
+        }
+        let entity = &mut context.entities[context.position(action.target())?];
+        damage = damage.saturating_sub(entity.modifiers().defense);
+        let mut outcome = ActionOutcome::default();
+        if entity.is(EffectKind::Shielded) {
+            damage = 0;
+        } else if entity.guarding {
+            damage /= 2;
+            entity.guarding = false;
+            outcome.change(&entity.name, "guarding", true, false);
+        }
+        let before = entity.stats.hp;
+        entity.stats.hp = before.saturating_sub(damage);
+        outcome.message(format!("attacked {} for {damage}", entity.name));
+        outcome.change(&entity.name, "hp", before, entity.stats.hp);
+        Ok(outcome)
+    }
+}
+
+struct Defend;
+
+impl ActionHandler for Defend {
+    fn kind(&self) -> ActionKind {
+        ActionKind::Defend
+    }
+
+    fn validate(&self, action: &Action, session: &Session) -> Result<()> {
+        let target = target(action, session)?;
+        if target.guarding {
+            return illegal(format!("{} is already guarding", target.name));
+        }
+        Ok(())
+    }
+
+    fn apply(&self, action: &Action, context: Context<'_>) -> Result<ActionOutcome> {
+        let entity = &mut context.entities[context.position(action.target())?];
+        let before = entity.guarding;
+        entity.guarding = true;
+        let mut outcome = ActionOutcome::default();
+        outcome.message(format!("{} is guarding", entity.name));
+        outcome.change(&entity.name, "guarding", before, entity.guarding);
+        Ok(outcome)
+    }
+}
+
+/// Moves `amount` hp from the target to the entity `with`.
+struct Trade;
+
+impl ActionHandler for Trade {
+    fn kind(&self) -> ActionKind {
+        ActionKind::Trade
+    }
+
+    fn validate(&self, action: &Action, session: &Session) -> Result<()> {
+        let amount = action.amount("amount")?;
+        let target = target(action, session)?;
+        let with = action.text("with")?;
+        let other = session.entity(&with).ok_or(Error::EntityNotFound(with))?;
+        if other.name == target.name {
+            return illegal(format!("{} cannot trade with itself", target.name));
+        }
+        if session.hostile(target, other) {
+            return illegal(format!(
+                "{} will not trade with its enemy {}",
+                target.name, other.name
+            ));
+        }
+        if target.stats.hp < amount {
+            return illegal(format!(
+                "{} has only {} to trade, not {amount}",
+                target.name, target.stats.hp
+            ));
+        }
+        if other.stats.hp.checked_add(amount).is_none() {
+            return illegal(format!("{} cannot hold {amount} more", other.name));
+        }
+        Ok(())
+    }
+
+    fn apply(&self, action: &Action, context: Context<'_>) -> Result<ActionOutcome> {
+        let amount = action.amount("amount")?;
+        let index = context.position(action.target())?;
+        let other = context.position(&action.text("with")?)?;
+        let entities = context.entities;
+        let mut outcome = ActionOutcome::default();
+        outcome.message(format!(
+            "{} gave {amount} to {}",
+            action.target(),
+            entities[other].name
+        ));
+        for (index, new) in [
+            (index, entities[index].stats.hp.saturating_sub(amount)),
+            (other, entities[other].stats.hp.saturating_add(amount)),
+        ] {
+            let entity = &mut entities[index];
+            outcome.change(&entity.name, "hp", entity.stats.hp, new);
+            entity.stats.hp = new;
+        }
+        Ok(outcome)
+    }
+}
+
+struct Inspect;
+
+impl ActionHandler for Inspect {
+    fn kind(&self) -> ActionKind {
+        ActionKind::Inspect
+    }
+
+    fn validate(&self, _action: &Action, _session: &Session) -> Result<()> {
+        Ok(())
+    }
+
+    fn apply(&self, action: &Action, context: Context<'_>) -> Result<ActionOutcome> {
+        let mut outcome = ActionOutcome::default();
+        outcome.message(context.entities[context.position(action.target())?].to_string());
+        Ok(outcome)
+    }
+}
+
+struct Say;
+
+impl ActionHandler for Say {
+    fn kind(&self) -> ActionKind {
+        ActionKind::Say
+    }
+
+    fn validate(&self, action: &Action, _session: &Session) -> Result<()> {
+        if action.text("text")?.trim().is_empty() {
+            return illegal("say needs something to say".to_string());
+        }
+        Ok(())
+    }
+
+    fn apply(&self, action: &Action, _context: Context<'_>) -> Result<ActionOutcome> {
+        let mut outcome = ActionOutcome::default();
+        outcome.message(format!(
+            "said to {}: {}",
+            action.target(),
+            action.text("text")?
+        ));
+        Ok(outcome)
+    }
+}
+
+/// The `item` and `count` parameters of an item action.
+fn item(action: &Action) -> Result<(String, u32)> {
+    let item = action.text("item")?;
+    if item.trim().is_empty() {
+        return Err(Error::IllegalAction(format!(
+            "{} needs an item to act on",
+            action.kind()
+        )));
+    }
+    Ok((item, u32::from(action.amount("count")?)))
+}
+
+/// Checks that `entity` carries at least `count` of `item`.
+fn carries(entity: &Entity, item: &str, count: u32) -> Result<()> {
+    match entity.count(item) {
+        0 => illegal(format!("{} has no {item}", entity.name)),
+        have if have < count => illegal(format!("{} has only {have} {item}", entity.name)),
+        _ => Ok(()),
+    }
+}
+
+/// The item a give makes: equippable in `slot` with any of `hp`,
+/// `attack`, `defense` and `speed` as modifiers, if `slot` is given.
+fn made_item(action: &Action) -> Result<Item> {
+    let (name, count) = item(action)?;
+    let mut item = Item::new(name, count);
+    if !action.params().contains_key("slot") {
+        return Ok(item);
+    }
+    item.slot = Some(slot(action)?);
+    let mut modifiers = Stats::default();
+    for (name, modifier) in modifiers.named_mut() {
+        if action.params().contains_key(name) {
+            *modifier = action.amount(name)?;
+        }
+    }
+    item.modifiers = modifiers;
+    Ok(item)
+}
+
+/// Gives the target `count` of `item`, out of nowhere. See `made_item`.
+struct Give;
+
+impl ActionHandler for Give {
+    fn kind(&self) -> ActionKind {
+        ActionKind::Give
+    }
+
+    fn validate(&self, action: &Action, _session: &Session) -> Result<()> {
+        made_item(action).map(drop)
+    }
+
+    fn apply(&self, action: &Action, context: Context<'_>) -> Result<ActionOutcome> {
+        let made = made_item(action)?;
+        let (item, count) = (made.name.clone(), made.count);
+        let entity = &mut context.entities[context.position(action.target())?];
+        let before = entity.count(&item);
+        entity.stow(made);
+        let mut outcome = ActionOutcome::default();
+        outcome.message(format!("gave {} {count} {item}", entity.name));
+        outcome.change(&entity.name, &item, before, entity.count(&item));
+        Ok(outcome)
+    }
+}
+
+/// Dropping loses `count` of `item`; using one uses it up.
+struct Items(ActionKind);
+
+impl ActionHandler for Items {
+    fn kind(&self) -> ActionKind {
+        self.0
+    }
+
+    fn validate(&self, action: &Action, session: &Session) -> Result<()> {
+        let (item, count) = item(action)?;
+        carries(target(action, session)?, &item, count)
+    }
+
+    fn apply(&self, action: &Action, context: Context<'_>) -> Result<ActionOutcome> {
+        let (item, count) = item(action)?;
+        let entity = &mut context.entities[context.position(action.target())?];
+        let before = entity.count(&item);
+        entity.remove_item(&item, count);
+        let verb = match self.0 {
+            ActionKind::Use => "used",
+            _ => "dropped",
+        };
+        let mut outcome = ActionOutcome::default();
+        outcome.message(format!("{} {verb} {count} {item}", entity.name));
+        outcome.change(&entity.name, &item, before, entity.count(&item));
+        Ok(outcome)
+    }
+}
+
+/// Moves `count` of `item` from the target to the entity `with`.
+struct Transfer;
+
+impl ActionHandler for Transfer {
+    fn kind(&self) -> ActionKind {
+        ActionKind::Transfer
+    }
+
+    fn validate(&self, action: &Action, session: &Session) -> Result<()> {
+        let (item, count) = item(action)?;
+        let target = target(action, session)?;
+        let with = action.text("with")?;
+        let other = session.entity(&with).ok_or(Error::EntityNotFound(with))?;
+        if other.name == target.name {
+            return illegal(format!("{} cannot transfer to itself", target.name));
+        }
+        if session.hostile(target, other) {
+            return illegal(format!(
+                "{} will not hand anything to its enemy {}",
+                target.name, other.name
+            ));
+        }
+        carries(target, &item, count)
+    }
+
+    fn apply(&self, action: &Action, context: Context<'_>) -> Result<ActionOutcome> {
+        let (item, count) = item(action)?;
+        let index = context.position(action.target())?;
+        let other = context.position(&action.text("with")?)?;
+        let entities = context.entities;
+        let mut outcome = ActionOutcome::default();
+        outcome.message(format!(
+            "{} handed {count} {item} to {}",
+            action.target(),
+            entities[other].name
+        ));
+        let handed = entities[index].item(&item).map(|stack| Item {
+            count,
+            ..stack.clone()
+        });
+        for (index, added) in [(index, false), (other, true)] {
+            let entity = &mut entities[index];
+            let before = entity.count(&item);
+            match (added, &handed) {
+                (true, Some(handed)) => entity.stow(handed.clone()),
+                (true, None) => {}
+                (false, _) => entity.remove_item(&item, count),
+            }
+            outcome.change(&entity.name, &item, before, entity.count(&item));
+        }
+        Ok(outcome)
+    }
+}
+
+/// The `direction` parameter of a move.
+fn direction(action: &Action) -> Result<Direction> {
+    let direction = action.text("direction")?;
+    Direction::parse(&direction).ok_or_else(|| {
+        Error::IllegalAction(format!(
+            "{direction:?} is not a direction; use north, south, east or west"
+        ))
+    })
+}
+
+/// Moves the target `steps` squares, 1 by default, in `direction`.
+struct Move;
+
+impl ActionHandler for Move {
+    fn kind(&self) -> ActionKind {
+        ActionKind::Move
+    }
+
+    fn validate(&self, action: &Action, session: &Session) -> Result<()> {
+        let direction = direction(action)?;
+        let steps = action.amount("steps")?;
+        let target = target(action, session)?;
+        let Some(from) = target.position else {
+            return illegal(format!("{} is not on the map", target.name));
+        };
+        let to = direction.step(from, steps);
+        if let Some(map) = session.map() {
+            if !to.is_some_and(|to| map.contains(to)) {
+                return illegal(format!(
+                    "{} cannot move {steps} {direction} from {from}: it is the edge of the {map} map",
+                    target.name
+                ));
+            }
+        } else if to.is_none() {
+            return illegal(format!("{} cannot move that far", target.name));
+        }
+        Ok(())
+    }
+
+    fn apply(&self, action: &Action, context: Context<'_>) -> Result<ActionOutcome> {
+        let direction = direction(action)?;
+        let steps = action.amount("steps")?;
+        let entity = &mut context.entities[context.position(action.target())?];
+        let mut outcome = ActionOutcome::default();
+        let Some(from) = entity.position else {
+            return Ok(outcome);
+        };
+        let to = direction.step(from, steps).unwrap_or(from);
+        entity.position = Some(to);
+        outcome.message(format!("{} moved {steps} {direction}", entity.name));
+        outcome.change(&entity.name, "position", from, to);
+        Ok(outcome)
+    }
+}
+
+/// Puts on one of the target's `item`, which must be made for a slot.
+struct Equip;
+
+impl ActionHandler for Equip {
+    fn kind(&self) -> ActionKind {
+        ActionKind::Equip
+    }
+
+    fn validate(&self, action: &Action, session: &Session) -> Result<()> {
+        let item = action.text("item")?;
+        let target = target(action, session)?;
+        carries(target, &item, 1)?;
+        match target.item(&item).and_then(|stack| stack.slot) {
+            Some(_) => Ok(()),
+            None => illegal(format!("{item} cannot be equipped")),
+        }
+    }
+
+    fn apply(&self, action: &Action, context: Context<'_>) -> Result<ActionOutcome> {
+        let item = action.text("item")?;
+        let entity = &mut context.entities[context.position(action.target())?];
+        Ok(entity.equip(&item))
+    }
+}
+
+/// The `slot` parameter of a give or unequip.
+fn slot(action: &Action) -> Result<Slot> {
+    let slot = action.text("slot")?;
+    Slot::parse(&slot).map_err(|_| {
+        Error::IllegalAction(format!(
+            "{slot:?} is not a slot; use weapon, armor or trinket"
+        ))
+    })
+}
+
+/// Takes off what the target has in `slot`, back into its inventory.
+struct Unequip;
+
+impl ActionHandler for Unequip {
+    fn kind(&self) -> ActionKind {
+        ActionKind::Unequip
+    }
+
+    fn validate(&self, action: &Action, session: &Session) -> Result<()> {
+        let slot = slot(action)?;
+        let target = target(action, session)?;
+        if !target.equipment.contains_key(&slot) {
+            return illegal(format!("{} has no {slot} equipped", target.name));
+        }
+        Ok(())
+    }
+
+    fn apply(&self, action: &Action, context: Context<'_>) -> Result<ActionOutcome> {
+        let slot = slot(action)?;
+        let entity = &mut context.entities[context.position(action.target())?];
+        let mut outcome = ActionOutcome::default();
+        if let Some(item) = entity.unequip(slot) {
+            outcome.message(format!("{} took off {item}", entity.name));
+            outcome.change(&entity.name, &slot.to_string(), item, "nothing".to_string());
+        }
+        Ok(outcome)
+    }
+}
+
+/// Runs the session's script named by the `script` parameter.
+struct Script;
+
+impl ActionHandler for Script {
+    fn kind(&self) -> ActionKind {
+        ActionKind::Script
+    }
+
+    fn validate(&self, action: &Action, session: &Session) -> Result<()> {
+        let name = action.text("script")?;
+        if !session.scripts().contains_key(&name) {
+            // Most likely a built-in kind mistyped.
+            let kinds: Vec<_> = ActionKind::ALL
+                .iter()
+                .filter(|kind| **kind != ActionKind::Script)
+                .map(ToString::to_string)
+                .collect();
+            let known = kinds.iter().chain(session.scripts().keys());
+            let err = Error::ScriptNotFound(name.clone());
+            return Err(suggest::did_you_mean(err, &name, known.map(String::as_str)));
+        }
+        Ok(())
+    }
+
+    fn apply(&self, action: &Action, context: Context<'_>) -> Result<ActionOutcome> {
+        let name = action.text("script")?;
+        let source = context
+            .scripts
+            .get(&name)
+            .ok_or(Error::ScriptNotFound(name))?;
+        script::run(source, action, context.entities)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{register, ActionHandler, Context};
+    use crate::actions::{Action, ActionKind};
+    use crate::error::{Error, Result};
+    use crate::log::Event;
+    use crate::outcome::ActionOutcome;
+    use crate::serde::{Deserialize, FieldReader, Serialize};
+    use crate::session::Session;
+    use crate::Entity;
+
+    /// Behaves like the built-in handler except towards bystanders, so
+    /// tests running alongside do not notice it.
+    struct Shy;
+
+    impl ActionHandler for Shy {
+        fn kind(&self) -> ActionKind {
+            ActionKind::Neutral
+        }
+
+        fn validate(&self, action: &Action, _session: &Session) -> Result<()> {
+            match action.target() {
+                "bystander" => Err(Error::IllegalAction("leave the bystander be".to_string())),
+                _ => Ok(()),
+            }
+        }
+
+        fn apply(&self, action: &Action, _context: Context<'_>) -> Result<ActionOutcome> {
+            let mut outcome = ActionOutcome::default();
+            outcome.message(format!("nothing happened to {}", action.target()));
+            Ok(outcome)
+        }
+    }
+
+    #[test]
+    fn unknown_kinds_are_kept_but_not_applied() {
+        let mut session = Session::new("game".to_string()).unwrap();
+        session.add_entity(Entity::new("hero".to_string())).unwrap();
+        let unknown = Action::new(ActionKind::Custom(250), "hero".to_string()).unwrap();
+        assert!(matches!(
+            session.apply(unknown.clone()),
+            Err(Error::UnknownActionKind(250))
+        ));
+
+        // As if written by a newer binary that knows the kind.
+        session.record(Event::Act(unknown.clone())).unwrap();
+        let bytes = session.serialize();
+        let copy = Session::deserialize(&mut FieldReader::new(&bytes)).unwrap();
+        assert_eq!(copy.action(), Some(&unknown));
+        assert_eq!(copy.serialize(), bytes);
+        assert!(copy.log()[1].to_string().starts_with("kind250 hero"));
+    }
+
+    #[test]
+    fn registered_handlers_replace_built_in_ones() {
+        let mut session = Session::new("game".to_string()).unwrap();
+        session
+            .add_entity(Entity::new("bystander".to_string()))
+            .unwrap();
+        let nudge = || Action::new(ActionKind::Neutral, "bystander".to_string()).unwrap();
+        session.apply(nudge()).unwrap();
+
+        register(Shy);
+        let err = session.apply(nudge()).unwrap_err();
+        assert_eq!(err.to_string(), "leave the bystander be");
+    }
+}
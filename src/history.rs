@@ -0,0 +1,124 @@
+use std::fmt::Display;
+
+use crate::actions::ActionKind;
+use crate::error::Result;
+use crate::log::Event;
+use crate::outcome::ActionOutcome;
+use crate::session::Session;
+
+/// Which actions `history` returns. Every condition given must hold.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Filter {
+    /// Only actions of this kind, which leaves out resolves.
+    pub kind: Option<ActionKind>,
+    /// Only entries acting on or changing the entity with this name.
+    pub entity: Option<String>,
+    /// Only the most recent this many of the entries that match.
+    pub last: Option<usize>,
+}
+
+impl Filter {
+    fn matches(&self, event: &Event, outcome: &ActionOutcome) -> bool {
+        let action = event.action();
+        if let Some(kind) = self.kind {
+            if action.map(|action| action.kind()) != Some(kind) {
+                return false;
+            }
+        }
+        match &self.entity {
+            Some(name) => {
+                action.is_some_and(|action| action.target() == name)
+                    || outcome.changes.iter().any(|change| change.entity == *name)
+            }
+            None => true,
+        }
+    }
+}
+
+/// An action taken or a turn resolved, with what it did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// Position in the log, counting from 1 as `log` shows it.
+    pub number: usize,
+    pub event: Event,
+    pub outcome: ActionOutcome,
+}
+
+/// The event as `log` shows it, then its outcome indented below.
+impl Display for Entry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{:>4}  {}", self.number, self.event)?;
+        for line in self.outcome.to_string().lines() {
+            writeln!(f, "      {line}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The actions and resolves in `session`'s log that match `filter`, oldest
+/// first. Outcomes are not stored, so the log is replayed to find them.
+pub fn history(session: &Session, filter: &Filter) -> Result<Vec<Entry>> {
+    let mut replay = session.at(0)?;
+    let mut entries = vec![];
+    for (n, event) in session.log().iter().enumerate() {
+        let outcome = replay.record(event.clone())?;
+        let shown = event.action().is_some() || matches!(event, Event::Resolve(_));
+        if shown && filter.matches(event, &outcome) {
+            entries.push(Entry {
+                number: n + 1,
+                event: event.clone(),
+                outcome,
+            });
+        }
+    }
+    if let Some(last) = filter.last {
+        entries.drain(..entries.len().saturating_sub(last));
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{history, Filter};
+    use crate::actions::{Action, ActionKind};
+    use crate::session::Session;
+    use crate::Entity;
+
+    #[test]
+    fn history_filters_actions() {
+        let mut session = Session::new("game".to_string()).unwrap();
+        session.add_entity(Entity::new("hero".to_string())).unwrap();
+        session
+            .add_entity(Entity::new("goblin".to_string()))
+            .unwrap();
+        let action = |kind, target: &str| Action::new(kind, target.to_string()).unwrap();
+        session.apply(action(ActionKind::Rest, "hero")).unwrap();
+        session.apply(action(ActionKind::Rest, "goblin")).unwrap();
+        session.apply(action(ActionKind::Attack, "goblin")).unwrap();
+        session.queue(action(ActionKind::Rest, "goblin")).unwrap();
+        session.resolve().unwrap();
+
+        let numbers = |filter: Filter| -> Vec<usize> {
+            let entries = history(&session, &filter).unwrap();
+            entries.iter().map(|entry| entry.number).collect()
+        };
+        assert_eq!(numbers(Filter::default()), [3, 4, 5, 7]);
+        let rests = Filter {
+            kind: Some(ActionKind::Rest),
+            ..Filter::default()
+        };
+        assert_eq!(numbers(rests), [3, 4]);
+        let goblin = Filter {
+            entity: Some("goblin".to_string()),
+            last: Some(2),
+            ..Filter::default()
+        };
+        assert_eq!(numbers(goblin), [5, 7]);
+
+        let entries = history(&session, &Filter::default()).unwrap();
+        assert_eq!(
+            entries[2].outcome.changes[0].to_string(),
+            "goblin hp: 1 -> 0"
+        );
+    }
+}
@@ -0,0 +1,185 @@
+use std::fmt::Display;
+
+use crate::equipment::{self, Slot};
+use crate::error::{Error, Result};
+use crate::serde::{Field, FieldWriter, Serialize, SerializeField, HEADER_LEN};
+use crate::{Entity, Stats};
+
+/// A stack of identical items an entity carries. Written as:
+///
+/// ```text
+/// List(Str(name) U32(count) U8(slot) Stats(modifiers))
+/// ```
+///
+/// with a slot of 0 for items that cannot be equipped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Item {
+    pub name: String,
+    pub count: u32,
+    /// Where the item goes when equipped, if it can be.
+    pub slot: Option<Slot>,
+    /// What the item adds to the stats of an entity that has it equipped.
+    pub modifiers: Stats,
+}
+
+impl Item {
+    /// `count` of an item that cannot be equipped.
+    pub fn new(name: String, count: u32) -> Self {
+        Self {
+            name,
+            count,
+            slot: None,
+            modifiers: Stats::default(),
+        }
+    }
+
+    /// Encoded size of the item as a field, header included.
+    pub(crate) fn field_len(&self) -> usize {
+        5 * HEADER_LEN + self.name.len() + 4 + 1 + 4 * (HEADER_LEN + 1)
+    }
+}
+
+/// `rope`, `3 rope` for more than one, or `sword (weapon, +2 attack)`.
+impl Display for Item {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.count {
+            1 => write!(f, "{}", self.name)?,
+            count => write!(f, "{count} {}", self.name)?,
+        }
+        if let Some(slot) = self.slot {
+            write!(f, " ({slot}")?;
+            match equipment::describe(&self.modifiers).as_str() {
+                "" => write!(f, ")")?,
+                bonuses => write!(f, ", {bonuses})")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for Item {
+    fn serialize_to(&self, writer: &mut FieldWriter<'_>) {
+        writer.write_str(&self.name);
+        writer.write_u32(self.count);
+        writer.write_u8(self.slot.map_or(0, |slot| slot.id()));
+        writer.write(&self.modifiers);
+    }
+
+    fn size_hint(&self) -> usize {
+        self.field_len() - HEADER_LEN
+    }
+}
+
+impl SerializeField for Item {
+    fn serialize_field(&self, writer: &mut FieldWriter<'_>) {
+        writer.write_list(self);
+    }
+}
+
+impl TryFrom<Field<'_>> for Item {
+    type Error = Error;
+
+    fn try_from(value: Field<'_>) -> Result<Self> {
+        let (name, count, slot, modifiers): (_, _, u8, _) = value.try_into()?;
+        let slot = match slot {
+            0 => None,
+            id => Some(Slot::from_id(id)?),
+        };
+        Ok(Self {
+            name,
+            count,
+            slot,
+            modifiers,
+        })
+    }
+}
+
+impl Entity {
+    /// How many of `item` the entity carries.
+    pub fn count(&self, item: &str) -> u32 {
+        self.inventory
+            .iter()
+            .find(|stack| stack.name == item)
+            .map_or(0, |stack| stack.count)
+    }
+
+    /// Adds `item` to the entity's stack of the same name, which keeps its
+    /// own slot and modifiers, or else as a new stack at the end.
+    pub(crate) fn stow(&mut self, item: Item) {
+        match self
+            .inventory
+            .iter_mut()
+            .find(|stack| stack.name == item.name)
+        {
+            Some(stack) => stack.count = stack.count.saturating_add(item.count),
+            None => self.inventory.push(item),
+        }
+    }
+
+    /// The entity's stack of `item`, if it carries any.
+    pub fn item(&self, item: &str) -> Option<&Item> {
+        self.inventory.iter().find(|stack| stack.name == item)
+    }
+
+    /// Takes up to `count` of `item`, dropping the stack once it is empty.
+    pub(crate) fn remove_item(&mut self, item: &str, count: u32) {
+        if let Some(index) = self.inventory.iter().position(|stack| stack.name == item) {
+            let stack = &mut self.inventory[index];
+            stack.count = stack.count.saturating_sub(count);
+            if stack.count == 0 {
+                self.inventory.remove(index);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::actions::{Action, ActionKind, Param};
+    use crate::session::Session;
+    use crate::Entity;
+
+    #[test]
+    fn items_change_hands() {
+        let mut session = Session::new("market".to_string()).unwrap();
+        session
+            .add_entity(Entity::new("alice".to_string()))
+            .unwrap();
+        session.add_entity(Entity::new("bob".to_string())).unwrap();
+        let mut act = |kind, target: &str, params: &[(&str, &str)]| {
+            let params = params
+                .iter()
+                .map(|(key, value)| (key.to_string(), Param::parse(value)))
+                .collect();
+            let action = Action::new(kind, target.to_string()).unwrap();
+            session.apply(action.with_params(params))
+        };
+
+        act(
+            ActionKind::Give,
+            "alice",
+            &[("item", "apple"), ("count", "3")],
+        )
+        .unwrap();
+        let outcome = act(
+            ActionKind::Transfer,
+            "alice",
+            &[("item", "apple"), ("count", "2"), ("with", "bob")],
+        )
+        .unwrap();
+        assert_eq!(outcome.messages, ["alice handed 2 apple to bob"]);
+        act(ActionKind::Use, "alice", &[("item", "apple")]).unwrap();
+        let err = act(ActionKind::Drop, "alice", &[("item", "apple")]).unwrap_err();
+        assert_eq!(err.to_string(), "alice has no apple");
+        let err = act(ActionKind::Use, "bob", &[("item", "apple"), ("count", "3")]).unwrap_err();
+        assert_eq!(err.to_string(), "bob has only 2 apple");
+
+        let alice = session.entity("alice").unwrap();
+        assert!(alice.inventory.is_empty());
+        let bob = session.entity("bob").unwrap();
+        assert_eq!(
+            bob.to_string(),
+            "bob (hp 0, attack 0, defense 0, speed 0) carrying 2 apple"
+        );
+    }
+}
@@ -0,0 +1,530 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+
+use crate::actions::{Action, Param};
+use crate::attributes::AttributeValue;
+use crate::error::Error;
+use crate::graveyard::Grave;
+use crate::history::Entry;
+use crate::inventory::Item;
+use crate::lobby::OpenGame;
+use crate::mailbox::{Body, Letter};
+use crate::outcome::{ActionOutcome, DiceRoll, StateChange};
+use crate::players::Player;
+use crate::replay::Replay;
+use crate::resources::Resources;
+use crate::rules::Rules;
+use crate::session::Session;
+use crate::sync::Synced;
+use crate::turnfile::Merged;
+use crate::turns::TurnOrder;
+use crate::version::Version;
+use crate::{stats, Entity, Stats};
+
+/// A JSON value, as `--json` prints it. Objects keep their keys in the
+/// order they were given.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Int(i128),
+    /// Printed as `null` unless finite.
+    Float(f64),
+    Str(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn object<const N: usize>(fields: [(&str, Json); N]) -> Self {
+        let fields = fields
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect();
+        Self::Object(fields)
+    }
+
+    /// `{"message": message}`, for commands that only report what they did.
+    pub fn message(message: impl Display) -> Self {
+        Self::object([("message", message.to_string().to_json())])
+    }
+
+    /// `{"error": message, "code": exit code}`.
+    pub fn error(err: &Error) -> Self {
+        Self::object([
+            ("error", err.to_string().to_json()),
+            ("code", err.exit_code().to_json()),
+        ])
+    }
+}
+
+/// On one line, with no spaces.
+impl Display for Json {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Null => write!(f, "null"),
+            Self::Bool(value) => write!(f, "{value}"),
+            Self::Int(value) => write!(f, "{value}"),
+            Self::Float(value) if value.is_finite() => write!(f, "{value:?}"),
+            Self::Float(_) => write!(f, "null"),
+            Self::Str(value) => write_str(f, value),
+            Self::Array(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{value}")?;
+                }
+                write!(f, "]")
+            }
+            Self::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_str(f, key)?;
+                    write!(f, ":{value}")?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_str(f: &mut std::fmt::Formatter<'_>, value: &str) -> std::fmt::Result {
+    write!(f, "\"")?;
+    for c in value.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if c < ' ' => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{c}")?,
+        }
+    }
+    write!(f, "\"")
+}
+
+/// Something `--json` can print.
+pub trait ToJson {
+    fn to_json(&self) -> Json;
+}
+
+impl ToJson for Json {
+    fn to_json(&self) -> Json {
+        self.clone()
+    }
+}
+
+impl<T: ToJson + ?Sized> ToJson for &T {
+    fn to_json(&self) -> Json {
+        (**self).to_json()
+    }
+}
+
+impl<T: ToJson> ToJson for [T] {
+    fn to_json(&self) -> Json {
+        Json::Array(self.iter().map(ToJson::to_json).collect())
+    }
+}
+
+impl<T: ToJson> ToJson for Vec<T> {
+    fn to_json(&self) -> Json {
+        self.as_slice().to_json()
+    }
+}
+
+impl<T: ToJson> ToJson for Option<T> {
+    fn to_json(&self) -> Json {
+        self.as_ref().map_or(Json::Null, ToJson::to_json)
+    }
+}
+
+impl<K: Display, V: ToJson> ToJson for BTreeMap<K, V> {
+    fn to_json(&self) -> Json {
+        let fields = self
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_json()))
+            .collect();
+        Json::Object(fields)
+    }
+}
+
+impl ToJson for str {
+    fn to_json(&self) -> Json {
+        Json::Str(self.to_string())
+    }
+}
+
+impl ToJson for String {
+    fn to_json(&self) -> Json {
+        Json::Str(self.clone())
+    }
+}
+
+impl ToJson for bool {
+    fn to_json(&self) -> Json {
+        Json::Bool(*self)
+    }
+}
+
+macro_rules! int_to_json {
+    ($($int:ty),*) => {
+        $(impl ToJson for $int {
+            fn to_json(&self) -> Json {
+                Json::Int(*self as i128)
+            }
+        })*
+    };
+}
+
+int_to_json!(u8, u16, u32, u64, usize, i32, i64);
+
+impl ToJson for Stats {
+    fn to_json(&self) -> Json {
+        let fields = self
+            .named()
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value.to_json()))
+            .collect();
+        Json::Object(fields)
+    }
+}
+
+impl ToJson for AttributeValue {
+    fn to_json(&self) -> Json {
+        match self {
+            Self::Str(value) => value.to_json(),
+            Self::Int(value) => value.to_json(),
+            Self::Bool(value) => value.to_json(),
+            Self::Float(value) => Json::Float(*value),
+        }
+    }
+}
+
+impl ToJson for Param {
+    fn to_json(&self) -> Json {
+        match self {
+            Self::Int(value) => value.to_json(),
+            Self::Text(value) => value.to_json(),
+        }
+    }
+}
+
+impl ToJson for Resources {
+    fn to_json(&self) -> Json {
+        Json::object([
+            ("action_points", self.action_points.to_json()),
+            ("mana", self.mana.to_json()),
+            ("gold", self.gold.to_json()),
+        ])
+    }
+}
+
+impl ToJson for Rules {
+    fn to_json(&self) -> Json {
+        Json::object([
+            ("max_actions", self.max_actions.to_json()),
+            ("cooldowns", self.cooldowns.to_json()),
+            ("costs", self.costs.to_json()),
+            ("xp", self.xp.to_json()),
+            (
+                "leveling",
+                Json::object([
+                    ("xp_per_level", self.leveling.xp_per_level.to_json()),
+                    ("gains", self.leveling.gains.to_json()),
+                ]),
+            ),
+        ])
+    }
+}
+
+impl ToJson for Item {
+    fn to_json(&self) -> Json {
+        Json::object([
+            ("name", self.name.to_json()),
+            ("count", self.count.to_json()),
+            ("slot", self.slot.map(|slot| slot.to_string()).to_json()),
+            ("modifiers", self.modifiers.to_json()),
+        ])
+    }
+}
+
+impl ToJson for Entity {
+    fn to_json(&self) -> Json {
+        let effects: Vec<_> = self
+            .effects
+            .iter()
+            .map(|effect| {
+                Json::object([
+                    ("kind", effect.kind.to_string().to_json()),
+                    ("turns", effect.turns.to_json()),
+                ])
+            })
+            .collect();
+        let position = self.position.map(|position| {
+            Json::object([("x", position.x.to_json()), ("y", position.y.to_json())])
+        });
+        Json::object([
+            ("id", self.id.to_string().to_json()),
+            ("name", self.name.to_json()),
+            ("stats", self.stats.to_json()),
+            ("effective_stats", self.effective_stats().to_json()),
+            ("xp", self.experience.xp.to_json()),
+            ("level", self.experience.level.to_json()),
+            ("guarding", self.guarding.to_json()),
+            ("effects", Json::Array(effects)),
+            ("resources", self.resources.to_json()),
+            ("inventory", self.inventory.to_json()),
+            ("equipment", self.equipment.to_json()),
+            ("attributes", self.attributes.to_json()),
+            ("position", position.unwrap_or(Json::Null)),
+            (
+                "ai",
+                self.ai.as_ref().map(|ai| ai.behavior.to_string()).to_json(),
+            ),
+            ("faction", self.faction.to_json()),
+            ("owner", self.owner.to_json()),
+        ])
+    }
+}
+
+impl ToJson for Player {
+    fn to_json(&self) -> Json {
+        Json::object([
+            ("id", self.id.to_string().to_json()),
+            ("name", self.name.to_json()),
+            ("key", self.key.to_json()),
+        ])
+    }
+}
+
+impl ToJson for Grave {
+    fn to_json(&self) -> Json {
+        Json::object([
+            ("entity", self.entity.to_json()),
+            ("turn", self.turn.to_json()),
+        ])
+    }
+}
+
+impl ToJson for Action {
+    fn to_json(&self) -> Json {
+        Json::object([
+            ("kind", self.kind().to_string().to_json()),
+            ("target", self.target().to_json()),
+            ("params", self.params().to_json()),
+        ])
+    }
+}
+
+impl ToJson for Letter {
+    fn to_json(&self) -> Json {
+        let (kind, body) = match &self.body {
+            Body::Acted(action) => ("action", action.to_json()),
+            Body::Chat(text) => ("chat", text.to_json()),
+        };
+        Json::object([
+            ("id", self.id.to_json()),
+            ("session", self.session.to_json()),
+            ("from", self.from.to_json()),
+            (kind, body),
+        ])
+    }
+}
+
+impl ToJson for OpenGame {
+    fn to_json(&self) -> Json {
+        Json::object([
+            ("name", self.name.to_json()),
+            ("rule_set", self.rule_set.to_json()),
+            ("slots", self.slots.to_json()),
+            ("players", self.players.to_json()),
+        ])
+    }
+}
+
+impl ToJson for Merged {
+    fn to_json(&self) -> Json {
+        Json::object([
+            ("applied", self.applied.to_json()),
+            ("conflicts", self.conflicts.to_json()),
+        ])
+    }
+}
+
+impl ToJson for TurnOrder {
+    fn to_json(&self) -> Json {
+        Json::object([
+            ("players", self.players().to_json()),
+            ("current", self.current().to_json()),
+            ("turn", self.number().to_json()),
+            ("time", self.time().to_json()),
+            ("forfeit", self.forfeit().to_json()),
+            (
+                "deadline",
+                self.deadline().map(|at| at.to_string()).to_json(),
+            ),
+        ])
+    }
+}
+
+impl ToJson for Synced {
+    fn to_json(&self) -> Json {
+        Json::object([
+            ("received", self.received.to_json()),
+            ("sent", self.sent.to_json()),
+            ("shared", self.shared.to_json()),
+        ])
+    }
+}
+
+impl ToJson for StateChange {
+    fn to_json(&self) -> Json {
+        Json::object([
+            ("entity", self.entity.to_json()),
+            ("field", self.field.to_json()),
+            ("before", self.before.to_json()),
+            ("after", self.after.to_json()),
+        ])
+    }
+}
+
+impl ToJson for DiceRoll {
+    fn to_json(&self) -> Json {
+        Json::object([
+            ("sides", self.sides.to_json()),
+            ("value", self.value.to_json()),
+        ])
+    }
+}
+
+impl ToJson for ActionOutcome {
+    fn to_json(&self) -> Json {
+        Json::object([
+            ("messages", self.messages.to_json()),
+            ("changes", self.changes.to_json()),
+            ("rolls", self.rolls.to_json()),
+        ])
+    }
+}
+
+impl ToJson for Entry {
+    fn to_json(&self) -> Json {
+        Json::object([
+            ("number", self.number.to_json()),
+            ("event", self.event.to_string().to_json()),
+            ("outcome", self.outcome.to_json()),
+        ])
+    }
+}
+
+impl ToJson for Replay {
+    fn to_json(&self) -> Json {
+        Json::object([
+            ("session", self.session.to_json()),
+            ("exported", self.exported.to_string().to_json()),
+            ("start", self.start.to_json()),
+            ("entries", self.entries.to_json()),
+            ("end", self.end.to_json()),
+            ("over", self.over.to_json()),
+        ])
+    }
+}
+
+impl ToJson for stats::Stats {
+    fn to_json(&self) -> Json {
+        let fields = self
+            .fields
+            .iter()
+            .map(|(field_type, count)| (format!("{field_type:?}"), count.to_json()))
+            .collect();
+        Json::object([
+            ("size", self.size.to_json()),
+            ("entities", self.entities.to_json()),
+            ("actions", self.actions.to_json()),
+            ("fields", Json::Object(fields)),
+        ])
+    }
+}
+
+/// What `show` prints: the session's state rather than its log.
+impl ToJson for Session {
+    fn to_json(&self) -> Json {
+        let map = self.map().map(|map| {
+            Json::object([
+                ("width", map.width.to_json()),
+                ("height", map.height.to_json()),
+            ])
+        });
+        Json::object([
+            ("name", self.name().to_json()),
+            ("created", self.created().to_string().to_json()),
+            ("modified", self.modified().to_string().to_json()),
+            ("tags", self.tags().to_json()),
+            ("events", self.log().len().to_json()),
+            ("action", self.action().to_json()),
+            ("turn", self.turn().number.to_json()),
+            ("rules", self.rule_set().name().to_json()),
+            ("redacted", self.redacted().to_json()),
+            ("over", self.over().is_some().to_json()),
+            (
+                "winner",
+                self.over().and_then(|over| over.winner.as_ref()).to_json(),
+            ),
+            ("players", self.roster().to_json()),
+            ("order", self.order().to_json()),
+            ("queued", self.queued().to_json()),
+            ("map", map.unwrap_or(Json::Null)),
+            ("entities", self.entities().to_json()),
+            ("dead", self.graveyard().to_json()),
+        ])
+    }
+}
+
+impl ToJson for Version {
+    fn to_json(&self) -> Json {
+        Json::object([
+            ("version", self.version.to_json()),
+            ("git_hash", self.git_hash.to_json()),
+            ("session_format", self.session_format.to_json()),
+            ("archive_format", self.archive_format.to_json()),
+            ("features", self.features.to_json()),
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Json, ToJson};
+    use crate::actions::{Action, ActionKind};
+    use crate::session::Session;
+    use crate::Entity;
+
+    #[test]
+    fn outcomes_print_as_json() {
+        let mut session = Session::new("arena".to_string()).unwrap();
+        session
+            .add_entity(Entity::new("troll \"big\"".to_string()))
+            .unwrap();
+        let action = Action::new(ActionKind::Fight, "troll \"big\"".to_string()).unwrap();
+        let outcome = session.apply(action).unwrap();
+        assert_eq!(
+            outcome.to_json().to_string(),
+            r#"{"messages":["fought troll \"big\""],"changes":[{"entity":"troll \"big\"","field":"hp","before":"0","after":"1"}],"rolls":[]}"#
+        );
+        assert_eq!(
+            Json::message("a\nb\u{1}").to_string(),
+            r#"{"message":"a\nb\u0001"}"#
+        );
+        assert_eq!(Json::Float(f64::NAN).to_string(), "null");
+        assert_eq!(Json::Float(1.0).to_string(), "1.0");
+        let show = session.to_json().to_string();
+        assert!(show.starts_with(r#"{"name":"arena","#));
+        assert!(show.contains(r#""stats":{"hp":1,"attack":0,"defense":0,"speed":0}"#));
+    }
+}
@@ -0,0 +1,295 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+
+use attributes::AttributeValue;
+use components::{Ai, Position, Raw};
+use effects::Effect;
+use equipment::Slot;
+use error::{Error, Result};
+use experience::Experience;
+use inventory::Item;
+use resources::Resources;
+use serde::{Deserialize, Field, FieldReader, FieldWriter, Serialize, SerializeField, HEADER_LEN};
+use uuid::Uuid;
+
+pub mod actions;
+pub mod ai;
+pub mod archetypes;
+pub mod archive;
+pub mod args;
+pub mod atomic;
+pub mod attributes;
+pub mod client;
+pub mod completions;
+pub mod components;
+pub mod config;
+pub mod conflict;
+pub mod crypt;
+pub mod delta;
+pub mod doctor;
+pub mod effects;
+pub mod equipment;
+pub mod error;
+pub mod experience;
+pub mod factions;
+pub mod graveyard;
+pub mod handlers;
+pub mod history;
+pub mod inventory;
+pub mod json;
+pub mod lobby;
+pub mod log;
+pub mod macros;
+pub mod mailbox;
+pub mod map;
+pub mod metadata;
+pub mod outcome;
+pub mod passphrase;
+pub mod paths;
+pub mod players;
+pub mod protocol;
+pub mod provider;
+pub mod proxy;
+pub mod recent;
+pub mod relations;
+pub mod replay;
+pub mod resolve;
+pub mod resources;
+pub mod rng;
+pub mod rules;
+pub mod script;
+pub mod serde;
+pub mod server;
+pub mod session;
+pub mod signing;
+pub mod stats;
+pub mod store;
+pub mod style;
+pub mod suggest;
+pub mod sync;
+#[cfg(test)]
+mod temp_dir;
+pub mod timestamp;
+pub mod tokens;
+pub mod turnfile;
+pub mod turns;
+pub mod usage;
+pub mod validate;
+pub mod verbosity;
+pub mod version;
+pub mod victory;
+pub mod visibility;
+pub mod webhook;
+
+/// What an entity is capable of. Written as:
+///
+/// ```text
+/// List(U8(hp) U8(attack) U8(defense) U8(speed))
+/// ```
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    /// Hit points, lost to attacks and regained by resting.
+    pub hp: u8,
+    pub attack: u8,
+    pub defense: u8,
+    /// Initiative when actions conflict; the fastest goes first.
+    pub speed: u8,
+}
+
+impl Stats {
+    /// Each stat with the name it is shown and scripted under.
+    pub fn named(&self) -> [(&'static str, u8); 4] {
+        [
+            ("hp", self.hp),
+            ("attack", self.attack),
+            ("defense", self.defense),
+            ("speed", self.speed),
+        ]
+    }
+
+    pub fn named_mut(&mut self) -> [(&'static str, &mut u8); 4] {
+        [
+            ("hp", &mut self.hp),
+            ("attack", &mut self.attack),
+            ("defense", &mut self.defense),
+            ("speed", &mut self.speed),
+        ]
+    }
+}
+
+impl Display for Stats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "hp {}, attack {}, defense {}, speed {}",
+            self.hp, self.attack, self.defense, self.speed
+        )
+    }
+}
+
+impl SerializeField for Stats {
+    fn serialize_field(&self, writer: &mut FieldWriter<'_>) {
+        writer.write(&[self.hp, self.attack, self.defense, self.speed]);
+    }
+}
+
+impl TryFrom<Field<'_>> for Stats {
+    type Error = Error;
+
+    fn try_from(value: Field<'_>) -> Result<Self> {
+        let [hp, attack, defense, speed] = value.try_into()?;
+        Ok(Self {
+            hp,
+            attack,
+            defense,
+            speed,
+        })
+    }
+}
+
+/// Something in a session: an id and name plus a set of components.
+/// Written as:
+///
+/// ```text
+/// U128(id) Str(name) Map(U8(tag) -> Bytes(component))
+/// ```
+///
+/// Components left at their defaults are not written. See `Component`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entity {
+    /// Stays the same for the life of the entity, unlike its name.
+    pub id: Uuid,
+    pub name: String,
+    pub stats: Stats,
+    pub experience: Experience,
+    /// Set by defending; the next attack does half damage and clears it.
+    pub guarding: bool,
+    /// Temporary conditions, in the order they took hold.
+    pub effects: Vec<Effect>,
+    /// What the entity has to pay for its actions.
+    pub resources: Resources,
+    /// What the entity carries, one stack per kind of item, in the order
+    /// they were first picked up.
+    pub inventory: Vec<Item>,
+    /// What the entity has equipped, one item in each slot at most. See
+    /// `Entity::effective_stats`.
+    pub equipment: BTreeMap<Slot, Item>,
+    /// Campaign-specific data a GM attached, by name.
+    pub attributes: BTreeMap<String, AttributeValue>,
+    pub position: Option<Position>,
+    pub ai: Option<Ai>,
+    /// The faction the entity belongs to, if any.
+    pub faction: Option<String>,
+    /// The player the entity belongs to, if any.
+    pub owner: Option<String>,
+    /// Every other component, encoded and by tag.
+    components: BTreeMap<u8, Vec<u8>>,
+}
+
+impl Display for Entity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({}", self.name, self.stats)?;
+        if self.experience != Experience::default() {
+            write!(f, ", {}", self.experience)?;
+        }
+        if self.guarding {
+            write!(f, ", guarding")?;
+        }
+        for effect in &self.effects {
+            write!(f, ", {effect}")?;
+        }
+        if !self.resources.is_empty() {
+            write!(f, ", {}", self.resources)?;
+        }
+        if let Some(ai) = &self.ai {
+            write!(f, ", ai {}", ai.behavior)?;
+        }
+        if let Some(faction) = &self.faction {
+            write!(f, ", faction {faction}")?;
+        }
+        if let Some(owner) = &self.owner {
+            write!(f, ", owned by {owner}")?;
+        }
+        for item in self.equipment.values() {
+            write!(f, ", equipped {item}")?;
+        }
+        write!(f, ")")?;
+        if let Some(position) = &self.position {
+            write!(f, " at {position}")?;
+        }
+        for (i, item) in self.inventory.iter().enumerate() {
+            match i {
+                0 => write!(f, " carrying {item}")?,
+                _ => write!(f, ", {item}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Entity {
+    pub fn new(name: String) -> Self {
+        Self::with_id(Uuid::new_v4(), name)
+    }
+
+    /// An entity with no components.
+    fn with_id(id: Uuid, name: String) -> Self {
+        Self {
+            id,
+            name,
+            stats: Stats::default(),
+            experience: Experience::default(),
+            guarding: false,
+            effects: vec![],
+            resources: Resources::default(),
+            inventory: vec![],
+            equipment: BTreeMap::new(),
+            attributes: BTreeMap::new(),
+            position: None,
+            ai: None,
+            faction: None,
+            owner: None,
+            components: BTreeMap::new(),
+        }
+    }
+}
+
+impl Serialize for Entity {
+    fn serialize_to(&self, writer: &mut FieldWriter<'_>) {
+        writer.write_u128(self.id.as_u128());
+        writer.write_str(&self.name);
+        let components = self.encode_components();
+        let components: BTreeMap<u8, Raw> = components
+            .iter()
+            .map(|(tag, bytes)| (*tag, Raw(bytes)))
+            .collect();
+        writer.write(&components);
+    }
+
+    fn size_hint(&self) -> usize {
+        let components: usize = self
+            .encode_components()
+            .values()
+            .map(|bytes| HEADER_LEN + 1 + HEADER_LEN + bytes.len())
+            .sum();
+        HEADER_LEN + 16 + HEADER_LEN + self.name.len() + HEADER_LEN + components
+    }
+}
+
+impl Deserialize for Entity {
+    fn deserialize(reader: &mut FieldReader<'_>) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let id = Uuid::from_u128(reader.read_field()?);
+        let mut entity = Self::with_id(id, reader.read_field()?);
+        let components: BTreeMap<u8, &[u8]> = reader.read_field()?;
+        entity.decode_components(
+            components
+                .into_iter()
+                .map(|(tag, bytes)| (tag, bytes.to_vec()))
+                .collect(),
+        )?;
+        Ok(entity)
+    }
+}
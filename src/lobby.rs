@@ -0,0 +1,216 @@
+use std::collections::BTreeMap;
+use std::sync::{Mutex, MutexGuard};
+
+use crate::error::{Error, Result};
+use crate::players::Player;
+use crate::provider::SessionProvider;
+use crate::rules;
+use crate::serde::{Field, FieldWriter, Serialize, SerializeField};
+use crate::session::Session;
+
+/// The most players a game in the lobby may wait for.
+pub const MAX_SLOTS: u8 = 16;
+
+/// The most games one player may have waiting in the lobby at once.
+pub const MAX_HOSTED: usize = 4;
+
+/// A game waiting in a server's lobby for players to fill its slots.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpenGame {
+    /// Name of the session it becomes.
+    pub name: String,
+    pub rule_set: String,
+    /// How many players it starts with.
+    pub slots: u8,
+    /// Those who have joined, the host first, in the order they take turns.
+    pub players: Vec<String>,
+}
+
+impl OpenGame {
+    /// How many slots are left.
+    pub fn free(&self) -> usize {
+        usize::from(self.slots).saturating_sub(self.players.len())
+    }
+}
+
+impl Serialize for OpenGame {
+    fn serialize_to(&self, writer: &mut FieldWriter<'_>) {
+        writer.write_str(&self.name);
+        writer.write_str(&self.rule_set);
+        writer.write_u8(self.slots);
+        writer.write(&self.players);
+    }
+}
+
+impl SerializeField for OpenGame {
+    fn serialize_field(&self, writer: &mut FieldWriter<'_>) {
+        writer.write_list(self);
+    }
+}
+
+impl TryFrom<Field<'_>> for OpenGame {
+    type Error = Error;
+
+    fn try_from(value: Field<'_>) -> Result<Self> {
+        let (name, rule_set, slots, players) = value.try_into()?;
+        Ok(Self {
+            name,
+            rule_set,
+            slots,
+            players,
+        })
+    }
+}
+
+/// The games a server's players have opened and not yet filled. Once the
+/// last slot is taken the game leaves the lobby as a session, played by
+/// its rule set with its players taking turns in the order they joined.
+///
+/// Kept in memory only: games still waiting when the server stops are
+/// forgotten, and have to be opened again.
+#[derive(Debug, Default)]
+pub struct Lobby {
+    games: Mutex<BTreeMap<String, OpenGame>>,
+}
+
+impl Lobby {
+    /// Opens game `name`, played by `rule_set`, for `slots` players, the
+    /// first of them `host`. The name must be free for a session, and
+    /// `host` may have no more than `MAX_HOSTED` games waiting.
+    pub fn host(
+        &self,
+        sessions: &dyn SessionProvider,
+        name: &str,
+        rule_set: &str,
+        slots: u8,
+        host: &str,
+    ) -> Result<()> {
+        Session::new(name.to_string())?;
+        rules::rule_set(rule_set)?;
+        if !(2..=MAX_SLOTS).contains(&slots) {
+            return Err(Error::InvalidSlots(slots));
+        }
+        let mut games = self.lock();
+        if games.contains_key(name) {
+            return Err(Error::SessionExists(name.to_string()));
+        }
+        let hosted = games.values().filter(|game| game.players[0] == host);
+        if hosted.count() >= MAX_HOSTED {
+            return Err(Error::TooManyGames(host.to_string()));
+        }
+        match sessions.load(name) {
+            Ok(_) => return Err(Error::SessionExists(name.to_string())),
+            Err(Error::SessionNotFound(_)) => {}
+            Err(err) => return Err(err),
+        }
+        let game = OpenGame {
+            name: name.to_string(),
+            rule_set: rule_set.to_string(),
+            slots,
+            players: vec![host.to_string()],
+        };
+        games.insert(name.to_string(), game);
+        Ok(())
+    }
+
+    /// Every game waiting for players, by name.
+    pub fn games(&self) -> Vec<OpenGame> {
+        self.lock().values().cloned().collect()
+    }
+
+    /// Takes a slot in game `name` for `player`, returning the game as it
+    /// now stands. Taking the last starts it, saving its session to
+    /// `sessions`; should that fail the slot is left free.
+    pub fn join(
+        &self,
+        sessions: &dyn SessionProvider,
+        name: &str,
+        player: &str,
+    ) -> Result<OpenGame> {
+        let mut games = self.lock();
+        let game = games
+            .get_mut(name)
+            .ok_or_else(|| Error::GameNotOpen(name.to_string()))?;
+        if game.players.iter().any(|joined| joined == player) {
+            return Err(Error::AlreadyJoined(player.to_string(), name.to_string()));
+        }
+        let mut joined = game.clone();
+        joined.players.push(player.to_string());
+        if joined.free() > 0 {
+            *game = joined.clone();
+            return Ok(joined);
+        }
+        start(sessions, &joined)?;
+        games.remove(name);
+        Ok(joined)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BTreeMap<String, OpenGame>> {
+        self.games.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// Creates `game`'s session in `sessions`, with its players on the roster
+/// and taking turns. It is built here and saved whole, as no player may
+/// send the events that set a session up.
+fn start(sessions: &dyn SessionProvider, game: &OpenGame) -> Result<()> {
+    let mut session = Session::new(game.name.clone())?;
+    session.play_by(&game.rule_set)?;
+    for player in &game.players {
+        session.add_player(Player::new(player.clone(), None))?;
+    }
+    session.set_players(game.players.clone())?;
+    sessions.save_new(session)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{Lobby, MAX_HOSTED};
+    use crate::error::Error;
+    use crate::provider::{LocalSessions, SessionProvider};
+    use crate::store::MemStore;
+
+    #[test]
+    fn games_start_once_every_slot_is_taken() {
+        let store = MemStore::new();
+        let sessions = LocalSessions::new(Path::new(""), &store);
+        let lobby = Lobby::default();
+        lobby
+            .host(&sessions, "duel", "skirmish", 3, "alice")
+            .unwrap();
+        let err = lobby.host(&sessions, "duel", "standard", 2, "bob");
+        assert!(matches!(err, Err(Error::SessionExists(_))));
+        let err = lobby.host(&sessions, "brawl", "standard", 1, "bob");
+        assert!(matches!(err, Err(Error::InvalidSlots(1))));
+
+        assert_eq!(lobby.join(&sessions, "duel", "bob").unwrap().free(), 1);
+        let err = lobby.join(&sessions, "duel", "bob");
+        assert!(matches!(err, Err(Error::AlreadyJoined(..))));
+        assert!(sessions.list().unwrap().is_empty());
+        assert_eq!(lobby.games()[0].players, ["alice", "bob"]);
+
+        let game = lobby.join(&sessions, "duel", "carol").unwrap();
+        assert_eq!(game.free(), 0);
+        assert!(lobby.games().is_empty());
+        let session = sessions.load("duel").unwrap();
+        assert_eq!(session.rule_set().name(), "skirmish");
+        assert_eq!(session.order().players(), ["alice", "bob", "carol"]);
+        assert_eq!(session.order().current(), Some("alice"));
+        assert_eq!(session.roster().len(), 3);
+        let err = lobby.join(&sessions, "duel", "dave");
+        assert!(matches!(err, Err(Error::GameNotOpen(_))));
+
+        for game in 0..MAX_HOSTED {
+            lobby
+                .host(&sessions, &format!("game{game}"), "standard", 2, "dave")
+                .unwrap();
+        }
+        let err = lobby.host(&sessions, "one_more", "standard", 2, "dave");
+        assert!(matches!(err, Err(Error::TooManyGames(_))));
+        lobby
+            .host(&sessions, "one_more", "standard", 2, "erin")
+            .unwrap();
+    }
+}
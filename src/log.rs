@@ -0,0 +1,453 @@
+use std::fmt::Display;
+
+use crate::actions::{Action, ActionKind};
+use crate::attributes::AttributeValue;
+use crate::components::Position;
+use crate::effects::Effect;
+use crate::error::{Error, Result};
+use crate::factions::Stance;
+use crate::map::Map;
+use crate::players::Player;
+use crate::relations::Relationship;
+use crate::resources::Resources;
+use crate::rules::{Prerequisite, Rules};
+use crate::serde::{
+    header_len, Deserialize, FieldReader, FieldWriter, Serialize, TaggedEnum, HEADER_LEN,
+};
+use crate::timestamp::Timestamp;
+use crate::Entity;
+
+/// One change to a session. A session is saved as the list of changes made
+/// to it, and its entities and current action are rebuilt by replaying them
+/// in order from an empty session.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    AddEntity(Entity),
+    /// Name of the entity removed.
+    RemoveEntity(String),
+    Act(Action),
+    /// An action taken by the named player, as a server whose clients
+    /// present tokens records it.
+    ActBy(String, Action),
+    /// An action stored until the next `Resolve`.
+    Queue(Action),
+    /// Applies every queued action in order and ends the turn, at the
+    /// given time.
+    Resolve(Timestamp),
+    SetRules(Rules),
+    /// Name and source of a script defining an action kind.
+    DefineScript(String, String),
+    /// Replaces what actions of a kind require.
+    Require(ActionKind, Vec<Prerequisite>),
+    /// Name of an entity and resources added to its pool.
+    Grant(String, Resources),
+    /// Name of an entity, and the name and new value of its attribute.
+    SetAttribute(String, String, AttributeValue),
+    /// Name of an entity and of the attribute it loses.
+    UnsetAttribute(String, String),
+    Relate(Relationship),
+    Unrelate(Relationship),
+    /// Name of an entity and an effect it comes under.
+    Afflict(String, Effect),
+    /// Bounds every position from now on.
+    SetMap(Map),
+    /// Name of an entity and where it is put on the map.
+    Place(String, Position),
+    DefineFaction(String),
+    /// Two factions and how they now stand towards each other.
+    SetStance(String, String, Stance),
+    /// Name of an entity and the faction it joins, or none to leave its own.
+    JoinFaction(String, Option<String>),
+    /// Who said what to the other players, and when. Chat is kept in the
+    /// log so that it travels with the session, but changes nothing.
+    Say(String, String, Timestamp),
+    /// The players who take turns, in order, none to stop taking turns,
+    /// and when the first turn began. Logs from before turns were timed
+    /// have no time.
+    SetPlayers(Vec<String>, Option<Timestamp>),
+    /// The player who ended their turn, and when, if logged.
+    Pass(String, Option<Timestamp>),
+    AddPlayer(Player),
+    /// Name of the player removed.
+    RemovePlayer(String),
+    /// Name of an entity and the player it now belongs to, or none.
+    Own(String, Option<String>),
+    /// Name of the rule set the session plays by from now on.
+    PlayBy(String),
+    /// Seconds each turn may last, or none for no limit, whether players
+    /// who run out of time forfeit, and when the limit was set.
+    TurnTime(Option<u32>, bool, Timestamp),
+    /// The player who ran out of time, and when they were found to have.
+    Timeout(String, Timestamp),
+    /// Ends the log of a copy redacted for the named player, or for no one
+    /// in particular if the name is empty, which sets up only what they may
+    /// see, with the turns resolved and the turn order's turn where the
+    /// session stood. See `visibility::redact`.
+    Redacted(String, u32, u32),
+}
+
+impl Event {
+    /// The action taken, if this event is one, by whomever.
+    pub fn action(&self) -> Option<&Action> {
+        match self {
+            Self::Act(action) | Self::ActBy(_, action) => Some(action),
+            _ => None,
+        }
+    }
+
+    /// The player who did this, for the events a player does themselves:
+    /// their actions, passes and messages. The rest set the session up or
+    /// keep it, and are nobody's in particular.
+    pub fn actor(&self) -> Option<&str> {
+        match self {
+            Self::ActBy(player, _) | Self::Pass(player, _) | Self::Say(player, ..) => Some(player),
+            _ => None,
+        }
+    }
+
+    /// Encoded size of the event as a field, header included.
+    pub(crate) fn field_len(&self) -> usize {
+        let payload = match self {
+            Self::AddEntity(entity) => HEADER_LEN + entity.size_hint(),
+            Self::RemoveEntity(name) => HEADER_LEN + name.len(),
+            Self::Act(action) | Self::Queue(action) => HEADER_LEN + action.size_hint(),
+            Self::ActBy(player, action) => 2 * HEADER_LEN + player.len() + action.size_hint(),
+            Self::Resolve(_) => HEADER_LEN + 16,
+            Self::SetRules(rules) => HEADER_LEN + rules.size_hint(),
+            Self::DefineScript(name, source) => {
+                HEADER_LEN + name.len() + header_len(source.len()) + source.len()
+            }
+            Self::Require(_, prerequisites) => {
+                HEADER_LEN
+                    + 1
+                    + HEADER_LEN
+                    + prerequisites
+                        .iter()
+                        .map(Prerequisite::field_len)
+                        .sum::<usize>()
+            }
+            Self::Grant(name, _) => HEADER_LEN + name.len() + Resources::FIELD_LEN,
+            Self::SetAttribute(name, key, value) => {
+                2 * HEADER_LEN + name.len() + key.len() + value.field_len()
+            }
+            Self::UnsetAttribute(name, key) => 2 * HEADER_LEN + name.len() + key.len(),
+            Self::Relate(_) | Self::Unrelate(_) => Relationship::FIELD_LEN,
+            Self::Afflict(name, _) => HEADER_LEN + name.len() + Effect::FIELD_LEN,
+            Self::SetMap(_) => Map::FIELD_LEN,
+            Self::Place(name, _) => HEADER_LEN + name.len() + Position::FIELD_LEN,
+            Self::DefineFaction(name) => HEADER_LEN + name.len(),
+            Self::SetStance(a, b, _) => 3 * HEADER_LEN + a.len() + b.len() + 1,
+            Self::JoinFaction(name, faction) => {
+                HEADER_LEN + name.len() + HEADER_LEN + faction.as_ref().map_or(0, String::len)
+            }
+            Self::Say(player, text, _) => {
+                2 * HEADER_LEN + player.len() + header_len(text.len()) + text.len() + 16
+            }
+            Self::SetPlayers(players, at) => {
+                HEADER_LEN
+                    + players
+                        .iter()
+                        .map(|player| HEADER_LEN + player.len())
+                        .sum::<usize>()
+                    + at.map_or(0, |_| HEADER_LEN + 16)
+            }
+            Self::Pass(name, at) => HEADER_LEN + name.len() + at.map_or(0, |_| HEADER_LEN + 16),
+            Self::RemovePlayer(name) | Self::PlayBy(name) => HEADER_LEN + name.len(),
+            Self::AddPlayer(player) => player.field_len(),
+            Self::Own(name, owner) => {
+                HEADER_LEN + name.len() + HEADER_LEN + owner.as_ref().map_or(0, String::len)
+            }
+            Self::TurnTime(..) => HEADER_LEN + 4 + HEADER_LEN + 1 + HEADER_LEN + 16,
+            Self::Timeout(player, _) => HEADER_LEN + player.len() + HEADER_LEN + 16,
+            Self::Redacted(player, ..) => HEADER_LEN + player.len() + 2 * (HEADER_LEN + 4),
+        };
+        header_len(1 + payload) + 1 + payload
+    }
+}
+
+impl Display for Event {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::AddEntity(entity) => write!(f, "add {}", entity.name),
+            Self::RemoveEntity(name) => write!(f, "remove {name}"),
+            Self::Act(action) => write!(f, "{action}"),
+            Self::ActBy(player, action) => write!(f, "{player}: {action}"),
+            Self::Queue(action) => write!(f, "queue {action}"),
+            Self::Resolve(at) => write!(f, "resolve at {at}"),
+            Self::SetRules(rules) => write!(f, "rules: {}", rules.to_string().replace('\n', ", ")),
+            Self::DefineScript(name, _) => write!(f, "define script {name}"),
+            Self::Grant(name, resources) => write!(f, "grant {name} {resources}"),
+            Self::SetAttribute(name, key, value) => write!(f, "set {name} {key}={value}"),
+            Self::UnsetAttribute(name, key) => write!(f, "unset {name} {key}"),
+            Self::Relate(relationship) => write!(f, "relate {relationship}"),
+            Self::Unrelate(relationship) => write!(f, "unrelate {relationship}"),
+            Self::Afflict(name, effect) => write!(f, "afflict {name}: {effect}"),
+            Self::SetMap(map) => write!(f, "map {map}"),
+            Self::Place(name, position) => write!(f, "place {name} at {position}"),
+            Self::DefineFaction(name) => write!(f, "faction {name}"),
+            Self::SetStance(a, b, stance) => write!(f, "{a} and {b} are {stance}"),
+            Self::JoinFaction(name, Some(faction)) => write!(f, "{name} joins {faction}"),
+            Self::JoinFaction(name, None) => write!(f, "{name} leaves its faction"),
+            Self::Say(player, text, _) => write!(f, "{player} says {text:?}"),
+            Self::SetPlayers(players, _) if players.is_empty() => write!(f, "no turn order"),
+            Self::SetPlayers(players, _) => write!(f, "turn order {}", players.join(", ")),
+            Self::Pass(player, _) => write!(f, "{player} passes"),
+            Self::AddPlayer(player) => write!(f, "player {}", player.name),
+            Self::RemovePlayer(name) => write!(f, "remove player {name}"),
+            Self::Own(name, Some(owner)) => write!(f, "{owner} owns {name}"),
+            Self::Own(name, None) => write!(f, "{name} has no owner"),
+            Self::PlayBy(name) => write!(f, "play by {name} rules"),
+            Self::TurnTime(None, ..) => write!(f, "turns are untimed"),
+            Self::TurnTime(Some(time), forfeit, _) => {
+                write!(
+                    f,
+                    "turns last {}",
+                    crate::turns::format_time(u64::from(*time))
+                )?;
+                if *forfeit {
+                    write!(f, "; running out forfeits")?;
+                }
+                Ok(())
+            }
+            Self::Timeout(player, _) => write!(f, "{player} ran out of time"),
+            Self::Redacted(player, ..) if player.is_empty() => write!(f, "as anyone sees it"),
+            Self::Redacted(player, ..) => write!(f, "as {player} sees it"),
+            Self::Require(kind, prerequisites) if prerequisites.is_empty() => {
+                write!(f, "{kind} requires nothing")
+            }
+            Self::Require(kind, prerequisites) => {
+                write!(f, "{kind} requires ")?;
+                for (i, prerequisite) in prerequisites.iter().enumerate() {
+                    match i {
+                        0 => write!(f, "{prerequisite}")?,
+                        _ => write!(f, " and {prerequisite}")?,
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl TaggedEnum for Event {
+    fn discriminant(&self) -> u8 {
+        match self {
+            Self::AddEntity(_) => 0,
+            Self::RemoveEntity(_) => 1,
+            Self::Act(_) => 2,
+            Self::Queue(_) => 3,
+            Self::Resolve(_) => 4,
+            Self::SetRules(_) => 5,
+            Self::DefineScript(..) => 6,
+            Self::Require(..) => 7,
+            Self::Grant(..) => 8,
+            Self::SetAttribute(..) => 9,
+            Self::UnsetAttribute(..) => 10,
+            Self::Relate(_) => 11,
+            Self::Unrelate(_) => 12,
+            Self::Afflict(..) => 13,
+            Self::SetMap(_) => 14,
+            Self::Place(..) => 15,
+            Self::DefineFaction(_) => 16,
+            Self::SetStance(..) => 17,
+            Self::JoinFaction(..) => 18,
+            Self::ActBy(..) => 19,
+            Self::Say(..) => 20,
+            Self::SetPlayers(..) => 21,
+            Self::Pass(..) => 22,
+            Self::AddPlayer(_) => 23,
+            Self::RemovePlayer(_) => 24,
+            Self::Own(..) => 25,
+            Self::PlayBy(_) => 26,
+            Self::TurnTime(..) => 27,
+            Self::Timeout(..) => 28,
+            Self::Redacted(..) => 29,
+        }
+    }
+
+    fn serialize_variant(&self, writer: &mut FieldWriter<'_>) {
+        match self {
+            Self::AddEntity(entity) => writer.write(entity),
+            Self::RemoveEntity(name) => writer.write_str(name),
+            Self::Act(action) | Self::Queue(action) => writer.write(action),
+            Self::ActBy(player, action) => {
+                writer.write_str(player);
+                writer.write(action);
+            }
+            Self::Resolve(at) => writer.write(at),
+            Self::SetRules(rules) => writer.write_list(rules),
+            Self::DefineScript(name, source) => {
+                writer.write_str(name);
+                writer.write_str(source);
+            }
+            Self::Require(kind, prerequisites) => {
+                writer.write(kind);
+                writer.write(prerequisites);
+            }
+            Self::Grant(name, resources) => {
+                writer.write_str(name);
+                writer.write(resources);
+            }
+            Self::SetAttribute(name, key, value) => {
+                writer.write_str(name);
+                writer.write_str(key);
+                writer.write(value);
+            }
+            Self::UnsetAttribute(name, key) => {
+                writer.write_str(name);
+                writer.write_str(key);
+            }
+            Self::Relate(relationship) | Self::Unrelate(relationship) => writer.write(relationship),
+            Self::Afflict(name, effect) => {
+                writer.write_str(name);
+                writer.write(effect);
+            }
+            Self::SetMap(map) => writer.write(map),
+            Self::Place(name, position) => {
+                writer.write_str(name);
+                writer.write(position);
+            }
+            Self::DefineFaction(name) => writer.write_str(name),
+            Self::SetStance(a, b, stance) => {
+                writer.write_str(a);
+                writer.write_str(b);
+                writer.write(stance);
+            }
+            Self::JoinFaction(name, faction) => {
+                writer.write_str(name);
+                writer.write_str(faction.as_deref().unwrap_or_default());
+            }
+            Self::Say(player, text, at) => {
+                writer.write_str(player);
+                writer.write_str(text);
+                writer.write(at);
+            }
+            Self::SetPlayers(players, at) => {
+                writer.write(players);
+                if let Some(at) = at {
+                    writer.write(at);
+                }
+            }
+            Self::Pass(name, at) => {
+                writer.write_str(name);
+                if let Some(at) = at {
+                    writer.write(at);
+                }
+            }
+            Self::RemovePlayer(name) | Self::PlayBy(name) => writer.write_str(name),
+            Self::AddPlayer(player) => writer.write(player),
+            Self::Own(name, owner) => {
+                writer.write_str(name);
+                writer.write_str(owner.as_deref().unwrap_or_default());
+            }
+            Self::TurnTime(time, forfeit, at) => {
+                writer.write_u32(time.unwrap_or_default());
+                writer.write_bool(*forfeit);
+                writer.write(at);
+            }
+            Self::Timeout(player, at) => {
+                writer.write_str(player);
+                writer.write(at);
+            }
+            Self::Redacted(player, turn, order) => {
+                writer.write_str(player);
+                writer.write_u32(*turn);
+                writer.write_u32(*order);
+            }
+        }
+    }
+
+    fn deserialize_variant(discriminant: u8, reader: &mut FieldReader<'_>) -> Result<Self> {
+        match discriminant {
+            0 => Ok(Self::AddEntity(reader.read_field()?)),
+            1 => Ok(Self::RemoveEntity(reader.read_field()?)),
+            2 => Ok(Self::Act(reader.read_field()?)),
+            3 => Ok(Self::Queue(reader.read_field()?)),
+            4 => Ok(Self::Resolve(reader.read_field()?)),
+            5 => {
+                let mut list = reader.read_list()?;
+                let rules = Rules::deserialize(&mut list)?;
+                list.finish()?;
+                Ok(Self::SetRules(rules))
+            }
+            6 => Ok(Self::DefineScript(
+                reader.read_field()?,
+                reader.read_field()?,
+            )),
+            7 => Ok(Self::Require(reader.read_field()?, reader.read_field()?)),
+            8 => Ok(Self::Grant(reader.read_field()?, reader.read_field()?)),
+            9 => Ok(Self::SetAttribute(
+                reader.read_field()?,
+                reader.read_field()?,
+                reader.read_field()?,
+            )),
+            10 => Ok(Self::UnsetAttribute(
+                reader.read_field()?,
+                reader.read_field()?,
+            )),
+            11 => Ok(Self::Relate(reader.read_field()?)),
+            12 => Ok(Self::Unrelate(reader.read_field()?)),
+            13 => Ok(Self::Afflict(reader.read_field()?, reader.read_field()?)),
+            14 => Ok(Self::SetMap(reader.read_field()?)),
+            15 => Ok(Self::Place(reader.read_field()?, reader.read_field()?)),
+            16 => Ok(Self::DefineFaction(reader.read_field()?)),
+            17 => Ok(Self::SetStance(
+                reader.read_field()?,
+                reader.read_field()?,
+                reader.read_field()?,
+            )),
+            18 => {
+                let name = reader.read_field()?;
+                let faction: String = reader.read_field()?;
+                Ok(Self::JoinFaction(
+                    name,
+                    Some(faction).filter(|faction| !faction.is_empty()),
+                ))
+            }
+            19 => Ok(Self::ActBy(reader.read_field()?, reader.read_field()?)),
+            20 => Ok(Self::Say(
+                reader.read_field()?,
+                reader.read_field()?,
+                reader.read_field()?,
+            )),
+            21 => Ok(Self::SetPlayers(reader.read_field()?, read_time(reader)?)),
+            22 => Ok(Self::Pass(reader.read_field()?, read_time(reader)?)),
+            23 => Ok(Self::AddPlayer(reader.read_field()?)),
+            24 => Ok(Self::RemovePlayer(reader.read_field()?)),
+            25 => {
+                let name = reader.read_field()?;
+                let owner: String = reader.read_field()?;
+                Ok(Self::Own(
+                    name,
+                    Some(owner).filter(|owner| !owner.is_empty()),
+                ))
+            }
+            26 => Ok(Self::PlayBy(reader.read_field()?)),
+            27 => {
+                let time: u32 = reader.read_field()?;
+                Ok(Self::TurnTime(
+                    Some(time).filter(|time| *time > 0),
+                    reader.read_field()?,
+                    reader.read_field()?,
+                ))
+            }
+            28 => Ok(Self::Timeout(reader.read_field()?, reader.read_field()?)),
+            29 => Ok(Self::Redacted(
+                reader.read_field()?,
+                reader.read_field()?,
+                reader.read_field()?,
+            )),
+            _ => Err(Error::InvalidVariant(discriminant)),
+        }
+    }
+}
+
+crate::impl_enum_field!(Event);
+
+/// The time trailing an event, which events logged before it was kept lack.
+fn read_time(reader: &mut FieldReader<'_>) -> Result<Option<Timestamp>> {
+    match reader.is_empty() {
+        true => Ok(None),
+        false => Ok(Some(reader.read_field()?)),
+    }
+}
@@ -0,0 +1,94 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use crate::error::{Error, Result};
+
+/// Holds the macros available to every session, one per line:
+///
+/// ```text
+/// # Comments and blank lines are ignored.
+/// opening = [rest hero, inspect goblin, attack goblin damage=2]
+/// ```
+///
+/// The brackets are optional.
+const FILENAME: &str = "relay_code.macros";
+
+/// Named sequences of actions, each written the way `queue` takes them.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Macros(BTreeMap<String, String>);
+
+impl Macros {
+    /// Reads the macros file in `dir`, if there is one.
+    pub fn load(dir: &Path) -> Result<Self> {
+        match fs::read_to_string(dir.join(FILENAME)) {
+            Ok(text) => Self::parse(&text),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut macros = BTreeMap::new();
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || Error::InvalidMacro(line.to_string());
+            let (name, body) = line.split_once('=').ok_or_else(invalid)?;
+            let (name, body) = (name.trim(), body.trim());
+            let body = body
+                .strip_prefix('[')
+                .and_then(|body| body.strip_suffix(']'))
+                .unwrap_or(body)
+                .trim();
+            if name.is_empty() || name.contains(char::is_whitespace) || body.is_empty() {
+                return Err(invalid());
+            }
+            macros.insert(name.to_string(), body.to_string());
+        }
+        Ok(Self(macros))
+    }
+
+    /// The actions `name` stands for, separated by commas. Macros are not
+    /// expanded inside other macros.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.get(name).map(String::as_str)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0
+            .iter()
+            .map(|(name, body)| (name.as_str(), body.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Macros;
+    use crate::error::Error;
+
+    #[test]
+    fn macros_parse_with_or_without_brackets() {
+        let macros = Macros::parse(
+            "# openers\n\nopening = [rest hero, attack goblin damage=2]\nlook=inspect goblin\n",
+        )
+        .unwrap();
+        assert_eq!(
+            macros.get("opening"),
+            Some("rest hero, attack goblin damage=2")
+        );
+        assert_eq!(macros.get("look"), Some("inspect goblin"));
+        assert_eq!(macros.get("rest"), None);
+
+        assert!(matches!(
+            Macros::parse("two words = rest hero"),
+            Err(Error::InvalidMacro(_))
+        ));
+        assert!(matches!(
+            Macros::parse("empty = []"),
+            Err(Error::InvalidMacro(_))
+        ));
+    }
+}
@@ -0,0 +1,271 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::actions::Action;
+use crate::error::{Error, Result};
+use crate::serde::{
+    from_bytes, Deserialize, Field, FieldReader, FieldWriter, Serialize, SerializeField, TaggedEnum,
+};
+
+const FILENAME: &str = "relay_code.mail";
+
+/// The most letters handed over at once. Clients fetch again once they
+/// have acknowledged these.
+pub const MAIL_BATCH: usize = 100;
+
+/// The longest chat message, in bytes.
+pub const MAX_CHAT_LEN: usize = 1000;
+
+/// Something that happened in a session while a player was away.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Letter {
+    /// Higher for every letter posted, so a player can tell which they have
+    /// already read.
+    pub id: u64,
+    pub session: String,
+    /// The player it is from.
+    pub from: String,
+    pub body: Body,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Body {
+    /// A turn the sender took, which changed the session.
+    Acted(Action),
+    Chat(String),
+}
+
+impl Display for Letter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.body {
+            Body::Acted(action) => write!(f, "[{}] {}: {action}", self.session, self.from),
+            Body::Chat(text) => write!(f, "[{}] {} says {text:?}", self.session, self.from),
+        }
+    }
+}
+
+impl Serialize for Letter {
+    fn serialize_to(&self, writer: &mut FieldWriter<'_>) {
+        writer.write_u64(self.id);
+        writer.write_str(&self.session);
+        writer.write_str(&self.from);
+        writer.write(&self.body);
+    }
+}
+
+impl SerializeField for Letter {
+    fn serialize_field(&self, writer: &mut FieldWriter<'_>) {
+        writer.write_list(self);
+    }
+}
+
+impl TryFrom<Field<'_>> for Letter {
+    type Error = Error;
+
+    fn try_from(value: Field<'_>) -> Result<Self> {
+        let (id, session, from, body) = value.try_into()?;
+        Ok(Self {
+            id,
+            session,
+            from,
+            body,
+        })
+    }
+}
+
+impl TaggedEnum for Body {
+    fn discriminant(&self) -> u8 {
+        match self {
+            Self::Acted(_) => 0,
+            Self::Chat(_) => 1,
+        }
+    }
+
+    fn serialize_variant(&self, writer: &mut FieldWriter<'_>) {
+        match self {
+            Self::Acted(action) => writer.write(action),
+            Self::Chat(text) => writer.write_str(text),
+        }
+    }
+
+    fn deserialize_variant(discriminant: u8, reader: &mut FieldReader<'_>) -> Result<Self> {
+        let body = match discriminant {
+            0 => Self::Acted(reader.read_field()?),
+            1 => Self::Chat(reader.read_field()?),
+            _ => return Err(Error::InvalidVariant(discriminant)),
+        };
+        reader.finish()?;
+        Ok(body)
+    }
+}
+
+crate::impl_enum_field!(Body);
+
+/// Every player's letters, kept by a server until the player acknowledges
+/// them, so that none is lost while they are offline or read twice.
+///
+/// Saved whole to the data directory after every change, as the id the
+/// next letter gets and then each letter after whom it is for:
+///
+/// ```text
+/// U64(next id) (Str(player) List(U64(id) Str(session) Str(from) Enum(body)))...
+/// ```
+#[derive(Debug, Default)]
+pub struct Mailboxes {
+    /// Where they are saved, or nowhere.
+    path: Option<PathBuf>,
+    mail: Mutex<Mail>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Mail {
+    next: u64,
+    letters: BTreeMap<String, Vec<Letter>>,
+}
+
+impl Mailboxes {
+    /// The mailboxes saved in `dir`, which has none if none were.
+    pub fn load(dir: &Path) -> Result<Self> {
+        let path = dir.join(FILENAME);
+        let mail = match fs::read(&path) {
+            Ok(bytes) => from_bytes(&bytes)?,
+            Err(err) if err.kind() == ErrorKind::NotFound => Mail::default(),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(Self {
+            path: Some(path),
+            mail: Mutex::new(mail),
+        })
+    }
+
+    /// Posts a letter from `from` about `session` to each of `to`.
+    pub fn post(&self, to: &[&str], session: &str, from: &str, body: Body) -> Result<()> {
+        if to.is_empty() {
+            return Ok(());
+        }
+        let mut mail = self.lock();
+        for player in to {
+            let letter = Letter {
+                id: mail.next,
+                session: session.to_string(),
+                from: from.to_string(),
+                body: body.clone(),
+            };
+            mail.next += 1;
+            mail.letters
+                .entry(player.to_string())
+                .or_default()
+                .push(letter);
+        }
+        self.save(&mail)
+    }
+
+    /// The oldest of `player`'s letters, up to `MAIL_BATCH` of them.
+    pub fn fetch(&self, player: &str) -> Vec<Letter> {
+        let mail = self.lock();
+        let letters = mail.letters.get(player).map_or(&[][..], Vec::as_slice);
+        letters.iter().take(MAIL_BATCH).cloned().collect()
+    }
+
+    /// Throws away `player`'s letters up to and including the one with id
+    /// `last`, returning how many there were.
+    pub fn acknowledge(&self, player: &str, last: u64) -> Result<usize> {
+        let mut mail = self.lock();
+        let Some(letters) = mail.letters.get_mut(player) else {
+            return Ok(0);
+        };
+        let read = letters
+            .iter()
+            .take_while(|letter| letter.id <= last)
+            .count();
+        letters.drain(..read);
+        if letters.is_empty() {
+            mail.letters.remove(player);
+        }
+        if read > 0 {
+            self.save(&mail)?;
+        }
+        Ok(read)
+    }
+
+    fn save(&self, mail: &Mail) -> Result<()> {
+        match &self.path {
+            Some(path) => crate::atomic::write(path, &mail.serialize()),
+            None => Ok(()),
+        }
+    }
+
+    /// The mail, whether or not a thread panicked holding it, as nothing
+    /// done while holding it can panic partway through a change.
+    fn lock(&self) -> MutexGuard<'_, Mail> {
+        self.mail.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl Serialize for Mail {
+    fn serialize_to(&self, writer: &mut FieldWriter<'_>) {
+        writer.write_u64(self.next);
+        for (player, letters) in &self.letters {
+            for letter in letters {
+                writer.write_str(player);
+                writer.write(letter);
+            }
+        }
+    }
+}
+
+impl Deserialize for Mail {
+    fn deserialize(reader: &mut FieldReader<'_>) -> Result<Self> {
+        let mut mail = Mail {
+            next: reader.read_field()?,
+            letters: BTreeMap::new(),
+        };
+        while !reader.is_empty() {
+            let player: String = reader.read_field()?;
+            let letter: Letter = reader.read_field()?;
+            mail.letters.entry(player).or_default().push(letter);
+        }
+        Ok(mail)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Body, Mailboxes};
+    use crate::actions::{Action, ActionKind};
+    use crate::temp_dir::TempDir;
+
+    #[test]
+    fn letters_wait_until_acknowledged() {
+        let dir = TempDir::new("mail");
+        let mail = Mailboxes::load(&dir).unwrap();
+        let fight = Action::new(ActionKind::Fight, "troll".to_string()).unwrap();
+        mail.post(
+            &["bob", "carol"],
+            "game",
+            "alice",
+            Body::Acted(fight.clone()),
+        )
+        .unwrap();
+        mail.post(&["bob"], "game", "carol", Body::Chat("hi".to_string()))
+            .unwrap();
+
+        let letters = mail.fetch("bob");
+        assert_eq!(letters.len(), 2);
+        assert_eq!(letters[0].to_string(), format!("[game] alice: {fight}"));
+        assert_eq!(letters[1].to_string(), "[game] carol says \"hi\"");
+        assert_eq!(mail.acknowledge("bob", letters[0].id).unwrap(), 1);
+        assert_eq!(mail.fetch("bob"), letters[1..]);
+
+        // Letters not yet acknowledged survive a restart; those that were
+        // do not come back.
+        let reloaded = Mailboxes::load(&dir).unwrap();
+        assert_eq!(reloaded.fetch("bob"), letters[1..]);
+        assert_eq!(reloaded.fetch("carol").len(), 1);
+        assert!(reloaded.fetch("alice").is_empty());
+    }
+}
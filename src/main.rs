@@ -1,89 +1,1685 @@
-//use std::io::Cursor;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::fs;
+use std::io::{stderr, stdin, stdout, IsTerminal, Write};
+use std::net::TcpListener;
+use std::path::Path;
+use std::process::ExitCode;
+use std::thread;
+use std::time::Duration;
 
-use args::Args;
-use error::Result;
-use serde::{serialize, Deserialize, Field, FieldReader, Serialize};
-use session::Session;
+use relay_code::actions::{Action, ActionKind, Param};
+use relay_code::archetypes;
+use relay_code::archive::Archive;
+use relay_code::args::{
+    parse_actions, Args, EntityCommand, FactionCommand, LobbyCommand, Options, PlayerCommand,
+    RelCommand, ReplayCommand, TurnCommand,
+};
+use relay_code::client::Client;
+use relay_code::completions::{self, Candidates};
+use relay_code::components::Ai;
+use relay_code::config::Config;
+use relay_code::error::{Error, Result};
+use relay_code::experience::Leveling;
+use relay_code::history::history;
+use relay_code::json::{Json, ToJson};
+use relay_code::macros::Macros;
+use relay_code::mailbox::Letter;
+use relay_code::map::Map;
+use relay_code::outcome::ActionOutcome;
+use relay_code::passphrase::Passphrases;
+use relay_code::players::Player;
+use relay_code::protocol::{Carrier, TlsServer};
+use relay_code::provider::{self, LocalSessions, SessionProvider};
+use relay_code::proxy::proxy;
+use relay_code::replay::Replay;
+use relay_code::resolve::resolve;
+use relay_code::serde::Serialize;
+use relay_code::server;
+use relay_code::session::Session;
+use relay_code::signing::Keyring;
+use relay_code::stats::Stats;
+#[cfg(feature = "sqlite")]
+use relay_code::store::SqliteStore;
+use relay_code::store::{FsStore, SessionLock, SessionStore};
+use relay_code::style::{self, paint, Style, Table};
+use relay_code::suggest;
+use relay_code::sync::sync;
+use relay_code::timestamp::Timestamp;
+use relay_code::tokens::Tokens;
+use relay_code::turnfile::{Exchanges, TurnFile};
+use relay_code::turns::format_time;
+use relay_code::verbosity::{self, Level};
+use relay_code::version::Version;
+use relay_code::visibility;
+use relay_code::webhook;
+use relay_code::{atomic, doctor, paths, recent, usage, Entity};
 
-pub mod actions;
-pub mod args;
-pub mod error;
-pub mod serde;
-pub mod session;
+/// Subdirectory of the data directory holding session files used as
+/// templates by `new --template`.
+const TEMPLATES: &str = "templates";
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct Entity {
-    pub name: String,
-    field_b: u8,
-    field_c: bool,
+/// How often `watch` asks the server for new events.
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+#[cfg(feature = "sqlite")]
+const DATABASE: &str = "relay_code.db";
+
+fn print_help(command: Option<&str>) {
+    if let Some(usage) = command.and_then(usage::usage) {
+        println!("{}", usage::command_help(usage));
+        if matches!(usage.command, "action" | "queue") {
+            println!();
+            print_actions();
+        }
+        return;
+    }
+    println!("{}", usage::help());
+    println!();
+    println!("Where <name> is optional it defaults to the last session used, and");
+    println!("it may always be given as --session <name> instead.");
+    print_actions();
+    println!();
+    println!("ENVIRONMENT");
+    println!("  RELAY_CODE_DATA_DIR | Data directory when --data-dir is not given");
+    println!("  RELAY_CODE_BACKUPS | Backups kept per session on save (default 3)");
+    println!("  RELAY_CODE_PASSPHRASE | Passphrase for encrypted sessions");
+    println!("  RELAY_CODE_TOKEN   | Server token when --token is not given");
+    println!("  RELAY_CODE_DELTAS  | Saves appended as deltas before a session is");
+    println!("                       rewritten in full (default 0)");
+    println!("  NO_COLOR           | Never color output when set and not empty");
+    println!();
+    println!("EXIT STATUS");
+    println!("  0 success, 1 any other failure, 2 not found, 3 invalid arguments or");
+    println!("  config, 4 corrupt or unsigned file, 5 session locked, 6 network error,");
+    println!("  7 token refused or required, 8 throttled by the server");
+    println!();
+    println!("CONFIG");
+    println!("  ~/.config/relay_code/config.toml, or the --config file, may set:");
+    println!("  data_dir = \"<dir>\"   session = \"<name>\"   player = \"<player>\"");
+    println!("  color, sqlite, keyring or encrypt = true|false");
+    println!("  webhook = \"<url>\", any number of times, to POST JSON to when a");
+    println!("  turn begins after resolve, and when serve takes an action");
+    println!("  rate_limit, player_rate_limit, max_message or max_sessions = <n>,");
+    println!("  what serve lets each connection or player send; 0 for no limit");
+    println!("  idle_timeout = <seconds> a connection to serve may be silent");
+    println!("  Flags and the environment override it.");
+}
+
+fn print_actions() {
+    println!("<action> is fight, love, neutral, attack [damage=<n>], defend,");
+    println!("trade with=<entity> [amount=<n>], rest, inspect, say text=<words>,");
+    println!("give, drop or use item=<item> [count=<n>], or transfer item=<item>");
+    println!("with=<entity> [count=<n>], or move direction=north|south|east|west");
+    println!("[steps=<n>], or equip item=<item>, or unequip slot=<slot>. Give makes");
+    println!("equipment with slot=weapon|armor|trinket and bonuses such as attack=2.");
+}
+
+/// How commands print what they did: as text, or as JSON with `--json`.
+#[derive(Debug, Clone, Copy)]
+struct Output {
+    json: bool,
+    /// Set by `--yes`; nothing is asked.
+    yes: bool,
 }
 
-impl Entity {
-    pub fn new(name: String) -> Self {
-        Self {
-            name,
-            field_b: 0,
-            field_c: false,
+impl Output {
+    /// Prints `message`, or `{"message": message}`, unless `-q` was given.
+    fn say(self, message: impl Display) {
+        if verbosity::level() == Level::Quiet && !self.json {
+            return;
+        }
+        if self.json {
+            println!("{}", Json::message(message));
+        } else {
+            println!("{message}");
+        }
+    }
+
+    /// Asks a yes/no question on stdin, defaulting to no, unless `--yes`
+    /// answered it already. The question goes to stderr, so as not to mix
+    /// with JSON.
+    fn confirm(self, prompt: impl Display) -> Result<bool> {
+        if self.yes {
+            return Ok(true);
+        }
+        eprint!("{prompt} [y/N] ");
+        stderr().flush()?;
+        let mut answer = String::new();
+        stdin().read_line(&mut answer)?;
+        Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+    }
+
+    /// Prints `value` as JSON, or else has `text` print it.
+    fn show<T: ToJson + ?Sized>(self, value: &T, text: impl FnOnce(&T)) {
+        if self.json {
+            println!("{}", value.to_json());
+        } else {
+            text(value);
         }
     }
 }
 
-impl Serialize for Entity {
-    fn serialize(&self) -> Vec<u8> {
-        let mut bytes = vec![];
-        serialize(&mut bytes, Field::Str(&self.name));
-        serialize(&mut bytes, Field::Byte(self.field_b));
-        serialize(&mut bytes, Field::Bool(self.field_c));
-        bytes
+/// What an action did, with values before a change in red and after it in
+/// green, every line after `indent`.
+fn print_outcome(outcome: &ActionOutcome, indent: &str) {
+    for message in &outcome.messages {
+        println!("{indent}{message}");
+    }
+    for change in &outcome.changes {
+        let before = paint(&change.before, Style::Red);
+        let after = paint(&change.after, Style::Green);
+        println!(
+            "{indent}  {} {}: {before} -> {after}",
+            change.entity, change.field
+        );
+    }
+    for roll in &outcome.rolls {
+        println!("{indent}  {}", paint(roll, Style::Yellow));
     }
 }
 
-impl Deserialize for Entity {
-    fn deserialize(reader: &mut FieldReader<'_>) -> Result<Self>
-    where
-        Self: Sized,
-    {
-        let entity = Self {
-            name: reader.read_field()?,
-            field_b: reader.read_field()?,
-            field_c: reader.read_field()?,
+/// Lists sessions carrying all of `tags`, reading only their metadata.
+/// Sessions that fail to load are shown with the error unless filtering,
+/// since their tags are unknown.
+fn list_sessions(out: Output, store: &dyn SessionStore, tags: &[String]) -> Result<()> {
+    let mut rows = vec![];
+    for info in store.list()? {
+        let (entities, session_tags) = match store.metadata(&info.name) {
+            Ok(metadata) => (Ok(metadata.entities), metadata.tags),
+            Err(_) if !tags.is_empty() => continue,
+            Err(err) => (Err(err), vec![]),
         };
+        if tags.iter().all(|tag| session_tags.contains(tag)) {
+            rows.push((info, entities, session_tags));
+        }
+    }
+    if out.json {
+        let rows: Vec<_> = rows
+            .iter()
+            .map(|(info, entities, tags)| {
+                let (entities, error) = match entities {
+                    Ok(entities) => (entities.to_json(), Json::Null),
+                    Err(err) => (Json::Null, err.to_string().to_json()),
+                };
+                Json::object([
+                    ("name", info.name.to_json()),
+                    ("entities", entities),
+                    ("size", info.size.to_json()),
+                    ("modified", info.modified.to_string().to_json()),
+                    ("tags", tags.to_json()),
+                    ("error", error),
+                ])
+            })
+            .collect();
+        println!("{}", Json::Array(rows));
+        return Ok(());
+    }
+    if rows.is_empty() {
+        println!("no sessions");
+        return Ok(());
+    }
+
+    let mut table = Table::new(&["NAME", ">ENTITIES", ">SIZE", "MODIFIED", "TAGS"]);
+    for (info, entities, tags) in rows {
+        let entities = match entities {
+            Ok(entities) => entities.to_string(),
+            Err(err) => format!("<{err}>"),
+        };
+        table.row(vec![
+            info.name,
+            entities,
+            format!("{} B", info.size),
+            info.modified.to_string(),
+            tags.join(","),
+        ]);
+    }
+    print!("{table}");
+    Ok(())
+}
+
+fn entity_command(out: Output, store: &dyn SessionStore, command: EntityCommand) -> Result<()> {
+    match command {
+        EntityCommand::Add(name, entity, stats, behavior) => {
+            let _lock = store.lock(&name)?;
+            let mut session = store.load(&name)?;
+            let mut entity = Entity::new(entity);
+            entity.stats = stats;
+            entity.ai = behavior.map(|behavior| Ai { behavior });
+            session.add_entity(entity)?;
+            session.save(store)?;
+            out.say("entity added");
+        }
+        EntityCommand::Remove(name, entity) => {
+            let _lock = store.lock(&name)?;
+            let mut session = store.load(&name)?;
+            let entity = resolve(session.entities(), &entity)?.name.clone();
+            session.remove_entity(&entity)?;
+            session.save(store)?;
+            out.say("entity removed");
+        }
+        EntityCommand::Show(name, None) => {
+            let session = store.load(&name)?;
+            out.show(session.entities(), |entities| {
+                if entities.is_empty() {
+                    println!("no entities");
+                }
+                for entity in entities {
+                    println!("{}  {entity}", entity.id);
+                }
+            });
+        }
+        EntityCommand::Show(name, Some(entity)) => {
+            let session = store.load(&name)?;
+            let entity = resolve(session.entities(), &entity)?;
+            out.show(entity, |entity| println!("{}  {entity}", entity.id));
+        }
+        EntityCommand::Set(name, entity, attributes) => {
+            let _lock = store.lock(&name)?;
+            let mut session = store.load(&name)?;
+            for (key, value) in attributes {
+                match value {
+                    Some(value) => session.set_attribute(&entity, key, value)?,
+                    None => session.unset_attribute(&entity, key)?,
+                }
+            }
+            session.save(store)?;
+            out.say("attributes set");
+        }
+        EntityCommand::Place(name, entity, position) => {
+            let _lock = store.lock(&name)?;
+            let mut session = store.load(&name)?;
+            session.place(&entity, position)?;
+            session.save(store)?;
+            out.say(format!("entity placed at {position}"));
+        }
+        EntityCommand::Own(name, entity, owner) => {
+            let _lock = store.lock(&name)?;
+            let mut session = store.load(&name)?;
+            session.own(&entity, owner.clone())?;
+            session.save(store)?;
+            match owner {
+                Some(owner) => out.say(format!("entity owned by {owner}")),
+                None => out.say("entity has no owner"),
+            }
+        }
+        EntityCommand::Get(name, entity, Some(key)) => {
+            let session = store.load(&name)?;
+            let entity = resolve(session.entities(), &entity)?;
+            let value = entity
+                .attributes
+                .get(&key)
+                .ok_or_else(|| Error::AttributeNotFound(entity.name.clone(), key))?;
+            out.show(value, |value| println!("{value}"));
+        }
+        EntityCommand::Get(name, entity, None) => {
+            let session = store.load(&name)?;
+            let entity = resolve(session.entities(), &entity)?;
+            out.show(&entity.attributes, |attributes| {
+                if attributes.is_empty() {
+                    println!("no attributes");
+                }
+                for (key, value) in attributes {
+                    println!("{key} = {value}");
+                }
+            });
+        }
+    }
+    Ok(())
+}
 
-        Ok(entity)
+fn rel_command(out: Output, store: &dyn SessionStore, command: RelCommand) -> Result<()> {
+    match command {
+        RelCommand::Show(name, entity) => {
+            let session = store.load(&name)?;
+            let entity = resolve(session.entities(), &entity)?;
+            let relationships: Vec<_> = session
+                .relationships_of(entity.id)
+                .map(|relationship| relationship.describe(session.entities()))
+                .collect();
+            out.show(&relationships, |relationships| {
+                if relationships.is_empty() {
+                    println!("{} has no relationships", entity.name);
+                }
+                for relationship in relationships {
+                    println!("{relationship}");
+                }
+            });
+        }
+        RelCommand::Add(name, from, relation, to) => {
+            let _lock = store.lock(&name)?;
+            let mut session = store.load(&name)?;
+            session.relate(&from, relation, &to)?;
+            session.save(store)?;
+            out.say("relationship added");
+        }
+        RelCommand::Remove(name, from, relation, to) => {
+            let _lock = store.lock(&name)?;
+            let mut session = store.load(&name)?;
+            session.unrelate(&from, relation, &to)?;
+            session.save(store)?;
+            out.say("relationship removed");
+        }
     }
+    Ok(())
 }
 
-fn print_help() {
-    println!("HELP!");
-    println!("-----");
-    println!("  -h, --help        | Show this help");
-    println!("  new <name>        | Create a new session");
-    println!("  load <name>       | Load a session");
-    println!("  action <action>   | Act upon a session");
+fn faction_command(out: Output, store: &dyn SessionStore, command: FactionCommand) -> Result<()> {
+    match command {
+        FactionCommand::Show(name) => {
+            let session = store.load(&name)?;
+            let factions = session.factions();
+            let members = |faction: &String| -> Vec<_> {
+                session
+                    .entities()
+                    .iter()
+                    .filter(|entity| entity.faction.as_ref() == Some(faction))
+                    .map(|entity| entity.name.as_str())
+                    .collect()
+            };
+            if out.json {
+                let factions: Vec<_> = factions
+                    .iter()
+                    .map(|faction| {
+                        let stances = factions
+                            .iter()
+                            .filter(|other| *other != faction)
+                            .map(|other| {
+                                let stance = session.stance(faction, other).to_string();
+                                (other.clone(), stance.to_json())
+                            })
+                            .collect();
+                        Json::object([
+                            ("name", faction.to_json()),
+                            ("members", members(faction).to_json()),
+                            ("stances", Json::Object(stances)),
+                        ])
+                    })
+                    .collect();
+                println!("{}", Json::Array(factions));
+                return Ok(());
+            }
+            if factions.is_empty() {
+                println!("no factions");
+            }
+            for (i, faction) in factions.iter().enumerate() {
+                println!("{faction}: {}", members(faction).join(" "));
+                for other in factions.iter().skip(i + 1) {
+                    println!("  {} towards {other}", session.stance(faction, other));
+                }
+            }
+        }
+        FactionCommand::Add(name, faction) => {
+            let _lock = store.lock(&name)?;
+            let mut session = store.load(&name)?;
+            session.define_faction(faction)?;
+            session.save(store)?;
+            out.say("faction added");
+        }
+        FactionCommand::Join(name, entity, faction) => {
+            let _lock = store.lock(&name)?;
+            let mut session = store.load(&name)?;
+            session.join_faction(&entity, faction)?;
+            session.save(store)?;
+            out.say("faction changed");
+        }
+        FactionCommand::Stance(name, a, b, stance) => {
+            let _lock = store.lock(&name)?;
+            let mut session = store.load(&name)?;
+            session.set_stance(&a, &b, stance)?;
+            session.save(store)?;
+            out.say(format!("{a} and {b} are {stance}"));
+        }
+    }
+    Ok(())
 }
 
-fn main() -> Result<()> {
-    let args = Args::parse()?;
+fn player_command(out: Output, store: &dyn SessionStore, command: PlayerCommand) -> Result<()> {
+    match command {
+        PlayerCommand::List(name) => {
+            let session = store.load(&name)?;
+            out.show(session.roster(), |roster| {
+                if roster.is_empty() {
+                    println!("no players");
+                }
+                for player in roster {
+                    println!("{}  {player}", player.id);
+                }
+            });
+        }
+        PlayerCommand::Add(name, player, key) => {
+            let _lock = store.lock(&name)?;
+            let mut session = store.load(&name)?;
+            session.add_player(Player::new(player, key))?;
+            session.save(store)?;
+            out.say("player added");
+        }
+        PlayerCommand::Remove(name, player) => {
+            let _lock = store.lock(&name)?;
+            let mut session = store.load(&name)?;
+            session.remove_player(&player)?;
+            session.save(store)?;
+            out.say("player removed");
+        }
+    }
+    Ok(())
+}
+
+/// Locks the source and destination of a rename or copy. Both being the same
+/// session would otherwise look like another process holding the lock.
+fn lock_pair(store: &dyn SessionStore, src: &str, dst: &str) -> Result<[SessionLock; 2]> {
+    if src == dst {
+        return Err(Error::SessionExists(dst.to_string()));
+    }
+    Ok([store.lock(src)?, store.lock(dst)?])
+}
+
+fn open_store<'a>(
+    dir: &Path,
+    keyring: &'a Keyring,
+    passphrases: &'a Passphrases,
+    sqlite: bool,
+) -> Result<Box<dyn SessionStore + 'a>> {
+    if !sqlite {
+        let store = FsStore::new(dir.to_path_buf(), keyring).with_passphrases(passphrases);
+        return Ok(Box::new(store));
+    }
+    #[cfg(feature = "sqlite")]
+    return Ok(Box::new(SqliteStore::open(&dir.join(DATABASE), keyring)?));
+    #[cfg(not(feature = "sqlite"))]
+    Err(Error::SqliteUnsupported)
+}
+
+/// Exits with the code `Error::exit_code` gives for any error, printed
+/// to stderr, or to stdout as JSON with `--json`.
+fn main() -> ExitCode {
+    let (options, args) = Args::parse();
+    verbosity::set_level(options.verbosity);
+    let terminal = stdout().is_terminal();
+    let color = style::wants_color(options.no_color, options.config.color, terminal);
+    style::set_color(color && !options.json);
+    let out = Output {
+        json: options.json,
+        yes: options.yes,
+    };
+    let Err(err) = args.and_then(|args| start(options, args, out)) else {
+        return ExitCode::SUCCESS;
+    };
+    if out.json {
+        println!("{}", Json::error(&err));
+    } else {
+        eprintln!("error: {err}");
+    }
+    ExitCode::from(err.exit_code())
+}
+
+fn start(options: Options, args: Args, out: Output) -> Result<()> {
+    let dir = paths::data_dir(options.data_dir, None)?;
+    if let Some(address) = &options.remote {
+        if !matches!(args, Args::Help(_) | Args::Version) {
+            let token = options
+                .token
+                .or_else(|| std::env::var("RELAY_CODE_TOKEN").ok());
+            let remote = Remote {
+                address,
+                token: token.as_deref(),
+                ca: options.ca.as_deref(),
+            };
+            return run_remote(out, args, &remote, &dir, &options.config);
+        }
+    }
+    let mut keyring = Keyring::load(&dir)?;
+    let passphrases = Passphrases::new(!options.no_keyring);
+
+    match args {
+        Args::KeyGen(player) => {
+            let player = player
+                .or(options.config.player)
+                .ok_or_else(|| Error::MissingArg("keygen".to_string(), "a <player>".to_string()))?;
+            let public_key = keyring.generate(player)?;
+            keyring.save(&dir)?;
+            out.say(format!("public key: {public_key}"));
+        }
+        Args::Trust(player, public_key) => {
+            keyring.trust(player, &public_key)?;
+            keyring.save(&dir)?;
+            out.say("key trusted");
+        }
+        Args::GenToken(player, spectator) => {
+            let mut tokens = Tokens::load(&dir)?;
+            let token = match spectator {
+                true => tokens.generate_spectator(player)?,
+                false => tokens.generate(player)?,
+            };
+            tokens.save(&dir)?;
+            out.say(format!("token: {token}"));
+        }
+        Args::Proxy(listen, upstream) => {
+            let listener = TcpListener::bind(&listen)?;
+            out.say(format!("relaying {} to {upstream}", listener.local_addr()?));
+            proxy(listener, &upstream, &|line| println!("{line}"))?;
+        }
+        Args::Serve(bind, ws, tls) => {
+            let tls = match tls {
+                Some((cert, key)) => Some(TlsServer::load(&cert, &key)?),
+                None => None,
+            };
+            let (tcp, ws_scheme) = match tls {
+                Some(_) => ("tls://", "wss://"),
+                None => ("", "ws://"),
+            };
+            let listener = TcpListener::bind(&bind)?;
+            let mut serving = format!(
+                "serving {} on {tcp}{}",
+                dir.display(),
+                listener.local_addr()?
+            );
+            let mut listeners = vec![(listener, Carrier::Tcp)];
+            if let Some(ws) = ws {
+                if !cfg!(feature = "websocket") {
+                    return Err(Error::WebSocketUnsupported);
+                }
+                let listener = TcpListener::bind(&ws)?;
+                serving += &format!(" and {ws_scheme}{}", listener.local_addr()?);
+                listeners.push((listener, Carrier::WebSocket));
+            }
+            let tokens = Tokens::load(&dir)?;
+            if tokens.is_empty() {
+                serving += ", to anyone";
+            }
+            out.say(serving);
+            let (webhooks, limits) = (&options.config.webhooks, &options.config.limits);
+            let open = || open_store(&dir, &keyring, &passphrases, options.sqlite);
+            #[cfg(feature = "async-server")]
+            server::serve_async(
+                listeners,
+                &dir,
+                &tokens,
+                tls.as_ref(),
+                webhooks,
+                limits,
+                open,
+            )?;
+            #[cfg(not(feature = "async-server"))]
+            server::serve(
+                listeners,
+                &dir,
+                &tokens,
+                tls.as_ref(),
+                webhooks,
+                limits,
+                open,
+            )?;
+        }
+        Args::Sync(name, peer) => {
+            let store = open_store(&dir, &keyring, &passphrases, options.sqlite)?;
+            let token = options
+                .token
+                .or_else(|| std::env::var("RELAY_CODE_TOKEN").ok());
+            let client = Client::connect(&peer, token.as_deref(), options.ca.as_deref())?;
+            let local = LocalSessions::new(&dir, &*store);
+            let mut exchanges = Exchanges::load(&dir)?;
+            let synced = sync(&name, &local, &client, exchanges.exchanged(&name))
+                .map_err(|err| suggest_session(err, &*store))?;
+            exchanges.exchange(&name, synced.shared);
+            exchanges.save(&dir)?;
+            recent::remember(&dir, &name)?;
+            out.show(&synced, |synced| {
+                for conflict in &synced.received.conflicts {
+                    println!("conflict: {conflict}");
+                }
+                for conflict in &synced.sent.conflicts {
+                    println!("conflict at {peer}: {conflict}");
+                }
+                println!(
+                    "received {} events and sent {}",
+                    synced.received.applied, synced.sent.applied
+                );
+            });
+        }
+        args => {
+            let store = open_store(&dir, &keyring, &passphrases, options.sqlite)?;
+            let used = args.session().map(str::to_string);
+            let deleted = match &args {
+                Args::Delete(name) => Some(name.clone()),
+                _ => None,
+            };
+            let config = &options.config;
+            run(out, args, &dir, &*store, &keyring, &passphrases, config)
+                .map_err(|err| suggest_session(err, &*store))?;
+            if let Some(name) = used {
+                recent::remember(&dir, &name)?;
+            }
+            if let Some(name) = deleted {
+                recent::forget(&dir, &name)?;
+                let mut exchanges = Exchanges::load(&dir)?;
+                if exchanges.forget(&name) {
+                    exchanges.save(&dir)?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The commands that work the same on a server with `--remote`.
+fn run_provided(
+    out: Output,
+    args: Args,
+    sessions: &dyn SessionProvider,
+    dir: &Path,
+    config: &Config,
+) -> Result<()> {
+    match args {
+        Args::Action(name, kind, target, params, dry_run) => {
+            let name = session_name(out, dir, name, config)?;
+            let action = Action::new(kind, target)?.with_params(params);
+            let outcome = sessions.apply(&name, action, dry_run)?;
+            out.show(&outcome, |outcome| print_outcome(outcome, ""));
+            if dry_run && !out.json {
+                println!("dry run; nothing saved");
+            }
+        }
+        Args::Load(name, at) => {
+            let name = session_name(out, dir, name, config)?;
+            let mut session = sessions.load(&name)?;
+            if let Some(n) = at {
+                session = session.at(n)?;
+            }
+            relay_code::trace!("{session:?}");
+            out.say(format!(
+                "session {name:?} loaded with {} events",
+                session.log().len()
+            ));
+        }
+        Args::Show(name, None) => {
+            let session = sessions.load(&session_name(out, dir, name, config)?)?;
+            out.show(&session, show_session);
+        }
+        Args::Show(name, Some(entity)) => {
+            let session = sessions.load(&session_name(out, dir, name, config)?)?;
+            let entity = resolve(session.entities(), &entity)?;
+            out.show(entity, |entity| show_entity(&session, entity));
+        }
+        Args::Pass(name) => {
+            let next = sessions.pass(&session_name(out, dir, name, config)?)?;
+            out.say(format!("{next} to play"));
+        }
+        Args::New(name, None, None) => {
+            sessions.create(&name)?;
+            out.say("session saved");
+        }
+        Args::List(tags) if tags.is_empty() => {
+            out.show(&sessions.list()?, |names| {
+                for name in names {
+                    println!("{name}");
+                }
+            });
+        }
+        _ => unreachable!("only commands that work remotely are provided"),
+    }
+    Ok(())
+}
+
+/// Where `--remote` points and how to connect there.
+struct Remote<'a> {
+    address: &'a str,
+    token: Option<&'a str>,
+    ca: Option<&'a Path>,
+}
+
+/// Runs `args` against the `remote` server, for the commands that can,
+/// after printing any mail the player has.
+fn run_remote(out: Output, args: Args, remote: &Remote, dir: &Path, config: &Config) -> Result<()> {
+    let unsupported = |what: &str| Err(Error::RemoteUnsupported(what.to_string()));
+    match args {
+        Args::New(_, Some(_), _) => unsupported("new --template"),
+        Args::New(_, _, Some(_)) => unsupported("new --rules"),
+        Args::List(tags) if !tags.is_empty() => unsupported("list --tag"),
+        Args::Mail => {
+            let client = Client::connect(remote.address, remote.token, remote.ca)?;
+            if client.player().is_none() {
+                return Err(Error::Unauthenticated);
+            }
+            if out.json {
+                let mut letters = vec![];
+                read_mail(&client, |batch| letters.extend(batch))?;
+                out.show(&letters, |_| {});
+            } else if read_mail(&client, print_mail)? == 0 {
+                out.say("no mail");
+            }
+            Ok(())
+        }
+        Args::Ping => {
+            let client = Client::connect(remote.address, remote.token, remote.ca)?;
+            let took = client.ping()?;
+            out.say(format!(
+                "{} answered in {} ms",
+                remote.address,
+                took.as_millis()
+            ));
+            Ok(())
+        }
+        Args::Lobby(command) => {
+            let client = Client::connect(remote.address, remote.token, remote.ca)?;
+            match command {
+                LobbyCommand::Host(name, rule_set, slots) => {
+                    out.say(client.host(&name, &rule_set, slots)?)
+                }
+                LobbyCommand::List => out.show(&client.games()?, |games| {
+                    if games.is_empty() {
+                        println!("no games are waiting for players");
+                    }
+                    for game in games {
+                        let joined = format!("{}/{}", game.players.len(), game.slots);
+                        let players = game.players.join(", ");
+                        println!(
+                            "{:<16} {:<10} {joined:>5}  {players}",
+                            game.name, game.rule_set
+                        );
+                    }
+                }),
+                LobbyCommand::Join(name) => out.say(client.join(&name)?),
+            }
+            Ok(())
+        }
+        Args::Watch(name) => {
+            let name = session_name(out, dir, name, config)?;
+            let client = Client::connect(remote.address, remote.token, remote.ca)?;
+            client.spectate()?;
+            let mut seen = client.load(&name)?.log().len();
+            out.say(format!("watching {name} from event {}", seen + 1));
+            loop {
+                thread::sleep(WATCH_INTERVAL);
+                let turn = client.events(&name, seen)?;
+                let base = turn.base as usize;
+                for (n, event) in turn.events.iter().enumerate() {
+                    let number = base + n + 1;
+                    out.show(&event.to_string(), |event| println!("{number:>4}  {event}"));
+                }
+                seen = base + turn.events.len();
+            }
+        }
+        Args::Action(..)
+        | Args::Load(..)
+        | Args::Show(..)
+        | Args::New(..)
+        | Args::List(_)
+        | Args::Pass(_)
+        | Args::Chat(..) => {
+            let client = Client::connect(remote.address, remote.token, remote.ca)?;
+            // Mail waits for the next command when it would be lost in
+            // JSON or asked not to be printed.
+            if client.player().is_some() && !out.json && verbosity::level() > Level::Quiet {
+                read_mail(&client, print_mail)?;
+            }
+            match args {
+                Args::Chat(name, text) => {
+                    let name = session_name(out, dir, name, config)?;
+                    out.say(client.chat(&name, &text)?);
+                    Ok(())
+                }
+                args => run_provided(out, args, &client, dir, config),
+            }
+        }
+        _ => unsupported("this command"),
+    }
+}
+
+/// Hands the player's letters to `read` a batch at a time, telling the
+/// server each batch was read once `read` returns, and returns how many
+/// there were.
+fn read_mail(client: &Client, mut read: impl FnMut(Vec<Letter>)) -> Result<usize> {
+    let mut count = 0;
+    loop {
+        let letters = client.mail()?;
+        let Some(last) = letters.last().map(|letter| letter.id) else {
+            return Ok(count);
+        };
+        count += letters.len();
+        read(letters);
+        client.acknowledge(last)?;
+    }
+}
 
+fn print_mail(letters: Vec<Letter>) {
+    for letter in letters {
+        println!("{}", paint(&letter, Style::Yellow));
+    }
+}
+
+/// A session not found, suggesting the saved session closest in name.
+fn suggest_session(err: Error, store: &dyn SessionStore) -> Error {
+    let Error::SessionNotFound(name) = &err else {
+        return err;
+    };
+    let name = name.clone();
+    let sessions = store.list().unwrap_or_default();
+    let names = sessions.iter().map(|session| session.name.as_str());
+    suggest::did_you_mean(err, &name, names)
+}
+
+/// The given session name, or else the most recently used one, announced
+/// so it is clear which session the command acts on. The config file can
+/// name a session to use instead. JSON output and `-q` leave the
+/// announcement out.
+fn session_name(out: Output, dir: &Path, name: Option<String>, config: &Config) -> Result<String> {
+    if name.is_some() {
+        return recent::resolve(dir, name);
+    }
+    let name = recent::resolve(dir, config.session.clone())?;
+    if !out.json && verbosity::level() > Level::Quiet {
+        println!("session {name:?}");
+    }
+    Ok(name)
+}
+
+/// The rules, then what each action kind requires.
+fn show_rules(out: Output, session: &Session) {
+    if out.json {
+        let rules = Json::object([
+            ("rule_set", session.rule_set().name().to_json()),
+            ("rules", session.rules().to_json()),
+            ("requirements", requirements(session).to_json()),
+        ]);
+        println!("{rules}");
+        return;
+    }
+    println!("plays by {} rules", session.rule_set().name());
+    println!("{}", session.rules());
+    for (kind, prerequisites) in requirements(session) {
+        println!("{kind} requires {}", prerequisites.join(" and "));
+    }
+}
+
+/// What each action kind requires, spelled out.
+fn requirements(session: &Session) -> BTreeMap<ActionKind, Vec<String>> {
+    session
+        .requirements()
+        .iter()
+        .map(|(kind, prerequisites)| {
+            let prerequisites = prerequisites.iter().map(ToString::to_string).collect();
+            (*kind, prerequisites)
+        })
+        .collect()
+}
+
+fn show_session(session: &Session) {
+    println!("name:     {}", session.name());
+    println!("created:  {}", session.created());
+    println!("modified: {}", session.modified());
+    println!("tags:     {}", session.tags().join(" "));
+    println!("events:   {}", session.log().len());
+    match session.action() {
+        Some(action) => println!("action:   {} {}", action.kind(), action.target()),
+        None => println!("action:   none"),
+    }
+    let turn = session.turn();
+    println!(
+        "turn:     {}, {} action(s) taken",
+        turn.number, turn.actions
+    );
+    println!("rules:    {}", session.rule_set().name());
+    if let Some(over) = session.over() {
+        println!("over:     {}", paint(over, Style::Green));
+    }
+    if let Some(player) = session.redacted() {
+        println!("seen as:  {player}, with what they cannot see left out");
+    }
+    if !session.roster().is_empty() {
+        let names: Vec<_> = session.roster().iter().map(|p| p.name.as_str()).collect();
+        println!("players:  {}", names.join(", "));
+    }
+    let order = session.order();
+    if let Some(current) = order.current() {
+        print!(
+            "turns:    {}; {current} to play, turn {}",
+            order.players().join(", "),
+            order.number()
+        );
+        match (order.deadline(), Timestamp::now()) {
+            (Some(deadline), Ok(now)) if deadline <= now => println!(", out of time"),
+            (Some(deadline), Ok(now)) => {
+                let left = (deadline.as_millis() - now.as_millis()).div_ceil(1000);
+                println!(", {} left", format_time(left as u64));
+            }
+            _ => println!(),
+        }
+    }
+    println!("queued:   {}", session.queued().len());
+    for (n, action) in session.queued().iter().enumerate() {
+        println!("  {}. {action}", n + 1);
+    }
+    if let Some(map) = session.map() {
+        println!("map:      {map}");
+    }
+    println!("entities: {}", session.entities().len());
+    print_entities(session.entities());
+    if !session.graveyard().is_empty() {
+        println!("dead:     {}", session.graveyard().len());
+        for grave in session.graveyard() {
+            println!("  {}", paint(grave, Style::Red));
+        }
+    }
+}
+
+/// A table of `entities`' stats and notes, if there are any.
+fn print_entities(entities: &[Entity]) {
+    if entities.is_empty() {
+        return;
+    }
+    let mut table = Table::new(&["  NAME", ">HP", ">ATK", ">DEF", ">SPD", ">LVL", "NOTES"]);
+    for entity in entities {
+        let stats = entity.effective_stats();
+        table.row(vec![
+            format!("  {}", entity.name),
+            stats.hp.to_string(),
+            stats.attack.to_string(),
+            stats.defense.to_string(),
+            stats.speed.to_string(),
+            entity.experience.level.to_string(),
+            notes(entity).join(", "),
+        ]);
+    }
+    print!("{table}");
+}
+
+/// Prints `replay` a turn at a time, waiting for Enter between turns if
+/// `step`.
+fn play_replay(replay: &Replay, step: bool) -> Result<()> {
+    let exported = replay.exported;
+    println!("replay of {:?}, exported {exported}", replay.session);
+    println!("start:    {} entities", replay.start.len());
+    print_entities(&replay.start);
+    let turns: Vec<_> = replay.turns().collect();
+    for (n, turn) in turns.iter().enumerate() {
+        if step && n > 0 {
+            eprint!(
+                "-- Enter for turn {} of {}, q to stop -- ",
+                n + 1,
+                turns.len()
+            );
+            stderr().flush()?;
+            let mut answer = String::new();
+            stdin().read_line(&mut answer)?;
+            if answer.trim() == "q" {
+                return Ok(());
+            }
+        }
+        println!("{}", paint(format!("turn {}", n + 1), Style::Bold));
+        for entry in *turn {
+            let event = paint(&entry.event, Style::Bold);
+            println!("{:>4}  {event}", entry.number);
+            print_outcome(&entry.outcome, "      ");
+        }
+    }
+    println!("end:      {} entities", replay.end.len());
+    print_entities(&replay.end);
+    if let Some(over) = &replay.over {
+        println!("over:     {}", paint(over, Style::Green));
+    }
+    Ok(())
+}
+
+/// What `show` lists after an entity's stats: whatever else it has.
+fn notes(entity: &Entity) -> Vec<String> {
+    let mut notes = vec![];
+    if entity.guarding {
+        notes.push("guarding".to_string());
+    }
+    notes.extend(entity.effects.iter().map(ToString::to_string));
+    if let Some(faction) = &entity.faction {
+        notes.push(format!("faction {faction}"));
+    }
+    if let Some(owner) = &entity.owner {
+        notes.push(format!("owned by {owner}"));
+    }
+    if let Some(ai) = &entity.ai {
+        notes.push(format!("ai {}", ai.behavior));
+    }
+    if let Some(position) = &entity.position {
+        notes.push(format!("at {position}"));
+    }
+    notes.extend(
+        entity
+            .equipment
+            .values()
+            .map(|item| format!("equipped {item}")),
+    );
+    notes.extend(
+        entity
+            .inventory
+            .iter()
+            .map(|item| format!("carrying {item}")),
+    );
+    notes
+}
+
+/// Everything about one entity, a line per component it has.
+fn show_entity(session: &Session, entity: &Entity) {
+    println!("name:       {}", entity.name);
+    println!("id:         {}", entity.id);
+    println!("stats:      {}", entity.stats);
+    if !entity.equipment.is_empty() {
+        println!("equipped:   {}", entity.effective_stats());
+    }
+    println!("experience: {}", entity.experience);
+    if entity.guarding {
+        println!("guarding:   yes");
+    }
+    if !entity.effects.is_empty() {
+        let effects: Vec<_> = entity.effects.iter().map(ToString::to_string).collect();
+        println!("effects:    {}", effects.join(", "));
+    }
+    println!("resources:  {}", entity.resources);
+    if let Some(position) = &entity.position {
+        println!("position:   {position}");
+    }
+    if let Some(ai) = &entity.ai {
+        println!("ai:         {}", ai.behavior);
+    }
+    if let Some(faction) = &entity.faction {
+        println!("faction:    {faction}");
+    }
+    if let Some(owner) = &entity.owner {
+        println!("owner:      {owner}");
+    }
+    println!("inventory:  {}", entity.inventory.len());
+    for item in &entity.inventory {
+        println!("  {item}");
+    }
+    for (slot, item) in &entity.equipment {
+        println!("{:<12}{item}", format!("{slot}:"));
+    }
+    println!("attributes: {}", entity.attributes.len());
+    for (key, value) in &entity.attributes {
+        println!("  {key} = {value}");
+    }
+    let relationships: Vec<_> = session.relationships_of(entity.id).collect();
+    println!("relations:  {}", relationships.len());
+    for relationship in relationships {
+        println!("  {}", relationship.describe(session.entities()));
+    }
+}
+
+/// Runs every subcommand that works on sessions rather than keys.
+fn run(
+    out: Output,
+    args: Args,
+    dir: &Path,
+    store: &dyn SessionStore,
+    keyring: &Keyring,
+    passphrases: &Passphrases,
+    config: &Config,
+) -> Result<()> {
     //let session = Session::load().unwrap();
     match args {
-        Args::Help => print_help(),
-        Args::Action(kind, target) => {
-            eprintln!("args are {kind:?} and {target}");
-            let session = Session::load()?;
-            eprintln!("Session comprises of: {session:?}");
-        }
-        Args::New(name) => {
-            let entity = Entity::new(name);
-            let session = Session::new(entity)?;
-            session.save()?;
-            println!("session saved");
-        }
-        Args::Load(name) => {
-            eprintln!("name is {name:?}");
-            let entity = Session::load()?;
-            eprintln!("{entity:?}");
+        Args::Help(command) => print_help(command.as_deref()),
+        Args::Version => out.show(&Version::current(), |version| println!("{version}")),
+        Args::Completions(shell) => print!("{}", completions::script(shell)),
+        Args::Complete(Candidates::Sessions) => {
+            for info in store.list()? {
+                println!("{}", info.name);
+            }
+        }
+        Args::Complete(candidates) => {
+            for candidate in completions::candidates(candidates) {
+                println!("{candidate}");
+            }
+        }
+        args @ (Args::Action(..) | Args::Load(..) | Args::Show(..) | Args::Pass(_)) => {
+            let player = config.player.clone();
+            let local = LocalSessions::new(dir, store);
+            let sessions = local.by(player.as_deref());
+            run_provided(out, args, &sessions, dir, config)?;
+        }
+        Args::Players(name, None) => {
+            let session = store.load(&name)?;
+            let order = session.order();
+            out.show(order, |order| match order.current() {
+                Some(current) => println!(
+                    "turn {}, {current} to play, of {}",
+                    order.number(),
+                    order.players().join(", ")
+                ),
+                None => println!("anyone may act at any time"),
+            });
+        }
+        Args::Deadline(name, time, forfeit) => {
+            let _lock = store.lock(&name)?;
+            let mut session = store.load(&name)?;
+            session.set_turn_time(time, forfeit)?;
+            session.save(store)?;
+            match time {
+                Some(time) if forfeit => out.say(format!(
+                    "turns last {}; players who run out of time forfeit",
+                    format_time(u64::from(time))
+                )),
+                Some(time) => out.say(format!("turns last {}", format_time(u64::from(time)))),
+                None => out.say("turns are untimed"),
+            }
+        }
+        Args::CheckDeadlines => {
+            let sessions = LocalSessions::new(dir, store);
+            for name in sessions.list()? {
+                match sessions.time_out(&name) {
+                    Ok(Some(player)) => out.say(format!("{player} ran out of time in {name}")),
+                    Ok(None) => {}
+                    Err(err) => relay_code::warn!("{name}: {err}"),
+                }
+            }
+        }
+        Args::Players(name, Some(players)) => {
+            let _lock = store.lock(&name)?;
+            let mut session = store.load(&name)?;
+            session.set_players(players)?;
+            session.save(store)?;
+            match session.order().current() {
+                Some(current) => out.say(format!("{current} to play")),
+                None => out.say("anyone may act at any time"),
+            }
+        }
+        Args::Actions(name, force, dry_run) => {
+            let name = session_name(out, dir, name, config)?;
+            let text = std::io::read_to_string(stdin())?;
+            let actions = parse_actions(&text, &Macros::load(dir)?)?;
+            let _lock = store.lock(&name)?;
+            let mut session = store.load(&name)?;
+            let mut outcomes = vec![];
+            for (kind, target, mut params) in actions {
+                if force {
+                    params.insert("force".to_string(), Param::Int(1));
+                }
+                let action = Action::new(kind, target)?.with_params(params);
+                provider::adopt_script(dir, &mut session, &action)?;
+                relay_code::verbose!("applying {action} to session {name:?}");
+                outcomes.push(match &config.player {
+                    Some(player) => session.apply_by(player, action)?,
+                    None => session.apply(action)?,
+                });
+            }
+            if !dry_run {
+                session.save(store)?;
+            }
+            out.show(&outcomes, |outcomes| {
+                for outcome in outcomes {
+                    print_outcome(outcome, "");
+                }
+            });
+            if dry_run && !out.json {
+                println!("dry run; nothing saved");
+            }
+        }
+        Args::Queue(name, actions) => {
+            let _lock = store.lock(&name)?;
+            let mut session = store.load(&name)?;
+            for (kind, target, params) in actions {
+                let action = Action::new(kind, target)?.with_params(params);
+                provider::adopt_script(dir, &mut session, &action)?;
+                session.queue(action)?;
+            }
+            session.save(store)?;
+            out.show(session.queued(), |queued| {
+                println!("{} actions queued", queued.len());
+            });
+        }
+        Args::Resolve(name) => {
+            let _lock = store.lock(&name)?;
+            let mut session = store.load(&name)?;
+            let outcome = session.resolve()?;
+            session.save(store)?;
+            out.show(&outcome, |outcome| print_outcome(outcome, ""));
+            webhook::post_all(&config.webhooks, &webhook::turn_began(&session));
+        }
+        Args::Rules(name, None, cooldowns) if cooldowns.is_empty() => {
+            show_rules(out, &store.load(&name)?);
+        }
+        Args::Rules(name, max_actions, cooldowns) => {
+            let _lock = store.lock(&name)?;
+            let mut session = store.load(&name)?;
+            let mut rules = session.rules().clone();
+            if let Some(max_actions) = max_actions {
+                rules.max_actions = max_actions;
+            }
+            for (kind, turns) in cooldowns {
+                match turns {
+                    0 => rules.cooldowns.remove(&kind),
+                    turns => rules.cooldowns.insert(kind, turns),
+                };
+            }
+            session.set_rules(rules)?;
+            session.save(store)?;
+            show_rules(out, &session);
+        }
+        Args::Cost(name, kind, cost) => {
+            let _lock = store.lock(&name)?;
+            let mut session = store.load(&name)?;
+            let mut rules = session.rules().clone();
+            if cost.is_empty() {
+                rules.costs.remove(&kind);
+            } else {
+                rules.costs.insert(kind, cost);
+            }
+            session.set_rules(rules)?;
+            session.save(store)?;
+            show_rules(out, &session);
+        }
+        Args::Xp(name, kind, xp) => {
+            let _lock = store.lock(&name)?;
+            let mut session = store.load(&name)?;
+            let mut rules = session.rules().clone();
+            if xp == 0 {
+                rules.xp.remove(&kind);
+            } else {
+                rules.xp.insert(kind, xp);
+            }
+            session.set_rules(rules)?;
+            session.save(store)?;
+            show_rules(out, &session);
+        }
+        Args::Leveling(name, xp_per_level, gains) => {
+            let _lock = store.lock(&name)?;
+            let mut session = store.load(&name)?;
+            let mut rules = session.rules().clone();
+            rules.leveling = Leveling {
+                xp_per_level,
+                gains,
+            };
+            session.set_rules(rules)?;
+            session.save(store)?;
+            show_rules(out, &session);
+        }
+        Args::Map(name, width, height) => {
+            let _lock = store.lock(&name)?;
+            let mut session = store.load(&name)?;
+            let map = Map { width, height };
+            session.set_map(map)?;
+            session.save(store)?;
+            out.say(format!("map set to {map}"));
+        }
+        Args::Afflict(name, entity, effect) => {
+            let _lock = store.lock(&name)?;
+            let mut session = store.load(&name)?;
+            session.afflict(&entity, effect)?;
+            session.save(store)?;
+            out.say(format!("{entity} is {effect}"));
+        }
+        Args::Grant(name, entity, resources) => {
+            let _lock = store.lock(&name)?;
+            let mut session = store.load(&name)?;
+            let outcome = session.grant(&entity, resources)?;
+            session.save(store)?;
+            out.show(&outcome, |outcome| print_outcome(outcome, ""));
+        }
+        Args::Require(name, kind, prerequisites) => {
+            let _lock = store.lock(&name)?;
+            let mut session = store.load(&name)?;
+            session.require(kind, prerequisites)?;
+            session.save(store)?;
+            show_rules(out, &session);
+        }
+        Args::Script(name, kind, path) => {
+            let source = fs::read_to_string(&path)?;
+            let _lock = store.lock(&name)?;
+            let mut session = store.load(&name)?;
+            session.define_script(kind, source)?;
+            session.save(store)?;
+            out.say("script defined");
+        }
+        Args::Macros => {
+            let macros: BTreeMap<_, _> = Macros::load(dir)?
+                .iter()
+                .map(|(name, body)| (name.to_string(), body.to_string()))
+                .collect();
+            out.show(&macros, |macros| {
+                if macros.is_empty() {
+                    println!(
+                        "no macros; define them in {}",
+                        dir.join("relay_code.macros").display()
+                    );
+                }
+                for (name, body) in macros {
+                    println!("{name} = [{body}]");
+                }
+            });
+        }
+        Args::Spawn(name, archetype, entity) => {
+            let archetype = archetypes::load(dir, &archetype)?;
+            let _lock = store.lock(&name)?;
+            let mut session = store.load(&name)?;
+            let entity = session.spawn(&archetype, entity)?;
+            session.save(store)?;
+            out.say(format!("spawned {entity}"));
+        }
+        Args::SaveArchetype(name, entity, archetype) => {
+            let session = store.load(&name)?;
+            let entity = resolve(session.entities(), &entity)?;
+            let archetype = archetype.unwrap_or_else(|| entity.name.clone());
+            archetypes::save(dir, &archetype, entity)?;
+            out.say(format!("archetype {archetype} saved"));
+        }
+        Args::Archetypes => {
+            out.show(&archetypes::list(dir)?, |names| {
+                if names.is_empty() {
+                    println!("no archetypes; save one with archetype save");
+                }
+                for name in names {
+                    println!("{name}");
+                }
+            });
+        }
+        Args::Undo(name) => {
+            let _lock = store.lock(&name)?;
+            let mut session = store.load(&name)?;
+            session.undo()?;
+            session.save(store)?;
+            out.say("undone");
+        }
+        Args::Redo(name) => {
+            let _lock = store.lock(&name)?;
+            let mut session = store.load(&name)?;
+            session.redo()?;
+            session.save(store)?;
+            out.say("redone");
+        }
+        Args::New(name, template, rule_set) => {
+            let _lock = store.lock(&name)?;
+            let replace = format_args!("Session {name:?} exists; replace it?");
+            if store.exists(&name)? && !out.confirm(replace)? {
+                out.say("aborted");
+                return Ok(());
+            }
+            let mut session = match template {
+                Some(template) => {
+                    let templates = FsStore::new(dir.join(TEMPLATES), keyring);
+                    let template = templates.load(&template).map_err(|err| match err {
+                        Error::SessionNotFound(template) => Error::TemplateNotFound(template),
+                        err => err,
+                    })?;
+                    Session::from_template(name, &template)?
+                }
+                None => Session::new(name)?,
+            };
+            if let Some(rule_set) = rule_set {
+                session.play_by(&rule_set)?;
+            }
+            session.save(store)?;
+            if config.encrypt {
+                let passphrase = passphrases.choose(session.name())?;
+                store.set_passphrase(session.name(), Some(&passphrase))?;
+            }
+            out.say("session saved");
+        }
+        Args::Export(name, output, player) => {
+            let session = store.load(&name)?;
+            let output = output.unwrap_or_else(|| format!("{name}.relay").into());
+            let events = session.log().len();
+            let session = match &player {
+                Some(player) => visibility::redact(session, Some(player))?,
+                None => session,
+            };
+            let redacted = session.redacted().is_some();
+            atomic::write(&output, &Archive::new(session)?.to_bytes(keyring))?;
+            // Turn files take over from the archive, unless it holds only a
+            // snapshot they cannot follow on from.
+            if !redacted {
+                let mut exchanges = Exchanges::load(dir)?;
+                exchanges.exchange(&name, events);
+                exchanges.save(dir)?;
+            }
+            out.say(format!("exported to {}", output.display()));
+        }
+        Args::Import(path) => {
+            let archive = Archive::from_bytes(&fs::read(path)?, keyring)?;
+            let name = archive.session.name().to_string();
+            let _lock = store.lock(&name)?;
+            if store.exists(&name)? {
+                return Err(Error::SessionExists(name));
+            }
+            store.save(&archive.session)?;
+            let mut exchanges = Exchanges::load(dir)?;
+            exchanges.exchange(&name, archive.session.log().len());
+            exchanges.save(dir)?;
+            out.say(format!("imported session {name:?}"));
+        }
+        Args::Turn(TurnCommand::Export(name, output)) => {
+            let session = store.load(&name)?;
+            let mut exchanges = Exchanges::load(dir)?;
+            let turn = TurnFile::since(&session, exchanges.exchanged(&name))?;
+            let output = output.unwrap_or_else(|| format!("{name}.turn").into());
+            atomic::write(&output, &turn.to_bytes(keyring))?;
+            exchanges.exchange(&name, session.log().len());
+            exchanges.save(dir)?;
+            out.say(format!(
+                "{} events exported to {}",
+                turn.events.len(),
+                output.display()
+            ));
+        }
+        Args::Turn(TurnCommand::Apply(path)) => {
+            let (turn, signer) = TurnFile::open(&fs::read(path)?, keyring)?;
+            let name = &turn.session;
+            let _lock = store.lock(name)?;
+            let mut session = store.load(name)?;
+            turn.check_signed_by(&session, signer.as_ref())?;
+            // Changes made here that the sender's copy lacks are still to
+            // be sent.
+            let base = turn.base as usize;
+            let unsent = session
+                .log()
+                .get(base..)
+                .is_some_and(|mine| mine.iter().any(|event| !turn.events.contains(event)));
+            let applied = turn.apply_to(&mut session)?;
+            if applied > 0 {
+                session.save(store)?;
+            }
+            let exchanged = match unsent {
+                true => base,
+                false => session.log().len(),
+            };
+            let mut exchanges = Exchanges::load(dir)?;
+            exchanges.exchange(name, exchanged);
+            exchanges.save(dir)?;
+            out.say(format!("{applied} events applied to session {name:?}"));
+        }
+        Args::Replay(ReplayCommand::Export(name, output)) => {
+            let replay = Replay::of(&store.load(&name)?)?;
+            let output = output.unwrap_or_else(|| format!("{name}.replay").into());
+            atomic::write(&output, &replay.to_bytes(keyring))?;
+            out.say(format!(
+                "{} events exported to {}",
+                replay.entries.len(),
+                output.display()
+            ));
+        }
+        Args::Replay(ReplayCommand::Play(path, step)) => {
+            let replay = Replay::from_bytes(&fs::read(path)?, keyring)?;
+            if out.json {
+                println!("{}", replay.to_json());
+            } else {
+                play_replay(&replay, step)?;
+            }
+        }
+        Args::Doctor(path, output) => {
+            let diagnosis = doctor::examine(&fs::read(&path)?, keyring);
+            let report = |recovered: Json, output: Json| {
+                Json::object([
+                    ("problems", diagnosis.problems.to_json()),
+                    ("recovered", recovered),
+                    ("output", output),
+                ])
+            };
+            if diagnosis.problems.is_empty() {
+                out.show(&report(Json::Null, Json::Null), |_| {
+                    println!("no problems found");
+                });
+                return Ok(());
+            }
+            if !out.json {
+                for problem in &diagnosis.problems {
+                    println!("problem: {problem}");
+                }
+            }
+            let Some(session) = &diagnosis.recovered else {
+                out.show(&report(Json::Null, Json::Null), |_| {
+                    println!("nothing could be recovered");
+                });
+                return Ok(());
+            };
+            let output = output.unwrap_or_else(|| {
+                let mut output = path.into_os_string();
+                output.push(".recovered");
+                output.into()
+            });
+            atomic::write(&output, &keyring.seal(&session.serialize()))?;
+            let events = session.log().len();
+            let report = report(events.to_json(), output.display().to_string().to_json());
+            out.show(&report, |_| {
+                println!("recovered {events} event(s) into {}", output.display());
+            });
+        }
+        Args::Merge(base, mine, theirs) => {
+            let base = store.load(&base)?;
+            let theirs = store.load(&theirs)?;
+            let _lock = store.lock(&mine)?;
+            let mut session = store.load(&mine)?;
+            let conflicts = session.merge(&base, &theirs)?;
+            session.save(store)?;
+            let conflicts: Vec<_> = conflicts.iter().map(ToString::to_string).collect();
+            out.show(&conflicts, |conflicts| {
+                for conflict in conflicts {
+                    println!("conflict: {conflict}");
+                }
+                println!("merged with {} conflict(s) skipped", conflicts.len());
+            });
+        }
+        Args::Log(name) => {
+            let session = store.load(&name)?;
+            let events: Vec<_> = session.log().iter().map(ToString::to_string).collect();
+            out.show(&events, |events| {
+                if events.is_empty() {
+                    println!("no events");
+                }
+                for (n, event) in events.iter().enumerate() {
+                    println!("{:>4}  {event}", n + 1);
+                }
+            });
+        }
+        Args::History(name, filter) => {
+            let entries = history(&store.load(&name)?, &filter)?;
+            out.show(&entries, |entries| {
+                if entries.is_empty() {
+                    println!("no matching actions");
+                }
+                for entry in entries {
+                    let event = paint(&entry.event, Style::Bold);
+                    println!("{:>4}  {event}", entry.number);
+                    print_outcome(&entry.outcome, "      ");
+                }
+            });
+        }
+        Args::Stats(name) => {
+            out.show(&Stats::of(&store.load(&name)?)?, |stats| {
+                println!("size:     {} B", stats.size);
+                println!("entities: {}", stats.entities);
+                println!("actions:  {}", stats.actions);
+                println!("fields:");
+                for (field_type, count) in &stats.fields {
+                    println!("  {:<12}{count:>6}", format!("{field_type:?}"));
+                }
+            });
+        }
+        Args::List(tags) => list_sessions(out, store, &tags)?,
+        Args::Tag(name, tags) => {
+            let _lock = store.lock(&name)?;
+            let mut session = store.load(&name)?;
+            for tag in tags {
+                session.add_tag(tag)?;
+            }
+            session.save(store)?;
+            out.show(session.tags(), |tags| println!("tags: {}", tags.join(" ")));
+        }
+        Args::Untag(name, tags) => {
+            let _lock = store.lock(&name)?;
+            let mut session = store.load(&name)?;
+            for tag in &tags {
+                session.remove_tag(tag);
+            }
+            session.save(store)?;
+            out.show(session.tags(), |tags| println!("tags: {}", tags.join(" ")));
+        }
+        Args::Delete(name) => {
+            if out.confirm(format_args!("Delete session {name:?} and its backups?"))? {
+                let _lock = store.lock(&name)?;
+                store.delete(&name)?;
+                out.say("session deleted");
+            } else {
+                out.say("aborted");
+            }
+        }
+        Args::Rename(old, new) => {
+            let _locks = lock_pair(store, &old, &new)?;
+            store.rename(&old, &new)?;
+            out.say("session renamed");
+        }
+        Args::Copy(src, dst) => {
+            let _locks = lock_pair(store, &src, &dst)?;
+            Session::copy(store, &src, &dst)?;
+            out.say("session copied");
+        }
+        Args::Compact(name) => {
+            let _lock = store.lock(&name)?;
+            store.compact(&name)?;
+            out.say("session compacted");
+        }
+        Args::Encrypt(name) => {
+            let _lock = store.lock(&name)?;
+            let passphrase = passphrases.choose(&name)?;
+            store.set_passphrase(&name, Some(&passphrase))?;
+            out.say("session encrypted");
+        }
+        Args::Decrypt(name) => {
+            let _lock = store.lock(&name)?;
+            store.set_passphrase(&name, None)?;
+            out.say("session decrypted");
+        }
+        Args::Restore(name, backup) => {
+            let _lock = store.lock(&name)?;
+            let replace = format!("Replace session {name:?} with backup {backup}?");
+            if !out.confirm(replace)? {
+                out.say("aborted");
+                return Ok(());
+            }
+            store.restore(&name, backup)?;
+            out.say(format!("session restored from backup {backup}"));
+        }
+        Args::Entity(command) => entity_command(out, store, command)?,
+        Args::Rel(command) => rel_command(out, store, command)?,
+        Args::Faction(command) => faction_command(out, store, command)?,
+        Args::Player(command) => player_command(out, store, command)?,
+        Args::KeyGen(_)
+        | Args::Trust(..)
+        | Args::Serve(..)
+        | Args::GenToken(..)
+        | Args::Sync(..)
+        | Args::Proxy(..) => {
+            unreachable!("handled before opening a store")
+        }
+        Args::Chat(name, text) => {
+            let name = session_name(out, dir, name, config)?;
+            let player = config.player.clone().ok_or_else(|| {
+                Error::MissingArg("chat".to_string(), "--as <player>".to_string())
+            })?;
+            LocalSessions::new(dir, store)
+                .by(Some(&player))
+                .say(&name, &text)?;
+            out.say(format!("{player} says {text:?}"));
         }
+        Args::Mail => return Err(Error::RemoteOnly("mail".to_string())),
+        Args::Ping => return Err(Error::RemoteOnly("ping".to_string())),
+        Args::Watch(_) => return Err(Error::RemoteOnly("watch".to_string())),
+        Args::Lobby(_) => return Err(Error::RemoteOnly("lobby".to_string())),
     }
 
     Ok(())
@@ -0,0 +1,141 @@
+use std::fmt::Display;
+
+use crate::components::Position;
+use crate::error::{Error, Result};
+use crate::serde::{Field, FieldWriter, SerializeField, HEADER_LEN};
+
+/// The grid entities stand on, from `(0, 0)` to `(width - 1, height - 1)`.
+/// Written as `List(U32(width) U32(height))`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Map {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Map {
+    pub(crate) const FIELD_LEN: usize = 3 * HEADER_LEN + 4 + 4;
+
+    pub fn contains(&self, position: Position) -> bool {
+        u32::try_from(position.x).is_ok_and(|x| x < self.width)
+            && u32::try_from(position.y).is_ok_and(|y| y < self.height)
+    }
+}
+
+impl Display for Map {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
+    }
+}
+
+impl SerializeField for Map {
+    fn serialize_field(&self, writer: &mut FieldWriter<'_>) {
+        writer.write(&(self.width, self.height));
+    }
+}
+
+impl TryFrom<Field<'_>> for Map {
+    type Error = Error;
+
+    fn try_from(value: Field<'_>) -> Result<Self> {
+        let (width, height) = value.try_into()?;
+        Ok(Self { width, height })
+    }
+}
+
+/// Which way a `move` goes. North is towards `y = 0`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    North,
+    South,
+    East,
+    West,
+}
+
+impl Direction {
+    pub const ALL: [Direction; 4] = [Self::North, Self::South, Self::East, Self::West];
+
+    /// A direction's name or its first letter, such as `north` or `n`.
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|direction| {
+            let full = direction.to_string();
+            full.eq_ignore_ascii_case(name) || full[..1].eq_ignore_ascii_case(name)
+        })
+    }
+
+    /// Where `steps` steps this way from `from` lead, or `None` if that is
+    /// past the edge of the coordinates.
+    pub fn step(&self, from: Position, steps: u8) -> Option<Position> {
+        let steps = i32::from(steps);
+        let (x, y) = match self {
+            Self::North => (Some(from.x), from.y.checked_sub(steps)),
+            Self::South => (Some(from.x), from.y.checked_add(steps)),
+            Self::East => (from.x.checked_add(steps), Some(from.y)),
+            Self::West => (from.x.checked_sub(steps), Some(from.y)),
+        };
+        Some(Position { x: x?, y: y? })
+    }
+}
+
+impl Display for Direction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::North => write!(f, "north"),
+            Self::South => write!(f, "south"),
+            Self::East => write!(f, "east"),
+            Self::West => write!(f, "west"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Map;
+    use crate::actions::{Action, ActionKind, Param};
+    use crate::components::Position;
+    use crate::session::Session;
+    use crate::Entity;
+
+    #[test]
+    fn moves_stay_on_the_map() {
+        let mut session = Session::new("game".to_string()).unwrap();
+        session.add_entity(Entity::new("hero".to_string())).unwrap();
+        let step = |direction: &str, steps| {
+            let params = [
+                ("direction".to_string(), Param::Text(direction.to_string())),
+                ("steps".to_string(), Param::Int(steps)),
+            ];
+            Action::new(ActionKind::Move, "hero".to_string())
+                .unwrap()
+                .with_params(params.into_iter().collect())
+        };
+        assert_eq!(
+            session.apply(step("east", 1)).unwrap_err().to_string(),
+            "hero is not on the map"
+        );
+        session
+            .set_map(Map {
+                width: 4,
+                height: 3,
+            })
+            .unwrap();
+        assert!(session.place("hero", Position { x: 4, y: 0 }).is_err());
+        session.place("hero", Position { x: 1, y: 1 }).unwrap();
+
+        let outcome = session.apply(step("e", 2)).unwrap();
+        assert_eq!(
+            outcome.changes[0].to_string(),
+            "hero position: (1, 1) -> (3, 1)"
+        );
+        assert_eq!(
+            session.apply(step("east", 1)).unwrap_err().to_string(),
+            "hero cannot move 1 east from (3, 1): it is the edge of the 4x3 map"
+        );
+        assert!(session.apply(step("up", 1)).is_err());
+        assert!(session
+            .set_map(Map {
+                width: 2,
+                height: 2
+            })
+            .is_err());
+    }
+}
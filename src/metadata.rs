@@ -0,0 +1,114 @@
+use crate::error::{Error, Result};
+use crate::serde::{Deserialize, FieldReader, FieldWriter, Serialize, HEADER_LEN};
+use crate::timestamp::Timestamp;
+
+/// Bumped whenever the layout of a serialized session changes.
+pub const FORMAT_VERSION: u16 = 15;
+
+/// What there is to know about a session without replaying its log. It is
+/// written first in every serialized session, as one `List` field:
+///
+/// ```text
+/// List(U16(version) Str(name) U128(created) U128(modified) U32(entities) List(tags))
+/// ```
+///
+/// so `from_session_bytes` can read it and skip everything after.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metadata {
+    pub name: String,
+    pub created: Timestamp,
+    pub modified: Timestamp,
+    /// Number of entities once the log is replayed.
+    pub entities: u32,
+    /// Sorted and unique.
+    pub tags: Vec<String>,
+}
+
+impl Metadata {
+    /// Reads only the metadata block at the start of a serialized session.
+    pub fn from_session_bytes(bytes: &[u8]) -> Result<Self> {
+        Self::read(&mut FieldReader::new(bytes))
+    }
+
+    /// Reads the metadata block from `reader`, leaving it at the log.
+    pub(crate) fn read(reader: &mut FieldReader<'_>) -> Result<Self> {
+        let mut block = reader.read_list()?;
+        let metadata = Self::deserialize(&mut block)?;
+        block.finish()?;
+        Ok(metadata)
+    }
+}
+
+impl Serialize for Metadata {
+    fn serialize_to(&self, writer: &mut FieldWriter<'_>) {
+        writer.write_u16(FORMAT_VERSION);
+        writer.write_str(&self.name);
+        writer.write(&self.created);
+        writer.write(&self.modified);
+        writer.write_u32(self.entities);
+        writer.write(&self.tags);
+    }
+
+    fn size_hint(&self) -> usize {
+        HEADER_LEN
+            + 2
+            + HEADER_LEN
+            + self.name.len()
+            + 2 * (HEADER_LEN + 16)
+            + HEADER_LEN
+            + 4
+            + HEADER_LEN
+            + self
+                .tags
+                .iter()
+                .map(|tag| HEADER_LEN + tag.len())
+                .sum::<usize>()
+    }
+}
+
+impl Deserialize for Metadata {
+    fn deserialize(reader: &mut FieldReader<'_>) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let version: u16 = reader.read_field()?;
+        if version != FORMAT_VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+        let metadata = Self {
+            name: reader.read_field()?,
+            created: reader.read_field()?,
+            modified: reader.read_field()?,
+            entities: reader.read_field()?,
+            tags: reader.read_field()?,
+        };
+        if !metadata.tags.windows(2).all(|pair| pair[0] < pair[1]) {
+            return Err(Error::NonCanonical);
+        }
+        Ok(metadata)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Metadata;
+    use crate::serde::{Serialize, HEADER_LEN};
+    use crate::session::Session;
+    use crate::Entity;
+
+    #[test]
+    fn metadata_is_read_without_the_log() {
+        let mut session = Session::new("game".to_string()).unwrap();
+        session.add_entity(Entity::new("hero".to_string())).unwrap();
+        session.add_tag("campaign".to_string()).unwrap();
+        let mut bytes = session.serialize();
+        // Anything after the metadata block goes unread.
+        let len = HEADER_LEN + session.metadata().size_hint();
+        bytes[len..].fill(0xff);
+
+        let metadata = Metadata::from_session_bytes(&bytes).unwrap();
+        assert_eq!(metadata, session.metadata());
+        assert_eq!(metadata.entities, 1);
+        assert_eq!(metadata.tags, ["campaign"]);
+    }
+}
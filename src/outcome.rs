@@ -0,0 +1,219 @@
+use std::fmt::Display;
+
+use crate::error::{Error, Result};
+use crate::serde::{
+    Deserialize, Field, FieldReader, FieldWriter, Serialize, SerializeField, HEADER_LEN,
+};
+
+/// What applying an action did, for showing to the player and to opponents
+/// reviewing a relayed turn. Written as three lists:
+///
+/// ```text
+/// List(Str...) List(List(Str(entity) Str(field) Str(before) Str(after))...)
+/// List(List(U8(sides) U8(value))...)
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ActionOutcome {
+    pub messages: Vec<String>,
+    /// Every value the action changed, in the order it changed them.
+    pub changes: Vec<StateChange>,
+    /// Dice rolled while applying the action, in order.
+    pub rolls: Vec<DiceRoll>,
+}
+
+impl ActionOutcome {
+    pub(crate) fn message(&mut self, message: String) {
+        self.messages.push(message);
+    }
+
+    /// Appends everything `other` reports after what this one does.
+    pub(crate) fn extend(&mut self, other: ActionOutcome) {
+        self.messages.extend(other.messages);
+        self.changes.extend(other.changes);
+        self.rolls.extend(other.rolls);
+    }
+
+    /// Records that `field` of `entity` went from `before` to `after`,
+    /// unless it stayed the same.
+    pub(crate) fn change<T: Display + PartialEq>(
+        &mut self,
+        entity: &str,
+        field: &str,
+        before: T,
+        after: T,
+    ) {
+        if before != after {
+            self.changes.push(StateChange {
+                entity: entity.to_string(),
+                field: field.to_string(),
+                before: before.to_string(),
+                after: after.to_string(),
+            });
+        }
+    }
+}
+
+/// One line per message, then per change and per roll, indented.
+impl Display for ActionOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for message in &self.messages {
+            writeln!(f, "{message}")?;
+        }
+        for change in &self.changes {
+            writeln!(f, "  {change}")?;
+        }
+        for roll in &self.rolls {
+            writeln!(f, "  {roll}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for ActionOutcome {
+    fn serialize_to(&self, writer: &mut FieldWriter<'_>) {
+        writer.write(&self.messages);
+        writer.write(&self.changes);
+        writer.write(&self.rolls);
+    }
+
+    fn size_hint(&self) -> usize {
+        let messages: usize = self.messages.iter().map(|m| HEADER_LEN + m.len()).sum();
+        let changes: usize = self.changes.iter().map(StateChange::field_len).sum();
+        let rolls = self.rolls.len() * DiceRoll::FIELD_LEN;
+        3 * HEADER_LEN + messages + changes + rolls
+    }
+}
+
+impl Deserialize for ActionOutcome {
+    fn deserialize(reader: &mut FieldReader<'_>) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        Ok(Self {
+            messages: reader.read_field()?,
+            changes: reader.read_field()?,
+            rolls: reader.read_field()?,
+        })
+    }
+}
+
+/// A value changed by an action, already formatted for display.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateChange {
+    pub entity: String,
+    pub field: String,
+    pub before: String,
+    pub after: String,
+}
+
+impl StateChange {
+    /// Encoded size of the change as a field, header included.
+    fn field_len(&self) -> usize {
+        5 * HEADER_LEN + self.entity.len() + self.field.len() + self.before.len() + self.after.len()
+    }
+}
+
+impl Display for StateChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {}: {} -> {}",
+            self.entity, self.field, self.before, self.after
+        )
+    }
+}
+
+impl Serialize for StateChange {
+    fn serialize_to(&self, writer: &mut FieldWriter<'_>) {
+        writer.write_str(&self.entity);
+        writer.write_str(&self.field);
+        writer.write_str(&self.before);
+        writer.write_str(&self.after);
+    }
+
+    fn size_hint(&self) -> usize {
+        self.field_len() - HEADER_LEN
+    }
+}
+
+impl SerializeField for StateChange {
+    fn serialize_field(&self, writer: &mut FieldWriter<'_>) {
+        writer.write_list(self);
+    }
+}
+
+impl TryFrom<Field<'_>> for StateChange {
+    type Error = Error;
+
+    fn try_from(value: Field<'_>) -> Result<Self> {
+        let [entity, field, before, after] = value.try_into()?;
+        Ok(Self {
+            entity,
+            field,
+            before,
+            after,
+        })
+    }
+}
+
+/// A die with `sides` sides that came up `value`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiceRoll {
+    pub sides: u8,
+    pub value: u8,
+}
+
+impl DiceRoll {
+    const FIELD_LEN: usize = 3 * HEADER_LEN + 2;
+}
+
+impl Display for DiceRoll {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "d{}: {}", self.sides, self.value)
+    }
+}
+
+impl SerializeField for DiceRoll {
+    fn serialize_field(&self, writer: &mut FieldWriter<'_>) {
+        writer.write(&[self.sides, self.value]);
+    }
+}
+
+impl TryFrom<Field<'_>> for DiceRoll {
+    type Error = Error;
+
+    fn try_from(value: Field<'_>) -> Result<Self> {
+        let [sides, value] = value.try_into()?;
+        Ok(Self { sides, value })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ActionOutcome, DiceRoll};
+    use crate::actions::{Action, ActionKind};
+    use crate::serde::{Deserialize, FieldReader, Serialize};
+    use crate::session::Session;
+    use crate::Entity;
+
+    #[test]
+    fn outcome_round_trip() {
+        let mut session = Session::new("arena".to_string()).unwrap();
+        session
+            .add_entity(Entity::new("troll".to_string()))
+            .unwrap();
+        let mut outcome = session
+            .apply(Action::new(ActionKind::Fight, "troll".to_string()).unwrap())
+            .unwrap();
+        outcome.rolls.push(DiceRoll { sides: 6, value: 4 });
+        assert_eq!(
+            outcome.to_string(),
+            "fought troll\n  troll hp: 0 -> 1\n  d6: 4\n"
+        );
+
+        let bytes = outcome.serialize();
+        assert_eq!(outcome.size_hint(), bytes.len());
+        let mut reader = FieldReader::new(&bytes);
+        assert_eq!(ActionOutcome::deserialize(&mut reader).unwrap(), outcome);
+    }
+}
@@ -0,0 +1,129 @@
+use std::collections::BTreeMap;
+use std::io::{stdin, Write};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::error::Result;
+
+/// Passphrase used for every session when set, so scripts never prompt.
+const ENV_VAR: &str = "RELAY_CODE_PASSPHRASE";
+
+/// Where the passphrases of encrypted sessions come from: the environment,
+/// then the OS keychain when built with the `keychain` feature and allowed
+/// to use it, then a prompt. Each one is asked for at most once per run.
+#[derive(Debug, Default)]
+pub struct Passphrases {
+    use_keychain: bool,
+    /// Shared by the connections `serve` handles at once.
+    known: Mutex<BTreeMap<String, String>>,
+}
+
+impl Passphrases {
+    pub fn new(use_keychain: bool) -> Self {
+        Self {
+            use_keychain,
+            known: Mutex::default(),
+        }
+    }
+
+    /// The passphrase for session `name`, which may turn out to be wrong.
+    /// Pass it to `confirm` once it has decrypted the session.
+    pub fn get(&self, name: &str) -> Result<String> {
+        if let Some(passphrase) = self.confirmed(name) {
+            return Ok(passphrase);
+        }
+        if let Ok(passphrase) = std::env::var(ENV_VAR) {
+            return Ok(passphrase);
+        }
+        if let Some(passphrase) = self.use_keychain.then(|| keychain::get(name)).flatten() {
+            return Ok(passphrase);
+        }
+        prompt(&format!("passphrase for session {name:?}: "))
+    }
+
+    /// A passphrase already confirmed for session `name` during this run.
+    pub fn confirmed(&self, name: &str) -> Option<String> {
+        self.known().get(name).cloned()
+    }
+
+    /// Asks for a new passphrase for session `name`, bypassing the keychain.
+    pub fn choose(&self, name: &str) -> Result<String> {
+        if let Ok(passphrase) = std::env::var(ENV_VAR) {
+            return Ok(passphrase);
+        }
+        prompt(&format!("new passphrase for session {name:?}: "))
+    }
+
+    /// Remembers a passphrase known to be right, in the keychain too so
+    /// later runs need not ask.
+    pub fn confirm(&self, name: &str, passphrase: &str) {
+        if self.use_keychain {
+            keychain::set(name, passphrase);
+        }
+        self.known()
+            .insert(name.to_string(), passphrase.to_string());
+    }
+
+    /// Forgets the passphrase of a session that is no longer encrypted.
+    pub fn forget(&self, name: &str) {
+        if self.use_keychain {
+            keychain::delete(name);
+        }
+        self.known().remove(name);
+    }
+
+    fn known(&self) -> MutexGuard<'_, BTreeMap<String, String>> {
+        self.known.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+fn prompt(message: &str) -> Result<String> {
+    eprint!("{message}");
+    std::io::stderr().flush()?;
+    let mut passphrase = String::new();
+    stdin().read_line(&mut passphrase)?;
+    Ok(passphrase.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Keychain failures are never fatal, since a prompt can always stand in.
+#[cfg(feature = "keychain")]
+mod keychain {
+    use keyring::{Entry, Error};
+
+    const SERVICE: &str = "relay_code";
+
+    pub fn get(name: &str) -> Option<String> {
+        match Entry::new(SERVICE, name).and_then(|entry| entry.get_password()) {
+            Ok(passphrase) => Some(passphrase),
+            Err(Error::NoEntry) => None,
+            Err(err) => {
+                crate::warn!("keychain unavailable: {err}");
+                None
+            }
+        }
+    }
+
+    pub fn set(name: &str, passphrase: &str) {
+        if let Err(err) = Entry::new(SERVICE, name).and_then(|entry| entry.set_password(passphrase))
+        {
+            crate::warn!("could not save passphrase to the keychain: {err}");
+        }
+    }
+
+    pub fn delete(name: &str) {
+        match Entry::new(SERVICE, name).and_then(|entry| entry.delete_credential()) {
+            Ok(()) | Err(Error::NoEntry) => {}
+            Err(err) => crate::warn!("could not remove passphrase from the keychain: {err}"),
+        }
+    }
+}
+
+#[cfg(not(feature = "keychain"))]
+mod keychain {
+    pub fn get(_name: &str) -> Option<String> {
+        None
+    }
+
+    pub fn set(_name: &str, _passphrase: &str) {}
+
+    pub fn delete(_name: &str) {}
+}
@@ -0,0 +1,72 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::error::{Error, Result};
+
+const APP_DIR: &str = "relay_code";
+
+/// Resolves where sessions and keys are stored, creating the directory if
+/// needed. The first of these wins:
+///
+/// 1. the `--data-dir` flag,
+/// 2. the `RELAY_CODE_DATA_DIR` environment variable,
+/// 3. `data_dir` in the config file,
+/// 4. the platform data directory: `$XDG_DATA_HOME/relay_code` (falling back
+///    to `~/.local/share/relay_code`) on Linux and other Unixes,
+///    `~/Library/Application Support/relay_code` on macOS and
+///    `%APPDATA%\relay_code` on Windows.
+pub fn data_dir(flag: Option<PathBuf>, configured: Option<PathBuf>) -> Result<PathBuf> {
+    let dir = flag
+        .or_else(|| env_path("RELAY_CODE_DATA_DIR"))
+        .or(configured)
+        .or_else(|| platform_data_dir().map(|dir| dir.join(APP_DIR)))
+        .ok_or(Error::NoDataDir)?;
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// Where the config file is unless `--config` says otherwise:
+/// `$XDG_CONFIG_HOME/relay_code/config.toml`, falling back to
+/// `~/.config/relay_code/config.toml`, or `%APPDATA%\relay_code\config.toml`
+/// on Windows.
+pub fn config_file() -> Option<PathBuf> {
+    let dir = if cfg!(windows) {
+        env_path("APPDATA")
+    } else {
+        env_path("XDG_CONFIG_HOME")
+            .filter(|dir| dir.is_absolute())
+            .or_else(|| env_path("HOME").map(|home| home.join(".config")))
+    };
+    dir.map(|dir| dir.join(APP_DIR).join("config.toml"))
+}
+
+/// An environment variable as a path, ignoring it when unset or empty.
+fn env_path(var: &str) -> Option<PathBuf> {
+    env::var_os(var)
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+}
+
+#[cfg(windows)]
+fn platform_data_dir() -> Option<PathBuf> {
+    env_path("APPDATA")
+}
+
+#[cfg(target_os = "macos")]
+fn platform_data_dir() -> Option<PathBuf> {
+    env_path("HOME").map(|home| home.join("Library/Application Support"))
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn platform_data_dir() -> Option<PathBuf> {
+    // The XDG spec says relative paths are invalid and must be ignored.
+    env_path("XDG_DATA_HOME")
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| env_path("HOME").map(|home| home.join(".local/share")))
+}
+
+#[cfg(not(any(unix, windows)))]
+fn platform_data_dir() -> Option<PathBuf> {
+    None
+}
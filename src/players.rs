@@ -0,0 +1,147 @@
+use std::fmt::Display;
+
+use uuid::Uuid;
+
+use crate::error::{Error, Result};
+use crate::serde::{Field, FieldWriter, Serialize, SerializeField, HEADER_LEN};
+use crate::signing;
+
+/// Someone who plays in a session. Once a session has players, every
+/// action taken in it is logged as taken by one of them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Player {
+    /// Stays the same for the life of the player.
+    pub id: Uuid,
+    /// What actions are logged under and tokens are issued to.
+    pub name: String,
+    /// The public key the player signs their files with, in hex, if known.
+    pub key: Option<String>,
+}
+
+impl Player {
+    pub fn new(name: String, key: Option<String>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            name,
+            key,
+        }
+    }
+
+    /// Fails if the name is not a valid one or the key not a public key.
+    pub(crate) fn validate(&self) -> Result<()> {
+        validate_name(&self.name)?;
+        if let Some(key) = &self.key {
+            signing::public_key(key)?;
+        }
+        Ok(())
+    }
+
+    /// Encoded size as a field, header included.
+    pub(crate) fn field_len(&self) -> usize {
+        let key = self.key.as_ref().map_or(0, String::len);
+        HEADER_LEN + HEADER_LEN + 16 + HEADER_LEN + self.name.len() + HEADER_LEN + key
+    }
+}
+
+impl Display for Player {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)?;
+        if let Some(key) = &self.key {
+            write!(f, " (key {key})")?;
+        }
+        Ok(())
+    }
+}
+
+/// Written as `List[U128(id), Str(name), Str(key)]`, the key empty if
+/// unknown.
+impl Serialize for Player {
+    fn serialize_to(&self, writer: &mut FieldWriter<'_>) {
+        writer.write_u128(self.id.as_u128());
+        writer.write_str(&self.name);
+        writer.write_str(self.key.as_deref().unwrap_or_default());
+    }
+}
+
+impl SerializeField for Player {
+    fn serialize_field(&self, writer: &mut FieldWriter<'_>) {
+        writer.write_list(self);
+    }
+}
+
+impl TryFrom<Field<'_>> for Player {
+    type Error = Error;
+
+    fn try_from(value: Field<'_>) -> Result<Self> {
+        let (id, name, key): (u128, String, String) = value.try_into()?;
+        Ok(Self {
+            id: Uuid::from_u128(id),
+            name,
+            key: Some(key).filter(|key| !key.is_empty()),
+        })
+    }
+}
+
+/// Fails unless `name` could be a player's. Player names are held to the
+/// rule for attribute names, which keeps out the whitespace the token and
+/// key files split their lines on.
+pub(crate) fn validate_name(name: &str) -> Result<()> {
+    crate::attributes::validate_name(name).map_err(|_| Error::InvalidPlayer(name.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Player;
+    use crate::actions::{Action, ActionKind};
+    use crate::error::Error;
+    use crate::log::Event;
+    use crate::serde::{from_bytes, Serialize};
+    use crate::session::Session;
+    use crate::Entity;
+
+    #[test]
+    fn sessions_with_players_only_take_their_actions() {
+        let mut session = Session::new("game".to_string()).unwrap();
+        session
+            .add_entity(Entity::new("troll".to_string()))
+            .unwrap();
+        let fight = || Action::new(ActionKind::Fight, "troll".to_string()).unwrap();
+        session.apply(fight()).unwrap();
+        session
+            .add_player(Player::new("alice".to_string(), None))
+            .unwrap();
+        assert!(matches!(
+            session.add_player(Player::new("alice".to_string(), None)),
+            Err(Error::PlayerExists(_))
+        ));
+        assert!(matches!(
+            session.add_player(Player::new("bob".to_string(), Some("00".to_string()))),
+            Err(Error::InvalidKey)
+        ));
+
+        assert!(matches!(
+            session.apply(fight()),
+            Err(Error::Unattributed(_))
+        ));
+        assert!(matches!(
+            session.apply_by("bob", fight()),
+            Err(Error::PlayerNotFound(_))
+        ));
+        session.apply_by("alice", fight()).unwrap();
+        session.own("troll", Some("alice".to_string())).unwrap();
+        assert!(session.own("troll", Some("bob".to_string())).is_err());
+        assert_eq!(
+            session.entity("troll").unwrap().owner.as_deref(),
+            Some("alice")
+        );
+        assert!(matches!(session.log().last(), Some(Event::Own(..))));
+        assert_eq!(session.size_hint(), session.serialize().len());
+
+        let copy: Session = from_bytes(&session.serialize()).unwrap();
+        assert_eq!(copy.roster(), session.roster());
+        assert_eq!(copy.entities(), session.entities());
+        session.remove_player("alice").unwrap();
+        assert_eq!(session.entity("troll").unwrap().owner, None);
+        session.apply(fight()).unwrap();
+    }
+}
@@ -0,0 +1,689 @@
+use std::fmt::Display;
+use std::io::{self, BufReader, BufWriter, ErrorKind, Read, Write};
+use std::net::TcpStream;
+
+use crate::actions::Action;
+use crate::error::{Error, Result};
+use crate::lobby::OpenGame;
+use crate::mailbox::Letter;
+use crate::metadata::FORMAT_VERSION;
+use crate::outcome::ActionOutcome;
+use crate::serde::{from_bytes, Deserialize, FieldReader, FieldWriter, Serialize};
+use crate::session::Session;
+use crate::turnfile::{Merged, TurnFile};
+
+#[cfg(feature = "tls")]
+mod tls;
+#[cfg(feature = "websocket")]
+mod websocket;
+
+#[cfg(not(feature = "tls"))]
+pub use no_tls::TlsServer;
+#[cfg(feature = "tls")]
+pub use tls::{TlsClient, TlsServer, TlsTransport};
+#[cfg(feature = "websocket")]
+pub use websocket::WebSocketTransport;
+
+/// The most a frame may hold. Anything longer is taken to be garbage or
+/// hostile rather than allocated for.
+pub const MAX_FRAME_LEN: u32 = 16 * 1024 * 1024;
+
+/// The versions of this protocol this build speaks. Raise `max` when the
+/// messages change, and `min` once the old ones are no longer understood.
+///
+/// - 1: the first
+/// - 2: adds `Authenticate` and `Authenticated`
+/// - 3: adds mail and `Chat`
+/// - 4: adds `FetchEvents`, `Events`, `PushEvents` and `Merged`, for sync
+/// - 5: adds `Ping` and `Pong`
+/// - 6: adds `Resume` and `Resumed`
+/// - 7: adds `Spectate`
+/// - 8: adds `Pass`
+/// - 9: adds `Host`, `ListGames`, `Games` and `Join`, for the lobby
+pub const PROTOCOL_VERSIONS: Versions = Versions { min: 1, max: 9 };
+
+/// A range of versions, both ends included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Versions {
+    pub min: u16,
+    pub max: u16,
+}
+
+impl Versions {
+    pub fn only(version: u16) -> Self {
+        Self {
+            min: version,
+            max: version,
+        }
+    }
+
+    /// The newest version in both ranges, if there is one.
+    pub fn agree(self, other: Self) -> Option<u16> {
+        let max = self.max.min(other.max);
+        (max >= self.min.max(other.min)).then_some(max)
+    }
+}
+
+/// As `v3`, or `v1-v2`.
+impl Display for Versions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.min == self.max {
+            write!(f, "v{}", self.min)
+        } else {
+            write!(f, "v{}-v{}", self.min, self.max)
+        }
+    }
+}
+
+/// The first message each way. The client offers what it supports, and the
+/// server answers with the one version of each it picked. Its layout must
+/// never change, so that any two builds can at least tell why they cannot
+/// talk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hello {
+    /// The software sent from, such as `relay_code 0.1.0`.
+    pub software: String,
+    pub protocol: Versions,
+    /// Session format versions, which sessions are sent in.
+    pub session_format: Versions,
+}
+
+impl Hello {
+    /// What this build offers.
+    pub fn current() -> Self {
+        Self {
+            software: format!("relay_code {}", env!("CARGO_PKG_VERSION")),
+            protocol: PROTOCOL_VERSIONS,
+            session_format: Versions::only(FORMAT_VERSION),
+        }
+    }
+
+    /// The answer a server offering `self` gives a client offering
+    /// `client`: the newest versions both support.
+    pub fn answer(&self, client: &Self) -> Result<Self> {
+        let agree = |what, server: Versions, client: Versions| {
+            server
+                .agree(client)
+                .map(Versions::only)
+                .ok_or(Error::IncompatibleVersions(what, server, client))
+        };
+        Ok(Self {
+            software: self.software.clone(),
+            protocol: agree("protocol", self.protocol, client.protocol)?,
+            session_format: agree("session format", self.session_format, client.session_format)?,
+        })
+    }
+}
+
+/// What client and server say to each other, one message to a frame. The
+/// client sends `Hello`, then `Authenticate` if it has a token, and then
+/// requests, and the server answers each with exactly one message. A
+/// `Ping` is answered with a `Pong` at any point, to tell whether the
+/// connection is still alive. Written
+/// as the discriminant and then the fields of the variant:
+///
+/// ```text
+/// U8(0) Str(software) U16(min protocol) U16(max protocol)
+///       U16(min session format) U16(max session format)
+///                                  Hello, from either side
+/// U8(1) Str(session)               CreateSession
+/// U8(2) Str(session) Action Bool(dry run)
+///                                  SubmitAction
+/// U8(3) Str(session)               FetchState
+/// U8(4)                            ListSessions
+/// U8(5) Str(message)               Done
+/// U8(6) ActionOutcome              Outcome
+/// U8(7) Session                    StateResponse, the session's fields inline
+/// U8(8) List(Str(session)...)      Sessions
+/// U8(9) Str(message) U8(exit code) Error
+/// U8(10) Str(token)                Authenticate
+/// U8(11) Str(player)               Authenticated
+/// U8(12)                           FetchMail
+/// U8(13) List(letter)...           Mail
+/// U8(14) U64(id)                   Acknowledge
+/// U8(15) Str(session) Str(text)    Chat
+/// U8(16) Str(session) U32(from)    FetchEvents
+/// U8(17) TurnFile                  Events, the turn's contents inline
+/// U8(18) TurnFile                  PushEvents, likewise
+/// U8(19) U32(applied) Str(conflict)...
+///                                  Merged
+/// U8(20) U64(nonce)                Ping
+/// U8(21) U64(nonce)                Pong
+/// U8(22) Str(token)                Resume
+/// U8(23) Str(token) (Str(session) U32(events))...
+///                                  Resumed
+/// U8(24)                           Spectate
+/// U8(25) Str(session)              Pass
+/// U8(26) Str(session) Str(rule set) U8(slots)
+///                                  Host
+/// U8(27)                           ListGames
+/// U8(28) List(Str(session) Str(rule set) U8(slots) List(Str(player)...))...
+///                                  Games
+/// U8(29) Str(session)              Join
+/// ```
+///
+/// A session is written inline rather than as one field, and so are
+/// letters, open games, events, conflicts and resume cursors, each after
+/// the last rather than nested in a list. Fields too long for a `u16`
+/// length have long headers; see `serde::LONG_HEADER_LEN`.
+#[derive(Debug, PartialEq)]
+pub enum Message {
+    Hello(Hello),
+    CreateSession(String),
+    /// Session name, the action and whether to leave the session unsaved.
+    SubmitAction(String, Action, bool),
+    FetchState(String),
+    ListSessions,
+    /// The request was carried out, as the message says.
+    Done(String),
+    /// What a submitted action did.
+    Outcome(ActionOutcome),
+    StateResponse(Box<Session>),
+    /// The name of every session, sorted.
+    Sessions(Vec<String>),
+    /// The error message and the exit code it would have locally.
+    Error(String, u8),
+    /// A token the server issued, to act as its player from now on.
+    Authenticate(String),
+    /// The player a token was issued to.
+    Authenticated(String),
+    /// Asks for the player's oldest letters.
+    FetchMail,
+    /// Up to `MAIL_BATCH` letters, oldest first, or none if there are no
+    /// more.
+    Mail(Vec<Letter>),
+    /// The id of the last letter read, which the server can throw away
+    /// with every one before it.
+    Acknowledge(u64),
+    /// Session name and what to tell its other players.
+    Chat(String, String),
+    /// Session name and how many of its events to leave out.
+    FetchEvents(String, u32),
+    Events(Box<TurnFile>),
+    /// Events for the server to merge into its copy of their session.
+    PushEvents(Box<TurnFile>),
+    /// What merging pushed events did.
+    Merged(Merged),
+    /// Asks for a `Pong` with the same number.
+    Ping(u64),
+    Pong(u64),
+    /// The token an earlier connection was resumable with, to carry on
+    /// from it, or empty to make this one resumable.
+    Resume(String),
+    /// The token to resume this connection with, and how many events of
+    /// each session the connection resumed was sent.
+    Resumed(String, Vec<(String, u32)>),
+    /// Makes the connection read-only, for a spectator to watch on.
+    Spectate,
+    /// Ends the player's turn in a session, answered with `Done` naming
+    /// whose turn it is now.
+    Pass(String),
+    /// Opens a game in the lobby: session name, the rule set to play by
+    /// and how many players it waits for, the sender being the first.
+    Host(String, String, u8),
+    ListGames,
+    /// Every game in the lobby waiting for players, by name.
+    Games(Vec<OpenGame>),
+    /// Takes a slot in a game in the lobby, answered with `Done` saying
+    /// how many are left or that it has started.
+    Join(String),
+}
+
+impl Message {
+    pub fn error(err: &Error) -> Self {
+        Self::Error(err.to_string(), err.exit_code())
+    }
+}
+
+impl Serialize for Message {
+    fn serialize_to(&self, writer: &mut FieldWriter<'_>) {
+        match self {
+            Self::Hello(hello) => {
+                writer.write_u8(0);
+                writer.write_str(&hello.software);
+                for versions in [hello.protocol, hello.session_format] {
+                    writer.write_u16(versions.min);
+                    writer.write_u16(versions.max);
+                }
+            }
+            Self::CreateSession(name) => {
+                writer.write_u8(1);
+                writer.write_str(name);
+            }
+            Self::SubmitAction(name, action, dry_run) => {
+                writer.write_u8(2);
+                writer.write_str(name);
+                writer.write(action);
+                writer.write_bool(*dry_run);
+            }
+            Self::FetchState(name) => {
+                writer.write_u8(3);
+                writer.write_str(name);
+            }
+            Self::ListSessions => writer.write_u8(4),
+            Self::Done(message) => {
+                writer.write_u8(5);
+                writer.write_str(message);
+            }
+            Self::Outcome(outcome) => {
+                writer.write_u8(6);
+                outcome.serialize_to(writer);
+            }
+            Self::StateResponse(session) => {
+                writer.write_u8(7);
+                session.serialize_to(writer);
+            }
+            Self::Sessions(names) => {
+                writer.write_u8(8);
+                writer.write(names);
+            }
+            Self::Error(message, code) => {
+                writer.write_u8(9);
+                writer.write_str(message);
+                writer.write_u8(*code);
+            }
+            Self::Authenticate(token) => {
+                writer.write_u8(10);
+                writer.write_str(token);
+            }
+            Self::Authenticated(player) => {
+                writer.write_u8(11);
+                writer.write_str(player);
+            }
+            Self::FetchMail => writer.write_u8(12),
+            Self::Mail(letters) => {
+                writer.write_u8(13);
+                for letter in letters {
+                    writer.write(letter);
+                }
+            }
+            Self::Acknowledge(id) => {
+                writer.write_u8(14);
+                writer.write_u64(*id);
+            }
+            Self::Chat(name, text) => {
+                writer.write_u8(15);
+                writer.write_str(name);
+                writer.write_str(text);
+            }
+            Self::FetchEvents(name, from) => {
+                writer.write_u8(16);
+                writer.write_str(name);
+                writer.write_u32(*from);
+            }
+            Self::Events(turn) => {
+                writer.write_u8(17);
+                turn.serialize_to(writer);
+            }
+            Self::PushEvents(turn) => {
+                writer.write_u8(18);
+                turn.serialize_to(writer);
+            }
+            Self::Merged(merged) => {
+                writer.write_u8(19);
+                writer.write_u32(merged.applied as u32);
+                for conflict in &merged.conflicts {
+                    writer.write_str(conflict);
+                }
+            }
+            Self::Ping(nonce) => {
+                writer.write_u8(20);
+                writer.write_u64(*nonce);
+            }
+            Self::Pong(nonce) => {
+                writer.write_u8(21);
+                writer.write_u64(*nonce);
+            }
+            Self::Resume(token) => {
+                writer.write_u8(22);
+                writer.write_str(token);
+            }
+            Self::Resumed(token, seen) => {
+                writer.write_u8(23);
+                writer.write_str(token);
+                for (session, events) in seen {
+                    writer.write_str(session);
+                    writer.write_u32(*events);
+                }
+            }
+            Self::Spectate => writer.write_u8(24),
+            Self::Pass(name) => {
+                writer.write_u8(25);
+                writer.write_str(name);
+            }
+            Self::Host(name, rule_set, slots) => {
+                writer.write_u8(26);
+                writer.write_str(name);
+                writer.write_str(rule_set);
+                writer.write_u8(*slots);
+            }
+            Self::ListGames => writer.write_u8(27),
+            Self::Games(games) => {
+                writer.write_u8(28);
+                for game in games {
+                    writer.write(game);
+                }
+            }
+            Self::Join(name) => {
+                writer.write_u8(29);
+                writer.write_str(name);
+            }
+        }
+    }
+}
+
+impl Deserialize for Message {
+    fn deserialize(reader: &mut FieldReader<'_>) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        match reader.read_field()? {
+            0 => Ok(Self::Hello(Hello {
+                software: reader.read_field()?,
+                protocol: Versions {
+                    min: reader.read_field()?,
+                    max: reader.read_field()?,
+                },
+                session_format: Versions {
+                    min: reader.read_field()?,
+                    max: reader.read_field()?,
+                },
+            })),
+            1 => Ok(Self::CreateSession(reader.read_field()?)),
+            2 => Ok(Self::SubmitAction(
+                reader.read_field()?,
+                reader.read_field()?,
+                reader.read_field()?,
+            )),
+            3 => Ok(Self::FetchState(reader.read_field()?)),
+            4 => Ok(Self::ListSessions),
+            5 => Ok(Self::Done(reader.read_field()?)),
+            6 => Ok(Self::Outcome(ActionOutcome::deserialize(reader)?)),
+            7 => Ok(Self::StateResponse(Box::new(Session::deserialize(reader)?))),
+            8 => Ok(Self::Sessions(reader.read_field()?)),
+            9 => Ok(Self::Error(reader.read_field()?, reader.read_field()?)),
+            10 => Ok(Self::Authenticate(reader.read_field()?)),
+            11 => Ok(Self::Authenticated(reader.read_field()?)),
+            12 => Ok(Self::FetchMail),
+            13 => {
+                let mut letters = vec![];
+                while !reader.is_empty() {
+                    letters.push(reader.read_field()?);
+                }
+                Ok(Self::Mail(letters))
+            }
+            14 => Ok(Self::Acknowledge(reader.read_field()?)),
+            15 => Ok(Self::Chat(reader.read_field()?, reader.read_field()?)),
+            16 => Ok(Self::FetchEvents(
+                reader.read_field()?,
+                reader.read_field()?,
+            )),
+            17 => Ok(Self::Events(Box::new(TurnFile::deserialize(reader)?))),
+            18 => Ok(Self::PushEvents(Box::new(TurnFile::deserialize(reader)?))),
+            19 => {
+                let applied = reader.read_field::<u32>()? as usize;
+                let mut conflicts = vec![];
+                while !reader.is_empty() {
+                    conflicts.push(reader.read_field()?);
+                }
+                Ok(Self::Merged(Merged { applied, conflicts }))
+            }
+            20 => Ok(Self::Ping(reader.read_field()?)),
+            21 => Ok(Self::Pong(reader.read_field()?)),
+            22 => Ok(Self::Resume(reader.read_field()?)),
+            23 => {
+                let token = reader.read_field()?;
+                let mut seen = vec![];
+                while !reader.is_empty() {
+                    seen.push((reader.read_field()?, reader.read_field()?));
+                }
+                Ok(Self::Resumed(token, seen))
+            }
+            24 => Ok(Self::Spectate),
+            25 => Ok(Self::Pass(reader.read_field()?)),
+            26 => Ok(Self::Host(
+                reader.read_field()?,
+                reader.read_field()?,
+                reader.read_field()?,
+            )),
+            27 => Ok(Self::ListGames),
+            28 => {
+                let mut games = vec![];
+                while !reader.is_empty() {
+                    games.push(reader.read_field()?);
+                }
+                Ok(Self::Games(games))
+            }
+            29 => Ok(Self::Join(reader.read_field()?)),
+            discriminant => Err(Error::InvalidVariant(discriminant)),
+        }
+    }
+}
+
+/// Writes messages, each as one frame: its length as a big-endian `u32`,
+/// then the message.
+pub struct FramedWriter<W> {
+    inner: W,
+    /// Reused for every message.
+    buf: Vec<u8>,
+}
+
+impl<W: Write> FramedWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self { inner, buf: vec![] }
+    }
+
+    /// Writes and flushes `message`. One too long to frame is not written.
+    pub fn write(&mut self, message: &Message) -> Result<()> {
+        message.serialize_into(&mut self.buf);
+        let len = frame_len(self.buf.len() as u64)?;
+        self.inner.write_all(&len.to_be_bytes())?;
+        self.inner.write_all(&self.buf)?;
+        self.inner.flush()?;
+        Ok(())
+    }
+}
+
+/// Reads the messages a `FramedWriter` writes.
+pub struct FramedReader<R> {
+    inner: R,
+    buf: Vec<u8>,
+    /// The longest frame accepted.
+    max_len: u32,
+}
+
+impl<R: Read> FramedReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            buf: vec![],
+            max_len: MAX_FRAME_LEN,
+        }
+    }
+
+    /// Refuses frames longer than `max_len`, up to `MAX_FRAME_LEN`.
+    pub fn limit(&mut self, max_len: u32) {
+        self.max_len = max_len.min(MAX_FRAME_LEN);
+    }
+
+    /// The next message, or `None` if the other end closed the connection
+    /// between messages. A frame that is empty, too long, cut short or
+    /// does not hold exactly one message is an error. One over the limit
+    /// but not `MAX_FRAME_LEN` is skipped, so the next can still be read.
+    pub fn read(&mut self) -> Result<Option<Message>> {
+        let mut len = [0; 4];
+        match self.inner.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err.into()),
+        }
+        let len = frame_len(u32::from_be_bytes(len).into())?;
+        if len > self.max_len {
+            io::copy(&mut (&mut self.inner).take(len.into()), &mut io::sink())?;
+            return Err(Error::MessageTooLarge(len, self.max_len));
+        }
+        self.buf.clear();
+        (&mut self.inner)
+            .take(len.into())
+            .read_to_end(&mut self.buf)?;
+        if self.buf.len() != len as usize {
+            return Err(Error::UnexpectedEof);
+        }
+        from_bytes(&self.buf).map(Some)
+    }
+}
+
+/// A connection that carries messages both ways.
+pub trait Transport {
+    /// The next message, or `None` if the other end closed the connection
+    /// between messages.
+    fn receive(&mut self) -> Result<Option<Message>>;
+
+    fn send(&mut self, message: &Message) -> Result<()>;
+
+    /// Refuses messages longer than `max_len` from now on; see
+    /// `FramedReader::limit`.
+    fn limit(&mut self, max_len: u32);
+}
+
+/// What carries messages on the connections a server accepts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Carrier {
+    Tcp,
+    /// Only in websocket builds.
+    WebSocket,
+}
+
+impl Carrier {
+    /// The transport for a connection a client made, encrypted with `tls`
+    /// if given.
+    pub fn accept(self, stream: TcpStream, tls: Option<&TlsServer>) -> Result<Box<dyn Transport>> {
+        #[cfg(feature = "tls")]
+        if let Some(tls) = tls {
+            let stream = tls.accept(stream)?;
+            return match self {
+                Self::Tcp => Ok(Box::new(TlsTransport::new(stream))),
+                #[cfg(feature = "websocket")]
+                Self::WebSocket => Ok(Box::new(WebSocketTransport::accept(stream)?)),
+                #[cfg(not(feature = "websocket"))]
+                Self::WebSocket => Err(Error::WebSocketUnsupported),
+            };
+        }
+        #[cfg(not(feature = "tls"))]
+        if let Some(tls) = tls {
+            match *tls {}
+        }
+        match self {
+            Self::Tcp => Ok(Box::new(TcpTransport::new(stream)?)),
+            #[cfg(feature = "websocket")]
+            Self::WebSocket => Ok(Box::new(WebSocketTransport::accept(stream)?)),
+            #[cfg(not(feature = "websocket"))]
+            Self::WebSocket => Err(Error::WebSocketUnsupported),
+        }
+    }
+}
+
+/// Frames written straight onto a TCP stream.
+pub struct TcpTransport {
+    reader: FramedReader<BufReader<TcpStream>>,
+    writer: FramedWriter<BufWriter<TcpStream>>,
+}
+
+impl TcpTransport {
+    pub fn new(stream: TcpStream) -> Result<Self> {
+        Ok(Self {
+            reader: FramedReader::new(BufReader::new(stream.try_clone()?)),
+            writer: FramedWriter::new(BufWriter::new(stream)),
+        })
+    }
+}
+
+impl Transport for TcpTransport {
+    fn receive(&mut self) -> Result<Option<Message>> {
+        self.reader.read()
+    }
+
+    fn send(&mut self, message: &Message) -> Result<()> {
+        self.writer.write(message)
+    }
+
+    fn limit(&mut self, max_len: u32) {
+        self.reader.limit(max_len);
+    }
+}
+
+/// Stands in for what builds without TLS cannot have.
+#[cfg(not(feature = "tls"))]
+mod no_tls {
+    use std::path::Path;
+
+    use crate::error::{Error, Result};
+
+    /// None can be made; see the `tls` feature.
+    #[derive(Debug, Clone)]
+    pub enum TlsServer {}
+
+    impl TlsServer {
+        pub fn load(_cert: &Path, _key: &Path) -> Result<Self> {
+            Err(Error::TlsUnsupported)
+        }
+    }
+}
+
+/// `len` as the length of a frame, if it may be one.
+pub(crate) fn frame_len(len: u64) -> Result<u32> {
+    match u32::try_from(len) {
+        Ok(len) if (1..=MAX_FRAME_LEN).contains(&len) => Ok(len),
+        _ => Err(Error::InvalidFrameLen(len)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FramedReader, FramedWriter, Hello, Message, Versions, MAX_FRAME_LEN};
+    use crate::actions::{Action, ActionKind};
+    use crate::error::Error;
+    use crate::session::Session;
+
+    #[test]
+    fn messages_survive_framing() {
+        let action = Action::new(ActionKind::Rest, "hero".to_string()).unwrap();
+        let request = Message::SubmitAction("game".to_string(), action, false);
+        let session = Session::new("game".to_string()).unwrap();
+        let response = Message::StateResponse(Box::new(session));
+        let mut wire = vec![];
+        let mut writer = FramedWriter::new(&mut wire);
+        writer.write(&request).unwrap();
+        writer.write(&response).unwrap();
+
+        let mut reader = FramedReader::new(wire.as_slice());
+        assert_eq!(reader.read().unwrap(), Some(request));
+        assert_eq!(reader.read().unwrap(), Some(response));
+        assert_eq!(reader.read().unwrap(), None);
+
+        let read = |wire: &[u8]| FramedReader::new(wire).read().unwrap_err();
+        assert!(matches!(read(&[0, 0, 0, 9, 1]), Error::UnexpectedEof));
+        assert!(matches!(read(&[0, 0, 0, 0]), Error::InvalidFrameLen(0)));
+        let too_long = (MAX_FRAME_LEN + 1).to_be_bytes();
+        assert!(matches!(read(&too_long), Error::InvalidFrameLen(_)));
+    }
+
+    #[test]
+    fn peers_agree_on_the_newest_shared_version() {
+        let offer = |min, max| Hello {
+            protocol: Versions { min, max },
+            ..Hello::current()
+        };
+        let answer = offer(1, 3).answer(&offer(2, 5)).unwrap();
+        assert_eq!(answer.protocol, Versions::only(3));
+        assert_eq!(answer.session_format, Hello::current().session_format);
+        assert_eq!(
+            offer(3, 4).answer(&offer(1, 2)).unwrap_err().to_string(),
+            "server requires protocol v3-v4, client supports v1-v2"
+        );
+        assert_eq!(
+            offer(1, 1).answer(&offer(2, 2)).unwrap_err().to_string(),
+            "server supports protocol v1, client requires v2"
+        );
+    }
+}
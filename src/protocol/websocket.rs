@@ -0,0 +1,153 @@
+use std::io::{ErrorKind, Read, Write};
+use std::net::TcpStream;
+
+use tungstenite::error::ProtocolError;
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{HandshakeError, Message as WsMessage, WebSocket};
+
+use super::{FramedReader, FramedWriter, Message, Transport, MAX_FRAME_LEN};
+use crate::error::{Error, Result};
+
+/// Frames carried one to a binary WebSocket message, for clients that can
+/// only reach a server over HTTP, such as browsers.
+pub struct WebSocketTransport<S: Read + Write> {
+    socket: WebSocket<S>,
+    max_len: u32,
+}
+
+impl<S: Read + Write> WebSocketTransport<S> {
+    fn new(socket: WebSocket<S>) -> Self {
+        Self {
+            socket,
+            max_len: MAX_FRAME_LEN,
+        }
+    }
+
+    /// Completes the opening handshake of a client connecting on `stream`.
+    pub fn accept(stream: S) -> Result<Self> {
+        match tungstenite::accept(stream) {
+            Ok(socket) => Ok(Self::new(socket)),
+            Err(HandshakeError::Failure(err)) => Err(err.into()),
+            Err(HandshakeError::Interrupted(_)) => Err(Error::Io(ErrorKind::WouldBlock.into())),
+        }
+    }
+
+    /// Completes the opening handshake with the server at `url` over
+    /// `stream`, which is already connected to it, encrypted or not.
+    pub fn handshake(url: &str, stream: S) -> Result<Self> {
+        match tungstenite::client(url, stream) {
+            Ok((socket, _)) => Ok(Self::new(socket)),
+            Err(HandshakeError::Failure(err)) => Err(err.into()),
+            Err(HandshakeError::Interrupted(_)) => Err(Error::Io(ErrorKind::WouldBlock.into())),
+        }
+    }
+}
+
+impl WebSocketTransport<MaybeTlsStream<TcpStream>> {
+    /// Connects to a server at `url`, such as `ws://example.com:7778`.
+    pub fn connect(url: &str) -> Result<Self> {
+        let (socket, _) = tungstenite::connect(url)?;
+        Ok(Self::new(socket))
+    }
+}
+
+impl<S: Read + Write> Transport for WebSocketTransport<S> {
+    fn receive(&mut self) -> Result<Option<Message>> {
+        loop {
+            let payload = match self.socket.read() {
+                Ok(WsMessage::Binary(payload)) => payload,
+                // Pings are answered as they are read.
+                Ok(WsMessage::Ping(_) | WsMessage::Pong(_) | WsMessage::Frame(_)) => continue,
+                Ok(WsMessage::Text(_)) => {
+                    return Err(Error::Io(std::io::Error::new(
+                        ErrorKind::InvalidData,
+                        "expected a binary message",
+                    )))
+                }
+                Ok(WsMessage::Close(_))
+                | Err(tungstenite::Error::ConnectionClosed)
+                | Err(tungstenite::Error::Protocol(ProtocolError::ResetWithoutClosingHandshake)) => {
+                    return Ok(None)
+                }
+                Err(err) => return Err(err.into()),
+            };
+            let mut reader = FramedReader::new(&payload[..]);
+            reader.limit(self.max_len);
+            let message = reader.read()?.ok_or(Error::UnexpectedEof)?;
+            return match reader.inner.len() {
+                0 => Ok(Some(message)),
+                trailing => Err(Error::TrailingBytes(trailing)),
+            };
+        }
+    }
+
+    fn send(&mut self, message: &Message) -> Result<()> {
+        let mut frame = vec![];
+        FramedWriter::new(&mut frame).write(message)?;
+        self.socket.send(WsMessage::binary(frame))?;
+        Ok(())
+    }
+
+    fn limit(&mut self, max_len: u32) {
+        self.max_len = max_len;
+    }
+}
+
+/// Says goodbye, if the other end is still listening.
+impl<S: Read + Write> Drop for WebSocketTransport<S> {
+    fn drop(&mut self) {
+        let _ = self.socket.close(None);
+        let _ = self.socket.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::path::Path;
+    use std::thread;
+
+    use super::WebSocketTransport;
+    use crate::client::Client;
+    use crate::lobby::Lobby;
+    use crate::mailbox::Mailboxes;
+    use crate::provider::{LocalSessions, SessionProvider};
+    use crate::server::{handle, respond, Connections};
+    use crate::store::MemStore;
+    use crate::tokens::Tokens;
+
+    #[test]
+    fn clients_connect_over_websocket() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut transport = WebSocketTransport::accept(stream).unwrap();
+            let store = MemStore::new();
+            let sessions = LocalSessions::new(Path::new(""), &store);
+            let tokens = Tokens::default();
+            handle(
+                &mut transport,
+                &tokens,
+                &Connections::default(),
+                |request, _| {
+                    respond(
+                        request,
+                        None,
+                        &sessions,
+                        &Mailboxes::default(),
+                        &Lobby::default(),
+                        &[],
+                    )
+                },
+            )
+            .unwrap();
+        });
+
+        let client = Client::connect(&format!("ws://{addr}"), None, None).unwrap();
+        client.create("game").unwrap();
+        assert_eq!(client.list().unwrap(), ["game"]);
+        drop(client);
+        server.join().unwrap();
+    }
+}
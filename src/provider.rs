@@ -0,0 +1,188 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use crate::actions::Action;
+use crate::error::{Error, Result};
+use crate::outcome::ActionOutcome;
+use crate::session::Session;
+use crate::store::{ensure_absent, SessionStore};
+use crate::timestamp::Timestamp;
+use crate::turnfile::{Merged, TurnFile};
+
+/// Where the commands that work both locally and with `--remote` get their
+/// sessions: the data directory, or a server. See `LocalSessions` and
+/// `Client`.
+pub trait SessionProvider {
+    /// Creates an empty session, failing if there is one by that name.
+    fn create(&self, name: &str) -> Result<()>;
+
+    fn load(&self, name: &str) -> Result<Session>;
+
+    /// Applies `action` to session `name`, saving the result unless
+    /// `dry_run`.
+    fn apply(&self, name: &str, action: Action, dry_run: bool) -> Result<ActionOutcome>;
+
+    /// The names of every session, sorted.
+    fn list(&self) -> Result<Vec<String>>;
+
+    /// The events of session `name` after its first `from`, for `sync`.
+    fn events(&self, name: &str, from: usize) -> Result<TurnFile>;
+
+    /// Merges `turn` into its session, saving the result.
+    fn receive(&self, turn: &TurnFile) -> Result<Merged>;
+
+    /// Saves `session`, built elsewhere, as a new one, failing if there is
+    /// one by that name. Whatever it holds is trusted, so only a server's
+    /// own lobby saves sessions this way.
+    fn save_new(&self, session: Session) -> Result<()>;
+
+    /// Logs that the player said `text` in session `name`.
+    fn say(&self, name: &str, text: &str) -> Result<()>;
+
+    /// Ends the player's turn in session `name`, returning whose turn it
+    /// is now.
+    fn pass(&self, name: &str) -> Result<String>;
+}
+
+/// The sessions in a store, with the scripts kept in the data directory.
+pub struct LocalSessions<'a> {
+    dir: &'a Path,
+    store: &'a dyn SessionStore,
+    /// Who actions are logged as taken by, if anyone in particular.
+    player: Option<&'a str>,
+    /// Whether the sessions are asked for by a player, whose own events
+    /// are the only ones received; see `by`.
+    attributed: bool,
+}
+
+impl<'a> LocalSessions<'a> {
+    pub fn new(dir: &'a Path, store: &'a dyn SessionStore) -> Self {
+        Self {
+            dir,
+            store,
+            player: None,
+            attributed: false,
+        }
+    }
+
+    /// Ends the turn of the player to play in session `name` if they have
+    /// run out of time, saving the result, and returns who did. Sessions
+    /// with time left are only read, so they are not locked.
+    pub fn time_out(&self, name: &str) -> Result<Option<String>> {
+        let now = Timestamp::now()?;
+        let deadline = self.store.load(name)?.order().deadline();
+        if deadline.is_none_or(|deadline| now < deadline) {
+            return Ok(None);
+        }
+        let _lock = self.store.lock(name)?;
+        let mut session = self.store.load(name)?;
+        let player = session.time_out(now)?;
+        if player.is_some() {
+            session.save(self.store)?;
+        }
+        Ok(player)
+    }
+
+    /// The same sessions, as `player` asks for them: actions are logged as
+    /// taken by them, and only events of their own are received from them,
+    /// none at all if they are no one in particular.
+    pub fn by<'b>(&'b self, player: Option<&'b str>) -> LocalSessions<'b> {
+        LocalSessions {
+            dir: self.dir,
+            store: self.store,
+            player,
+            attributed: true,
+        }
+    }
+}
+
+impl SessionProvider for LocalSessions<'_> {
+    fn create(&self, name: &str) -> Result<()> {
+        let _lock = self.store.lock(name)?;
+        ensure_absent(self.store, name)?;
+        Session::new(name.to_string())?.save(self.store)
+    }
+
+    fn load(&self, name: &str) -> Result<Session> {
+        self.store.load(name)
+    }
+
+    fn apply(&self, name: &str, action: Action, dry_run: bool) -> Result<ActionOutcome> {
+        let _lock = self.store.lock(name)?;
+        let mut session = self.store.load(name)?;
+        adopt_script(self.dir, &mut session, &action)?;
+        crate::verbose!("applying {action} to session {name:?}");
+        let outcome = match self.player {
+            Some(player) => session.apply_by(player, action)?,
+            None => session.apply(action)?,
+        };
+        if !dry_run {
+            session.save(self.store)?;
+        }
+        Ok(outcome)
+    }
+
+    fn list(&self) -> Result<Vec<String>> {
+        let sessions = self.store.list()?;
+        Ok(sessions.into_iter().map(|info| info.name).collect())
+    }
+
+    fn events(&self, name: &str, from: usize) -> Result<TurnFile> {
+        TurnFile::after(&self.store.load(name)?, from)
+    }
+
+    fn receive(&self, turn: &TurnFile) -> Result<Merged> {
+        let _lock = self.store.lock(&turn.session)?;
+        let mut session = self.store.load(&turn.session)?;
+        if self.attributed {
+            let player = self.player.ok_or(Error::Unauthenticated)?;
+            turn.check_sent_by(&session, player)?;
+        }
+        let merged = turn.merge_into(&mut session)?;
+        if merged.applied > 0 {
+            session.save(self.store)?;
+        }
+        Ok(merged)
+    }
+
+    fn save_new(&self, mut session: Session) -> Result<()> {
+        let _lock = self.store.lock(session.name())?;
+        ensure_absent(self.store, session.name())?;
+        session.save(self.store)
+    }
+
+    /// Fails unless the sessions are someone's in particular; see `by`.
+    fn say(&self, name: &str, text: &str) -> Result<()> {
+        let player = self.player.ok_or(Error::Unauthenticated)?;
+        let _lock = self.store.lock(name)?;
+        let mut session = self.store.load(name)?;
+        session.say(player, text)?;
+        session.save(self.store)
+    }
+
+    fn pass(&self, name: &str) -> Result<String> {
+        let _lock = self.store.lock(name)?;
+        let mut session = self.store.load(name)?;
+        let next = session.pass(self.player)?;
+        session.save(self.store)?;
+        Ok(next)
+    }
+}
+
+/// Defines the script a scripted action needs from
+/// `<data-dir>/scripts/<kind>.rhai` if the session does not have it yet.
+/// From then on the session carries its own copy.
+pub fn adopt_script(dir: &Path, session: &mut Session, action: &Action) -> Result<()> {
+    let Some(name) = action.script() else {
+        return Ok(());
+    };
+    if session.scripts().contains_key(&name) {
+        return Ok(());
+    }
+    match fs::read_to_string(dir.join("scripts").join(format!("{name}.rhai"))) {
+        Ok(source) => session.define_script(name, source),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err.into()),
+    }
+}
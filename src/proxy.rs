@@ -0,0 +1,191 @@
+use std::io::{BufReader, ErrorKind, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use crate::error::Result;
+use crate::protocol::{frame_len, Message};
+use crate::serde::from_bytes;
+
+/// The most of a message shown in a transcript, in characters. States
+/// can run to megabytes.
+const MAX_SHOWN: usize = 500;
+
+/// Relays each client that connects to `listener` to a server at
+/// `upstream` and back, passing on every frame unchanged and `log` a line
+/// for each, with the message decoded. Only unencrypted TCP can be
+/// followed. Runs until the listener fails.
+pub fn proxy(listener: TcpListener, upstream: &str, log: &(dyn Fn(&str) + Sync)) -> Result<()> {
+    let connections = AtomicUsize::new(0);
+    thread::scope(|scope| {
+        for client in listener.incoming() {
+            let client = client?;
+            let id = connections.fetch_add(1, Ordering::Relaxed) + 1;
+            scope.spawn(move || {
+                if let Err(err) = relay(client, upstream, id, log) {
+                    log(&format!("#{id} {err}"));
+                }
+            });
+        }
+        Ok(())
+    })
+}
+
+/// Relays one client, numbered `id` in the transcript, until both ends
+/// have closed.
+pub(crate) fn relay(
+    client: TcpStream,
+    upstream: &str,
+    id: usize,
+    log: &(dyn Fn(&str) + Sync),
+) -> Result<()> {
+    let peer = client.peer_addr()?;
+    let server = TcpStream::connect(upstream)?;
+    log(&format!("#{id} {peer} connected, relayed to {upstream}"));
+    let (client_said, server_said) = (format!("#{id} client:"), format!("#{id} server:"));
+    thread::scope(|scope| {
+        let up = scope.spawn(|| pass(&client, &server, &client_said, log));
+        let down = pass(&server, &client, &server_said, log);
+        let up = up
+            .join()
+            .unwrap_or_else(|err| std::panic::resume_unwind(err));
+        up.and(down)
+    })
+}
+
+/// Passes frames from `from` to `to` until `from` closes, then closes
+/// `to` for writing, as `from` did. If a frame cannot be followed, both
+/// are closed, so that neither end waits on the other.
+fn pass(from: &TcpStream, to: &TcpStream, who: &str, log: &dyn Fn(&str)) -> Result<()> {
+    let mut reader = BufReader::new(from);
+    let mut frame = vec![];
+    let result = loop {
+        match next_frame(&mut reader, &mut frame) {
+            Ok(true) => {}
+            Ok(false) => {
+                log(&format!("{who} closed"));
+                break Ok(());
+            }
+            Err(err) => {
+                let _ = from.shutdown(Shutdown::Both);
+                break Err(err);
+            }
+        }
+        log(&format!("{who} {}", describe(&frame[4..])));
+        if let Err(err) = (&*to).write_all(&frame) {
+            break Err(err.into());
+        }
+    };
+    let _ = to.shutdown(match result {
+        Ok(()) => Shutdown::Write,
+        Err(_) => Shutdown::Both,
+    });
+    result
+}
+
+/// Reads the next frame whole, length first, into `frame`, returning
+/// whether there was one.
+fn next_frame(reader: &mut impl Read, frame: &mut Vec<u8>) -> Result<bool> {
+    let mut len = [0; 4];
+    match reader.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(false),
+        Err(err) => return Err(err.into()),
+    }
+    let body = frame_len(u32::from_be_bytes(len).into())? as usize;
+    frame.clear();
+    frame.extend_from_slice(&len);
+    frame.resize(4 + body, 0);
+    reader.read_exact(&mut frame[4..])?;
+    Ok(true)
+}
+
+/// The message in `bytes`, or why it could not be decoded, shortened to
+/// `MAX_SHOWN` characters.
+fn describe(bytes: &[u8]) -> String {
+    let shown = match from_bytes::<Message>(bytes) {
+        Ok(message) => format!("{message:?}"),
+        Err(err) => format!("undecodable ({err})"),
+    };
+    match shown.char_indices().nth(MAX_SHOWN) {
+        Some((cut, _)) => format!("{}... ({} bytes)", &shown[..cut], bytes.len()),
+        None => shown,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::path::Path;
+    use std::sync::Mutex;
+    use std::thread;
+
+    use super::relay;
+    use crate::client::Client;
+    use crate::lobby::Lobby;
+    use crate::mailbox::Mailboxes;
+    use crate::protocol::TcpTransport;
+    use crate::provider::{LocalSessions, SessionProvider};
+    use crate::server::{handle, respond, Connections};
+    use crate::store::MemStore;
+    use crate::tokens::Tokens;
+
+    #[test]
+    fn transcripts_show_each_message_decoded() {
+        let upstream = TcpListener::bind("127.0.0.1:0").unwrap();
+        let upstream_addr = upstream.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            let (stream, _) = upstream.accept().unwrap();
+            let store = MemStore::new();
+            let sessions = LocalSessions::new(Path::new(""), &store);
+            let mut transport = TcpTransport::new(stream).unwrap();
+            handle(
+                &mut transport,
+                &Tokens::default(),
+                &Connections::default(),
+                |request, _| {
+                    respond(
+                        request,
+                        None,
+                        &sessions,
+                        &Mailboxes::default(),
+                        &Lobby::default(),
+                        &[],
+                    )
+                },
+            )
+            .unwrap();
+        });
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let lines = Mutex::new(vec![]);
+
+        thread::scope(|scope| {
+            let proxied = scope.spawn(|| {
+                let (client, _) = listener.accept().unwrap();
+                let log = |line: &str| lines.lock().unwrap().push(line.to_string());
+                relay(client, &upstream_addr, 1, &log)
+            });
+            let client = Client::connect(&addr, None, None).unwrap();
+            client.create("game").unwrap();
+            assert_eq!(client.list().unwrap(), ["game"]);
+            drop(client);
+            proxied.join().unwrap().unwrap();
+        });
+        server.join().unwrap();
+
+        let lines = lines.into_inner().unwrap();
+        assert!(lines[0].starts_with("#1 127.0.0.1:"));
+        for line in [
+            "#1 client: CreateSession(\"game\")",
+            "#1 server: Sessions([\"game\"])",
+            "#1 client: closed",
+            "#1 server: closed",
+        ] {
+            assert!(
+                lines.iter().any(|shown| shown == line),
+                "{line} in {lines:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use crate::atomic;
+use crate::error::{Error, Result};
+
+/// Holds the name of the most recently used session, so commands can leave
+/// it out.
+const FILENAME: &str = "relay_code.current";
+
+pub fn remember(dir: &Path, name: &str) -> Result<()> {
+    atomic::write(&dir.join(FILENAME), name.as_bytes())
+}
+
+pub fn last(dir: &Path) -> Result<Option<String>> {
+    match fs::read_to_string(dir.join(FILENAME)) {
+        Ok(name) if name.trim().is_empty() => Ok(None),
+        Ok(name) => Ok(Some(name.trim().to_string())),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Forgets `name` if it is the most recent session, e.g. once deleted.
+pub fn forget(dir: &Path, name: &str) -> Result<()> {
+    if last(dir)?.as_deref() == Some(name) {
+        fs::remove_file(dir.join(FILENAME))?;
+    }
+    Ok(())
+}
+
+/// `name` if given, otherwise the most recent session.
+pub fn resolve(dir: &Path, name: Option<String>) -> Result<String> {
+    match name {
+        Some(name) => Ok(name),
+        None => last(dir)?.ok_or(Error::NoRecentSession),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::temp_dir::TempDir;
+
+    #[test]
+    fn recent_session_is_remembered_and_forgotten() {
+        let dir = TempDir::new("recent");
+
+        assert_eq!(super::last(&dir).unwrap(), None);
+        super::remember(&dir, "game").unwrap();
+        assert_eq!(super::resolve(&dir, None).unwrap(), "game");
+        assert_eq!(super::resolve(&dir, Some("other".into())).unwrap(), "other");
+
+        super::forget(&dir, "other").unwrap();
+        assert_eq!(super::last(&dir).unwrap().as_deref(), Some("game"));
+        super::forget(&dir, "game").unwrap();
+        assert!(super::resolve(&dir, None).is_err());
+    }
+}
@@ -0,0 +1,204 @@
+use std::fmt::Display;
+
+use uuid::Uuid;
+
+use crate::error::{Error, Result};
+use crate::serde::{Field, FieldWriter, SerializeField, HEADER_LEN};
+use crate::Entity;
+
+/// How one entity stands towards another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Relation {
+    Ally,
+    Enemy,
+    Owns,
+    LocatedIn,
+}
+
+impl Relation {
+    pub const ALL: [Relation; 4] = [Self::Ally, Self::Enemy, Self::Owns, Self::LocatedIn];
+
+    /// Whether it holds both ways, as being allies does and owning does not.
+    pub fn is_mutual(&self) -> bool {
+        matches!(self, Self::Ally | Self::Enemy)
+    }
+
+    /// The name typed on the command line, such as `located-in`.
+    pub fn parse(name: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|relation| relation.to_string().eq_ignore_ascii_case(name))
+            .ok_or(Error::InvalidArgs)
+    }
+
+    /// Reads between the names of the two entities, as in `a owns b`.
+    pub fn phrase(&self) -> &'static str {
+        match self {
+            Self::Ally => "is an ally of",
+            Self::Enemy => "is an enemy of",
+            Self::Owns => "owns",
+            Self::LocatedIn => "is located in",
+        }
+    }
+
+    fn id(&self) -> u8 {
+        match self {
+            Self::Ally => 0,
+            Self::Enemy => 1,
+            Self::Owns => 2,
+            Self::LocatedIn => 3,
+        }
+    }
+}
+
+impl Display for Relation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Ally => write!(f, "ally"),
+            Self::Enemy => write!(f, "enemy"),
+            Self::Owns => write!(f, "owns"),
+            Self::LocatedIn => write!(f, "located-in"),
+        }
+    }
+}
+
+impl SerializeField for Relation {
+    fn serialize_field(&self, writer: &mut FieldWriter<'_>) {
+        writer.write_u8(self.id());
+    }
+}
+
+impl TryFrom<Field<'_>> for Relation {
+    type Error = Error;
+
+    fn try_from(value: Field<'_>) -> Result<Self> {
+        let id: u8 = value.try_into()?;
+        Self::ALL
+            .into_iter()
+            .find(|relation| relation.id() == id)
+            .ok_or(Error::InvalidVariant(id))
+    }
+}
+
+/// A relation between two entities, by id so that it follows them through
+/// renames. Mutual relations are kept with the lower id first, so each pair
+/// is stored once whichever way round it was given. Written as:
+///
+/// ```text
+/// List(U128(from) U8(relation) U128(to))
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Relationship {
+    pub from: Uuid,
+    pub relation: Relation,
+    pub to: Uuid,
+}
+
+impl Relationship {
+    pub(crate) const FIELD_LEN: usize = 4 * HEADER_LEN + 16 + 1 + 16;
+
+    pub fn new(from: Uuid, relation: Relation, to: Uuid) -> Self {
+        let (from, to) = match relation.is_mutual() && to < from {
+            true => (to, from),
+            false => (from, to),
+        };
+        Self { from, relation, to }
+    }
+
+    pub fn involves(&self, id: Uuid) -> bool {
+        self.from == id || self.to == id
+    }
+
+    /// The relationship in words, with the entities' names taken from
+    /// `entities`.
+    pub fn describe(&self, entities: &[Entity]) -> String {
+        let name = |id: Uuid| {
+            entities
+                .iter()
+                .find(|entity| entity.id == id)
+                .map_or_else(|| id.to_string(), |entity| entity.name.clone())
+        };
+        format!(
+            "{} {} {}",
+            name(self.from),
+            self.relation.phrase(),
+            name(self.to)
+        )
+    }
+}
+
+impl SerializeField for Relationship {
+    fn serialize_field(&self, writer: &mut FieldWriter<'_>) {
+        writer.write(&(self.from.as_u128(), self.relation, self.to.as_u128()));
+    }
+}
+
+impl TryFrom<Field<'_>> for Relationship {
+    type Error = Error;
+
+    fn try_from(value: Field<'_>) -> Result<Self> {
+        let (from, relation, to): (u128, _, u128) = value.try_into()?;
+        Ok(Self::new(
+            Uuid::from_u128(from),
+            relation,
+            Uuid::from_u128(to),
+        ))
+    }
+}
+
+impl Display for Relationship {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {}", self.from, self.relation, self.to)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Relation;
+    use crate::actions::{Action, ActionKind, Param};
+    use crate::serde::{Deserialize, FieldReader, Serialize};
+    use crate::session::Session;
+    use crate::Entity;
+
+    #[test]
+    fn enemies_will_not_trade() {
+        let mut session = Session::new("game".to_string()).unwrap();
+        for name in ["hero", "goblin"] {
+            let mut entity = Entity::new(name.to_string());
+            entity.stats.hp = 5;
+            session.add_entity(entity).unwrap();
+        }
+        let trade = || {
+            let params = [
+                ("with".to_string(), Param::Text("hero".to_string())),
+                ("amount".to_string(), Param::Int(1)),
+            ];
+            Action::new(ActionKind::Trade, "goblin".to_string())
+                .unwrap()
+                .with_params(params.into_iter().collect())
+        };
+        session.relate("hero", Relation::Enemy, "gob").unwrap();
+        assert!(session.relate("goblin", Relation::Enemy, "hero").is_err());
+        assert_eq!(
+            session.apply(trade()).unwrap_err().to_string(),
+            "goblin will not trade with its enemy hero"
+        );
+
+        let bytes = session.serialize();
+        let copy = Session::deserialize(&mut FieldReader::new(&bytes)).unwrap();
+        assert_eq!(copy.relationships(), session.relationships());
+        let goblin = session.entity("goblin").unwrap().id;
+        let shown: Vec<_> = session
+            .relationships_of(goblin)
+            .map(|relationship| relationship.describe(session.entities()))
+            .collect();
+        assert_eq!(shown.len(), 1);
+        assert!(shown[0].contains("is an enemy of"));
+
+        session.unrelate("goblin", Relation::Enemy, "hero").unwrap();
+        session.apply(trade()).unwrap();
+        session.relate("hero", Relation::Owns, "goblin").unwrap();
+        session.remove_entity("goblin").unwrap();
+        assert!(session.relationships().is_empty());
+    }
+}
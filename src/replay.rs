@@ -0,0 +1,202 @@
+use crate::error::{Error, Result};
+use crate::history::Entry;
+use crate::log::Event;
+use crate::outcome::ActionOutcome;
+use crate::serde::{from_bytes, Deserialize, FieldReader, FieldWriter, Serialize, HEADER_LEN};
+use crate::session::Session;
+use crate::signing::Keyring;
+use crate::timestamp::Timestamp;
+use crate::Entity;
+
+/// First field of every replay, so stray files are rejected up front.
+const MAGIC: &str = "relay_code replay";
+
+/// Bumped whenever the layout of `Replay` changes.
+pub const FORMAT_VERSION: u16 = 1;
+
+/// A game packed into one file for review: its entities as they stood when
+/// play began, every event from then on with what it did, and how it all
+/// ended. Outcomes are kept rather than replayed, so a replay plays back
+/// the same wherever it is opened, whatever rule sets are registered there.
+/// The file is laid out like an archive:
+///
+/// ```text
+/// Str(MAGIC) U16(FORMAT_VERSION) Bytes(sealed contents)
+/// ```
+#[derive(Debug, PartialEq)]
+pub struct Replay {
+    pub exported: Timestamp,
+    /// Name of the session played.
+    pub session: String,
+    /// The entities once the session was set up, before its first action.
+    pub start: Vec<Entity>,
+    /// Every event from the first action on, numbered as `log` shows them.
+    pub entries: Vec<Entry>,
+    /// The entities after the last event.
+    pub end: Vec<Entity>,
+    /// How the game ended, if it has.
+    pub over: Option<String>,
+}
+
+impl Replay {
+    /// Replays `session`'s log to find what each of its events did. Events
+    /// before the first action taken, queued or resolved only set the game
+    /// up, and are shown as where it starts.
+    pub fn of(session: &Session) -> Result<Self> {
+        let log = session.log();
+        let setup = log
+            .iter()
+            .position(|event| {
+                event.action().is_some() || matches!(event, Event::Queue(_) | Event::Resolve(_))
+            })
+            .unwrap_or(log.len());
+        let mut replay = session.at(setup)?;
+        let start = replay.entities().to_vec();
+        let mut entries = vec![];
+        for (n, event) in log.iter().enumerate().skip(setup) {
+            entries.push(Entry {
+                number: n + 1,
+                event: event.clone(),
+                outcome: replay.record(event.clone())?,
+            });
+        }
+        Ok(Self {
+            exported: Timestamp::now()?,
+            session: session.name().to_string(),
+            start,
+            entries,
+            end: replay.entities().to_vec(),
+            over: replay.over().map(ToString::to_string),
+        })
+    }
+
+    /// The entries a turn at a time, each ending where a turn was resolved
+    /// or a player's turn ended.
+    pub fn turns(&self) -> impl Iterator<Item = &[Entry]> {
+        self.entries.split_inclusive(|entry| {
+            matches!(
+                entry.event,
+                Event::Resolve(_) | Event::Pass(..) | Event::Timeout(..)
+            )
+        })
+    }
+
+    pub fn to_bytes(&self, keyring: &Keyring) -> Vec<u8> {
+        let sealed = keyring.seal(&self.serialize());
+        let mut bytes = Vec::with_capacity(3 * HEADER_LEN + MAGIC.len() + 2 + sealed.len());
+        let mut writer = FieldWriter::new(&mut bytes);
+        writer.write_str(MAGIC);
+        writer.write_u16(FORMAT_VERSION);
+        writer.write_bytes(&sealed);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8], keyring: &Keyring) -> Result<Self> {
+        let mut reader = FieldReader::new(bytes);
+        match reader.read_field::<&str>() {
+            Ok(MAGIC) => {}
+            _ => return Err(Error::NotAReplay),
+        }
+        let version: u16 = reader.read_field()?;
+        if version != FORMAT_VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+        let sealed: &[u8] = reader.read_field()?;
+        reader.finish()?;
+        from_bytes(keyring.open(sealed)?)
+    }
+}
+
+/// The entries follow the rest, each a `List(U32(number) Event outcome)`.
+impl Serialize for Replay {
+    fn serialize_to(&self, writer: &mut FieldWriter<'_>) {
+        writer.write(&self.exported);
+        writer.write_str(&self.session);
+        writer.write(&self.start);
+        writer.write(&self.end);
+        writer.write_str(self.over.as_deref().unwrap_or_default());
+        for entry in &self.entries {
+            writer.write_list(&Step(entry));
+        }
+    }
+}
+
+impl Deserialize for Replay {
+    fn deserialize(reader: &mut FieldReader<'_>) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let exported = reader.read_field()?;
+        let session = reader.read_field()?;
+        let start = reader.read_field()?;
+        let end = reader.read_field()?;
+        let over: String = reader.read_field()?;
+        let mut entries = vec![];
+        while !reader.is_empty() {
+            let mut step = reader.read_list()?;
+            let number: u32 = step.read_field()?;
+            entries.push(Entry {
+                number: number as usize,
+                event: step.read_field()?,
+                outcome: ActionOutcome::deserialize(&mut step)?,
+            });
+            step.finish()?;
+        }
+        Ok(Self {
+            exported,
+            session,
+            start,
+            entries,
+            end,
+            over: Some(over).filter(|over| !over.is_empty()),
+        })
+    }
+}
+
+/// An entry as a replay writes it.
+struct Step<'a>(&'a Entry);
+
+impl Serialize for Step<'_> {
+    fn serialize_to(&self, writer: &mut FieldWriter<'_>) {
+        writer.write_u32(self.0.number as u32);
+        writer.write(&self.0.event);
+        self.0.outcome.serialize_to(writer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Replay;
+    use crate::actions::{Action, ActionKind};
+    use crate::session::Session;
+    use crate::signing::Keyring;
+    use crate::Entity;
+
+    #[test]
+    fn replays_keep_what_each_turn_did() {
+        let mut session = Session::new("arena".to_string()).unwrap();
+        session.play_by("skirmish").unwrap();
+        for (name, hp) in [("hero", 5), ("goblin", 1)] {
+            let mut entity = Entity::new(name.to_string());
+            entity.stats.hp = hp;
+            session.add_entity(entity).unwrap();
+        }
+        let action = |kind, target: &str| Action::new(kind, target.to_string()).unwrap();
+        session.apply(action(ActionKind::Rest, "hero")).unwrap();
+        session.resolve().unwrap();
+        session.queue(action(ActionKind::Attack, "goblin")).unwrap();
+        session.resolve().unwrap();
+
+        let replay = Replay::of(&session).unwrap();
+        assert_eq!(replay.start.len(), 2);
+        assert_eq!(replay.entries.first().unwrap().number, 4);
+        let turns: Vec<_> = replay.turns().map(<[_]>::len).collect();
+        assert_eq!(turns, [2, 2]);
+        assert_eq!(replay.end.len(), 1);
+        assert_eq!(replay.over.as_deref(), Some("hero won, last side standing"));
+
+        let keyring = Keyring::default();
+        let bytes = replay.to_bytes(&keyring);
+        assert_eq!(Replay::from_bytes(&bytes, &keyring).unwrap(), replay);
+    }
+}
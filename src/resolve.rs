@@ -0,0 +1,59 @@
+use uuid::Uuid;
+
+use crate::error::{Error, Result};
+use crate::Entity;
+
+/// Finds the entity `query` refers to: the one with that id, the one with
+/// exactly that name, or the only one whose name starts with it. Names are
+/// matched case-insensitively when nothing matches exactly.
+pub fn resolve<'a>(entities: &'a [Entity], query: &str) -> Result<&'a Entity> {
+    if let Ok(id) = Uuid::parse_str(query) {
+        if let Some(entity) = entities.iter().find(|entity| entity.id == id) {
+            return Ok(entity);
+        }
+    }
+    if let Some(entity) = entities.iter().find(|entity| entity.name == query) {
+        return Ok(entity);
+    }
+    let lower = query.to_lowercase();
+    let matches: Vec<_> = entities
+        .iter()
+        .filter(|entity| entity.name.to_lowercase().starts_with(&lower))
+        .collect();
+    match matches[..] {
+        [entity] => Ok(entity),
+        [] => Err(Error::EntityNotFound(query.to_string())),
+        _ => Err(Error::AmbiguousEntity(
+            query.to_string(),
+            matches.iter().map(|entity| entity.name.clone()).collect(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::resolve;
+    use crate::error::Error;
+    use crate::Entity;
+
+    #[test]
+    fn targets_resolve_by_id_name_or_prefix() {
+        let entities: Vec<_> = ["goblin", "goblin king", "golem", "Troll"]
+            .map(|name| Entity::new(name.to_string()))
+            .into();
+        let id = entities[2].id.to_string();
+        let name = |query| resolve(&entities, query).map(|entity| entity.name.as_str());
+
+        assert_eq!(name(&id).unwrap(), "golem");
+        assert_eq!(name("goblin").unwrap(), "goblin");
+        assert_eq!(name("goblin k").unwrap(), "goblin king");
+        assert_eq!(name("tr").unwrap(), "Troll");
+        assert!(matches!(name("orc"), Err(Error::EntityNotFound(_))));
+
+        let err = name("go").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            r#""go" matches several entities; did you mean "goblin", "goblin king" or "golem"?"#
+        );
+    }
+}
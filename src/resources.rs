@@ -0,0 +1,127 @@
+use std::fmt::Display;
+
+use crate::error::{Error, Result};
+use crate::serde::{Field, FieldWriter, SerializeField, HEADER_LEN};
+
+/// A pool of what entities spend to act, and also what an action costs.
+/// Written as:
+///
+/// ```text
+/// List(U16(action_points) U32(mana) U64(gold))
+/// ```
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Resources {
+    pub action_points: u16,
+    pub mana: u32,
+    pub gold: u64,
+}
+
+impl Resources {
+    pub(crate) const FIELD_LEN: usize = 4 * HEADER_LEN + 2 + 4 + 8;
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// What is left after paying `cost`, or `None` if it cannot be paid.
+    pub fn checked_sub(&self, cost: &Resources) -> Option<Resources> {
+        Some(Self {
+            action_points: self.action_points.checked_sub(cost.action_points)?,
+            mana: self.mana.checked_sub(cost.mana)?,
+            gold: self.gold.checked_sub(cost.gold)?,
+        })
+    }
+
+    /// Both pools together, or `None` if any resource would overflow.
+    pub fn checked_add(&self, other: &Resources) -> Option<Resources> {
+        Some(Self {
+            action_points: self.action_points.checked_add(other.action_points)?,
+            mana: self.mana.checked_add(other.mana)?,
+            gold: self.gold.checked_add(other.gold)?,
+        })
+    }
+}
+
+/// Only the resources there are any of, such as `2 ap, 10 gold`.
+impl Display for Resources {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_empty() {
+            return write!(f, "nothing");
+        }
+        let amounts = [
+            (u64::from(self.action_points), "ap"),
+            (u64::from(self.mana), "mana"),
+            (self.gold, "gold"),
+        ];
+        let mut first = true;
+        for (amount, name) in amounts.into_iter().filter(|(amount, _)| *amount > 0) {
+            if !first {
+                write!(f, ", ")?;
+            }
+            write!(f, "{amount} {name}")?;
+            first = false;
+        }
+        Ok(())
+    }
+}
+
+impl SerializeField for Resources {
+    fn serialize_field(&self, writer: &mut FieldWriter<'_>) {
+        writer.write(&(self.action_points, self.mana, self.gold));
+    }
+}
+
+impl TryFrom<Field<'_>> for Resources {
+    type Error = Error;
+
+    fn try_from(value: Field<'_>) -> Result<Self> {
+        let (action_points, mana, gold) = value.try_into()?;
+        Ok(Self {
+            action_points,
+            mana,
+            gold,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Resources;
+    use crate::actions::{Action, ActionKind};
+    use crate::rules::Rules;
+    use crate::session::Session;
+    use crate::Entity;
+
+    #[test]
+    fn actions_cost_resources() {
+        let mut session = Session::new("game".to_string()).unwrap();
+        session.add_entity(Entity::new("mage".to_string())).unwrap();
+        let cost = Resources {
+            action_points: 1,
+            mana: 3,
+            gold: 0,
+        };
+        let mut rules = Rules::default();
+        rules.costs.insert(ActionKind::Rest, cost);
+        session.set_rules(rules).unwrap();
+        let rest = || Action::new(ActionKind::Rest, "mage".to_string()).unwrap();
+
+        assert_eq!(
+            session.apply(rest()).unwrap_err().to_string(),
+            "rest costs 1 ap, 3 mana but mage has nothing"
+        );
+        let pool = Resources {
+            action_points: 2,
+            mana: 4,
+            gold: 10,
+        };
+        session.grant("ma", pool).unwrap();
+        let outcome = session.apply(rest()).unwrap();
+        assert_eq!(
+            outcome.changes.last().unwrap().to_string(),
+            "mage resources: 2 ap, 4 mana, 10 gold -> 1 ap, 1 mana, 10 gold"
+        );
+        assert!(session.apply(rest()).is_err());
+        assert_eq!(session.entity("mage").unwrap().resources.gold, 10);
+    }
+}
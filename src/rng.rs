@@ -0,0 +1,27 @@
+use crate::outcome::DiceRoll;
+
+/// A small deterministic random number generator (SplitMix64). Everything
+/// random in a session is drawn from one seeded from the log, so every
+/// machine replaying it rolls the same.
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Rolls a die with `sides` sides, from 1 to `sides`.
+    pub fn roll(&mut self, sides: u8) -> DiceRoll {
+        let value = (self.next_u64() % u64::from(sides.max(1))) as u8 + 1;
+        DiceRoll { sides, value }
+    }
+}
@@ -0,0 +1,465 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::sync::{Arc, OnceLock, RwLock};
+
+use crate::actions::{Action, ActionKind};
+use crate::error::{Error, Result};
+use crate::experience::Leveling;
+use crate::resources::Resources;
+use crate::serde::{Deserialize, FieldReader, FieldWriter, Serialize, TaggedEnum, HEADER_LEN};
+use crate::session::Session;
+use crate::victory::Victory;
+use crate::visibility::Visibility;
+
+/// Limits on what may be done each turn. A turn ends whenever the session
+/// is resolved. Written as:
+///
+/// ```text
+/// U32(max_actions) Map(ActionKind -> U32(turns)) Map(ActionKind -> Resources)
+/// Map(ActionKind -> U32(xp)) Leveling
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Rules {
+    /// Actions allowed per turn, or 0 for no limit.
+    pub max_actions: u32,
+    /// Turns to wait after an action of a kind before the next one. With a
+    /// cooldown of 1 a kind can be used once per turn.
+    pub cooldowns: BTreeMap<ActionKind, u32>,
+    /// What the target of an action of each kind pays to take it.
+    pub costs: BTreeMap<ActionKind, Resources>,
+    /// What the target of an action of each kind earns for taking it.
+    pub xp: BTreeMap<ActionKind, u32>,
+    pub leveling: Leveling,
+}
+
+impl Display for Rules {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.max_actions {
+            0 => write!(f, "no limit on actions per turn")?,
+            max => write!(f, "at most {max} actions per turn")?,
+        }
+        for (kind, turns) in &self.cooldowns {
+            write!(f, "\n{kind} cooldown: {turns}")?;
+        }
+        for (kind, cost) in &self.costs {
+            write!(f, "\n{kind} costs {cost}")?;
+        }
+        for (kind, xp) in &self.xp {
+            write!(f, "\n{kind} earns {xp} xp")?;
+        }
+        if !self.xp.is_empty() || self.leveling != Leveling::default() {
+            write!(f, "\n{}", self.leveling)?;
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for Rules {
+    fn serialize_to(&self, writer: &mut FieldWriter<'_>) {
+        writer.write_u32(self.max_actions);
+        writer.write(&self.cooldowns);
+        writer.write(&self.costs);
+        writer.write(&self.xp);
+        writer.write(&self.leveling);
+    }
+
+    fn size_hint(&self) -> usize {
+        HEADER_LEN
+            + 4
+            + HEADER_LEN
+            + self.cooldowns.len() * (HEADER_LEN + 1 + HEADER_LEN + 4)
+            + HEADER_LEN
+            + self.costs.len() * (HEADER_LEN + 1 + Resources::FIELD_LEN)
+            + HEADER_LEN
+            + self.xp.len() * (HEADER_LEN + 1 + HEADER_LEN + 4)
+            + Leveling::FIELD_LEN
+    }
+}
+
+impl Deserialize for Rules {
+    fn deserialize(reader: &mut FieldReader<'_>) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        Ok(Self {
+            max_actions: reader.read_field()?,
+            cooldowns: reader.read_field()?,
+            costs: reader.read_field()?,
+            xp: reader.read_field()?,
+            leveling: reader.read_field()?,
+        })
+    }
+}
+
+/// The game a session is played as: which actions are legal when, what
+/// they cost and how the game is won, on top of the session's `Rules`.
+/// Rule sets are looked up by name in a process-wide registry, like action
+/// handlers, so a game can bring its own: register it with `register`
+/// before any session playing it is loaded.
+pub trait RuleSet: Send + Sync {
+    /// What sessions name it by, as `new --rules` takes it.
+    fn name(&self) -> &str;
+
+    /// Checks that `action` may be taken now. Its target is known to exist
+    /// and nothing on it to block the action. By default the session's
+    /// limits on actions per turn and its cooldowns must allow it, the
+    /// target must be able to pay for it and its prerequisites must hold.
+    fn check(&self, action: &Action, session: &Session) -> Result<()> {
+        session.turn().check(session.rules(), action.kind())?;
+        if let Some(cost) = self.cost(action, session) {
+            let pool = session
+                .entity(action.target())
+                .map(|target| target.resources)
+                .unwrap_or_default();
+            if pool.checked_sub(&cost).is_none() {
+                return Err(Error::IllegalAction(format!(
+                    "{} costs {cost} but {} has {pool}",
+                    action.kind(),
+                    action.target()
+                )));
+            }
+        }
+        let requirements = session.requirements().get(&action.kind());
+        for prerequisite in requirements.into_iter().flatten() {
+            if !prerequisite.holds(action, session) {
+                return Err(Error::IllegalAction(format!(
+                    "{} requires {prerequisite}",
+                    action.kind()
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// What the target of `action` pays to take it. By default, what the
+    /// session's rules set for its kind.
+    fn cost(&self, action: &Action, session: &Session) -> Option<Resources> {
+        session.rules().costs.get(&action.kind()).copied()
+    }
+
+    /// How the game may end, checked after every resolve in this order.
+    /// By default it never does; it goes on for as long as its players
+    /// like.
+    fn victory(&self) -> Vec<Victory> {
+        vec![]
+    }
+
+    /// What players may see of entities they do not own. By default,
+    /// everything.
+    fn visibility(&self) -> Visibility {
+        Visibility::default()
+    }
+}
+
+/// The rule set of sessions that were not given one.
+pub const STANDARD: &str = "standard";
+
+/// The session's own `Rules` and nothing more.
+pub(crate) struct Standard;
+
+impl RuleSet for Standard {
+    fn name(&self) -> &str {
+        STANDARD
+    }
+}
+
+/// A fight to the last side standing.
+struct Skirmish;
+
+impl RuleSet for Skirmish {
+    fn name(&self) -> &str {
+        "skirmish"
+    }
+
+    fn victory(&self) -> Vec<Victory> {
+        vec![Victory::LastSideStanding]
+    }
+}
+
+/// First to a score of 10, or the highest score after 20 turns.
+struct Race;
+
+impl RuleSet for Race {
+    fn name(&self) -> &str {
+        "race"
+    }
+
+    fn victory(&self) -> Vec<Victory> {
+        vec![Victory::Score(10), Victory::TurnLimit(20)]
+    }
+}
+
+/// A skirmish fought in the fog: players see only as far as five squares
+/// from their own entities, and nothing of others' packs or purses.
+struct Fog;
+
+impl RuleSet for Fog {
+    fn name(&self) -> &str {
+        "fog"
+    }
+
+    fn victory(&self) -> Vec<Victory> {
+        vec![Victory::LastSideStanding]
+    }
+
+    fn visibility(&self) -> Visibility {
+        Visibility {
+            sight: Some(5),
+            hands: true,
+            resources: true,
+            secrets: vec![],
+        }
+    }
+}
+
+type Registry = RwLock<BTreeMap<String, Arc<dyn RuleSet>>>;
+
+fn registry() -> &'static Registry {
+    static REGISTRY: OnceLock<Registry> = OnceLock::new();
+    REGISTRY.get_or_init(|| {
+        let builtin: [Arc<dyn RuleSet>; 4] = [
+            Arc::new(Standard),
+            Arc::new(Skirmish),
+            Arc::new(Race),
+            Arc::new(Fog),
+        ];
+        RwLock::new(
+            builtin
+                .into_iter()
+                .map(|rules| (rules.name().to_string(), rules))
+                .collect(),
+        )
+    })
+}
+
+/// Makes sessions that name `rules` play by it from now on, in place of
+/// the rule set it replaces.
+pub fn register(rules: impl RuleSet + 'static) {
+    registry()
+        .write()
+        .unwrap_or_else(|err| err.into_inner())
+        .insert(rules.name().to_string(), Arc::new(rules));
+}
+
+/// The rule set registered as `name`.
+pub fn rule_set(name: &str) -> Result<Arc<dyn RuleSet>> {
+    let rule_sets = registry().read().unwrap_or_else(|err| err.into_inner());
+    rule_sets
+        .get(name)
+        .cloned()
+        .ok_or_else(|| Error::UnknownRuleSet(name.to_string()))
+}
+
+/// The names of every rule set registered, sorted.
+pub fn names() -> Vec<String> {
+    let rule_sets = registry().read().unwrap_or_else(|err| err.into_inner());
+    rule_sets.keys().cloned().collect()
+}
+
+/// State that must hold before an action of some kind may be taken. Each
+/// kind may require several, and the first to fail is named in the error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Prerequisite {
+    /// The target has at least this much hp.
+    MinHp(u8),
+    /// The target is guarding, or is not.
+    Guarding(bool),
+    /// An entity with this name exists.
+    Exists(String),
+    /// An action of this kind has been taken before.
+    After(ActionKind),
+}
+
+impl Prerequisite {
+    pub fn holds(&self, action: &Action, session: &Session) -> bool {
+        let target = session.entity(action.target());
+        match self {
+            Self::MinHp(n) => target.is_some_and(|target| target.stats.hp >= *n),
+            Self::Guarding(guarding) => target.is_some_and(|target| target.guarding == *guarding),
+            Self::Exists(name) => session.entity(name).is_some(),
+            Self::After(kind) => session.turn().has_taken(*kind),
+        }
+    }
+
+    /// Encoded size of the prerequisite as a field, header included.
+    pub(crate) fn field_len(&self) -> usize {
+        let payload = match self {
+            Self::MinHp(_) | Self::Guarding(_) | Self::After(_) => HEADER_LEN + 1,
+            Self::Exists(name) => HEADER_LEN + name.len(),
+        };
+        HEADER_LEN + 1 + payload
+    }
+}
+
+/// Worded to follow "<kind> requires".
+impl Display for Prerequisite {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MinHp(n) => write!(f, "the target to have at least {n} hp"),
+            Self::Guarding(true) => write!(f, "the target to be guarding"),
+            Self::Guarding(false) => write!(f, "the target not to be guarding"),
+            Self::Exists(name) => write!(f, "an entity named {name:?}"),
+            Self::After(kind) => write!(f, "a {kind} action first"),
+        }
+    }
+}
+
+impl TaggedEnum for Prerequisite {
+    fn discriminant(&self) -> u8 {
+        match self {
+            Self::MinHp(_) => 0,
+            Self::Guarding(_) => 1,
+            Self::Exists(_) => 2,
+            Self::After(_) => 3,
+        }
+    }
+
+    fn serialize_variant(&self, writer: &mut FieldWriter<'_>) {
+        match self {
+            Self::MinHp(n) => writer.write_u8(*n),
+            Self::Guarding(guarding) => writer.write_bool(*guarding),
+            Self::Exists(name) => writer.write_str(name),
+            Self::After(kind) => writer.write(kind),
+        }
+    }
+
+    fn deserialize_variant(discriminant: u8, reader: &mut FieldReader<'_>) -> Result<Self> {
+        let prerequisite = match discriminant {
+            0 => Self::MinHp(reader.read_field()?),
+            1 => Self::Guarding(reader.read_field()?),
+            2 => Self::Exists(reader.read_field()?),
+            3 => Self::After(reader.read_field()?),
+            _ => return Err(Error::InvalidVariant(discriminant)),
+        };
+        reader.finish()?;
+        Ok(prerequisite)
+    }
+}
+
+crate::impl_enum_field!(Prerequisite);
+
+/// Where the session is in the current turn, rebuilt by replaying the log.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Turn {
+    /// Turns resolved so far.
+    pub number: u32,
+    /// Actions taken so far this turn.
+    pub actions: u32,
+    /// The turn each kind of action was last taken in.
+    last_taken: BTreeMap<ActionKind, u32>,
+}
+
+impl Turn {
+    pub(crate) fn take(&mut self, kind: ActionKind) {
+        self.actions += 1;
+        self.last_taken.insert(kind, self.number);
+    }
+
+    pub fn has_taken(&self, kind: ActionKind) -> bool {
+        self.last_taken.contains_key(&kind)
+    }
+
+    pub(crate) fn end(&mut self) {
+        self.number += 1;
+        self.actions = 0;
+    }
+
+    /// Checks that `rules` allow an action of `kind` to be taken now.
+    pub(crate) fn check(&self, rules: &Rules, kind: ActionKind) -> Result<()> {
+        if rules.max_actions != 0 && self.actions >= rules.max_actions {
+            return Err(Error::IllegalAction(format!(
+                "only {} actions are allowed per turn",
+                rules.max_actions
+            )));
+        }
+        let cooldown = rules.cooldowns.get(&kind).copied().unwrap_or(0);
+        if let Some(last) = self.last_taken.get(&kind) {
+            let remaining = (last + cooldown).saturating_sub(self.number);
+            if remaining > 0 {
+                let turns = if remaining == 1 { "turn" } else { "turns" };
+                return Err(Error::IllegalAction(format!(
+                    "{kind} is on cooldown for {remaining} more {turns}"
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Rules;
+    use crate::actions::{Action, ActionKind};
+    use crate::error::Error;
+    use crate::serde::{from_bytes, Serialize};
+    use crate::session::Session;
+    use crate::Entity;
+
+    #[test]
+    fn rules_limit_actions_per_turn() {
+        let mut session = Session::new("game".to_string()).unwrap();
+        session.add_entity(Entity::new("hero".to_string())).unwrap();
+        let rules = Rules {
+            max_actions: 2,
+            cooldowns: [(ActionKind::Rest, 2)].into(),
+            ..Rules::default()
+        };
+        session.set_rules(rules.clone()).unwrap();
+        let action = |kind| Action::new(kind, "hero".to_string()).unwrap();
+        let error = |session: &mut Session, kind| session.apply(action(kind)).unwrap_err();
+
+        session.apply(action(ActionKind::Rest)).unwrap();
+        assert_eq!(
+            error(&mut session, ActionKind::Rest).to_string(),
+            "rest is on cooldown for 2 more turns"
+        );
+        session.apply(action(ActionKind::Inspect)).unwrap();
+        assert_eq!(
+            error(&mut session, ActionKind::Inspect).to_string(),
+            "only 2 actions are allowed per turn"
+        );
+
+        session.resolve().unwrap();
+        assert_eq!(
+            error(&mut session, ActionKind::Rest).to_string(),
+            "rest is on cooldown for 1 more turn"
+        );
+        session.resolve().unwrap();
+        session.apply(action(ActionKind::Rest)).unwrap();
+        assert_eq!(session.turn().number, 2);
+        assert_eq!(session.rules(), &rules);
+    }
+
+    #[test]
+    fn skirmishes_end_with_the_last_side_standing() {
+        let mut session = Session::new("arena".to_string()).unwrap();
+        assert!(matches!(
+            session.play_by("chess"),
+            Err(Error::UnknownRuleSet(_))
+        ));
+        session.play_by("skirmish").unwrap();
+        for (name, hp) in [("hero", 5), ("goblin", 1)] {
+            let mut entity = Entity::new(name.to_string());
+            entity.stats.hp = hp;
+            session.add_entity(entity).unwrap();
+        }
+        let attack = |target: &str| Action::new(ActionKind::Attack, target.to_string()).unwrap();
+
+        session.queue(attack("goblin")).unwrap();
+        assert_eq!(session.over(), None);
+        let outcome = session.resolve().unwrap();
+        assert_eq!(
+            outcome.messages.last().unwrap(),
+            "hero won, last side standing"
+        );
+        let err = session.apply(attack("hero")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the game is over; hero won, last side standing"
+        );
+
+        let copy: Session = from_bytes(&session.serialize()).unwrap();
+        assert_eq!(copy.rule_set().name(), "skirmish");
+        assert_eq!(copy.over(), session.over());
+    }
+}
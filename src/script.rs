@@ -0,0 +1,188 @@
+use crate::actions::{Action, ActionKind};
+use crate::error::{Error, Result};
+use crate::outcome::ActionOutcome;
+use crate::Entity;
+
+/// Checks the name of a scripted action kind. It is given on the command
+/// line in place of a built-in kind, so it must not be one.
+pub fn validate_name(name: &str) -> Result<()> {
+    let valid = name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        && ActionKind::ALL
+            .iter()
+            .all(|kind| !kind.to_string().eq_ignore_ascii_case(name));
+    match valid {
+        true => Ok(()),
+        false => Err(Error::InvalidScriptName(name.to_string())),
+    }
+}
+
+/// Runs the rhai script `source` to carry out `action`.
+///
+/// The script sees `target`, the name of the action's target, `params`, a
+/// map of the action's parameters, and `entities`, a map from each entity's
+/// name to `#{ hp, attack, defense, speed, guarding }`. It changes
+/// entities by assigning to `entities`, evaluates to a message or an array
+/// of messages, and rejects the action with `throw "reason"`. Nothing changes unless it succeeds.
+///
+/// Scripts run again whenever the log is replayed, on every machine the
+/// session is relayed to, so rhai's lack of randomness and clocks is what
+/// keeps them deterministic. They are cut off after a fixed number of
+/// operations for the same reason.
+#[cfg(feature = "scripting")]
+pub fn run(source: &str, action: &Action, entities: &mut [Entity]) -> Result<ActionOutcome> {
+    use rhai::{Dynamic, Engine, EvalAltResult, Map, Scope};
+
+    use crate::actions::Param;
+
+    const MAX_OPERATIONS: u64 = 100_000;
+
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    let params: Map = action
+        .params()
+        .iter()
+        .map(|(key, value)| {
+            let value = match value {
+                Param::Int(n) => Dynamic::from(*n),
+                Param::Text(text) => Dynamic::from(text.clone()),
+            };
+            (key.into(), value)
+        })
+        .collect();
+    let state: Map = entities
+        .iter()
+        .map(|entity| {
+            let mut fields = Map::new();
+            for (name, value) in entity.stats.named() {
+                fields.insert(name.into(), Dynamic::from(i64::from(value)));
+            }
+            fields.insert("guarding".into(), Dynamic::from(entity.guarding));
+            (entity.name.as_str().into(), Dynamic::from(fields))
+        })
+        .collect();
+    let mut scope = Scope::new();
+    scope.push("target", action.target().to_string());
+    scope.push("params", params);
+    scope.push("entities", state);
+
+    let result = engine
+        .eval_with_scope::<Dynamic>(&mut scope, source)
+        .map_err(|err| match *err {
+            EvalAltResult::ErrorRuntime(reason, _) => Error::IllegalAction(reason.to_string()),
+            err => Error::Script(err.to_string()),
+        })?;
+    let mut outcome = ActionOutcome::default();
+    if result.is_array() {
+        for message in result.cast::<rhai::Array>() {
+            outcome.message(message.to_string());
+        }
+    } else if !result.is_unit() {
+        outcome.message(result.to_string());
+    }
+
+    // Check every change before making any.
+    let state = scope
+        .get_value::<Map>("entities")
+        .ok_or_else(|| Error::Script("entities is no longer a map".to_string()))?;
+    let mut updates = vec![];
+    for (index, entity) in entities.iter().enumerate() {
+        let Some(fields) = state
+            .get(entity.name.as_str())
+            .and_then(|fields| fields.read_lock::<Map>().map(|fields| fields.clone()))
+        else {
+            continue;
+        };
+        let invalid = |field| Error::Script(format!("invalid {field} for {}", entity.name));
+        let mut stats = entity.stats;
+        for (name, stat) in stats.named_mut() {
+            if let Some(value) = fields.get(name) {
+                *stat = value
+                    .as_int()
+                    .ok()
+                    .and_then(|value| u8::try_from(value).ok())
+                    .ok_or_else(|| invalid(name))?;
+            }
+        }
+        let guarding = match fields.get("guarding") {
+            Some(guarding) => guarding.as_bool().map_err(|_| invalid("guarding"))?,
+            None => entity.guarding,
+        };
+        updates.push((index, stats, guarding));
+    }
+    for (index, stats, guarding) in updates {
+        let entity = &mut entities[index];
+        for ((name, before), (_, after)) in entity.stats.named().into_iter().zip(stats.named()) {
+            outcome.change(&entity.name, name, before, after);
+        }
+        outcome.change(&entity.name, "guarding", entity.guarding, guarding);
+        (entity.stats, entity.guarding) = (stats, guarding);
+    }
+    Ok(outcome)
+}
+
+#[cfg(not(feature = "scripting"))]
+pub fn run(_source: &str, _action: &Action, _entities: &mut [Entity]) -> Result<ActionOutcome> {
+    Err(Error::ScriptingUnsupported)
+}
+
+#[cfg(all(test, feature = "scripting"))]
+mod tests {
+    use crate::actions::{Action, ActionKind, Param};
+    use crate::error::Error;
+    use crate::serde::{Deserialize, FieldReader, Serialize};
+    use crate::session::Session;
+    use crate::Entity;
+
+    const HEAL: &str = r#"
+        let amount = params.amount ?? 1;
+        if entities[target].hp + amount > 10 { throw `${target} cannot heal that much`; }
+        entities[target].hp += amount;
+        `healed ${target}`
+    "#;
+
+    fn heal(amount: i64) -> Action {
+        let params = [("script".to_string(), Param::Text("heal".to_string()))]
+            .into_iter()
+            .chain([("amount".to_string(), Param::Int(amount))])
+            .collect();
+        Action::new(ActionKind::Script, "hero".to_string())
+            .unwrap()
+            .with_params(params)
+    }
+
+    #[test]
+    fn scripts_define_action_kinds() {
+        let mut session = Session::new("game".to_string()).unwrap();
+        session.add_entity(Entity::new("hero".to_string())).unwrap();
+        assert!(matches!(
+            session.apply(heal(3)),
+            Err(Error::ScriptNotFound(_))
+        ));
+        session
+            .define_script("heal".to_string(), HEAL.to_string())
+            .unwrap();
+
+        let outcome = session.apply(heal(3)).unwrap();
+        assert_eq!(outcome.to_string(), "healed hero\n  hero hp: 0 -> 3\n");
+        let err = session.apply(heal(8)).unwrap_err();
+        assert_eq!(err.to_string(), "hero cannot heal that much");
+        assert_eq!(session.entity("hero").unwrap().stats.hp, 3);
+
+        let bytes = session.serialize();
+        let copy = Session::deserialize(&mut FieldReader::new(&bytes)).unwrap();
+        assert_eq!(copy.entities(), session.entities());
+        assert_eq!(
+            session
+                .log()
+                .last()
+                .unwrap()
+                .to_string()
+                .split(" at ")
+                .next(),
+            Some("heal hero amount=3")
+        );
+    }
+}
@@ -1,12 +1,66 @@
-use std::u128;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
 
 use crate::actions::{Action, ActionKind};
 use crate::error::{Error, Result};
 use crate::session::Session;
 use crate::Entity;
 
+/// Size of the type byte plus the big-endian `u16` length that precede every field
+/// whose payload fits in a `u16`.
+pub const HEADER_LEN: usize = 3;
+
+/// Size of the header of a long field: the type byte with `LONG` set, then
+/// the length as a big-endian `u64`. Only payloads too long for a `u16`
+/// length are written this way, so every value still has one encoding.
+pub const LONG_HEADER_LEN: usize = 9;
+
+/// Set in the type byte of a long field.
+pub const LONG: u8 = 0x80;
+
+/// Size of the header written before a payload of `len` bytes.
+pub fn header_len(len: usize) -> usize {
+    if len > usize::from(u16::MAX) {
+        LONG_HEADER_LEN
+    } else {
+        HEADER_LEN
+    }
+}
+
+/// Re-serialize everything read through `from_bytes` and compare it with the
+/// input. Always on in debug builds; opt in for release builds with the
+/// `verify-canonical` feature.
+const VERIFY_CANONICAL: bool = cfg!(any(debug_assertions, feature = "verify-canonical"));
+
 pub trait Serialize {
-    fn serialize(&self) -> Vec<u8>;
+    /// Writes the fields of `self` after whatever `writer` already holds.
+    fn serialize_to(&self, writer: &mut FieldWriter<'_>);
+
+    /// Number of bytes `serialize_to` will append. Used to pre-reserve
+    /// buffers, so it should be exact or a close overestimate.
+    fn size_hint(&self) -> usize {
+        0
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.size_hint());
+        self.serialize_to(&mut FieldWriter::new(&mut bytes));
+        bytes
+    }
+
+    /// Serializes into a caller-owned buffer, clearing it first so the same
+    /// allocation can be reused across many messages.
+    fn serialize_into(&self, buf: &mut Vec<u8>) {
+        buf.clear();
+        buf.reserve(self.size_hint());
+        self.serialize_to(&mut FieldWriter::new(buf));
+    }
+}
+
+/// Values that are written as exactly one field, so they can be nested
+/// inside containers such as maps.
+pub trait SerializeField {
+    fn serialize_field(&self, writer: &mut FieldWriter<'_>);
 }
 
 pub trait Deserialize {
@@ -16,7 +70,7 @@ pub trait Deserialize {
 }
 
 #[repr(u8)]
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
 pub enum FieldType {
     Str = 1,
     U128,
@@ -26,6 +80,56 @@ pub enum FieldType {
     ActionKind,
     Entity,
     Session,
+    Enum,
+    Map,
+    Bytes,
+    List,
+    U16,
+    U32,
+    U64,
+    I32,
+    I64,
+}
+
+impl FieldType {
+    /// Payload length for types whose size never varies.
+    fn fixed_len(self) -> Option<usize> {
+        match self {
+            Self::U128 => Some(16),
+            Self::U64 | Self::I64 => Some(8),
+            Self::U32 | Self::I32 => Some(4),
+            Self::U16 => Some(2),
+            Self::Byte | Self::Bool | Self::ActionKind => Some(1),
+            _ => None,
+        }
+    }
+}
+
+impl TryFrom<u8> for FieldType {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self> {
+        match value {
+            1 => Ok(Self::Str),
+            2 => Ok(Self::U128),
+            3 => Ok(Self::Byte),
+            4 => Ok(Self::Bool),
+            5 => Ok(Self::Action),
+            6 => Ok(Self::ActionKind),
+            7 => Ok(Self::Entity),
+            8 => Ok(Self::Session),
+            9 => Ok(Self::Enum),
+            10 => Ok(Self::Map),
+            11 => Ok(Self::Bytes),
+            12 => Ok(Self::List),
+            13 => Ok(Self::U16),
+            14 => Ok(Self::U32),
+            15 => Ok(Self::U64),
+            16 => Ok(Self::I32),
+            17 => Ok(Self::I64),
+            _ => Err(Error::InvalidFieldType),
+        }
+    }
 }
 
 pub enum Field<'a> {
@@ -33,10 +137,66 @@ pub enum Field<'a> {
     Byte(u8),
     Bool(bool),
     U128(u128),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    I32(i32),
+    I64(i64),
     Action(Action),
     ActionKind(ActionKind),
     Entity(Entity),
-    Session(Session),
+    /// Boxed, being far larger than any other variant.
+    Session(Box<Session>),
+    /// Discriminant plus the serialized fields of the active variant.
+    Enum(u8, &'a [u8]),
+    /// Alternating key and value fields, keys in strictly ascending order.
+    Map(&'a [u8]),
+    /// Opaque bytes, borrowed straight from the input when reading.
+    Bytes(&'a [u8]),
+    /// A sequence of fields, used for tuples, arrays and vectors.
+    List(&'a [u8]),
+}
+
+/// Rust enums that carry data. On the wire they are a single `Enum` field
+/// holding the discriminant byte followed by the variant's own fields.
+///
+/// Pair an implementation with `impl_enum_field!` so the enum can be written
+/// with `FieldWriter::write` and read back with `FieldReader::read_field`.
+pub trait TaggedEnum: Sized {
+    fn discriminant(&self) -> u8;
+
+    /// Writes the fields of the active variant, without the discriminant.
+    fn serialize_variant(&self, writer: &mut FieldWriter<'_>);
+
+    fn deserialize_variant(discriminant: u8, reader: &mut FieldReader<'_>) -> Result<Self>;
+}
+
+#[macro_export]
+macro_rules! impl_enum_field {
+    ($type:ty) => {
+        impl TryFrom<$crate::serde::Field<'_>> for $type {
+            type Error = $crate::error::Error;
+
+            fn try_from(value: $crate::serde::Field<'_>) -> $crate::error::Result<Self> {
+                match value {
+                    $crate::serde::Field::Enum(discriminant, payload) => {
+                        let mut reader = $crate::serde::FieldReader::new(payload);
+                        <$type as $crate::serde::TaggedEnum>::deserialize_variant(
+                            discriminant,
+                            &mut reader,
+                        )
+                    }
+                    _ => Err($crate::error::Error::InvalidFieldType),
+                }
+            }
+        }
+
+        impl $crate::serde::SerializeField for $type {
+            fn serialize_field(&self, writer: &mut $crate::serde::FieldWriter<'_>) {
+                writer.write_enum(self);
+            }
+        }
+    };
 }
 
 macro_rules! impl_try_from {
@@ -55,6 +215,11 @@ macro_rules! impl_try_from {
 }
 
 impl_try_from!(u128, Field::U128);
+impl_try_from!(u16, Field::U16);
+impl_try_from!(u32, Field::U32);
+impl_try_from!(u64, Field::U64);
+impl_try_from!(i32, Field::I32);
+impl_try_from!(i64, Field::I64);
 impl_try_from!(u8, Field::Byte);
 impl_try_from!(bool, Field::Bool);
 impl_try_from!(String, Field::Str);
@@ -62,136 +227,914 @@ impl_try_from!(Action, Field::Action);
 impl_try_from!(ActionKind, Field::ActionKind);
 impl_try_from!(Entity, Field::Entity);
 
-fn write_len(buf: &mut Vec<u8>, len: usize) {
-    let len = len as u16;
-    buf.extend(len.to_be_bytes());
+impl TryFrom<Field<'_>> for Session {
+    type Error = Error;
+
+    fn try_from(value: Field<'_>) -> Result<Self> {
+        match value {
+            Field::Session(session) => Ok(*session),
+            _ => Err(Error::InvalidFieldType),
+        }
+    }
 }
 
-pub fn serialize(buf: &mut Vec<u8>, field: Field<'_>) {
-    match field {
-        Field::Str(s) => {
-            buf.push(FieldType::Str as u8);
-            write_len(buf, s.len());
-            buf.extend_from_slice(s.as_bytes());
+impl<'a> TryFrom<Field<'a>> for &'a str {
+    type Error = Error;
+
+    fn try_from(value: Field<'a>) -> Result<Self> {
+        match value {
+            Field::Str(text) => Ok(text),
+            _ => Err(Error::InvalidFieldType),
         }
-        Field::U128(b) => {
-            buf.push(FieldType::U128 as u8);
-            write_len(buf, 16);
-            buf.extend(b.to_be_bytes());
+    }
+}
+
+impl<'a> TryFrom<Field<'a>> for &'a [u8] {
+    type Error = Error;
+
+    fn try_from(value: Field<'a>) -> Result<Self> {
+        match value {
+            Field::Bytes(bytes) => Ok(bytes),
+            _ => Err(Error::InvalidFieldType),
         }
-        Field::Byte(b) => {
-            buf.push(FieldType::Byte as u8);
-            write_len(buf, 1);
-            buf.push(b);
+    }
+}
+
+impl<'a, K, V> TryFrom<Field<'a>> for BTreeMap<K, V>
+where
+    K: TryFrom<Field<'a>, Error = Error> + Ord,
+    V: TryFrom<Field<'a>, Error = Error>,
+{
+    type Error = Error;
+
+    fn try_from(value: Field<'a>) -> Result<Self> {
+        let Field::Map(bytes) = value else {
+            return Err(Error::InvalidFieldType);
+        };
+        let mut reader = FieldReader::new(bytes);
+        let mut map = BTreeMap::new();
+        while !reader.is_empty() {
+            let key: K = reader.read_field()?;
+            // Anything but strictly ascending keys has a second encoding.
+            if map.last_key_value().is_some_and(|(last, _)| *last >= key) {
+                return Err(Error::NonCanonical);
+            }
+            let value = reader.read_field()?;
+            map.insert(key, value);
+        }
+        Ok(map)
+    }
+}
+
+impl<'a, K, V> TryFrom<Field<'a>> for HashMap<K, V>
+where
+    K: TryFrom<Field<'a>, Error = Error> + Ord + Hash,
+    V: TryFrom<Field<'a>, Error = Error>,
+{
+    type Error = Error;
+
+    fn try_from(value: Field<'a>) -> Result<Self> {
+        let map: BTreeMap<K, V> = value.try_into()?;
+        Ok(map.into_iter().collect())
+    }
+}
+
+impl<'a, T, const N: usize> TryFrom<Field<'a>> for [T; N]
+where
+    T: TryFrom<Field<'a>, Error = Error>,
+{
+    type Error = Error;
+
+    fn try_from(value: Field<'a>) -> Result<Self> {
+        let mut reader = list_reader(value)?;
+        let items = (0..N)
+            .map(|_| reader.read_field())
+            .collect::<Result<Vec<T>>>()?;
+        reader.finish()?;
+        Ok(items
+            .try_into()
+            .unwrap_or_else(|_| unreachable!("exactly N items were read")))
+    }
+}
+
+impl<T: SerializeField, const N: usize> SerializeField for [T; N] {
+    fn serialize_field(&self, writer: &mut FieldWriter<'_>) {
+        writer.write_with(FieldType::List, |writer| {
+            for item in self {
+                writer.write(item);
+            }
+        });
+    }
+}
+
+impl<'a, T> TryFrom<Field<'a>> for Vec<T>
+where
+    T: TryFrom<Field<'a>, Error = Error>,
+{
+    type Error = Error;
+
+    fn try_from(value: Field<'a>) -> Result<Self> {
+        let mut reader = list_reader(value)?;
+        let mut items = vec![];
+        while !reader.is_empty() {
+            items.push(reader.read_field()?);
         }
-        Field::Bool(b) => {
-            buf.push(FieldType::Bool as u8);
-            write_len(buf, 1);
-            buf.push(b as u8);
+        Ok(items)
+    }
+}
+
+/// Written as a `List` like arrays. Note that this includes `Vec<u8>`; use
+/// `FieldWriter::write_bytes` for compact opaque bytes.
+impl<T: SerializeField> SerializeField for Vec<T> {
+    fn serialize_field(&self, writer: &mut FieldWriter<'_>) {
+        writer.write_with(FieldType::List, |writer| {
+            for item in self {
+                writer.write(item);
+            }
+        });
+    }
+}
+
+fn list_reader(value: Field<'_>) -> Result<FieldReader<'_>> {
+    match value {
+        Field::List(bytes) => Ok(FieldReader::new(bytes)),
+        _ => Err(Error::InvalidFieldType),
+    }
+}
+
+/// Tuples are written as a `List` holding one field per element.
+macro_rules! impl_tuple {
+    ($($name:ident),+) => {
+        impl<$($name: SerializeField),+> SerializeField for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn serialize_field(&self, writer: &mut FieldWriter<'_>) {
+                let ($($name,)+) = self;
+                writer.write_with(FieldType::List, |writer| {
+                    $(writer.write($name);)+
+                });
+            }
         }
-        Field::Action(action) => {
-            buf.push(FieldType::Action as u8);
-            let bytes = action.serialize();
-            write_len(buf, bytes.len());
-            buf.extend(bytes);
+
+        impl<'a, $($name),+> TryFrom<Field<'a>> for ($($name,)+)
+        where
+            $($name: TryFrom<Field<'a>, Error = Error>,)+
+        {
+            type Error = Error;
+
+            fn try_from(value: Field<'a>) -> Result<Self> {
+                let mut reader = list_reader(value)?;
+                let tuple = ($(reader.read_field::<$name>()?,)+);
+                reader.finish()?;
+                Ok(tuple)
+            }
         }
-        Field::Entity(entity) => {
-            buf.push(FieldType::Entity as u8);
-            let bytes = entity.serialize();
-            write_len(buf, bytes.len());
-            buf.extend(bytes);
+    };
+}
+
+impl_tuple!(A);
+impl_tuple!(A, B);
+impl_tuple!(A, B, C);
+impl_tuple!(A, B, C, D);
+impl_tuple!(A, B, C, D, E);
+impl_tuple!(A, B, C, D, E, F);
+impl_tuple!(A, B, C, D, E, F, G);
+impl_tuple!(A, B, C, D, E, F, G, H);
+
+impl SerializeField for u8 {
+    fn serialize_field(&self, writer: &mut FieldWriter<'_>) {
+        writer.write_u8(*self);
+    }
+}
+
+impl SerializeField for bool {
+    fn serialize_field(&self, writer: &mut FieldWriter<'_>) {
+        writer.write_bool(*self);
+    }
+}
+
+impl SerializeField for u128 {
+    fn serialize_field(&self, writer: &mut FieldWriter<'_>) {
+        writer.write_u128(*self);
+    }
+}
+
+impl SerializeField for u16 {
+    fn serialize_field(&self, writer: &mut FieldWriter<'_>) {
+        writer.write_u16(*self);
+    }
+}
+
+impl SerializeField for u32 {
+    fn serialize_field(&self, writer: &mut FieldWriter<'_>) {
+        writer.write_u32(*self);
+    }
+}
+
+impl SerializeField for u64 {
+    fn serialize_field(&self, writer: &mut FieldWriter<'_>) {
+        writer.write_u64(*self);
+    }
+}
+
+impl SerializeField for i32 {
+    fn serialize_field(&self, writer: &mut FieldWriter<'_>) {
+        writer.write_i32(*self);
+    }
+}
+
+impl SerializeField for i64 {
+    fn serialize_field(&self, writer: &mut FieldWriter<'_>) {
+        writer.write_i64(*self);
+    }
+}
+
+impl SerializeField for str {
+    fn serialize_field(&self, writer: &mut FieldWriter<'_>) {
+        writer.write_str(self);
+    }
+}
+
+impl SerializeField for String {
+    fn serialize_field(&self, writer: &mut FieldWriter<'_>) {
+        writer.write_str(self);
+    }
+}
+
+impl SerializeField for [u8] {
+    fn serialize_field(&self, writer: &mut FieldWriter<'_>) {
+        writer.write_bytes(self);
+    }
+}
+
+impl SerializeField for ActionKind {
+    fn serialize_field(&self, writer: &mut FieldWriter<'_>) {
+        writer.write_field(Field::ActionKind(*self));
+    }
+}
+
+impl SerializeField for Action {
+    fn serialize_field(&self, writer: &mut FieldWriter<'_>) {
+        writer.write_nested(FieldType::Action, self);
+    }
+}
+
+impl SerializeField for Entity {
+    fn serialize_field(&self, writer: &mut FieldWriter<'_>) {
+        writer.write_nested(FieldType::Entity, self);
+    }
+}
+
+impl SerializeField for Session {
+    fn serialize_field(&self, writer: &mut FieldWriter<'_>) {
+        writer.write_nested(FieldType::Session, self);
+    }
+}
+
+impl<K: SerializeField, V: SerializeField> SerializeField for BTreeMap<K, V> {
+    fn serialize_field(&self, writer: &mut FieldWriter<'_>) {
+        writer.write_map(self.iter());
+    }
+}
+
+impl<K, V, S> SerializeField for HashMap<K, V, S>
+where
+    K: SerializeField + Ord,
+    V: SerializeField,
+{
+    /// Entries are sorted by key first, so equal maps always produce equal
+    /// bytes regardless of hasher state or insertion order.
+    fn serialize_field(&self, writer: &mut FieldWriter<'_>) {
+        let mut entries: Vec<_> = self.iter().collect();
+        entries.sort_unstable_by(|a, b| a.0.cmp(b.0));
+        writer.write_map(entries.into_iter());
+    }
+}
+
+/// Writes fields into a byte buffer. The write-side counterpart of
+/// `FieldReader`.
+pub struct FieldWriter<'a> {
+    buf: &'a mut Vec<u8>,
+    start: usize,
+}
+
+impl<'a> FieldWriter<'a> {
+    /// Appends to `buf`, leaving anything already in it untouched.
+    pub fn new(buf: &'a mut Vec<u8>) -> Self {
+        let start = buf.len();
+        Self { buf, start }
+    }
+
+    /// Number of bytes written through this writer so far.
+    pub fn offset(&self) -> usize {
+        self.buf.len() - self.start
+    }
+
+    pub fn write_field(&mut self, field: Field<'_>) {
+        match field {
+            Field::Str(s) => self.write_str(s),
+            Field::U128(n) => self.write_u128(n),
+            Field::U16(n) => self.write_u16(n),
+            Field::U32(n) => self.write_u32(n),
+            Field::U64(n) => self.write_u64(n),
+            Field::I32(n) => self.write_i32(n),
+            Field::I64(n) => self.write_i64(n),
+            Field::Byte(b) => self.write_u8(b),
+            Field::Bool(b) => self.write_bool(b),
+            Field::Action(action) => self.write_nested(FieldType::Action, &action),
+            Field::Entity(entity) => self.write_nested(FieldType::Entity, &entity),
+            Field::Session(session) => self.write_nested(FieldType::Session, &*session),
+            Field::ActionKind(action_kind) => {
+                self.write_raw(FieldType::ActionKind, &[action_kind.id()])
+            }
+            Field::Enum(discriminant, payload) => self.write_with(FieldType::Enum, |writer| {
+                writer.buf.push(discriminant);
+                writer.buf.extend_from_slice(payload);
+            }),
+            Field::Map(entries) => self.write_raw(FieldType::Map, entries),
+            Field::Bytes(bytes) => self.write_bytes(bytes),
+            Field::List(items) => self.write_raw(FieldType::List, items),
         }
-        Field::Session(session) => {
-            buf.push(FieldType::Session as u8);
-            let bytes = session.serialize();
-            write_len(buf, bytes.len());
-            buf.extend(bytes);
+    }
+
+    /// Writes any value that occupies a single field.
+    pub fn write<T: SerializeField + ?Sized>(&mut self, value: &T) {
+        value.serialize_field(self);
+    }
+
+    pub fn write_str(&mut self, value: &str) {
+        self.write_raw(FieldType::Str, value.as_bytes());
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.write_raw(FieldType::Byte, &[value]);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.write_raw(FieldType::Bool, &[value as u8]);
+    }
+
+    pub fn write_u128(&mut self, value: u128) {
+        self.write_raw(FieldType::U128, &value.to_be_bytes());
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.write_raw(FieldType::U16, &value.to_be_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.write_raw(FieldType::U32, &value.to_be_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.write_raw(FieldType::U64, &value.to_be_bytes());
+    }
+
+    pub fn write_i32(&mut self, value: i32) {
+        self.write_raw(FieldType::I32, &value.to_be_bytes());
+    }
+
+    pub fn write_i64(&mut self, value: i64) {
+        self.write_raw(FieldType::I64, &value.to_be_bytes());
+    }
+
+    pub fn write_bytes(&mut self, value: &[u8]) {
+        self.write_raw(FieldType::Bytes, value);
+    }
+
+    /// Writes `value` as a single `Enum` field.
+    pub fn write_enum(&mut self, value: &impl TaggedEnum) {
+        self.write_with(FieldType::Enum, |writer| {
+            writer.buf.push(value.discriminant());
+            value.serialize_variant(writer);
+        });
+    }
+
+    /// Writes the fields of `value` as one `List` field, for nesting a
+    /// struct without a field type of its own.
+    pub fn write_list(&mut self, value: &impl Serialize) {
+        self.write_nested(FieldType::List, value);
+    }
+
+    /// Writes the fields of `value` as the payload of one `field_type` field.
+    fn write_nested(&mut self, field_type: FieldType, value: &impl Serialize) {
+        self.write_with(field_type, |writer| value.serialize_to(writer));
+    }
+
+    fn write_map<'v, K, V>(&mut self, entries: impl Iterator<Item = (&'v K, &'v V)>)
+    where
+        K: SerializeField + 'v,
+        V: SerializeField + 'v,
+    {
+        self.write_with(FieldType::Map, |writer| {
+            for (key, value) in entries {
+                writer.write(key);
+                writer.write(value);
+            }
+        });
+    }
+
+    fn write_raw(&mut self, field_type: FieldType, payload: &[u8]) {
+        match u16::try_from(payload.len()) {
+            Ok(len) => {
+                self.buf.push(field_type as u8);
+                self.buf.extend(len.to_be_bytes());
+            }
+            Err(_) => {
+                self.buf.push(field_type as u8 | LONG);
+                self.buf.extend((payload.len() as u64).to_be_bytes());
+            }
         }
-        Field::ActionKind(action_kind) => {
-            buf.push(FieldType::ActionKind as u8);
-            write_len(buf, 1);
-            buf.push(action_kind as u8);
+        self.buf.extend_from_slice(payload);
+    }
+
+    /// Writes a field whose payload is produced by `body`, patching the
+    /// length in once the payload is complete so nothing is buffered twice.
+    /// The rare payload too long for a `u16` is moved along to make room
+    /// for a long header.
+    fn write_with(&mut self, field_type: FieldType, body: impl FnOnce(&mut Self)) {
+        let type_at = self.buf.len();
+        self.buf.push(field_type as u8);
+        let len_at = self.buf.len();
+        self.buf.extend([0, 0]);
+        body(self);
+        let len = self.buf.len() - len_at - 2;
+        match u16::try_from(len) {
+            Ok(len) => self.buf[len_at..len_at + 2].copy_from_slice(&len.to_be_bytes()),
+            Err(_) => {
+                self.buf[type_at] |= LONG;
+                let len = (len as u64).to_be_bytes();
+                self.buf.splice(len_at..len_at + 2, len);
+            }
         }
     }
 }
 
+/// Deserializes a complete value, rejecting any bytes left over after it.
+fn deserialize_exact<T: Deserialize>(bytes: &[u8]) -> Result<T> {
+    let mut reader = FieldReader::new(bytes);
+    let value = T::deserialize(&mut reader)?;
+    reader.finish()?;
+    Ok(value)
+}
+
+/// Deserializes a top-level value. The encoding is canonical: every value has
+/// exactly one byte representation, so anything read here can be hashed or
+/// signed. In verification mode the value is re-serialized and compared with
+/// `bytes` to catch any `Serialize`/`Deserialize` pair that breaks this.
+pub fn from_bytes<T: Deserialize + Serialize>(bytes: &[u8]) -> Result<T> {
+    let value = deserialize_exact(bytes)?;
+    if VERIFY_CANONICAL {
+        verify_canonical(&value, bytes)?;
+    }
+    Ok(value)
+}
+
+/// Checks that `bytes` is exactly what `value` serializes to.
+pub fn verify_canonical<T: Serialize>(value: &T, bytes: &[u8]) -> Result<()> {
+    if value.serialize() != bytes {
+        return Err(Error::NonCanonical);
+    }
+    Ok(())
+}
+
 pub struct FieldReader<'a> {
     buffer: &'a [u8],
+    offset: usize,
 }
 
 impl<'a> FieldReader<'a> {
     pub fn new(buffer: &'a [u8]) -> Self {
-        Self { buffer }
-    }
-
-    fn field_type(&mut self) -> Result<FieldType> {
-        if self.buffer.is_empty() {
-            return Err(Error::MissingFieldType);
-        }
-        let byte = self.buffer[0];
-        self.buffer = &self.buffer[1..];
-
-        eprintln!("Field Type: {:?}", byte);
-        match byte {
-            1 => Ok(FieldType::Str),
-            2 => Ok(FieldType::U128),
-            3 => Ok(FieldType::Byte),
-            4 => Ok(FieldType::Bool),
-            5 => Ok(FieldType::Action),
-            6 => Ok(FieldType::ActionKind),
-            7 => Ok(FieldType::Entity),
-            8 => Ok(FieldType::Session),
-            _ => Err(Error::InvalidFieldType),
+        Self { buffer, offset: 0 }
+    }
+
+    /// Number of bytes consumed so far. Errors from `read_field` carry the
+    /// offset of the field that failed via `Error::At`.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Fails if any bytes are left unread.
+    pub fn finish(&self) -> Result<()> {
+        if self.is_empty() {
+            return Ok(());
         }
+        let trailing = Error::TrailingBytes(self.remaining());
+        Err(Error::At(self.offset, Box::new(trailing)))
     }
 
-    fn len(&mut self) -> Result<usize> {
-        if self.buffer.len() < 2 {
+    fn advance(&mut self, count: usize) -> &'a [u8] {
+        let (consumed, rest) = self.buffer.split_at(count);
+        self.buffer = rest;
+        self.offset += count;
+        consumed
+    }
+
+    /// The type of the next field, consumed, and whether it is long.
+    fn field_type(&mut self) -> Result<(FieldType, bool)> {
+        let field_type = self.peek_field_type()?;
+        let long = self.advance(1)[0] & LONG != 0;
+        Ok((field_type, long))
+    }
+
+    /// Returns the type of the next field without consuming it.
+    pub fn peek_field_type(&self) -> Result<FieldType> {
+        let byte = *self.buffer.first().ok_or(Error::MissingFieldType)?;
+        crate::trace!("field type byte {byte}");
+        FieldType::try_from(byte & !LONG)
+    }
+
+    /// Number of unread bytes left in the buffer.
+    pub fn remaining(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// The unread bytes themselves, for salvaging data after a field fails
+    /// to decode.
+    pub fn unread(&self) -> &'a [u8] {
+        self.buffer
+    }
+
+    /// Reads a `List` field and returns a reader over its elements, for
+    /// lists whose elements should be read one at a time.
+    pub fn read_list(&mut self) -> Result<FieldReader<'a>> {
+        let start = self.offset;
+        match self.field_bytes() {
+            Ok((FieldType::List, bytes)) => Ok(FieldReader::new(bytes)),
+            Ok(_) => Err(Error::At(start, Box::new(Error::InvalidFieldType))),
+            Err(err) => Err(err.at(start, start)),
+        }
+    }
+
+    /// Reads the next field without decoding it, returning its type and
+    /// payload.
+    pub fn read_raw(&mut self) -> Result<(FieldType, &'a [u8])> {
+        let start = self.offset;
+        self.field_bytes().map_err(|err| err.at(start, start))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    /// Reads the length of a field, a `u64` if it is `long`. A long length
+    /// that would fit in a `u16` has a second encoding, and is refused.
+    fn len(&mut self, long: bool) -> Result<usize> {
+        if !long {
+            if self.buffer.len() < 2 {
+                return Err(Error::MissingFieldLen);
+            }
+            return Ok(u16::from_be_bytes(Self::fixed(self.advance(2))).into());
+        }
+        if self.buffer.len() < 8 {
             return Err(Error::MissingFieldLen);
         }
-        let bytes = &self.buffer[..2];
-        self.buffer = &self.buffer[2..];
-        let len = u16::from_be_bytes([bytes[0], bytes[1]]);
-        Ok(len as usize)
+        let len = u64::from_be_bytes(Self::fixed(self.advance(8)));
+        if len <= u16::MAX.into() {
+            return Err(Error::NonCanonical);
+        }
+        usize::try_from(len).map_err(|_| Error::UnexpectedEof)
     }
 
-    fn read_be_u128(input: &[u8]) -> u128 {
-        let (int_bytes, _) = input.split_at(std::mem::size_of::<u128>());
-        u128::from_be_bytes(int_bytes.try_into().unwrap())
+    /// Copies out bytes already checked to number `N`, such as a payload
+    /// checked against `FieldType::fixed_len`.
+    fn fixed<const N: usize>(bytes: &[u8]) -> [u8; N] {
+        bytes.try_into().expect("fixed length checked")
     }
 
     pub fn read_field<T>(&mut self) -> Result<T>
     where
         T: TryFrom<Field<'a>, Error = Error>,
     {
-        let field_type = self.field_type()?;
-        let len = self.len()?;
-        eprintln!("Field Type parsed: {field_type:?} with length: {len}");
-        let bytes = &self.buffer[..len];
-        self.buffer = &self.buffer[len..];
-
-        eprintln!("Entity bytes: {bytes:?}");
-        eprintln!("Remaining buffer: {:?}", self.buffer);
+        let start = self.offset;
+        let (field_type, bytes) = self.field_bytes().map_err(|err| err.at(start, start))?;
+        // Errors from nested readers are relative to the start of their own
+        // buffer, which for enums begins after the discriminant.
+        let payload = self.offset - bytes.len();
+        let base = match field_type {
+            FieldType::Enum => payload + 1,
+            _ => payload,
+        };
+
+        Self::decode(field_type, bytes)
+            .and_then(|field| field.try_into())
+            .map_err(|err| err.at(start, base))
+    }
+
+    /// Consumes the next field header and returns the type and payload.
+    fn field_bytes(&mut self) -> Result<(FieldType, &'a [u8])> {
+        let (field_type, long) = self.field_type()?;
+        let len = self.len(long)?;
+        crate::trace!("{field_type:?} field of {len} bytes");
+        if self.buffer.len() < len {
+            return Err(Error::UnexpectedEof);
+        }
+        if field_type.fixed_len().is_some_and(|fixed| fixed != len) {
+            return Err(Error::InvalidFieldLen(field_type, len));
+        }
+        let bytes = self.advance(len);
+        crate::trace!("  {bytes:?}, {} bytes left", self.buffer.len());
+        Ok((field_type, bytes))
+    }
+
+    fn decode(field_type: FieldType, bytes: &'a [u8]) -> Result<Field<'a>> {
         let field = match field_type {
             FieldType::Str => Field::Str(std::str::from_utf8(bytes)?),
-            FieldType::Bool => Field::Bool(bytes[0] == 1),
+            FieldType::Bool => match bytes[0] {
+                0 => Field::Bool(false),
+                1 => Field::Bool(true),
+                _ => return Err(Error::NonCanonical),
+            },
             FieldType::Byte => Field::Byte(bytes[0]),
-            FieldType::Action => {
-                let mut new_reader = FieldReader::new(bytes);
-                Field::Action(Action::deserialize(&mut new_reader)?)
+            FieldType::Action => Field::Action(deserialize_exact(bytes)?),
+            FieldType::Entity => Field::Entity(deserialize_exact(bytes)?),
+            FieldType::Session => Field::Session(Box::new(deserialize_exact(bytes)?)),
+            FieldType::U128 => Field::U128(u128::from_be_bytes(Self::fixed(bytes))),
+            FieldType::U16 => Field::U16(u16::from_be_bytes(Self::fixed(bytes))),
+            FieldType::U32 => Field::U32(u32::from_be_bytes(Self::fixed(bytes))),
+            FieldType::U64 => Field::U64(u64::from_be_bytes(Self::fixed(bytes))),
+            FieldType::I32 => Field::I32(i32::from_be_bytes(Self::fixed(bytes))),
+            FieldType::I64 => Field::I64(i64::from_be_bytes(Self::fixed(bytes))),
+            FieldType::ActionKind => Field::ActionKind(ActionKind::from(bytes[0])),
+            FieldType::Enum => {
+                let (discriminant, payload) =
+                    bytes.split_first().ok_or(Error::MissingDiscriminant)?;
+                Field::Enum(*discriminant, payload)
             }
-            FieldType::Entity => {
-                let mut new_reader = FieldReader::new(bytes);
-                Field::Entity(Entity::deserialize(&mut new_reader)?)
+            FieldType::Map => Field::Map(bytes),
+            FieldType::Bytes => Field::Bytes(bytes),
+            FieldType::List => Field::List(bytes),
+        };
+        Ok(field)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashMap};
+
+    use super::{
+        from_bytes, Field, FieldReader, FieldType, FieldWriter, SerializeField, TaggedEnum,
+    };
+    use crate::error::{Error, Result};
+    use crate::serde::Serialize;
+    use crate::Entity;
+
+    #[derive(Debug, PartialEq)]
+    enum Command {
+        Move { dx: u8, dy: u8 },
+        Say(String),
+        Wait,
+    }
+
+    impl TaggedEnum for Command {
+        fn discriminant(&self) -> u8 {
+            match self {
+                Self::Move { .. } => 0,
+                Self::Say(_) => 1,
+                Self::Wait => 2,
             }
-            FieldType::Session => {
-                let mut new_reader = FieldReader::new(bytes);
-                Field::Session(Session::deserialize(&mut new_reader)?)
+        }
+
+        fn serialize_variant(&self, writer: &mut FieldWriter<'_>) {
+            match self {
+                Self::Move { dx, dy } => {
+                    writer.write_u8(*dx);
+                    writer.write_u8(*dy);
+                }
+                Self::Say(text) => writer.write_str(text),
+                Self::Wait => {}
             }
-            FieldType::U128 => Field::U128(Self::read_be_u128(bytes)),
-            FieldType::ActionKind => Field::ActionKind(unsafe { std::mem::transmute(bytes[0]) }),
+        }
+
+        fn deserialize_variant(discriminant: u8, reader: &mut FieldReader<'_>) -> Result<Self> {
+            match discriminant {
+                0 => Ok(Self::Move {
+                    dx: reader.read_field()?,
+                    dy: reader.read_field()?,
+                }),
+                1 => Ok(Self::Say(reader.read_field()?)),
+                2 => Ok(Self::Wait),
+                _ => Err(Error::InvalidVariant(discriminant)),
+            }
+        }
+    }
+
+    crate::impl_enum_field!(Command);
+
+    #[test]
+    fn tagged_enum_round_trip() {
+        let commands = [
+            Command::Move { dx: 1, dy: 2 },
+            Command::Say("hello".to_string()),
+            Command::Wait,
+        ];
+
+        let mut bytes = vec![];
+        let mut writer = FieldWriter::new(&mut bytes);
+        for command in &commands {
+            writer.write(command);
+        }
+
+        let mut reader = FieldReader::new(&bytes);
+        for expected in commands {
+            let actual: Command = reader.read_field().unwrap();
+            assert_eq!(actual, expected);
+        }
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn unknown_variant_is_rejected() {
+        let mut bytes = vec![];
+        FieldWriter::new(&mut bytes).write_field(Field::Enum(42, &[]));
+
+        let mut reader = FieldReader::new(&bytes);
+        let result: Result<Command> = reader.read_field();
+        assert!(matches!(
+            result.unwrap_err().cause(),
+            Error::InvalidVariant(42)
+        ));
+    }
+
+    #[test]
+    fn peek_does_not_consume() {
+        let mut bytes = vec![];
+        let mut writer = FieldWriter::new(&mut bytes);
+        writer.write_u8(7);
+        writer.write_str("tail");
+
+        let mut reader = FieldReader::new(&bytes);
+        assert_eq!(reader.remaining(), bytes.len());
+        assert_eq!(reader.peek_field_type().unwrap(), FieldType::Byte);
+        assert_eq!(reader.peek_field_type().unwrap(), FieldType::Byte);
+
+        let byte: u8 = reader.read_field().unwrap();
+        assert_eq!(byte, 7);
+        assert_eq!(reader.peek_field_type().unwrap(), FieldType::Str);
+
+        let tail: String = reader.read_field().unwrap();
+        assert_eq!(tail, "tail");
+        assert!(reader.is_empty());
+        assert!(reader.peek_field_type().is_err());
+    }
+
+    #[test]
+    fn map_encoding_ignores_insertion_order() {
+        let forward: HashMap<String, u8> = (0..20).map(|i| (format!("key{i}"), i)).collect();
+        let backward: HashMap<String, u8> = (0..20).rev().map(|i| (format!("key{i}"), i)).collect();
+        let sorted: BTreeMap<String, u8> = forward.clone().into_iter().collect();
+
+        let mut a = vec![];
+        let mut b = vec![];
+        let mut c = vec![];
+        FieldWriter::new(&mut a).write(&forward);
+        FieldWriter::new(&mut b).write(&backward);
+        FieldWriter::new(&mut c).write(&sorted);
+        assert_eq!(a, b);
+        assert_eq!(a, c);
+
+        let mut reader = FieldReader::new(&a);
+        let actual: HashMap<String, u8> = reader.read_field().unwrap();
+        assert_eq!(actual, forward);
+    }
+
+    #[test]
+    fn unsorted_map_is_rejected() {
+        let mut entries = vec![];
+        let mut writer = FieldWriter::new(&mut entries);
+        writer.write_str("b");
+        writer.write_u8(1);
+        writer.write_str("a");
+        writer.write_u8(2);
+        let mut bytes = vec![];
+        FieldWriter::new(&mut bytes).write_field(Field::Map(&entries));
+
+        let mut reader = FieldReader::new(&bytes);
+        let result: Result<BTreeMap<String, u8>> = reader.read_field();
+        assert!(matches!(result.unwrap_err().cause(), Error::NonCanonical));
+    }
+
+    #[test]
+    fn non_canonical_bool_is_rejected() {
+        let bytes = [FieldType::Bool as u8, 0, 1, 2];
+        let mut reader = FieldReader::new(&bytes);
+        let result: Result<bool> = reader.read_field();
+        assert!(matches!(result.unwrap_err().cause(), Error::NonCanonical));
+    }
+
+    #[test]
+    fn from_bytes_rejects_trailing_bytes() {
+        let entity = Entity::new("florp".to_string());
+        let mut bytes = entity.serialize();
+        assert_eq!(from_bytes::<Entity>(&bytes).unwrap(), entity);
+
+        bytes.push(0);
+        let err = from_bytes::<Entity>(&bytes).unwrap_err();
+        assert!(matches!(err.cause(), Error::TrailingBytes(1)));
+    }
+
+    fn round_trip<T>(value: &T) -> T
+    where
+        T: SerializeField + for<'a> TryFrom<Field<'a>, Error = Error>,
+    {
+        let mut bytes = vec![];
+        FieldWriter::new(&mut bytes).write(value);
+        let mut reader = FieldReader::new(&bytes);
+        let actual = reader.read_field().unwrap();
+        assert!(reader.is_empty());
+        actual
+    }
+
+    #[test]
+    fn tuples_and_arrays_round_trip() {
+        let coords: (i32, i32) = (-3, 7);
+        assert_eq!(round_trip(&coords), coords);
+
+        let rgb: [u8; 3] = [255, 128, 0];
+        assert_eq!(round_trip(&rgb), rgb);
+
+        let wide = (
+            1u8,
+            2u16,
+            3u32,
+            4u64,
+            -5i32,
+            -6i64,
+            true,
+            "eight".to_string(),
+        );
+        assert_eq!(round_trip(&wide), wide);
+
+        let nested: [(i32, i32); 2] = [(0, 1), (2, 3)];
+        assert_eq!(round_trip(&nested), nested);
+
+        let names = vec!["a".to_string(), "b".to_string()];
+        assert_eq!(round_trip(&names), names);
+        assert_eq!(round_trip(&Vec::<u8>::new()), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn tuple_arity_mismatch_is_rejected() {
+        let mut bytes = vec![];
+        FieldWriter::new(&mut bytes).write(&(1i32, 2i32, 3i32));
+
+        let mut reader = FieldReader::new(&bytes);
+        let err = reader.read_field::<(i32, i32)>().unwrap_err();
+        assert!(matches!(err.cause(), Error::TrailingBytes(_)));
+
+        let mut reader = FieldReader::new(&bytes);
+        let err = reader.read_field::<[i32; 4]>().unwrap_err();
+        assert!(matches!(err.cause(), Error::MissingFieldType));
+    }
+
+    #[test]
+    fn errors_report_absolute_offsets() {
+        let mut nested = vec![];
+        let mut writer = FieldWriter::new(&mut nested);
+        writer.write_str("ok");
+        let bad_at = writer.offset();
+        writer.write_field(Field::Enum(42, &[]));
+
+        let mut bytes = vec![];
+        let mut writer = FieldWriter::new(&mut bytes);
+        writer.write_u8(1);
+        let map_at = writer.offset();
+        writer.write_field(Field::Map(&nested));
+
+        let mut reader = FieldReader::new(&bytes);
+        let _: u8 = reader.read_field().unwrap();
+        let err = reader
+            .read_field::<BTreeMap<String, Command>>()
+            .unwrap_err();
+        let Error::At(offset, cause) = err else {
+            panic!("expected a positioned error, got {err:?}");
         };
+        assert_eq!(offset, map_at + super::HEADER_LEN + bad_at);
+        assert!(matches!(*cause, Error::InvalidVariant(42)));
+    }
+
+    #[test]
+    fn payloads_too_long_for_a_u16_get_long_headers() {
+        let short = "s".repeat(u16::MAX as usize);
+        let long = "l".repeat(u16::MAX as usize + 1);
+        let mut bytes = vec![];
+        FieldWriter::new(&mut bytes).write(&vec![short.clone(), long.clone()]);
+        assert_eq!(bytes[0], FieldType::List as u8 | super::LONG);
+        let inner = super::LONG_HEADER_LEN;
+        assert_eq!(bytes[inner], FieldType::Str as u8);
+        let second = inner + super::HEADER_LEN + short.len();
+        assert_eq!(bytes[second], FieldType::Str as u8 | super::LONG);
+        assert_eq!(bytes.len(), second + super::LONG_HEADER_LEN + long.len());
+
+        let mut reader = FieldReader::new(&bytes);
+        let read: Vec<String> = reader.read_field().unwrap();
+        assert_eq!(read, [short, long]);
+        reader.finish().unwrap();
 
-        field.try_into()
+        let mut bytes = vec![FieldType::Str as u8 | super::LONG];
+        bytes.extend(2u64.to_be_bytes());
+        bytes.extend(b"hi");
+        let err = FieldReader::new(&bytes).read_field::<String>().unwrap_err();
+        assert!(matches!(err.cause(), Error::NonCanonical));
     }
 }
@@ -0,0 +1,1033 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::io::ErrorKind;
+use std::net::{TcpListener, TcpStream};
+use std::ops::ControlFlow;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+
+use crate::actions::Action;
+use crate::error::{Error, Result};
+use crate::lobby::Lobby;
+use crate::mailbox::{Body, Mailboxes};
+use crate::outcome::ActionOutcome;
+use crate::protocol::{Carrier, Hello, Message, TlsServer, Transport};
+use crate::provider::{LocalSessions, SessionProvider};
+use crate::session::Session;
+use crate::store::SessionStore;
+use crate::tokens::Tokens;
+use crate::turnfile::{Merged, TurnFile};
+use crate::visibility::{self, Visibility};
+use crate::webhook::{self, Webhook};
+
+mod deadlines;
+mod limits;
+mod resume;
+#[cfg(feature = "async-server")]
+mod tokio_io;
+
+pub use limits::Limits;
+use limits::{Throttle, Throttles};
+use resume::Resumes;
+
+#[cfg(feature = "async-server")]
+pub use tokio_io::serve_async;
+
+/// Answers requests from every client connecting to one of `listeners`,
+/// each on its own thread with its own store from `open`, until every
+/// listener has failed, and then returns the first failure. Clients must
+/// present one of `tokens`, unless there are none, and their actions are
+/// logged as taken by its player. Every connection is encrypted with `tls`,
+/// if given. Clients acting on the same session take turns; see
+/// `SessionLocks`. Players are sent mail about each other's turns, kept in
+/// `dir` until they fetch it; see `Mailboxes`, and each action is posted
+/// to `webhooks`. Scripts are adopted from `dir` as they are locally.
+/// Clients asking more than `limits` allow are told to slow down. Players
+/// who run out of time for their turn lose it; see `Session::time_out`.
+pub fn serve<'a, F>(
+    listeners: Vec<(TcpListener, Carrier)>,
+    dir: &Path,
+    tokens: &Tokens,
+    tls: Option<&TlsServer>,
+    webhooks: &[Webhook],
+    limits: &Limits,
+    open: F,
+) -> Result<()>
+where
+    F: Fn() -> Result<Box<dyn SessionStore + 'a>> + Sync,
+{
+    let shared = Shared::load(dir, webhooks)?;
+    let connections = Connections::new(limits.clone());
+    let done = AtomicBool::new(false);
+    thread::scope(|scope| {
+        let keeper = scope.spawn(|| deadlines::keep_time(&done, dir, &open, &shared.locks));
+        let accepting: Vec<_> = listeners
+            .into_iter()
+            .map(|(listener, carrier)| {
+                let (open, shared, connections) = (&open, &shared, &connections);
+                scope.spawn(move || -> Result<()> {
+                    for stream in listener.incoming() {
+                        let stream = stream?;
+                        let gate = Gate {
+                            tokens,
+                            connections,
+                        };
+                        scope.spawn(move || {
+                            connected(stream, carrier, tls, dir, gate, open, shared)
+                        });
+                    }
+                    Ok(())
+                })
+            })
+            .collect();
+        let result = accepting.into_iter().try_for_each(|thread| {
+            thread
+                .join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+        });
+        done.store(true, Ordering::Release);
+        keeper.thread().unpark();
+        result
+    })
+}
+
+/// Serves the client that connected on `stream`.
+fn connected<'a, F>(
+    stream: TcpStream,
+    carrier: Carrier,
+    tls: Option<&TlsServer>,
+    dir: &Path,
+    gate: Gate<'_>,
+    open: &F,
+    shared: &Shared,
+) where
+    F: Fn() -> Result<Box<dyn SessionStore + 'a>>,
+{
+    let peer = stream.peer_addr().map(|addr| addr.to_string());
+    let peer = peer.unwrap_or_else(|_| "client".to_string());
+    crate::verbose!("{peer} connected");
+    let idle = gate.connections.limits().idle();
+    let result = stream.set_read_timeout(idle).map_err(Error::from);
+    let result = result.and_then(|()| open()).and_then(|store| {
+        let sessions = LocalSessions::new(dir, &*store);
+        handle(
+            &mut *carrier.accept(stream, tls)?,
+            gate.tokens,
+            gate.connections,
+            |request, player| {
+                let sessions = sessions.by(player);
+                let sessions = Serialized::new(&sessions, &shared.locks);
+                let (mail, lobby) = (&shared.mail, &shared.lobby);
+                respond(request, player, &sessions, mail, lobby, &shared.webhooks)
+            },
+        )
+    });
+    disconnected(peer, result);
+}
+
+/// Logs how a connection ended.
+fn disconnected(peer: impl Display, result: Result<()>) {
+    match result {
+        Ok(()) => crate::verbose!("{peer} disconnected"),
+        // Whatever it was doing ended with its last request.
+        Err(Error::Io(err))
+            if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+        {
+            crate::verbose!("{peer} went quiet and was disconnected")
+        }
+        Err(err) => crate::warn!("{peer}: {err}"),
+    }
+}
+
+/// What a connection must get past before its requests are answered.
+#[derive(Clone, Copy)]
+struct Gate<'a> {
+    tokens: &'a Tokens,
+    connections: &'a Connections,
+}
+
+/// What every connection to a server shares before its requests reach a
+/// store: how near each player is to `limits`, and what each resumable
+/// connection was sent.
+#[derive(Debug, Default)]
+pub(crate) struct Connections {
+    throttles: Throttles,
+    resumes: Resumes,
+}
+
+impl Connections {
+    pub(crate) fn new(limits: Limits) -> Self {
+        Self {
+            throttles: Throttles::new(limits),
+            resumes: Resumes::default(),
+        }
+    }
+
+    fn limits(&self) -> &Limits {
+        self.throttles.limits()
+    }
+}
+
+/// Answers requests on one connection with `respond`, given the player
+/// who sent each, until the client closes it. The handshake comes first;
+/// see `Connection`. Messages too long for `connections` are skipped.
+pub(crate) fn handle(
+    transport: &mut dyn Transport,
+    tokens: &Tokens,
+    connections: &Connections,
+    respond: impl Fn(Message, Option<&str>) -> Result<Message>,
+) -> Result<()> {
+    transport.limit(connections.limits().max_frame_len());
+    let mut connection = Connection::new(tokens, connections);
+    loop {
+        let response = match transport.receive() {
+            Ok(Some(request)) => match connection.screen(request) {
+                ControlFlow::Break(response) => response,
+                ControlFlow::Continue((request, player)) => respond(request, player),
+            },
+            Ok(None) => return Ok(()),
+            // Skipped whole, so the connection can carry on.
+            Err(err @ Error::MessageTooLarge(..)) => Err(err),
+            Err(err) => return Err(err),
+        };
+        connection.answered(&response);
+        transport.send(&response.unwrap_or_else(|err| Message::error(&err)))?;
+    }
+}
+
+/// One client's connection: how far it has got in the handshake, what it
+/// has asked of late, and the token to resume it with, if it asked for one.
+pub(crate) struct Connection<'a> {
+    handshake: Handshake<'a>,
+    throttle: Throttle<'a>,
+    resumes: &'a Resumes,
+    resume: Option<String>,
+}
+
+impl<'a> Connection<'a> {
+    pub(crate) fn new(tokens: &'a Tokens, connections: &'a Connections) -> Self {
+        Self {
+            handshake: Handshake::new(tokens),
+            throttle: Throttle::new(&connections.throttles),
+            resumes: &connections.resumes,
+            resume: None,
+        }
+    }
+
+    /// As `Handshake::screen`, but refusing what the throttle does too, and
+    /// answering `Resume` itself.
+    pub(crate) fn screen(
+        &mut self,
+        request: Message,
+    ) -> ControlFlow<Result<Message>, (Message, Option<&str>)> {
+        if let Err(err) = self.throttle.pace() {
+            return ControlFlow::Break(Err(err));
+        }
+        let (request, player) = self.handshake.screen(request)?;
+        if let Message::Resume(token) = request {
+            let resumed = resume(self.resumes, token, player);
+            if let Ok(Message::Resumed(token, _)) = &resumed {
+                self.resume = Some(token.clone());
+            }
+            return ControlFlow::Break(resumed);
+        }
+        match self.throttle.admit(&request, player) {
+            Ok(()) => ControlFlow::Continue((request, player)),
+            Err(err) => ControlFlow::Break(Err(err)),
+        }
+    }
+
+    /// Notes what `response` sent the client, for it to resume from, and
+    /// counts any session it reports created against the throttle.
+    pub(crate) fn answered(&mut self, response: &Result<Message>) {
+        if let Ok(Message::Done(_)) = response {
+            self.throttle.created();
+        }
+        let Some(token) = &self.resume else {
+            return;
+        };
+        match response {
+            Ok(Message::StateResponse(session)) => {
+                let events = session.log().len() as u32;
+                self.resumes.saw(token, session.name(), events);
+            }
+            Ok(Message::Events(turn)) => {
+                let events = turn.base + turn.events.len() as u32;
+                self.resumes.saw(token, &turn.session, events);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// How far a client has got in saying hello and who it is. Until it has
+/// said hello, and presented a token if the server has issued any, its
+/// requests are refused. A spectator's requests to change anything are
+/// refused throughout.
+pub(crate) struct Handshake<'a> {
+    tokens: &'a Tokens,
+    agreed: Option<Hello>,
+    player: Option<String>,
+    /// Who presented a spectator's token, if anyone did.
+    spectator: Option<String>,
+    /// Whether the connection is read-only.
+    watching: bool,
+}
+
+impl<'a> Handshake<'a> {
+    pub(crate) fn new(tokens: &'a Tokens) -> Self {
+        Self {
+            tokens,
+            agreed: None,
+            player: None,
+            spectator: None,
+            watching: false,
+        }
+    }
+
+    /// The answer to `request` if it is part of the handshake or comes too
+    /// early, or else `request` to answer, with the player who sent it.
+    pub(crate) fn screen(
+        &mut self,
+        request: Message,
+    ) -> ControlFlow<Result<Message>, (Message, Option<&str>)> {
+        match (request, self.agreed.is_some()) {
+            (Message::Ping(nonce), _) => ControlFlow::Break(Ok(Message::Pong(nonce))),
+            (Message::Hello(client), _) => ControlFlow::Break(greet(&client).map(|hello| {
+                self.agreed = Some(hello.clone());
+                Message::Hello(hello)
+            })),
+            (_, false) => ControlFlow::Break(Err(Error::NoHello)),
+            (Message::Authenticate(token), true) => ControlFlow::Break(self.authenticate(&token)),
+            (Message::Spectate, true) => {
+                self.watching = true;
+                ControlFlow::Break(Ok(Message::Done("watching".to_string())))
+            }
+            (request, true) if self.watching && changes(&request) => {
+                ControlFlow::Break(Err(Error::Spectating))
+            }
+            (request, true)
+                if self.player.is_some() || self.spectator.is_some() || self.tokens.is_empty() =>
+            {
+                ControlFlow::Continue((request, self.player.as_deref()))
+            }
+            (_, true) => ControlFlow::Break(Err(Error::Unauthenticated)),
+        }
+    }
+
+    fn authenticate(&mut self, token: &str) -> Result<Message> {
+        if let Some(spectator) = self.tokens.spectator(token) {
+            crate::verbose!("client is {spectator}, a spectator");
+            self.spectator = Some(spectator.to_string());
+            self.watching = true;
+            return Ok(Message::Authenticated(spectator.to_string()));
+        }
+        let Some(player) = self.tokens.player(token) else {
+            crate::warn!("refused a client's token");
+            return Err(Error::InvalidToken);
+        };
+        crate::verbose!("client is {player}");
+        self.player = Some(player.to_string());
+        Ok(Message::Authenticated(player.to_string()))
+    }
+}
+
+/// Whether answering `request` would change a session, which spectators
+/// may not. Trying an action without saving it changes nothing.
+fn changes(request: &Message) -> bool {
+    matches!(
+        request,
+        Message::CreateSession(_)
+            | Message::SubmitAction(_, _, false)
+            | Message::Chat(..)
+            | Message::PushEvents(_)
+            | Message::Pass(_)
+            | Message::Host(..)
+            | Message::Join(_)
+    )
+}
+
+/// The versions to speak with a client offering `client`.
+fn greet(client: &Hello) -> Result<Hello> {
+    crate::verbose!(
+        "client runs {}, speaking protocol {}",
+        client.software,
+        client.protocol
+    );
+    Hello::current()
+        .answer(client)
+        .inspect_err(|err| crate::warn!("{err}"))
+}
+
+/// What every connection to a server shares.
+struct Shared {
+    locks: SessionLocks,
+    mail: Mailboxes,
+    lobby: Lobby,
+    webhooks: Vec<Webhook>,
+}
+
+impl Shared {
+    fn load(dir: &Path, webhooks: &[Webhook]) -> Result<Self> {
+        Ok(Self {
+            locks: SessionLocks::default(),
+            mail: Mailboxes::load(dir)?,
+            lobby: Lobby::default(),
+            webhooks: webhooks.to_vec(),
+        })
+    }
+}
+
+/// A lock for each session a server has been asked to change. Stores lock
+/// sessions too, but fail rather than wait when one is taken, which would
+/// turn away a client whose request merely arrived second.
+#[derive(Debug, Default)]
+pub struct SessionLocks {
+    sessions: Mutex<BTreeMap<String, Arc<Mutex<()>>>>,
+}
+
+impl SessionLocks {
+    /// Runs `f` once no other caller holds `name`'s lock.
+    pub fn with<T>(&self, name: &str, f: impl FnOnce() -> T) -> T {
+        let lock = Arc::clone(relock(&self.sessions).entry(name.to_string()).or_default());
+        let _held = relock(&lock);
+        f()
+    }
+}
+
+/// `mutex` locked, whether or not a thread panicked holding it, as a
+/// session is only changed by saving it whole.
+fn relock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Sessions from `sessions`, changed one request at a time.
+struct Serialized<'a> {
+    sessions: &'a dyn SessionProvider,
+    locks: &'a SessionLocks,
+}
+
+impl<'a> Serialized<'a> {
+    fn new(sessions: &'a dyn SessionProvider, locks: &'a SessionLocks) -> Self {
+        Self { sessions, locks }
+    }
+}
+
+impl SessionProvider for Serialized<'_> {
+    fn create(&self, name: &str) -> Result<()> {
+        self.locks.with(name, || self.sessions.create(name))
+    }
+
+    fn load(&self, name: &str) -> Result<Session> {
+        self.sessions.load(name)
+    }
+
+    fn apply(&self, name: &str, action: Action, dry_run: bool) -> Result<ActionOutcome> {
+        self.locks
+            .with(name, || self.sessions.apply(name, action, dry_run))
+    }
+
+    fn list(&self) -> Result<Vec<String>> {
+        self.sessions.list()
+    }
+
+    fn events(&self, name: &str, from: usize) -> Result<TurnFile> {
+        self.sessions.events(name, from)
+    }
+
+    fn receive(&self, turn: &TurnFile) -> Result<Merged> {
+        self.locks
+            .with(&turn.session, || self.sessions.receive(turn))
+    }
+
+    fn save_new(&self, session: Session) -> Result<()> {
+        let name = session.name().to_string();
+        self.locks.with(&name, || self.sessions.save_new(session))
+    }
+
+    fn say(&self, name: &str, text: &str) -> Result<()> {
+        self.locks.with(name, || self.sessions.say(name, text))
+    }
+
+    fn pass(&self, name: &str) -> Result<String> {
+        self.locks.with(name, || self.sessions.pass(name))
+    }
+}
+
+/// Carries on from the connection `token` was issued on, for `player`, or
+/// issues a token for this one if `token` is empty.
+fn resume(resumes: &Resumes, token: String, player: Option<&str>) -> Result<Message> {
+    match token.is_empty() {
+        true => Ok(Message::Resumed(resumes.issue(player), vec![])),
+        false => {
+            let seen = resumes.resume(&token, player)?;
+            Ok(Message::Resumed(token, seen))
+        }
+    }
+}
+
+/// Answers `request` from `player`, if the client is anyone in particular,
+/// telling other players and `webhooks` of any action taken. Games opened
+/// in `lobby` start in `sessions` once full.
+pub(crate) fn respond(
+    request: Message,
+    player: Option<&str>,
+    sessions: &dyn SessionProvider,
+    mail: &Mailboxes,
+    lobby: &Lobby,
+    webhooks: &[Webhook],
+) -> Result<Message> {
+    crate::verbose!("{request:?}");
+    let response = match request {
+        Message::CreateSession(name) => {
+            sessions.create(&name)?;
+            Message::Done("session saved".to_string())
+        }
+        Message::SubmitAction(name, action, dry_run) => {
+            let taken = (!dry_run).then(|| action.clone());
+            let outcome = sessions.apply(&name, action, dry_run)?;
+            if let Some(action) = taken {
+                // The turn was taken whether or not anyone hears of it.
+                let session = sessions.load(&name);
+                let told = session.and_then(|session| {
+                    announce(&session, player, &action, webhooks);
+                    match player {
+                        Some(player) => tell(&session, mail, player, Body::Acted(action)),
+                        None => Ok(0),
+                    }
+                });
+                if let Err(err) = told {
+                    crate::warn!("{err}");
+                }
+            }
+            Message::Outcome(outcome)
+        }
+        Message::FetchState(name) => {
+            let session = visibility::redact(sessions.load(&name)?, player)?;
+            Message::StateResponse(Box::new(session))
+        }
+        Message::ListSessions => Message::Sessions(sessions.list()?),
+        Message::FetchMail => {
+            Message::Mail(player.map(|player| mail.fetch(player)).unwrap_or_default())
+        }
+        Message::Acknowledge(last) => {
+            let read = match player {
+                Some(player) => mail.acknowledge(player, last)?,
+                None => 0,
+            };
+            Message::Done(format!("{read} letters acknowledged"))
+        }
+        Message::Chat(name, text) => {
+            let player = player.ok_or(Error::Unauthenticated)?;
+            sessions.say(&name, &text)?;
+            let told = tell(&sessions.load(&name)?, mail, player, Body::Chat(text))?;
+            Message::Done(format!("told {told} other players"))
+        }
+        Message::FetchEvents(name, from) => {
+            // The log would give away whatever the state leaves out, to
+            // players and spectators alike.
+            if Visibility::hides(&sessions.load(&name)?) {
+                return Err(Error::Hidden(name));
+            }
+            Message::Events(Box::new(sessions.events(&name, from as usize)?))
+        }
+        Message::PushEvents(turn) => Message::Merged(sessions.receive(&turn)?),
+        Message::Pass(name) => Message::Done(sessions.pass(&name)?),
+        Message::Host(name, rule_set, slots) => {
+            let player = player.ok_or(Error::Unauthenticated)?;
+            lobby.host(sessions, &name, &rule_set, slots, player)?;
+            Message::Done(format!("{name:?} waits for {} more player(s)", slots - 1))
+        }
+        Message::ListGames => Message::Games(lobby.games()),
+        Message::Join(name) => {
+            let player = player.ok_or(Error::Unauthenticated)?;
+            let game = lobby.join(sessions, &name, player)?;
+            Message::Done(match game.free() {
+                0 => format!("{name:?} has started; {} to play", game.players[0]),
+                free => format!("joined {name:?}, which waits for {free} more player(s)"),
+            })
+        }
+        Message::Hello(_)
+        | Message::Done(_)
+        | Message::Outcome(_)
+        | Message::StateResponse(_)
+        | Message::Sessions(_)
+        | Message::Error(..)
+        | Message::Authenticate(_)
+        | Message::Authenticated(_)
+        | Message::Mail(_)
+        | Message::Events(_)
+        | Message::Merged(_)
+        | Message::Ping(_)
+        | Message::Pong(_)
+        | Message::Resume(_)
+        | Message::Resumed(..)
+        | Message::Games(_)
+        | Message::Spectate => return Err(Error::UnexpectedRequest),
+    };
+    Ok(response)
+}
+
+/// Posts `body` from `from` to every other player of `session`, returning
+/// how many there were.
+fn tell(session: &Session, mail: &Mailboxes, from: &str, body: Body) -> Result<usize> {
+    let mut players = session.players();
+    players.retain(|player| *player != from);
+    mail.post(&players, session.name(), from, body)?;
+    Ok(players.len())
+}
+
+/// Posts `action` to `webhooks` on a thread of its own, so that a slow
+/// webhook holds up no client.
+fn announce(session: &Session, player: Option<&str>, action: &Action, webhooks: &[Webhook]) {
+    if webhooks.is_empty() {
+        return;
+    }
+    let (webhooks, payload) = (webhooks.to_vec(), webhook::acted(session, player, action));
+    thread::spawn(move || webhook::post_all(&webhooks, &payload));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+    use std::net::TcpListener;
+    use std::path::Path;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::{handle, respond, Connections, Limits};
+    use crate::actions::{Action, ActionKind};
+    use crate::client::Client;
+    use crate::components::Position;
+    use crate::error::Error;
+    use crate::lobby::Lobby;
+    use crate::log::Event;
+    use crate::mailbox::Mailboxes;
+    use crate::map::Map;
+    use crate::players::Player;
+    use crate::protocol::{Message, TcpTransport, PROTOCOL_VERSIONS};
+    use crate::provider::{LocalSessions, SessionProvider};
+    use crate::session::Session;
+    use crate::store::MemStore;
+    use crate::tokens::Tokens;
+    use crate::Entity;
+
+    #[test]
+    fn clients_create_sessions_and_act_on_them() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let store = MemStore::new();
+            let sessions = LocalSessions::new(Path::new(""), &store);
+            let mut transport = TcpTransport::new(stream).unwrap();
+            let tokens = Tokens::default();
+            handle(
+                &mut transport,
+                &tokens,
+                &Connections::default(),
+                |request, _| {
+                    respond(
+                        request,
+                        None,
+                        &sessions,
+                        &Mailboxes::default(),
+                        &Lobby::default(),
+                        &[],
+                    )
+                },
+            )
+            .unwrap();
+        });
+
+        let client = Client::connect(&addr.to_string(), None, None).unwrap();
+        assert_eq!(client.protocol(), PROTOCOL_VERSIONS.max);
+        client.create("game").unwrap();
+        let err = client.create("game").unwrap_err();
+        assert_eq!(err.to_string(), "session \"game\" already exists");
+        let rest = Action::new(ActionKind::Rest, "hero".to_string()).unwrap();
+        let err = client.apply("game", rest, false).unwrap_err();
+        assert!(matches!(err, Error::Remote(_, 2)));
+        assert_eq!(err.exit_code(), 2);
+        assert_eq!(client.list().unwrap(), ["game"]);
+        assert_eq!(client.load("game").unwrap().name(), "game");
+        drop(client);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn clients_act_as_the_player_their_token_names() {
+        let mut tokens = Tokens::default();
+        let token = tokens.generate("alice".to_string()).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let store = MemStore::new();
+            let mut session = Session::new("game".to_string()).unwrap();
+            session
+                .add_entity(Entity::new("troll".to_string()))
+                .unwrap();
+            session.save(&store).unwrap();
+            let sessions = LocalSessions::new(Path::new(""), &store);
+            let mut transport = TcpTransport::new(stream).unwrap();
+            let mail = Mailboxes::default();
+            handle(
+                &mut transport,
+                &tokens,
+                &Connections::default(),
+                |request, player| {
+                    respond(
+                        request,
+                        player,
+                        &sessions.by(player),
+                        &mail,
+                        &Lobby::default(),
+                        &[],
+                    )
+                },
+            )
+            .unwrap();
+        });
+
+        let client = Client::connect(&addr.to_string(), None, None).unwrap();
+        assert_eq!(client.list().unwrap_err().exit_code(), 7);
+        let guess = Message::Authenticate("0".repeat(64));
+        assert_eq!(client.request(&guess).unwrap_err().exit_code(), 7);
+        let signed_in = client.request(&Message::Authenticate(token)).unwrap();
+        assert_eq!(signed_in, Message::Authenticated("alice".to_string()));
+        let fight = Action::new(ActionKind::Fight, "troll".to_string()).unwrap();
+        client.apply("game", fight.clone(), false).unwrap();
+        let session = client.load("game").unwrap();
+        let Some(Event::ActBy(player, action)) = session.log().last() else {
+            panic!("the action was not attributed");
+        };
+        assert_eq!((player.as_str(), action.kind()), ("alice", fight.kind()));
+        drop(client);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn players_get_mail_from_while_they_were_away() {
+        let mut tokens = Tokens::default();
+        let alice = tokens.generate("alice".to_string()).unwrap();
+        let bob = tokens.generate("bob".to_string()).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            let store = MemStore::new();
+            let mut session = Session::new("game".to_string()).unwrap();
+            session
+                .add_entity(Entity::new("troll".to_string()))
+                .unwrap();
+            session.save(&store).unwrap();
+            let sessions = LocalSessions::new(Path::new(""), &store);
+            let mail = Mailboxes::default();
+            for _ in 0..3 {
+                let (stream, _) = listener.accept().unwrap();
+                let mut transport = TcpTransport::new(stream).unwrap();
+                handle(
+                    &mut transport,
+                    &tokens,
+                    &Connections::default(),
+                    |request, player| {
+                        respond(
+                            request,
+                            player,
+                            &sessions.by(player),
+                            &mail,
+                            &Lobby::default(),
+                            &[],
+                        )
+                    },
+                )
+                .unwrap();
+            }
+        });
+        let fight = Action::new(ActionKind::Fight, "troll".to_string()).unwrap();
+
+        let client = Client::connect(&addr, Some(&bob), None).unwrap();
+        client.apply("game", fight.clone(), false).unwrap();
+        drop(client);
+        let client = Client::connect(&addr, Some(&alice), None).unwrap();
+        client.apply("game", fight.clone(), false).unwrap();
+        assert_eq!(
+            client.chat("game", "your turn").unwrap(),
+            "told 1 other players"
+        );
+        assert!(client
+            .mail()
+            .unwrap()
+            .iter()
+            .all(|letter| letter.from == "alice"));
+        drop(client);
+
+        let client = Client::connect(&addr, Some(&bob), None).unwrap();
+        let letters = client.mail().unwrap();
+        let read: Vec<_> = letters.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            read,
+            [
+                format!("[game] alice: {fight}"),
+                "[game] alice says \"your turn\"".to_string()
+            ]
+        );
+        client.acknowledge(letters[1].id).unwrap();
+        assert!(client.mail().unwrap().is_empty());
+        drop(client);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn spectators_watch_but_cannot_act() {
+        let mut tokens = Tokens::default();
+        let alice = tokens.generate("alice".to_string()).unwrap();
+        let carol = tokens.generate_spectator("carol".to_string()).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            let store = MemStore::new();
+            let mut session = Session::new("game".to_string()).unwrap();
+            session
+                .add_entity(Entity::new("troll".to_string()))
+                .unwrap();
+            session.save(&store).unwrap();
+            let sessions = LocalSessions::new(Path::new(""), &store);
+            for _ in 0..2 {
+                let (stream, _) = listener.accept().unwrap();
+                let mut transport = TcpTransport::new(stream).unwrap();
+                handle(
+                    &mut transport,
+                    &tokens,
+                    &Connections::default(),
+                    |request, player| {
+                        let mail = Mailboxes::default();
+                        respond(
+                            request,
+                            player,
+                            &sessions.by(player),
+                            &mail,
+                            &Lobby::default(),
+                            &[],
+                        )
+                    },
+                )
+                .unwrap();
+            }
+        });
+        let fight = || Action::new(ActionKind::Fight, "troll".to_string()).unwrap();
+
+        let client = Client::connect(&addr, Some(&carol), None).unwrap();
+        assert_eq!(client.load("game").unwrap().log().len(), 1);
+        assert!(client.apply("game", fight(), true).is_ok());
+        let err = client.apply("game", fight(), false).unwrap_err();
+        assert_eq!(err.to_string(), "spectators can only watch");
+        assert_eq!(err.exit_code(), 7);
+        assert!(client.chat("game", "gg").is_err());
+        assert!(client.create("mine").is_err());
+        drop(client);
+
+        let client = Client::connect(&addr, Some(&alice), None).unwrap();
+        client.spectate().unwrap();
+        assert!(client.apply("game", fight(), false).is_err());
+        assert_eq!(client.load("game").unwrap().log().len(), 1);
+        drop(client);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn hidden_sessions_are_redacted_for_everyone() {
+        let store = MemStore::new();
+        let mut session = Session::new("fog".to_string()).unwrap();
+        session.play_by("fog").unwrap();
+        session
+            .set_map(Map {
+                width: 10,
+                height: 10,
+            })
+            .unwrap();
+        session
+            .add_player(Player::new("alice".to_string(), None))
+            .unwrap();
+        let mut scout = Entity::new("scout".to_string());
+        scout.owner = Some("alice".to_string());
+        scout.position = Some(Position { x: 0, y: 0 });
+        session.add_entity(scout).unwrap();
+        session.save(&store).unwrap();
+        let sessions = LocalSessions::new(Path::new(""), &store);
+        let ask = |request, player| {
+            let mail = Mailboxes::default();
+            respond(
+                request,
+                player,
+                &sessions.by(player),
+                &mail,
+                &Lobby::default(),
+                &[],
+            )
+        };
+
+        for player in [None, Some("alice")] {
+            let Ok(Message::StateResponse(seen)) =
+                ask(Message::FetchState("fog".to_string()), player)
+            else {
+                panic!("no state for {player:?}");
+            };
+            assert_eq!(seen.redacted(), Some(player.unwrap_or_default()));
+            assert_eq!(seen.entities().len(), usize::from(player.is_some()));
+            let events = ask(Message::FetchEvents("fog".to_string(), 0), player);
+            assert!(matches!(events, Err(Error::Hidden(_))));
+        }
+    }
+
+    #[test]
+    fn greedy_clients_are_told_to_slow_down() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let store = MemStore::new();
+            let sessions = LocalSessions::new(Path::new(""), &store);
+            let mut transport = TcpTransport::new(stream).unwrap();
+            let connections = Connections::new(Limits {
+                rate: 4,
+                player_rate: 0,
+                max_message: 64,
+                max_sessions: 1,
+                ..Limits::default()
+            });
+            handle(
+                &mut transport,
+                &Tokens::default(),
+                &connections,
+                |request, _| {
+                    respond(
+                        request,
+                        None,
+                        &sessions,
+                        &Mailboxes::default(),
+                        &Lobby::default(),
+                        &[],
+                    )
+                },
+            )
+            .unwrap();
+        });
+
+        // Saying hello is the first of four messages allowed at once.
+        let client = Client::connect(&addr.to_string(), None, None).unwrap();
+        let huge = Message::CreateSession("x".repeat(100));
+        let err = client.request(&huge).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "message of 107 bytes is over the server's limit of 64"
+        );
+        // A session that could not be created is not counted against them.
+        assert!(client.create("").is_err());
+        client.create("game").unwrap();
+        let err = client.create("again").unwrap_err();
+        assert_eq!(err.exit_code(), 8);
+        let err = client.list().unwrap_err();
+        assert!(err.to_string().contains("slow down"));
+        drop(client);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn dropped_connections_resume_where_they_left_off() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            let store = MemStore::new();
+            let mut session = Session::new("game".to_string()).unwrap();
+            session
+                .add_entity(Entity::new("troll".to_string()))
+                .unwrap();
+            session.save(&store).unwrap();
+            let sessions = LocalSessions::new(Path::new(""), &store);
+            let connections = Connections::default();
+            for _ in 0..3 {
+                let (stream, _) = listener.accept().unwrap();
+                let mut transport = TcpTransport::new(stream).unwrap();
+                handle(
+                    &mut transport,
+                    &Tokens::default(),
+                    &connections,
+                    |request, _| {
+                        respond(
+                            request,
+                            None,
+                            &sessions,
+                            &Mailboxes::default(),
+                            &Lobby::default(),
+                            &[],
+                        )
+                    },
+                )
+                .unwrap();
+            }
+        });
+
+        let client = Client::connect(&addr, None, None).unwrap();
+        let token = client.resume_token().unwrap();
+        assert_eq!(client.load("game").unwrap().log().len(), 1);
+        drop(client);
+        let client = Client::connect(&addr, None, None).unwrap();
+        let fight = Action::new(ActionKind::Fight, "troll".to_string()).unwrap();
+        client.apply("game", fight, false).unwrap();
+        drop(client);
+
+        let client = Client::connect(&addr, None, None).unwrap();
+        assert!(client.resume("not a token").is_err());
+        let missed = client.resume(&token).unwrap();
+        assert_eq!(missed.len(), 1);
+        assert_eq!((missed[0].base, missed[0].events.len()), (1, 1));
+        // Fetching what was missed counts as having been sent it.
+        assert!(client.resume(&token).unwrap().is_empty());
+        drop(client);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn quiet_connections_are_closed() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_millis(200)))
+                .unwrap();
+            let store = MemStore::new();
+            let sessions = LocalSessions::new(Path::new(""), &store);
+            let mut transport = TcpTransport::new(stream).unwrap();
+            handle(
+                &mut transport,
+                &Tokens::default(),
+                &Connections::default(),
+                |request, _| {
+                    respond(
+                        request,
+                        None,
+                        &sessions,
+                        &Mailboxes::default(),
+                        &Lobby::default(),
+                        &[],
+                    )
+                },
+            )
+        });
+
+        let client = Client::connect(&addr.to_string(), None, None).unwrap();
+        client.ping().unwrap();
+        let Err(Error::Io(err)) = server.join().unwrap() else {
+            panic!("the connection outlived its timeout");
+        };
+        assert!(matches!(
+            err.kind(),
+            ErrorKind::WouldBlock | ErrorKind::TimedOut
+        ));
+        assert!(client.ping().is_err());
+    }
+}
@@ -0,0 +1,40 @@
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use super::SessionLocks;
+use crate::error::Result;
+use crate::provider::{LocalSessions, SessionProvider};
+use crate::store::SessionStore;
+
+/// How often a server looks for players who have run out of time.
+const INTERVAL: Duration = Duration::from_secs(15);
+
+/// Ends the turn of every player who has run out of time, in any session,
+/// every `INTERVAL` until `done` is set. Unpark the thread to have it
+/// notice sooner.
+pub(super) fn keep_time<'a, F>(done: &AtomicBool, dir: &Path, open: &F, locks: &SessionLocks)
+where
+    F: Fn() -> Result<Box<dyn SessionStore + 'a>>,
+{
+    let store = match open() {
+        Ok(store) => store,
+        Err(err) => {
+            crate::warn!("turn deadlines will not be kept: {err}");
+            return;
+        }
+    };
+    let sessions = LocalSessions::new(dir, &*store);
+    while !done.load(Ordering::Acquire) {
+        for name in sessions.list().unwrap_or_default() {
+            match locks.with(&name, || sessions.time_out(&name)) {
+                Ok(Some(player)) => crate::verbose!("{player} ran out of time in {name:?}"),
+                Ok(None) => {}
+                // Most likely taken by someone else; it is tried again.
+                Err(err) => crate::verbose!("could not check {name:?} for deadlines: {err}"),
+            }
+        }
+        thread::park_timeout(INTERVAL);
+    }
+}
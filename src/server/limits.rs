@@ -0,0 +1,240 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::relock;
+use crate::error::{Error, Result};
+use crate::protocol::{Message, MAX_FRAME_LEN};
+
+/// How much a server lets each client ask of it, so that one misbehaving
+/// client cannot starve the rest. Zero means no limit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Limits {
+    /// Messages a second on one connection.
+    pub rate: u32,
+    /// Messages a second from one player, over all their connections.
+    pub player_rate: u32,
+    /// The longest message, in bytes, up to `MAX_FRAME_LEN`.
+    pub max_message: u32,
+    /// Sessions one player, or one connection without a token, may create
+    /// while the server runs, counting the games they host in the lobby.
+    pub max_sessions: u32,
+    /// Seconds a connection may send nothing before it is taken for dead
+    /// and closed. Clients ping to keep theirs open.
+    pub idle_timeout: u32,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            rate: 20,
+            player_rate: 40,
+            max_message: 4 * 1024 * 1024,
+            max_sessions: 100,
+            idle_timeout: 300,
+        }
+    }
+}
+
+impl Limits {
+    /// How long a connection may be idle, if there is a limit.
+    pub fn idle(&self) -> Option<Duration> {
+        (self.idle_timeout != 0).then(|| Duration::from_secs(self.idle_timeout.into()))
+    }
+
+    /// `max_message` as a frame length, where no limit is the protocol's.
+    pub fn max_frame_len(&self) -> u32 {
+        match self.max_message {
+            0 => MAX_FRAME_LEN,
+            max => max.min(MAX_FRAME_LEN),
+        }
+    }
+}
+
+/// `limits` and how near each player is to them, shared by every
+/// connection to a server.
+#[derive(Debug, Default)]
+pub(crate) struct Throttles {
+    limits: Limits,
+    players: Mutex<BTreeMap<String, Usage>>,
+}
+
+impl Throttles {
+    pub(crate) fn new(limits: Limits) -> Self {
+        Self {
+            limits,
+            players: Mutex::default(),
+        }
+    }
+
+    pub(crate) fn limits(&self) -> &Limits {
+        &self.limits
+    }
+}
+
+/// What one connection has asked of a server.
+pub(crate) struct Throttle<'a> {
+    throttles: &'a Throttles,
+    connection: Usage,
+    /// Who is creating a session with the request being answered, if it
+    /// creates one: the player, or `None` for the connection itself.
+    creating: Option<Option<String>>,
+}
+
+impl<'a> Throttle<'a> {
+    pub(crate) fn new(throttles: &'a Throttles) -> Self {
+        Self {
+            throttles,
+            connection: Usage::new(throttles.limits.rate),
+            creating: None,
+        }
+    }
+
+    /// Fails if the connection has sent too many messages of late. Called
+    /// for each request, before anything else, so forgets the last one.
+    pub(crate) fn pace(&mut self) -> Result<()> {
+        self.creating = None;
+        let rate = self.throttles.limits.rate;
+        match self.connection.requests.take(rate) {
+            true => Ok(()),
+            false => Err(Error::RateLimited(rate)),
+        }
+    }
+
+    /// Fails if answering `request` from `player` would take them past a
+    /// limit, and otherwise counts it against them. A session it creates is
+    /// only counted once `created` says it was.
+    pub(crate) fn admit(&mut self, request: &Message, player: Option<&str>) -> Result<()> {
+        let limits = &self.throttles.limits;
+        let mut players = relock(&self.throttles.players);
+        let usage = match player {
+            Some(player) => {
+                let usage = players
+                    .entry(player.to_string())
+                    .or_insert_with(|| Usage::new(limits.player_rate));
+                if !usage.requests.take(limits.player_rate) {
+                    return Err(Error::RateLimited(limits.player_rate));
+                }
+                usage
+            }
+            None => &mut self.connection,
+        };
+        if let Message::CreateSession(_) | Message::Host(..) = request {
+            if limits.max_sessions != 0 && usage.created >= limits.max_sessions {
+                return Err(Error::TooManySessions(limits.max_sessions));
+            }
+            self.creating = Some(player.map(str::to_string));
+        }
+        Ok(())
+    }
+
+    /// Counts the session the request `admit` let through created, now that
+    /// it has been answered without error.
+    pub(crate) fn created(&mut self) {
+        match self.creating.take() {
+            Some(Some(player)) => {
+                let limits = &self.throttles.limits;
+                relock(&self.throttles.players)
+                    .entry(player)
+                    .or_insert_with(|| Usage::new(limits.player_rate))
+                    .created += 1;
+            }
+            Some(None) => self.connection.created += 1,
+            None => {}
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Usage {
+    requests: Bucket,
+    /// Sessions created so far.
+    created: u32,
+}
+
+impl Usage {
+    fn new(rate: u32) -> Self {
+        Self {
+            requests: Bucket::full(rate),
+            created: 0,
+        }
+    }
+}
+
+/// Allows requests at a steady rate a second, in bursts of up to as many.
+#[derive(Debug)]
+struct Bucket {
+    level: f64,
+    filled: Instant,
+}
+
+impl Bucket {
+    fn full(rate: u32) -> Self {
+        Self {
+            level: rate.into(),
+            filled: Instant::now(),
+        }
+    }
+
+    /// Whether a request may be made now, at up to `rate` a second.
+    fn take(&mut self, rate: u32) -> bool {
+        if rate == 0 {
+            return true;
+        }
+        let now = Instant::now();
+        let refill = now.duration_since(self.filled).as_secs_f64() * f64::from(rate);
+        self.level = (self.level + refill).min(rate.into());
+        self.filled = now;
+        if self.level < 1.0 {
+            return false;
+        }
+        self.level -= 1.0;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Limits, Throttle, Throttles};
+    use crate::error::Error;
+    use crate::protocol::Message;
+
+    #[test]
+    fn hosting_a_game_counts_as_creating_a_session() {
+        let throttles = Throttles::new(Limits {
+            max_sessions: 2,
+            ..Limits::default()
+        });
+        let mut throttle = Throttle::new(&throttles);
+        let host = |name: &str| Message::Host(name.to_string(), "standard".to_string(), 2);
+        let create = Message::CreateSession("duel".to_string());
+        for request in [create, host("brawl")] {
+            throttle.pace().unwrap();
+            throttle.admit(&request, Some("alice")).unwrap();
+            throttle.created();
+        }
+        throttle.pace().unwrap();
+        let err = throttle.admit(&host("melee"), Some("alice"));
+        assert!(matches!(err, Err(Error::TooManySessions(2))));
+        throttle.pace().unwrap();
+        throttle.admit(&host("melee"), Some("bob")).unwrap();
+    }
+
+    #[test]
+    fn failed_requests_create_nothing() {
+        let throttles = Throttles::new(Limits {
+            max_sessions: 1,
+            ..Limits::default()
+        });
+        let mut throttle = Throttle::new(&throttles);
+        let host = Message::Host("brawl".to_string(), "standard".to_string(), 2);
+        for _ in 0..3 {
+            throttle.pace().unwrap();
+            throttle.admit(&host, Some("alice")).unwrap();
+        }
+        throttle.created();
+        throttle.pace().unwrap();
+        let err = throttle.admit(&host, Some("alice"));
+        assert!(matches!(err, Err(Error::TooManySessions(1))));
+    }
+}
@@ -0,0 +1,81 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use rand_core::{OsRng, RngCore};
+
+use super::relock;
+use crate::error::{Error, Result};
+use crate::signing::to_hex;
+
+const TOKEN_LEN: usize = 32;
+
+/// The most connections remembered at once. The one resumed least recently
+/// is forgotten to make room for another.
+const MAX_RESUMES: usize = 10_000;
+
+/// What the connections that asked to be resumable had been sent, so that
+/// a client that reconnects with its resume token can fetch only the
+/// events it missed. Kept in memory, and so forgotten on restart.
+#[derive(Debug, Default)]
+pub(crate) struct Resumes {
+    cursors: Mutex<BTreeMap<String, Cursor>>,
+}
+
+#[derive(Debug)]
+struct Cursor {
+    player: Option<String>,
+    /// How many events of each session the client was last sent.
+    seen: BTreeMap<String, u32>,
+    used: Instant,
+}
+
+impl Resumes {
+    /// A new token for a connection acting as `player`.
+    pub(crate) fn issue(&self, player: Option<&str>) -> String {
+        let mut token = [0; TOKEN_LEN];
+        OsRng.fill_bytes(&mut token);
+        let token = to_hex(&token);
+        let mut cursors = relock(&self.cursors);
+        if cursors.len() >= MAX_RESUMES {
+            let stalest = cursors
+                .iter()
+                .min_by_key(|(_, cursor)| cursor.used)
+                .map(|(token, _)| token.clone());
+            if let Some(stalest) = stalest {
+                cursors.remove(&stalest);
+            }
+        }
+        let cursor = Cursor {
+            player: player.map(str::to_string),
+            seen: BTreeMap::new(),
+            used: Instant::now(),
+        };
+        cursors.insert(token.clone(), cursor);
+        token
+    }
+
+    /// How many events of each session the connection `token` was issued
+    /// on was sent, if it acted as `player` too.
+    pub(crate) fn resume(&self, token: &str, player: Option<&str>) -> Result<Vec<(String, u32)>> {
+        let mut cursors = relock(&self.cursors);
+        let cursor = cursors
+            .get_mut(token)
+            .filter(|cursor| cursor.player.as_deref() == player)
+            .ok_or(Error::InvalidToken)?;
+        cursor.used = Instant::now();
+        let seen = cursor
+            .seen
+            .iter()
+            .map(|(name, &events)| (name.clone(), events));
+        Ok(seen.collect())
+    }
+
+    /// Notes that the connection `token` was sent `events` of `session`.
+    pub(crate) fn saw(&self, token: &str, session: &str, events: u32) {
+        if let Some(cursor) = relock(&self.cursors).get_mut(token) {
+            cursor.seen.insert(session.to_string(), events);
+            cursor.used = Instant::now();
+        }
+    }
+}
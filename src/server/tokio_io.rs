@@ -0,0 +1,313 @@
+use std::io::ErrorKind;
+use std::net::TcpListener;
+use std::ops::ControlFlow;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader, BufWriter};
+use tokio::net::TcpStream;
+use tokio::sync::oneshot;
+use tokio::task::JoinSet;
+
+use super::{
+    deadlines, disconnected, relock, respond, Connection, Connections, Limits, Serialized, Shared,
+};
+use crate::error::{Error, Result};
+use crate::protocol::{frame_len, Carrier, Message, TlsServer};
+use crate::provider::LocalSessions;
+use crate::serde::{from_bytes, Serialize};
+use crate::store::SessionStore;
+use crate::tokens::Tokens;
+use crate::webhook::Webhook;
+
+/// A request for a worker, the player it is from, and where its answer
+/// goes.
+type Job = (Message, Option<String>, oneshot::Sender<Message>);
+
+/// As `serve`, but with each unencrypted TCP connection a task on a tokio runtime, so
+/// that idle clients cost no thread of their own, and returning as soon as
+/// a listener fails. Requests are answered by a worker thread for each
+/// core, and at least four, each with its own store from `open`.
+pub fn serve_async<'a, F>(
+    listeners: Vec<(TcpListener, Carrier)>,
+    dir: &Path,
+    tokens: &Tokens,
+    tls: Option<&TlsServer>,
+    webhooks: &[Webhook],
+    limits: &Limits,
+    open: F,
+) -> Result<()>
+where
+    F: Fn() -> Result<Box<dyn SessionStore + 'a>> + Sync,
+{
+    // Workers mostly wait on the disk, so a machine with few cores gets more.
+    let workers = thread::available_parallelism().map_or(4, |cores| cores.get().max(4));
+    let shared = Shared::load(dir, webhooks)?;
+    let (jobs, queue) = mpsc::channel();
+    let queue = Mutex::new(queue);
+    let tokens = Arc::new(tokens.clone());
+    let connections = Arc::new(Connections::new(limits.clone()));
+    let done = AtomicBool::new(false);
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| work(&queue, dir, &open, &shared));
+        }
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_io()
+            .enable_time()
+            .build()?;
+        let keeper = scope.spawn(|| deadlines::keep_time(&done, dir, &open, &shared.locks));
+        let result = runtime.block_on(async {
+            let mut accepting = JoinSet::new();
+            for (listener, carrier) in listeners {
+                listener.set_nonblocking(true)?;
+                let gate = (tokens.clone(), connections.clone());
+                accepting.spawn(accept(listener, carrier, gate, tls.cloned(), jobs.clone()));
+            }
+            match accepting.join_next().await {
+                Some(Ok(result)) => result,
+                Some(Err(err)) => std::panic::resume_unwind(err.into_panic()),
+                None => Ok(()),
+            }
+        });
+        // Takes every connection's sender with it, so the workers finish.
+        drop(runtime);
+        done.store(true, Ordering::Release);
+        keeper.thread().unpark();
+        result
+    })
+}
+
+/// Answers jobs from `queue` until every sender is gone. A worker whose
+/// store failed to open answers each request with that error.
+fn work<'a, F>(queue: &Mutex<Receiver<Job>>, dir: &Path, open: &F, shared: &Shared)
+where
+    F: Fn() -> Result<Box<dyn SessionStore + 'a>>,
+{
+    let store = open();
+    if let Err(err) = &store {
+        crate::warn!("{err}");
+    }
+    let sessions = store.as_deref().map(|store| LocalSessions::new(dir, store));
+    loop {
+        let job = relock(queue).recv();
+        let Ok((request, player, reply)) = job else {
+            return;
+        };
+        let response = match &sessions {
+            Ok(sessions) => {
+                let player = player.as_deref();
+                let sessions = sessions.by(player);
+                let sessions = Serialized::new(&sessions, &shared.locks);
+                let (mail, lobby) = (&shared.mail, &shared.lobby);
+                respond(request, player, &sessions, mail, lobby, &shared.webhooks)
+                    .unwrap_or_else(|err| Message::error(&err))
+            }
+            Err(err) => Message::error(err),
+        };
+        // A client that has gone cannot be told anything.
+        let _ = reply.send(response);
+    }
+}
+
+async fn accept(
+    listener: TcpListener,
+    carrier: Carrier,
+    (tokens, connections): (Arc<Tokens>, Arc<Connections>),
+    tls: Option<TlsServer>,
+    jobs: Sender<Job>,
+) -> Result<()> {
+    let listener = tokio::net::TcpListener::from_std(listener)?;
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        crate::verbose!("{peer} connected");
+        let (tokens, connections) = (tokens.clone(), connections.clone());
+        let (tls, jobs) = (tls.clone(), jobs.clone());
+        if carrier == Carrier::Tcp && tls.is_none() {
+            tokio::spawn(async move {
+                disconnected(peer, handle(stream, &tokens, &connections, jobs).await)
+            });
+            continue;
+        }
+        // TLS and other transports block, so their connections each take
+        // one of tokio's threads for blocking work.
+        let stream = stream.into_std()?;
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(connections.limits().idle())?;
+        tokio::task::spawn_blocking(move || {
+            let result = carrier
+                .accept(stream, tls.as_ref())
+                .and_then(|mut transport| {
+                    super::handle(&mut *transport, &tokens, &connections, |request, player| {
+                        submit(&jobs, request, player)?
+                            .blocking_recv()
+                            .map_err(unanswered)
+                    })
+                });
+            disconnected(peer, result);
+        });
+    }
+}
+
+/// Queues `request` from `player` for a worker, whose answer arrives on
+/// what is returned.
+fn submit(
+    jobs: &Sender<Job>,
+    request: Message,
+    player: Option<&str>,
+) -> Result<oneshot::Receiver<Message>> {
+    let (reply, answer) = oneshot::channel();
+    jobs.send((request, player.map(str::to_string), reply))
+        .map_err(|_| std::io::Error::other("server is shutting down"))?;
+    Ok(answer)
+}
+
+fn unanswered(_: oneshot::error::RecvError) -> Error {
+    std::io::Error::other("request went unanswered").into()
+}
+
+/// As the threaded `handle`, passing requests on to the workers.
+async fn handle(
+    stream: TcpStream,
+    tokens: &Tokens,
+    connections: &Connections,
+    jobs: Sender<Job>,
+) -> Result<()> {
+    let (reader, writer) = stream.into_split();
+    let (mut reader, mut writer) = (BufReader::new(reader), BufWriter::new(writer));
+    let mut buf = vec![];
+    let (max_len, idle) = (
+        connections.limits().max_frame_len(),
+        connections.limits().idle(),
+    );
+    let mut connection = Connection::new(tokens, connections);
+    loop {
+        let request = read(&mut reader, &mut buf, max_len);
+        let request = match idle {
+            Some(idle) => tokio::time::timeout(idle, request)
+                .await
+                .unwrap_or_else(|_| Err(std::io::Error::from(ErrorKind::TimedOut).into())),
+            None => request.await,
+        };
+        let response = match request {
+            Ok(Some(request)) => match connection.screen(request) {
+                ControlFlow::Break(response) => response,
+                ControlFlow::Continue((request, player)) => {
+                    submit(&jobs, request, player)?.await.map_err(unanswered)
+                }
+            },
+            Ok(None) => return Ok(()),
+            // Skipped whole, so the connection can carry on.
+            Err(err @ Error::MessageTooLarge(..)) => Err(err),
+            Err(err) => return Err(err),
+        };
+        connection.answered(&response);
+        let response = response.unwrap_or_else(|err| Message::error(&err));
+        write(&mut writer, &mut buf, &response).await?;
+    }
+}
+
+/// As `FramedReader::read`, refusing frames longer than `max_len`.
+async fn read<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    buf: &mut Vec<u8>,
+    max_len: u32,
+) -> Result<Option<Message>> {
+    let mut len = [0; 4];
+    match reader.read_exact(&mut len).await {
+        Ok(_) => {}
+        Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err.into()),
+    }
+    let len = frame_len(u32::from_be_bytes(len).into())?;
+    if len > max_len {
+        tokio::io::copy(&mut reader.take(len.into()), &mut tokio::io::sink()).await?;
+        return Err(Error::MessageTooLarge(len, max_len));
+    }
+    buf.clear();
+    reader.take(len.into()).read_to_end(buf).await?;
+    if buf.len() != len as usize {
+        return Err(Error::UnexpectedEof);
+    }
+    from_bytes(buf).map(Some)
+}
+
+/// As `FramedWriter::write`.
+async fn write<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    buf: &mut Vec<u8>,
+    message: &Message,
+) -> Result<()> {
+    message.serialize_into(buf);
+    let len = frame_len(buf.len() as u64)?;
+    writer.write_all(&len.to_be_bytes()).await?;
+    writer.write_all(buf).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::thread;
+
+    use super::serve_async;
+    use crate::actions::{Action, ActionKind};
+    use crate::client::Client;
+    use crate::protocol::Carrier;
+    use crate::provider::SessionProvider;
+    use crate::server::Limits;
+    use crate::session::Session;
+    use crate::signing::Keyring;
+    use crate::store::{FsStore, SessionStore};
+    use crate::temp_dir::TempDir;
+    use crate::tokens::Tokens;
+    use crate::Entity;
+
+    #[test]
+    fn clients_acting_on_one_session_take_turns() {
+        let dir = TempDir::new("async_server");
+        let mut session = Session::new("game".to_string()).unwrap();
+        session
+            .add_entity(Entity::new("troll".to_string()))
+            .unwrap();
+        session
+            .save(&FsStore::new(dir.to_path_buf(), &Keyring::default()))
+            .unwrap();
+        let events = session.log().len();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let served = dir.to_path_buf();
+        // Serves until the test process exits.
+        thread::spawn(move || {
+            let keyring = Keyring::default();
+            serve_async(
+                vec![(listener, Carrier::Tcp)],
+                &served,
+                &Tokens::default(),
+                None,
+                &[],
+                &Limits::default(),
+                || Ok(Box::new(FsStore::new(served.clone(), &keyring)) as Box<dyn SessionStore>),
+            )
+        });
+
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    let client = Client::connect(&addr, None, None).unwrap();
+                    for _ in 0..10 {
+                        let fight = Action::new(ActionKind::Fight, "troll".to_string()).unwrap();
+                        client.apply("game", fight, false).unwrap();
+                    }
+                });
+            }
+        });
+        let client = Client::connect(&addr, None, None).unwrap();
+        assert_eq!(client.load("game").unwrap().log().len(), events + 40);
+    }
+}
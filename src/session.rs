@@ -1,89 +1,1173 @@
-use std::fs::{File, OpenOptions};
-use std::io::{Read, Write};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Display;
+use std::sync::Arc;
+
+use uuid::Uuid;
 
 use crate::actions::{Action, ActionKind};
+use crate::ai;
+use crate::attributes::{self, AttributeValue};
+use crate::components::Position;
+use crate::conflict;
+use crate::delta::Delta;
+use crate::effects::Effect;
 use crate::error::{Error, Result};
-use crate::serde::{serialize, Deserialize, Field, FieldReader, Serialize};
+use crate::factions::{self, Stance};
+use crate::graveyard::{self, Grave};
+use crate::log::Event;
+use crate::mailbox::MAX_CHAT_LEN;
+use crate::map::Map;
+use crate::metadata::Metadata;
+use crate::outcome::ActionOutcome;
+use crate::players::Player;
+use crate::relations::{Relation, Relationship};
+use crate::resolve::resolve;
+use crate::resources::Resources;
+use crate::rng::Rng;
+use crate::rules::{self, Prerequisite, RuleSet, Rules, Turn};
+use crate::script;
+use crate::serde::{header_len, Deserialize, FieldReader, FieldWriter, Serialize, HEADER_LEN};
+use crate::store::SessionStore;
+use crate::timestamp::Timestamp;
+use crate::turns::TurnOrder;
+use crate::validate::Validator;
+use crate::victory::GameOver;
 use crate::Entity;
 
-const FILENAME: &str = "entity.lol";
-
+/// A game session, stored as the log of every change made to it. The
+/// entities and current action are not saved; they are rebuilt by replaying
+/// the log, which is also what makes `undo` and `at` possible.
+///
+/// The whole log is rewritten on save, so the file stays signed and is
+/// replaced atomically. It is preceded by the session's `Metadata`, which
+/// can be read on its own.
 #[derive(Debug, PartialEq)]
 pub struct Session {
-    action: Action,
-    entity: Entity,
+    name: String,
+    created: Timestamp,
+    modified: Timestamp,
+    /// Free-form labels for organizing sessions, sorted and unique. They are
+    /// metadata rather than game state, so changing them is not logged.
+    tags: Vec<String>,
+    log: Vec<Event>,
+    /// Undone events, most recently undone last.
+    redo: Vec<Event>,
+    action: Option<Action>,
+    entities: Vec<Entity>,
+    /// Actions queued since the last resolve, in order.
+    queue: Vec<Action>,
+    rules: Rules,
+    /// The name of the rule set the session plays by.
+    rule_set: String,
+    turn: Turn,
+    /// How the game ended, once a resolve has found it has.
+    over: Option<GameOver>,
+    order: TurnOrder,
+    /// Who plays in the session, in the order they joined.
+    roster: Vec<Player>,
+    /// Source of each scripted action kind, by name.
+    scripts: BTreeMap<String, String>,
+    /// What must hold before an action of each kind is taken.
+    requirements: BTreeMap<ActionKind, Vec<Prerequisite>>,
+    relationships: BTreeSet<Relationship>,
+    /// Bounds positions when set; without one they are unbounded.
+    map: Option<Map>,
+    factions: BTreeSet<String>,
+    /// How each pair of factions stands, by `factions::pair`. Pairs not
+    /// here are neutral.
+    stances: BTreeMap<(String, String), Stance>,
+    /// Entities that died in resolves, in the order they died.
+    graveyard: Vec<Grave>,
+    /// The player the session is a redacted copy for, if it is one.
+    redacted: Option<String>,
 }
 
 impl Session {
-    pub fn new(entity: Entity) -> Result<Self> {
-        let inst = Self {
-            entity,
-            action: Action::new(ActionKind::Fight, "Nobody".into())?,
+    /// A session with the given metadata and an empty log, for rebuilding one
+    /// event at a time.
+    pub(crate) fn from_parts(name: String, created: Timestamp, modified: Timestamp) -> Self {
+        Self {
+            name,
+            created,
+            modified,
+            tags: vec![],
+            log: vec![],
+            redo: vec![],
+            action: None,
+            entities: vec![],
+            queue: vec![],
+            rules: Rules::default(),
+            rule_set: rules::STANDARD.to_string(),
+            turn: Turn::default(),
+            over: None,
+            order: TurnOrder::default(),
+            roster: vec![],
+            scripts: BTreeMap::new(),
+            requirements: BTreeMap::new(),
+            relationships: BTreeSet::new(),
+            map: None,
+            factions: BTreeSet::new(),
+            stances: BTreeMap::new(),
+            graveyard: vec![],
+            redacted: None,
+        }
+    }
+
+    /// Creates an empty session; entities are added with `add_entity`.
+    pub fn new(name: String) -> Result<Self> {
+        validate_name(&name)?;
+        let now = Timestamp::now()?;
+        Ok(Self::from_parts(name, now, now))
+    }
+
+    /// Starts a new session from a template, which is any saved session: its
+    /// log is replayed under the new name with fresh timestamps.
+    pub fn from_template(name: String, template: &Session) -> Result<Self> {
+        let mut session = Self::new(name)?;
+        for event in &template.log {
+            session.record(event.clone())?;
+        }
+        Ok(session)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn entities(&self) -> &[Entity] {
+        &self.entities
+    }
+
+    pub fn entity(&self, name: &str) -> Option<&Entity> {
+        self.entities.iter().find(|entity| entity.name == name)
+    }
+
+    pub fn metadata(&self) -> Metadata {
+        Metadata {
+            name: self.name.clone(),
+            created: self.created,
+            modified: self.modified,
+            entities: self.entities.len() as u32,
+            tags: self.tags.clone(),
+        }
+    }
+
+    /// Reads a stored session's metadata without replaying its log.
+    pub fn load_metadata(store: &(impl SessionStore + ?Sized), name: &str) -> Result<Metadata> {
+        store.metadata(name)
+    }
+
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    /// Adds a tag, returning whether it was new. Tags are single words.
+    pub fn add_tag(&mut self, tag: String) -> Result<bool> {
+        if tag.is_empty() || tag.contains(char::is_whitespace) {
+            return Err(Error::InvalidTag(tag));
+        }
+        match self.tags.binary_search(&tag) {
+            Ok(_) => Ok(false),
+            Err(index) => {
+                self.tags.insert(index, tag);
+                Ok(true)
+            }
+        }
+    }
+
+    /// Removes a tag, returning whether it was there.
+    pub fn remove_tag(&mut self, tag: &str) -> bool {
+        match self.tags.binary_search_by(|probe| probe.as_str().cmp(tag)) {
+            Ok(index) => {
+                self.tags.remove(index);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// The most recent action, if any has been taken.
+    pub fn action(&self) -> Option<&Action> {
+        self.action.as_ref()
+    }
+
+    /// Every change made to the session, oldest first.
+    pub fn log(&self) -> &[Event] {
+        &self.log
+    }
+
+    /// The players of the session and any others who have acted or spoken
+    /// in it, sorted.
+    pub fn players(&self) -> Vec<&str> {
+        let mut players: Vec<_> = self
+            .log
+            .iter()
+            .filter_map(|event| match event {
+                Event::ActBy(player, _) | Event::Say(player, ..) => Some(player.as_str()),
+                _ => None,
+            })
+            .chain(self.roster.iter().map(|player| player.name.as_str()))
+            .collect();
+        players.sort_unstable();
+        players.dedup();
+        players
+    }
+
+    /// Adds an entity. Names are unique within a session.
+    pub fn add_entity(&mut self, entity: Entity) -> Result<()> {
+        self.record(Event::AddEntity(entity))?;
+        Ok(())
+    }
+
+    /// Adds a copy of `archetype` under a fresh id. Without a name it is
+    /// named after the archetype, numbered from 2 if that is taken. Returns
+    /// the name given.
+    pub fn spawn(&mut self, archetype: &Entity, name: Option<String>) -> Result<String> {
+        let name = name.unwrap_or_else(|| {
+            (1..)
+                .map(|n| match n {
+                    1 => archetype.name.clone(),
+                    n => format!("{}-{n}", archetype.name),
+                })
+                .find(|name| self.entity(name).is_none())
+                .unwrap_or_default()
+        });
+        self.add_entity(archetype.spawn(name.clone()))?;
+        Ok(name)
+    }
+
+    pub fn remove_entity(&mut self, name: &str) -> Result<Entity> {
+        let entity = self
+            .entity(name)
+            .cloned()
+            .ok_or_else(|| Error::EntityNotFound(name.to_string()))?;
+        self.record(Event::RemoveEntity(name.to_string()))?;
+        Ok(entity)
+    }
+
+    /// Performs `action` on its target entity, logs it and makes it the
+    /// current action. Entities may be named by id or unambiguous prefix.
+    /// Nothing changes if the action is not legal, if the game is over, or
+    /// if the session is played in turns, which only named players may act
+    /// in.
+    pub fn apply(&mut self, action: Action) -> Result<ActionOutcome> {
+        self.check_player(None)?;
+        let action = action.resolved(&self.entities)?;
+        Validator::new(self).check(&action)?;
+        self.record(Event::Act(action))
+    }
+
+    /// As `apply`, logging that `player` took the action, which must be in
+    /// their turn if the session is played in turns, and who must be one of
+    /// its players if it has any.
+    pub fn apply_by(&mut self, player: &str, action: Action) -> Result<ActionOutcome> {
+        self.check_player(Some(player))?;
+        let action = action.resolved(&self.entities)?;
+        Validator::new(self).check(&action)?;
+        self.record(Event::ActBy(player.to_string(), action))
+    }
+
+    /// Fails unless `player`, if anyone in particular, may act now.
+    fn check_player(&self, player: Option<&str>) -> Result<()> {
+        self.check_playing()?;
+        self.order.check(player)?;
+        match player {
+            _ if self.roster.is_empty() => Ok(()),
+            None => Err(Error::Unattributed(self.name.clone())),
+            Some(player) => self.player(player).map(|_| ()),
+        }
+    }
+
+    /// Logs that `player` said `text` to the other players.
+    pub fn say(&mut self, player: &str, text: &str) -> Result<()> {
+        if text.len() > MAX_CHAT_LEN {
+            return Err(Error::ChatTooLong(text.len()));
+        }
+        let said = Event::Say(player.to_string(), text.to_string(), Timestamp::now()?);
+        self.record(said)?;
+        Ok(())
+    }
+
+    /// Stores `action` to be applied by the next `resolve`, so that every
+    /// player's orders can be given before any take effect. Its target must
+    /// exist now; whether it is legal is only checked when it is resolved.
+    pub fn queue(&mut self, action: Action) -> Result<()> {
+        self.check_playing()?;
+        let action = action.resolved(&self.entities)?;
+        self.record(Event::Queue(action))?;
+        Ok(())
+    }
+
+    pub fn queued(&self) -> &[Action] {
+        &self.queue
+    }
+
+    /// Applies every queued action in the order queued, then ends the
+    /// turn, which ticks every entity's effects. Entities played by the
+    /// computer pick their actions first and queue them last. Of actions
+    /// claiming the same thing only one is applied, and actions no longer
+    /// legal by their turn are skipped; the outcome says why. Ties and
+    /// the computer's choices are drawn from a generator seeded by the time
+    /// of the resolve, which is logged, so replays come out the same.
+    pub fn resolve(&mut self) -> Result<ActionOutcome> {
+        self.check_playing()?;
+        self.record(Event::Resolve(Timestamp::now()?))
+    }
+
+    pub fn rules(&self) -> &Rules {
+        &self.rules
+    }
+
+    /// Whose turn it is, if the session is played in turns.
+    pub fn order(&self) -> &TurnOrder {
+        &self.order
+    }
+
+    /// Has `players` take turns in the session, in order, from the first;
+    /// none lets anyone act at any time again.
+    pub fn set_players(&mut self, players: Vec<String>) -> Result<()> {
+        self.record(Event::SetPlayers(players, Some(Timestamp::now()?)))?;
+        Ok(())
+    }
+
+    /// Who plays in the session, in the order they joined.
+    pub fn roster(&self) -> &[Player] {
+        &self.roster
+    }
+
+    pub fn player(&self, name: &str) -> Result<&Player> {
+        self.roster
+            .iter()
+            .find(|player| player.name == name)
+            .ok_or_else(|| Error::PlayerNotFound(name.to_string()))
+    }
+
+    /// Adds a player. Names are unique within a session; from the first
+    /// player on, every action must be taken by one.
+    pub fn add_player(&mut self, player: Player) -> Result<()> {
+        self.record(Event::AddPlayer(player))?;
+        Ok(())
+    }
+
+    /// Removes a player, leaving the entities they owned with no owner.
+    pub fn remove_player(&mut self, name: &str) -> Result<Player> {
+        let player = self.player(name)?.clone();
+        self.record(Event::RemovePlayer(name.to_string()))?;
+        Ok(player)
+    }
+
+    /// Gives the entity `name`, by id or unambiguous prefix, to the player
+    /// `owner`, or takes it from its owner with none.
+    pub fn own(&mut self, name: &str, owner: Option<String>) -> Result<()> {
+        let name = resolve(&self.entities, name)?.name.clone();
+        self.record(Event::Own(name, owner))?;
+        Ok(())
+    }
+
+    /// Ends `player`'s turn, returning whose turn it is now.
+    pub fn pass(&mut self, player: Option<&str>) -> Result<String> {
+        if self.order.current().is_none() {
+            return Err(Error::NoTurnOrder(self.name.clone()));
+        }
+        self.check_playing()?;
+        self.order.check(player)?;
+        let player = player.unwrap_or_default().to_string();
+        self.record(Event::Pass(player, Some(Timestamp::now()?)))?;
+        Ok(self.order.current().unwrap_or_default().to_string())
+    }
+
+    /// Limits each turn to `time` seconds, or none, the turn under way
+    /// counting from now. With `forfeit`, players who run out of time drop
+    /// out of the turn order; otherwise they only lose their turn.
+    pub fn set_turn_time(&mut self, time: Option<u32>, forfeit: bool) -> Result<()> {
+        self.record(Event::TurnTime(time, forfeit, Timestamp::now()?))?;
+        Ok(())
+    }
+
+    /// Ends the turn of the player to play if it was due to end by `now`,
+    /// returning who ran out of time.
+    pub fn time_out(&mut self, now: Timestamp) -> Result<Option<String>> {
+        if self.over.is_some() || self.order.deadline().is_none_or(|deadline| now < deadline) {
+            return Ok(None);
+        }
+        let player = self.order.current().unwrap_or_default().to_string();
+        self.record(Event::Timeout(player.clone(), now))?;
+        Ok(Some(player))
+    }
+
+    /// Replaces the rules from now on. Actions already taken this turn
+    /// still count against the new limits.
+    pub fn set_rules(&mut self, rules: Rules) -> Result<()> {
+        self.record(Event::SetRules(rules))?;
+        Ok(())
+    }
+
+    /// The rule set the session plays by.
+    pub fn rule_set(&self) -> Arc<dyn RuleSet> {
+        rules::rule_set(&self.rule_set).unwrap_or_else(|_| Arc::new(rules::Standard))
+    }
+
+    /// Plays the session by the rule set registered as `name` from now on.
+    pub fn play_by(&mut self, name: &str) -> Result<()> {
+        self.record(Event::PlayBy(name.to_string()))?;
+        Ok(())
+    }
+
+    /// How the game ended, if it has by its rule set's conditions.
+    pub fn over(&self) -> Option<&GameOver> {
+        self.over.as_ref()
+    }
+
+    /// Fails once the game is over.
+    fn check_playing(&self) -> Result<()> {
+        match &self.over {
+            Some(over) => Err(Error::GameOver(over.to_string())),
+            None => Ok(()),
+        }
+    }
+
+    /// The player this is a redacted copy of the session for, if it is one;
+    /// see `visibility::redact`.
+    pub fn redacted(&self) -> Option<&str> {
+        self.redacted.as_deref()
+    }
+
+    pub fn turn(&self) -> &Turn {
+        &self.turn
+    }
+
+    /// Defines or replaces the action kind `name`, carried out by the rhai
+    /// script `source`. Scripts are kept in the log so that everyone the
+    /// session is relayed to replays the same rules.
+    pub fn define_script(&mut self, name: String, source: String) -> Result<()> {
+        self.record(Event::DefineScript(name, source))?;
+        Ok(())
+    }
+
+    pub fn scripts(&self) -> &BTreeMap<String, String> {
+        &self.scripts
+    }
+
+    /// Makes actions of `kind` require every one of `prerequisites` from
+    /// now on, replacing what they required before. An empty list lifts
+    /// the requirements.
+    pub fn require(&mut self, kind: ActionKind, prerequisites: Vec<Prerequisite>) -> Result<()> {
+        self.record(Event::Require(kind, prerequisites))?;
+        Ok(())
+    }
+
+    pub fn requirements(&self) -> &BTreeMap<ActionKind, Vec<Prerequisite>> {
+        &self.requirements
+    }
+
+    /// Sets the attribute `key` of the entity named `name`, which may be
+    /// given by id or unambiguous prefix.
+    pub fn set_attribute(&mut self, name: &str, key: String, value: AttributeValue) -> Result<()> {
+        attributes::validate_name(&key)?;
+        let name = resolve(&self.entities, name)?.name.clone();
+        self.record(Event::SetAttribute(name, key, value))?;
+        Ok(())
+    }
+
+    /// Removes the attribute `key` of the entity named `name`.
+    pub fn unset_attribute(&mut self, name: &str, key: String) -> Result<()> {
+        let name = resolve(&self.entities, name)?.name.clone();
+        self.record(Event::UnsetAttribute(name, key))?;
+        Ok(())
+    }
+
+    pub fn graveyard(&self) -> &[Grave] {
+        &self.graveyard
+    }
+
+    /// Moves the entities among `living` that have no hp left to the
+    /// graveyard. The dead cannot be targeted, and their relationships end.
+    fn bury(&mut self, living: &BTreeSet<Uuid>, outcome: &mut ActionOutcome) {
+        for entity in graveyard::bury(&mut self.entities, living, outcome) {
+            self.relationships
+                .retain(|relationship| !relationship.involves(entity.id));
+            self.graveyard.push(Grave {
+                entity,
+                turn: self.turn.number,
+            });
+        }
+    }
+
+    pub fn map(&self) -> Option<&Map> {
+        self.map.as_ref()
+    }
+
+    /// Bounds positions by `map` from now on. Every entity already on the
+    /// map must be within it.
+    pub fn set_map(&mut self, map: Map) -> Result<()> {
+        self.record(Event::SetMap(map))?;
+        Ok(())
+    }
+
+    /// Puts the entity named `name`, which may be given by id or unambiguous
+    /// prefix, at `position` on the map.
+    pub fn place(&mut self, name: &str, position: Position) -> Result<()> {
+        let name = resolve(&self.entities, name)?.name.clone();
+        self.record(Event::Place(name, position))?;
+        Ok(())
+    }
+
+    /// Puts the entity named `name`, which may be given by id or unambiguous
+    /// prefix, under `effect`. It stacks with any effects it is already under.
+    pub fn afflict(&mut self, name: &str, effect: Effect) -> Result<()> {
+        let name = resolve(&self.entities, name)?.name.clone();
+        self.record(Event::Afflict(name, effect))?;
+        Ok(())
+    }
+
+    /// Relates the entity named `from` to the one named `to`; either may be
+    /// given by id or unambiguous prefix.
+    pub fn relate(&mut self, from: &str, relation: Relation, to: &str) -> Result<()> {
+        let relationship = self.relationship(from, relation, to)?;
+        self.record(Event::Relate(relationship))?;
+        Ok(())
+    }
+
+    pub fn unrelate(&mut self, from: &str, relation: Relation, to: &str) -> Result<()> {
+        let relationship = self.relationship(from, relation, to)?;
+        self.record(Event::Unrelate(relationship))?;
+        Ok(())
+    }
+
+    fn relationship(&self, from: &str, relation: Relation, to: &str) -> Result<Relationship> {
+        let from = resolve(&self.entities, from)?.id;
+        let to = resolve(&self.entities, to)?.id;
+        Ok(Relationship::new(from, relation, to))
+    }
+
+    pub fn relationships(&self) -> &BTreeSet<Relationship> {
+        &self.relationships
+    }
+
+    /// Every relationship the entity with id `id` has, either way round.
+    pub fn relationships_of(&self, id: Uuid) -> impl Iterator<Item = &Relationship> {
+        self.relationships
+            .iter()
+            .filter(move |relationship| relationship.involves(id))
+    }
+
+    /// Whether `from` stands in `relation` to `to`.
+    pub fn related(&self, from: &Entity, relation: Relation, to: &Entity) -> bool {
+        self.relationships
+            .contains(&Relationship::new(from.id, relation, to.id))
+    }
+
+    /// Whether `a` and `b` are enemies or belong to hostile factions.
+    pub fn hostile(&self, a: &Entity, b: &Entity) -> bool {
+        self.related(a, Relation::Enemy, b) || self.allegiance(a, b) == Some(Stance::Hostile)
+    }
+
+    pub fn define_faction(&mut self, name: String) -> Result<()> {
+        self.record(Event::DefineFaction(name))?;
+        Ok(())
+    }
+
+    pub fn factions(&self) -> &BTreeSet<String> {
+        &self.factions
+    }
+
+    /// Sets how factions `a` and `b` stand towards each other, either way
+    /// round.
+    pub fn set_stance(&mut self, a: &str, b: &str, stance: Stance) -> Result<()> {
+        self.record(Event::SetStance(a.to_string(), b.to_string(), stance))?;
+        Ok(())
+    }
+
+    pub fn stance(&self, a: &str, b: &str) -> Stance {
+        match a == b {
+            true => Stance::Allied,
+            false => self
+                .stances
+                .get(&factions::pair(a, b))
+                .copied()
+                .unwrap_or_default(),
+        }
+    }
+
+    /// How the factions of `a` and `b` stand, if both belong to one.
+    pub fn allegiance(&self, a: &Entity, b: &Entity) -> Option<Stance> {
+        Some(self.stance(a.faction.as_ref()?, b.faction.as_ref()?))
+    }
+
+    /// Moves the entity named `name`, which may be given by id or
+    /// unambiguous prefix, into `faction`, or out of its faction with none.
+    pub fn join_faction(&mut self, name: &str, faction: Option<String>) -> Result<()> {
+        let name = resolve(&self.entities, name)?.name.clone();
+        self.record(Event::JoinFaction(name, faction))?;
+        Ok(())
+    }
+
+    /// Adds `resources` to the pool of the entity named `name`, which may
+    /// be given by id or unambiguous prefix.
+    pub fn grant(&mut self, name: &str, resources: Resources) -> Result<ActionOutcome> {
+        let name = resolve(&self.entities, name)?.name.clone();
+        self.record(Event::Grant(name, resources))
+    }
+
+    /// Applies `event` and appends it to the log. A new change abandons
+    /// whatever could have been redone.
+    pub(crate) fn record(&mut self, event: Event) -> Result<ActionOutcome> {
+        let outcome = self.apply_event(&event)?;
+        self.log.push(event);
+        self.redo.clear();
+        Ok(outcome)
+    }
+
+    /// Updates the entities, current action and queue for one event, leaving them
+    /// untouched if the event does not fit the current state.
+    fn apply_event(&mut self, event: &Event) -> Result<ActionOutcome> {
+        match event {
+            Event::AddEntity(entity) => {
+                if self.entity(&entity.name).is_some() {
+                    return Err(Error::EntityExists(entity.name.clone()));
+                }
+                self.entities.push(entity.clone());
+            }
+            Event::RemoveEntity(name) => {
+                let index = self
+                    .entities
+                    .iter()
+                    .position(|entity| entity.name == *name)
+                    .ok_or_else(|| Error::EntityNotFound(name.to_string()))?;
+                let id = self.entities.remove(index).id;
+                self.relationships
+                    .retain(|relationship| !relationship.involves(id));
+            }
+            Event::Act(action) | Event::ActBy(_, action) => {
+                let outcome = self.act(action)?;
+                self.turn.take(action.kind());
+                self.action = Some(action.clone());
+                return Ok(outcome);
+            }
+            Event::Queue(action) => self.queue.push(action.clone()),
+            Event::Resolve(at) => {
+                let mut outcome = ActionOutcome::default();
+                let living = self.living();
+                let mut rng = Rng::new(at.as_millis() as u64);
+                let npcs = ai::plan(self, &mut rng, *at, &mut outcome);
+                let mut queue = std::mem::take(&mut self.queue);
+                queue.extend(npcs);
+                for action in conflict::settle(queue, &self.entities, &mut rng, &mut outcome) {
+                    let step = Validator::new(self)
+                        .check(&action)
+                        .and_then(|()| self.act(&action));
+                    match step {
+                        Ok(step) => {
+                            outcome.extend(step);
+                            self.turn.take(action.kind());
+                            self.action = Some(action);
+                            self.bury(&living, &mut outcome);
+                        }
+                        Err(err) => outcome.message(format!(
+                            "skipped {} {}: {err}",
+                            action.kind(),
+                            action.target()
+                        )),
+                    }
+                }
+                for entity in &mut self.entities {
+                    outcome.extend(entity.tick_effects());
+                }
+                self.bury(&living, &mut outcome);
+                self.turn.end();
+                let victory = self.rule_set().victory();
+                self.over = victory.iter().find_map(|victory| victory.check(self));
+                match &self.over {
+                    Some(over) => outcome.message(over.to_string()),
+                    None => outcome.message(format!("turn {} begins", self.turn.number)),
+                }
+                return Ok(outcome);
+            }
+            Event::SetRules(rules) => self.rules = rules.clone(),
+            Event::PlayBy(name) => {
+                rules::rule_set(name)?;
+                self.rule_set = name.clone();
+            }
+            Event::Require(kind, prerequisites) if prerequisites.is_empty() => {
+                self.requirements.remove(kind);
+            }
+            Event::Require(kind, prerequisites) => {
+                self.requirements.insert(*kind, prerequisites.clone());
+            }
+            Event::Grant(name, resources) => {
+                let entity = self.entity_mut(name)?;
+                let pool = entity
+                    .resources
+                    .checked_add(resources)
+                    .ok_or_else(|| Error::IllegalAction(format!("{name} cannot hold any more")))?;
+                let mut outcome = ActionOutcome::default();
+                outcome.change(name, "resources", entity.resources, pool);
+                entity.resources = pool;
+                return Ok(outcome);
+            }
+            Event::SetAttribute(name, key, value) => {
+                attributes::validate_name(key)?;
+                let entity = self.entity_mut(name)?;
+                let before = entity.attributes.insert(key.clone(), value.clone());
+                let mut outcome = ActionOutcome::default();
+                let show = |value: Option<&AttributeValue>| match value {
+                    Some(value) => value.to_string(),
+                    None => "unset".to_string(),
+                };
+                outcome.change(name, key, show(before.as_ref()), show(Some(value)));
+                return Ok(outcome);
+            }
+            Event::UnsetAttribute(name, key) => {
+                self.entity_mut(name)?
+                    .attributes
+                    .remove(key)
+                    .ok_or_else(|| Error::AttributeNotFound(name.clone(), key.clone()))?;
+            }
+            Event::DefineFaction(name) => {
+                factions::validate_name(name)?;
+                if !self.factions.insert(name.clone()) {
+                    return Err(Error::IllegalAction(format!(
+                        "faction {name:?} already exists"
+                    )));
+                }
+            }
+            Event::SetStance(a, b, stance) => {
+                for faction in [a, b] {
+                    if !self.factions.contains(faction) {
+                        return Err(Error::FactionNotFound(faction.clone()));
+                    }
+                }
+                if a == b {
+                    return Err(Error::IllegalAction(format!(
+                        "{a} is always allied with itself"
+                    )));
+                }
+                match stance {
+                    Stance::Neutral => self.stances.remove(&factions::pair(a, b)),
+                    stance => self.stances.insert(factions::pair(a, b), *stance),
+                };
+            }
+            Event::JoinFaction(name, faction) => {
+                if let Some(faction) = faction.as_ref().filter(|f| !self.factions.contains(*f)) {
+                    return Err(Error::FactionNotFound(faction.clone()));
+                }
+                self.entity_mut(name)?.faction = faction.clone();
+            }
+            Event::Say(..) => {}
+            Event::SetPlayers(players, at) => {
+                if !self.roster.is_empty() {
+                    for player in players {
+                        self.player(player)?;
+                    }
+                }
+                self.order = self.order.restart(players.clone(), *at);
+            }
+            Event::AddPlayer(player) => {
+                player.validate()?;
+                if self.player(&player.name).is_ok() {
+                    return Err(Error::PlayerExists(player.name.clone()));
+                }
+                self.roster.push(player.clone());
+            }
+            Event::RemovePlayer(name) => {
+                self.player(name)?;
+                if self.order.players().contains(name) {
+                    return Err(Error::IllegalAction(format!(
+                        "{name} takes turns; set the players without them first"
+                    )));
+                }
+                self.roster.retain(|player| player.name != *name);
+                for entity in &mut self.entities {
+                    if entity.owner.as_ref() == Some(name) {
+                        entity.owner = None;
+                    }
+                }
+            }
+            Event::Own(name, owner) => {
+                if let Some(owner) = owner {
+                    self.player(owner)?;
+                }
+                self.entity_mut(name)?.owner = owner.clone();
+            }
+            Event::Pass(player, at) => self.order.pass(player, *at)?,
+            Event::TurnTime(time, forfeit, at) => {
+                if *time == Some(0) {
+                    return Err(Error::IllegalAction(
+                        "turns cannot last no time".to_string(),
+                    ));
+                }
+                self.order.set_time(*time, *forfeit, *at);
+            }
+            Event::Timeout(player, at) => self.order.time_out(player, *at)?,
+            Event::Redacted(player, turn, order) => {
+                self.turn.number = *turn;
+                self.order.renumber(*order);
+                self.redacted = Some(player.clone());
+            }
+            Event::SetMap(map) => {
+                if map.width == 0 || map.height == 0 {
+                    return Err(Error::IllegalAction(format!("a {map} map has no squares")));
+                }
+                let outside = self.entities.iter().find(|entity| {
+                    entity
+                        .position
+                        .is_some_and(|position| !map.contains(position))
+                });
+                if let Some(entity) = outside {
+                    return Err(Error::IllegalAction(format!(
+                        "{} would be off the {map} map",
+                        entity.name
+                    )));
+                }
+                self.map = Some(*map);
+            }
+            Event::Place(name, position) => {
+                if let Some(map) = self.map.filter(|map| !map.contains(*position)) {
+                    return Err(Error::IllegalAction(format!(
+                        "{position} is off the {map} map"
+                    )));
+                }
+                let entity = self.entity_mut(name)?;
+                let before = entity.position.replace(*position);
+                let mut outcome = ActionOutcome::default();
+                if let Some(before) = before {
+                    outcome.change(name, "position", before, *position);
+                }
+                return Ok(outcome);
+            }
+            Event::Afflict(name, effect) => {
+                if effect.turns == 0 {
+                    return Err(Error::IllegalAction(format!(
+                        "{} must last at least one turn",
+                        effect.kind
+                    )));
+                }
+                self.entity_mut(name)?.effects.push(*effect);
+            }
+            Event::Relate(relationship) => {
+                let name = |id: Uuid| {
+                    self.entities
+                        .iter()
+                        .find(|entity| entity.id == id)
+                        .map(|entity| entity.name.clone())
+                        .ok_or_else(|| Error::EntityNotFound(id.to_string()))
+                };
+                let from = name(relationship.from)?;
+                name(relationship.to)?;
+                if relationship.from == relationship.to {
+                    return Err(Error::IllegalAction(format!(
+                        "{from} cannot be related to itself"
+                    )));
+                }
+                if !self.relationships.insert(*relationship) {
+                    return Err(Error::IllegalAction(format!(
+                        "{} already",
+                        relationship.describe(&self.entities)
+                    )));
+                }
+            }
+            Event::Unrelate(relationship) => {
+                if !self.relationships.remove(relationship) {
+                    return Err(Error::IllegalAction(format!(
+                        "no relationship {:?}",
+                        relationship.describe(&self.entities)
+                    )));
+                }
+            }
+            Event::DefineScript(name, source) => {
+                script::validate_name(name)?;
+                self.scripts.insert(name.clone(), source.clone());
+            }
+        }
+        Ok(ActionOutcome::default())
+    }
+
+    fn entity_mut(&mut self, name: &str) -> Result<&mut Entity> {
+        self.entities
+            .iter_mut()
+            .find(|entity| entity.name == name)
+            .ok_or_else(|| Error::EntityNotFound(name.to_string()))
+    }
+
+    /// Carries out `action`, paying its cost first so that one its target
+    /// cannot afford changes nothing. Should it fail, the cost is refunded.
+    fn act(&mut self, action: &Action) -> Result<ActionOutcome> {
+        let funds = self.entity(action.target()).map(|target| target.resources);
+        let paid = self.pay(action)?;
+        let mut outcome = match action.exec(&mut self.entities, &self.scripts) {
+            Ok(outcome) => outcome,
+            Err(err) => {
+                if let (Some(funds), Ok(target)) = (funds, self.entity_mut(action.target())) {
+                    target.resources = funds;
+                }
+                return Err(err);
+            }
         };
-        Ok(inst)
+        outcome.extend(paid);
+        outcome.extend(self.reward(action)?);
+        Ok(outcome)
+    }
+
+    /// Takes what an action of its kind costs from the target's pool. The
+    /// `Validator` has already checked it can be paid.
+    fn pay(&mut self, action: &Action) -> Result<ActionOutcome> {
+        let mut outcome = ActionOutcome::default();
+        let Some(cost) = self.rule_set().cost(action, self) else {
+            return Ok(outcome);
+        };
+        let entity = self
+            .entities
+            .iter_mut()
+            .find(|entity| entity.name == action.target())
+            .ok_or_else(|| Error::EntityNotFound(action.target().to_string()))?;
+        let pool = entity.resources.checked_sub(&cost).ok_or_else(|| {
+            Error::IllegalAction(format!("{} cannot afford {}", entity.name, action.kind()))
+        })?;
+        outcome.change(&entity.name, "resources", entity.resources, pool);
+        entity.resources = pool;
+        Ok(outcome)
+    }
+
+    /// Gives the target the xp its action earns under the rules, leveling
+    /// it up if that is enough.
+    fn reward(&mut self, action: &Action) -> Result<ActionOutcome> {
+        let Some(&xp) = self.rules.xp.get(&action.kind()) else {
+            return Ok(ActionOutcome::default());
+        };
+        let leveling = self.rules.leveling;
+        Ok(self.entity_mut(action.target())?.earn(xp, &leveling))
+    }
+
+    /// Rebuilds everything but the metadata from the log.
+    fn replay(&mut self) -> Result<()> {
+        self.action = None;
+        self.entities.clear();
+        self.queue.clear();
+        self.rules = Rules::default();
+        self.rule_set = rules::STANDARD.to_string();
+        self.turn = Turn::default();
+        self.over = None;
+        self.order = TurnOrder::default();
+        self.roster.clear();
+        self.scripts.clear();
+        self.requirements.clear();
+        self.relationships.clear();
+        self.map = None;
+        self.factions.clear();
+        self.stances.clear();
+        self.graveyard.clear();
+        for event in std::mem::take(&mut self.log) {
+            self.apply_event(&event)?;
+            self.log.push(event);
+        }
+        Ok(())
+    }
+
+    /// What changed since a saved copy of this session whose log was
+    /// `saved`, for a journal on top of the snapshot taken at `snapshot`.
+    pub(crate) fn delta(&self, saved: &[Event], snapshot: Timestamp) -> Delta {
+        let base = saved
+            .iter()
+            .zip(&self.log)
+            .take_while(|(saved, event)| saved == event)
+            .count();
+        Delta {
+            snapshot,
+            modified: self.modified,
+            base: base as u32,
+            events: self.log[base..].to_vec(),
+            redo: self.redo.clone(),
+            tags: self.tags.clone(),
+        }
+    }
+
+    pub(crate) fn apply_delta(&mut self, delta: Delta) -> Result<()> {
+        let base = delta.base as usize;
+        if base > self.log.len() {
+            return Err(Error::EventNotFound(base));
+        }
+        if !delta.tags.windows(2).all(|pair| pair[0] < pair[1]) {
+            return Err(Error::NonCanonical);
+        }
+        if base < self.log.len() {
+            self.log.truncate(base);
+            self.replay()?;
+        }
+        for event in delta.events {
+            self.apply_event(&event)?;
+            self.log.push(event);
+        }
+        self.redo = delta.redo;
+        self.tags = delta.tags;
+        self.modified = delta.modified;
+        Ok(())
+    }
+
+    /// The session as it was after the first `n` events, with no redo
+    /// history. Event 0 is the empty session.
+    pub fn at(&self, n: usize) -> Result<Self> {
+        if n > self.log.len() {
+            return Err(Error::EventNotFound(n));
+        }
+        let mut session = Self::from_parts(self.name.clone(), self.created, self.modified);
+        session.tags = self.tags.clone();
+        session.log = self.log[..n].to_vec();
+        session.replay()?;
+        Ok(session)
+    }
+
+    /// Reverts the most recent change.
+    pub fn undo(&mut self) -> Result<()> {
+        let event = self.log.pop().ok_or(Error::NothingToUndo)?;
+        self.redo.push(event);
+        self.replay()
+    }
+
+    /// Reapplies the most recently undone change.
+    pub fn redo(&mut self) -> Result<()> {
+        let event = self.redo.last().ok_or(Error::NothingToRedo)?.clone();
+        self.apply_event(&event)?;
+        self.redo.pop();
+        self.log.push(event);
+        Ok(())
+    }
+
+    /// Three-way merge for two copies of `base` that were played separately:
+    /// the events `theirs` added since `base` are replayed on top of this
+    /// session. Events both sides added are kept once; events that no longer
+    /// apply are skipped and returned as conflicts.
+    pub fn merge(&mut self, base: &Session, theirs: &Session) -> Result<Vec<Conflict>> {
+        for side in [&*self, theirs] {
+            if !side.log.starts_with(&base.log) {
+                return Err(Error::Diverged(side.name.clone()));
+            }
+        }
+        let mine = self.log[base.log.len()..].to_vec();
+        let mut conflicts = vec![];
+        for event in &theirs.log[base.log.len()..] {
+            if mine.contains(event) {
+                continue;
+            }
+            if let Err(reason) = self.record(event.clone()) {
+                conflicts.push(Conflict {
+                    event: event.clone(),
+                    reason,
+                });
+            }
+        }
+        Ok(conflicts)
+    }
+
+    pub fn created(&self) -> Timestamp {
+        self.created
+    }
+
+    pub fn modified(&self) -> Timestamp {
+        self.modified
     }
 }
 
-fn entity_file() -> Result<File> {
-    let file = OpenOptions::new()
-        .create(true)
-        .read(true)
-        .write(true)
-        .open(FILENAME)?;
-    Ok(file)
+/// Session names become file names, so they must not be able to point
+/// anywhere else.
+pub(crate) fn validate_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty() && !name.starts_with('.') && !name.contains(['/', '\\', '\0']);
+    if !valid {
+        return Err(Error::InvalidSessionName(name.to_string()));
+    }
+    Ok(())
 }
 
 impl Session {
-    pub fn load() -> Result<Self> {
-        let mut file = entity_file()?;
-        let mut bytes = vec![];
-        file.read_to_end(&mut bytes)?;
-        if bytes.is_empty() {
-            eprintln!("No entity found");
-            return Err(Error::NoEntity);
-        }
-        let mut reader = FieldReader::new(bytes.as_slice());
-        Self::deserialize(&mut reader)
+    /// Stamps the modification time and hands the session to `store`.
+    pub fn save(&mut self, store: &(impl SessionStore + ?Sized)) -> Result<()> {
+        self.touch()?;
+        store.save(self)
     }
 
-    pub fn save(&self) -> Result<()> {
-        let mut file = entity_file()?;
-        let bytes = self.serialize();
-        file.write_all(&bytes)?;
+    /// Copies a session under a new name. The copy is a new session, so it
+    /// gets fresh timestamps and none of the source's history.
+    pub fn copy(store: &(impl SessionStore + ?Sized), src: &str, dst: &str) -> Result<()> {
+        store.copy(src, dst)
+    }
+
+    /// This session as the start of a copy named `name`.
+    pub(crate) fn copy_as(mut self, name: &str) -> Result<Self> {
+        self.set_name(name.to_string())?;
+        self.created = Timestamp::now()?;
+        Ok(self)
+    }
+
+    pub(crate) fn touch(&mut self) -> Result<()> {
+        self.modified = Timestamp::now()?;
+        Ok(())
+    }
+
+    pub(crate) fn set_name(&mut self, name: String) -> Result<()> {
+        validate_name(&name)?;
+        self.name = name;
         Ok(())
     }
 }
 
+/// An event from the other side of a merge that could not be replayed.
+#[derive(Debug)]
+pub struct Conflict {
+    pub event: Event,
+    pub reason: Error,
+}
+
+impl Display for Conflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.event, self.reason)
+    }
+}
+
 impl Deserialize for Session {
     fn deserialize(reader: &mut FieldReader<'_>) -> Result<Self>
     where
         Self: Sized,
     {
-        eprintln!("Deserializing");
-        let entity = reader.read_field()?;
-        let action = reader.read_field()?;
-        eprintln!("Action: {:?}", action);
-
-        let entity = Self { action, entity };
+        let metadata = Metadata::read(reader)?;
+        crate::trace!("replaying session {:?}", metadata.name);
+        let mut session = Self::from_parts(metadata.name, metadata.created, metadata.modified);
+        session.tags = metadata.tags;
+        session.log = reader.read_field()?;
+        session.redo = reader.read_field()?;
+        session.replay()?;
+        crate::trace!("current action {:?}", session.action);
+        if session.entities.len() != metadata.entities as usize {
+            return Err(Error::NonCanonical);
+        }
 
-        Ok(entity)
+        Ok(session)
     }
 }
 
 impl Serialize for Session {
-    fn serialize(&self) -> Vec<u8> {
-        let mut bytes = vec![];
-        serialize(&mut bytes, Field::Entity(self.entity.clone()));
-        serialize(&mut bytes, Field::Action(self.action.clone()));
-        bytes
+    fn serialize_to(&self, writer: &mut FieldWriter<'_>) {
+        writer.write_list(&self.metadata());
+        writer.write(&self.log);
+        writer.write(&self.redo);
+    }
+
+    fn size_hint(&self) -> usize {
+        let events = |events: &[Event]| {
+            let len = events.iter().map(Event::field_len).sum::<usize>();
+            header_len(len) + len
+        };
+        HEADER_LEN + self.metadata().size_hint() + events(&self.log) + events(&self.redo)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::{
-        actions::{Action, ActionKind},
+        actions::{Action, ActionKind, Param},
+        attributes::AttributeValue,
+        error::Error,
+        resources::Resources,
         serde::{Deserialize, FieldReader, Serialize},
+        timestamp::Timestamp,
         Entity,
     };
 
@@ -96,14 +1180,22 @@ mod tests {
 
     #[test]
     fn session_round_trip() {
-        let session = Session {
-            action: Action::new(ActionKind::Fight, "Gilgamesh".to_string()).unwrap(),
-            entity: crate::Entity {
-                name: "florp".to_string(),
-                field_b: 69,
-                field_c: true,
-            },
-        };
+        let mut session = Session::new("florp".to_string()).unwrap();
+        session.created = Timestamp::from_millis(1);
+        session.modified = Timestamp::from_millis(2);
+        let mut florp = Entity::new("florp".to_string());
+        florp.id = uuid::Uuid::from_u128(7);
+        florp.stats.hp = 69;
+        florp.guarding = true;
+        session.add_entity(florp).unwrap();
+        session
+            .add_entity(Entity::new("glorp".to_string()))
+            .unwrap();
+        session
+            .apply(Action::new(ActionKind::Fight, "glorp".to_string()).unwrap())
+            .unwrap();
+        session.remove_entity("florp").unwrap();
+        session.undo().unwrap();
 
         let serialized = session.serialize();
         let actual = deserialize::<Session>(&serialized).unwrap();
@@ -120,17 +1212,341 @@ mod tests {
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn action_params_round_trip() {
+        let params = [("dx", "1"), ("dy", "-2"), ("item", "rope")]
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), Param::parse(value)))
+            .collect();
+        let expected = Action::new(ActionKind::Neutral, "goblin".to_string())
+            .unwrap()
+            .with_params(params);
+        assert_eq!(expected.params()["dy"], Param::Int(-2));
+        assert_eq!(expected.params()["item"], Param::Text("rope".to_string()));
+
+        let serialized = expected.serialize();
+        assert_eq!(expected.size_hint(), serialized.len());
+        assert_eq!(deserialize::<Action>(&serialized).unwrap(), expected);
+    }
+
     #[test]
     fn entity_round_trip() {
-        let expected = Entity {
-            name: "florp".to_string(),
-            field_b: 69,
-            field_c: true,
+        let mut expected = Entity::new("florp".to_string());
+        expected.id = uuid::Uuid::from_u128(7);
+        expected.stats = crate::Stats {
+            hp: 69,
+            attack: 4,
+            defense: 2,
+            speed: 9,
         };
+        expected.guarding = true;
+        expected.resources = Resources {
+            action_points: 3,
+            mana: 70_000,
+            gold: 5_000_000_000,
+        };
+        expected.inventory = vec![crate::inventory::Item::new("rope".to_string(), 2)];
+        expected.attributes = [
+            ("title".to_string(), AttributeValue::parse("Baron")),
+            ("renown".to_string(), AttributeValue::parse("1.5")),
+        ]
+        .into();
+        expected.position = Some(crate::components::Position { x: -3, y: 8 });
+        expected.ai = Some(crate::components::Ai {
+            behavior: crate::components::Behavior::Wander,
+        });
         let serialized = expected.serialize();
+        assert_eq!(expected.size_hint(), serialized.len());
         eprintln!("BYTES: {serialized:?}");
         let actual = deserialize::<Entity>(&serialized).unwrap();
 
         assert_eq!(actual, expected);
     }
+
+    #[test]
+    fn size_hint_is_exact() {
+        let mut session = Session::new("florp".to_string()).unwrap();
+        assert_eq!(session.size_hint(), session.serialize().len());
+        session.add_entity(Entity::new("a".to_string())).unwrap();
+        session.add_entity(Entity::new("bb".to_string())).unwrap();
+        session
+            .apply(Action::new(ActionKind::Neutral, "a".to_string()).unwrap())
+            .unwrap();
+        session.undo().unwrap();
+        session.add_tag("pvp".to_string()).unwrap();
+        assert_eq!(session.size_hint(), session.serialize().len());
+    }
+
+    #[test]
+    fn serialize_into_reuses_buffer() {
+        let first = Entity::new("a much longer entity name".to_string());
+        let second = Entity::new("short".to_string());
+
+        let mut buf = Vec::new();
+        first.serialize_into(&mut buf);
+        let capacity = buf.capacity();
+        second.serialize_into(&mut buf);
+
+        assert_eq!(buf, second.serialize());
+        assert_eq!(buf.capacity(), capacity);
+    }
+
+    #[test]
+    fn session_names_cannot_escape_the_directory() {
+        for name in ["", ".hidden", "../up", "a/b", "a\\b"] {
+            assert!(Session::new(name.to_string()).is_err(), "{name:?}");
+        }
+    }
+
+    #[test]
+    fn entity_names_are_unique() {
+        let mut session = Session::new("game".to_string()).unwrap();
+        session
+            .add_entity(Entity::new("goblin".to_string()))
+            .unwrap();
+        assert!(session
+            .add_entity(Entity::new("goblin".to_string()))
+            .is_err());
+
+        session.remove_entity("goblin").unwrap();
+        assert!(session.entities().is_empty());
+        assert!(session.remove_entity("goblin").is_err());
+    }
+
+    #[test]
+    fn undo_and_redo_walk_the_log() {
+        let mut session = Session::new("game".to_string()).unwrap();
+        session.add_entity(Entity::new("hero".to_string())).unwrap();
+        let love = Action::new(ActionKind::Love, "hero".to_string()).unwrap();
+        session.apply(love.clone()).unwrap();
+
+        session.undo().unwrap();
+        assert_eq!(session.action(), None);
+        session.undo().unwrap();
+        assert!(session.entities().is_empty());
+        assert!(matches!(session.undo(), Err(Error::NothingToUndo)));
+
+        session.redo().unwrap();
+        session.redo().unwrap();
+        assert_eq!(session.action(), Some(&love));
+        assert!(matches!(session.redo(), Err(Error::NothingToRedo)));
+
+        session.undo().unwrap();
+        session
+            .add_entity(Entity::new("villain".to_string()))
+            .unwrap();
+        assert!(matches!(session.redo(), Err(Error::NothingToRedo)));
+    }
+
+    #[test]
+    fn state_can_be_replayed_to_any_event() {
+        let mut session = Session::new("game".to_string()).unwrap();
+        session.add_entity(Entity::new("hero".to_string())).unwrap();
+        session
+            .add_entity(Entity::new("villain".to_string()))
+            .unwrap();
+        session.remove_entity("hero").unwrap();
+
+        assert!(session.at(0).unwrap().entities().is_empty());
+        let names = |session: &Session| -> Vec<String> {
+            session.entities().iter().map(|e| e.name.clone()).collect()
+        };
+        assert_eq!(names(&session.at(2).unwrap()), ["hero", "villain"]);
+        assert_eq!(names(&session.at(3).unwrap()), ["villain"]);
+        assert!(matches!(session.at(4), Err(Error::EventNotFound(4))));
+    }
+
+    #[test]
+    fn actions_need_an_existing_target() {
+        let mut session = Session::new("game".to_string()).unwrap();
+        let action = Action::new(ActionKind::Fight, "ghost".to_string()).unwrap();
+        assert!(matches!(
+            session.apply(action),
+            Err(Error::EntityNotFound(_))
+        ));
+        assert!(session.log().is_empty());
+    }
+
+    #[test]
+    fn actions_change_their_target_and_replay() {
+        let mut session = Session::new("game".to_string()).unwrap();
+        session
+            .add_entity(Entity::new("troll".to_string()))
+            .unwrap();
+        for _ in 0..2 {
+            let fight = Action::new(ActionKind::Fight, "troll".to_string()).unwrap();
+            session.apply(fight).unwrap();
+        }
+        let love = Action::new(ActionKind::Love, "troll".to_string()).unwrap();
+        let outcome = session.apply(love).unwrap();
+        assert_eq!(outcome.messages, ["loved troll"]);
+        assert_eq!(
+            outcome.changes[0].to_string(),
+            "troll guarding: false -> true"
+        );
+
+        let troll = session.entity("troll").unwrap();
+        assert_eq!((troll.stats.hp, troll.guarding), (2, true));
+        let copy = deserialize::<Session>(&session.serialize()).unwrap();
+        assert_eq!(copy.entities(), session.entities());
+        assert_eq!(session.at(2).unwrap().entity("troll").unwrap().stats.hp, 1);
+    }
+
+    #[test]
+    fn queued_actions_wait_for_resolve() {
+        let mut session = Session::new("game".to_string()).unwrap();
+        session.add_entity(Entity::new("hero".to_string())).unwrap();
+        session
+            .add_entity(Entity::new("troll".to_string()))
+            .unwrap();
+        let action = |kind, target: &str| Action::new(kind, target.to_string()).unwrap();
+        // Legal only once the rest before it has been resolved.
+        session.queue(action(ActionKind::Rest, "tr")).unwrap();
+        session.queue(action(ActionKind::Attack, "troll")).unwrap();
+        session.queue(action(ActionKind::Attack, "hero")).unwrap();
+        assert!(session.queue(action(ActionKind::Rest, "orc")).is_err());
+        assert_eq!(session.queued().len(), 3);
+        assert_eq!(session.entity("troll").unwrap().stats.hp, 0);
+
+        let copy = deserialize::<Session>(&session.serialize()).unwrap();
+        assert_eq!(copy.queued(), session.queued());
+
+        let outcome = session.resolve().unwrap();
+        assert_eq!(
+            outcome.messages,
+            [
+                "rested troll",
+                "attacked troll for 1",
+                "skipped attack hero: hero has nothing left to lose",
+                "turn 1 begins"
+            ]
+        );
+        assert!(session.queued().is_empty());
+        assert_eq!(session.turn().number, 1);
+
+        session.undo().unwrap();
+        assert_eq!(session.queued().len(), 3);
+    }
+
+    #[test]
+    fn merge_replays_the_other_side() {
+        let mut base = Session::new("base".to_string()).unwrap();
+        base.add_entity(Entity::new("hero".to_string())).unwrap();
+        base.add_entity(Entity::new("troll".to_string())).unwrap();
+        let copy = |session: &Session| deserialize::<Session>(&session.serialize()).unwrap();
+
+        let villain = Entity::new("villain".to_string());
+        let mut mine = copy(&base);
+        mine.add_entity(villain.clone()).unwrap();
+        mine.remove_entity("troll").unwrap();
+
+        let mut theirs = copy(&base);
+        theirs.add_entity(villain).unwrap();
+        theirs
+            .apply(Action::new(ActionKind::Fight, "troll".to_string()).unwrap())
+            .unwrap();
+        theirs
+            .add_entity(Entity::new("sidekick".to_string()))
+            .unwrap();
+
+        let conflicts = mine.merge(&base, &theirs).unwrap();
+        assert_eq!(conflicts.len(), 1);
+        assert!(matches!(conflicts[0].reason, Error::EntityNotFound(_)));
+        let names: Vec<_> = mine.entities().iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["hero", "villain", "sidekick"]);
+
+        let mut unrelated = Session::new("other".to_string()).unwrap();
+        unrelated.add_entity(Entity::new("x".to_string())).unwrap();
+        assert!(matches!(
+            copy(&unrelated).merge(&base, &theirs),
+            Err(Error::Diverged(_))
+        ));
+    }
+
+    #[test]
+    fn merged_actions_that_cannot_be_paid_change_nothing() {
+        let mut base = Session::new("base".to_string()).unwrap();
+        let mut troll = Entity::new("troll".to_string());
+        troll.stats.hp = 10;
+        base.add_entity(troll).unwrap();
+        let mut rules = crate::rules::Rules::default();
+        let cost = Resources {
+            action_points: 1,
+            ..Resources::default()
+        };
+        rules.costs.insert(ActionKind::Attack, cost);
+        base.set_rules(rules).unwrap();
+        base.grant("troll", cost).unwrap();
+        let copy = |session: &Session| deserialize::<Session>(&session.serialize()).unwrap();
+        let attack = |damage| {
+            let params = [("damage".to_string(), crate::actions::Param::Int(damage))];
+            Action::new(ActionKind::Attack, "troll".to_string())
+                .unwrap()
+                .with_params(params.into())
+        };
+
+        let mut mine = copy(&base);
+        mine.apply(attack(3)).unwrap();
+        let mut theirs = copy(&base);
+        theirs.apply(attack(2)).unwrap();
+        let before = mine.entities().to_vec();
+        let conflicts = mine.merge(&base, &theirs).unwrap();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(mine.entities(), before);
+        assert_eq!(mine.entity("troll").unwrap().stats.hp, 7);
+        assert_eq!(copy(&mine).entities(), mine.entities());
+    }
+
+    #[test]
+    fn templates_seed_new_sessions() {
+        let mut template = Session::new("dungeon".to_string()).unwrap();
+        template
+            .add_entity(Entity::new("goblin".to_string()))
+            .unwrap();
+        template.add_entity(Entity::new("rat".to_string())).unwrap();
+        template.undo().unwrap();
+
+        let mut session = Session::from_template("game".to_string(), &template).unwrap();
+        assert_eq!(session.name(), "game");
+        assert_eq!(session.log(), template.log());
+        assert_eq!(session.entities(), template.entities());
+        assert!(matches!(session.redo(), Err(Error::NothingToRedo)));
+    }
+
+    #[test]
+    fn tags_stay_sorted_and_unique() {
+        let mut session = Session::new("game".to_string()).unwrap();
+        assert!(session.add_tag("weekly".to_string()).unwrap());
+        assert!(session.add_tag("pvp".to_string()).unwrap());
+        assert!(!session.add_tag("pvp".to_string()).unwrap());
+        assert!(session.add_tag("two words".to_string()).is_err());
+        assert_eq!(session.tags(), ["pvp", "weekly"]);
+
+        let copy = deserialize::<Session>(&session.serialize()).unwrap();
+        assert_eq!(copy.tags(), session.tags());
+        assert!(session.remove_tag("pvp"));
+        assert!(!session.remove_tag("pvp"));
+        assert!(session.log().is_empty());
+    }
+
+    #[test]
+    fn chat_is_logged_but_changes_nothing() {
+        let mut session = Session::new("game".to_string()).unwrap();
+        session
+            .add_entity(Entity::new("troll".to_string()))
+            .unwrap();
+        session.say("alice", "gg").unwrap();
+        assert!(matches!(
+            session.say("bob", &"x".repeat(1001)),
+            Err(Error::ChatTooLong(1001))
+        ));
+        assert_eq!(session.log().len(), 2);
+        assert_eq!(session.log()[1].to_string(), "alice says \"gg\"");
+        assert_eq!(session.players(), ["alice"]);
+        assert_eq!(session.size_hint(), session.serialize().len());
+
+        let copy = deserialize::<Session>(&session.serialize()).unwrap();
+        assert_eq!(copy.log(), session.log());
+        assert_eq!(copy.entities(), session.entities());
+    }
 }
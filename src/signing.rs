@@ -0,0 +1,266 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use ed25519_dalek::{Signature, Signer as _, SigningKey, VerifyingKey};
+use rand_core::OsRng;
+
+use crate::error::{Error, Result};
+use crate::players;
+use crate::serde::{FieldReader, FieldWriter};
+
+const FILENAME: &str = "relay_code.keys";
+
+/// Who signed a file a keyring opened, and the key it was verified with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signer {
+    pub player: String,
+    pub key: VerifyingKey,
+}
+
+/// The local player's signing key plus the public keys of every player whose
+/// files we accept.
+///
+/// Stored as plain text, one entry per line:
+///
+/// ```text
+/// identity <player> <secret key hex>
+/// trust <player> <public key hex>
+/// ```
+#[derive(Debug, Default)]
+pub struct Keyring {
+    identity: Option<(String, SigningKey)>,
+    trusted: BTreeMap<String, VerifyingKey>,
+}
+
+impl Keyring {
+    /// Loads the keyring from `dir`, treating a missing file as an empty one.
+    pub fn load(dir: &Path) -> Result<Self> {
+        match fs::read_to_string(dir.join(FILENAME)) {
+            Ok(text) => Self::parse(&text),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn parse(text: &str) -> Result<Self> {
+        let mut keyring = Self::default();
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.split_whitespace();
+            match (parts.next(), parts.next(), parts.next()) {
+                (Some("identity"), Some(player), Some(key)) => {
+                    let key = SigningKey::from_bytes(&from_hex(key)?);
+                    keyring.identity = Some((player.to_string(), key));
+                }
+                (Some("trust"), Some(player), Some(key)) => {
+                    keyring.trust(player.to_string(), key)?;
+                }
+                _ => return Err(Error::InvalidKey),
+            }
+        }
+        Ok(keyring)
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<()> {
+        write_private(&dir.join(FILENAME), &self.to_text())
+    }
+
+    fn to_text(&self) -> String {
+        let mut text = String::new();
+        if let Some((player, key)) = &self.identity {
+            text.push_str(&format!("identity {player} {}\n", to_hex(&key.to_bytes())));
+        }
+        for (player, key) in &self.trusted {
+            text.push_str(&format!("trust {player} {}\n", to_hex(key.as_bytes())));
+        }
+        text
+    }
+
+    /// Creates a fresh identity for `player`, trusting its own public key.
+    /// Returns the public key in hex so it can be handed to other players.
+    /// Fails if `player` is not a valid player name.
+    pub fn generate(&mut self, player: String) -> Result<String> {
+        players::validate_name(&player)?;
+        let key = SigningKey::generate(&mut OsRng);
+        let public = key.verifying_key();
+        self.trusted.insert(player.clone(), public);
+        self.identity = Some((player, key));
+        Ok(to_hex(public.as_bytes()))
+    }
+
+    /// Accepts files signed by `player` with the given hex-encoded public key.
+    pub fn trust(&mut self, player: String, public_key: &str) -> Result<()> {
+        players::validate_name(&player)?;
+        self.trusted.insert(player, self::public_key(public_key)?);
+        Ok(())
+    }
+
+    /// Wraps `payload` for writing to disk, signing it when a local identity
+    /// is configured.
+    pub fn seal(&self, payload: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(payload.len() + 128);
+        let mut writer = FieldWriter::new(&mut bytes);
+        writer.write_bytes(payload);
+        if let Some((player, key)) = &self.identity {
+            let signature = key.sign(payload);
+            writer.write_str(player);
+            writer.write_bytes(&signature.to_bytes());
+        }
+        bytes
+    }
+
+    /// Unwraps bytes written by `seal`, verifying the signature against the
+    /// claimed signer's trusted key. Unsigned files are only accepted while
+    /// no keys are trusted at all.
+    pub fn open<'a>(&self, bytes: &'a [u8]) -> Result<&'a [u8]> {
+        self.open_signed(bytes).map(|(payload, _)| payload)
+    }
+
+    /// As `open`, also returning who signed the file, if anyone did.
+    pub fn open_signed<'a>(&self, bytes: &'a [u8]) -> Result<(&'a [u8], Option<Signer>)> {
+        let mut reader = FieldReader::new(bytes);
+        let payload: &[u8] = reader.read_field()?;
+        if reader.is_empty() {
+            if self.trusted.is_empty() {
+                return Ok((payload, None));
+            }
+            return Err(Error::Unsigned);
+        }
+
+        let player: String = reader.read_field()?;
+        let signature: &[u8] = reader.read_field()?;
+        if !reader.is_empty() {
+            let trailing = Error::TrailingBytes(reader.remaining());
+            return Err(Error::At(reader.offset(), Box::new(trailing)));
+        }
+
+        let key = self
+            .trusted
+            .get(&player)
+            .ok_or_else(|| Error::UnknownSigner(player.clone()))?;
+        let signature = Signature::from_slice(signature).map_err(|_| Error::InvalidSignature)?;
+        key.verify_strict(payload, &signature)
+            .map_err(|_| Error::InvalidSignature)?;
+        let key = *key;
+        Ok((payload, Some(Signer { player, key })))
+    }
+}
+
+#[cfg(unix)]
+pub(crate) fn write_private(path: &Path, text: &str) -> Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    file.write_all(text.as_bytes())?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub(crate) fn write_private(path: &Path, text: &str) -> Result<()> {
+    fs::write(path, text)?;
+    Ok(())
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Parses a hex-encoded public key.
+pub(crate) fn public_key(hex: &str) -> Result<VerifyingKey> {
+    VerifyingKey::from_bytes(&from_hex(hex)?).map_err(|_| Error::InvalidKey)
+}
+
+fn from_hex<const N: usize>(hex: &str) -> Result<[u8; N]> {
+    if hex.len() != N * 2 || !hex.is_ascii() {
+        return Err(Error::InvalidKey);
+    }
+    let mut bytes = [0; N];
+    for (byte, pair) in bytes.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        let pair = std::str::from_utf8(pair)?;
+        *byte = u8::from_str_radix(pair, 16).map_err(|_| Error::InvalidKey)?;
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Keyring;
+    use crate::error::Error;
+
+    #[test]
+    fn signed_payload_round_trip() {
+        let mut alice = Keyring::default();
+        let public = alice.generate("alice".to_string()).unwrap();
+
+        let mut bob = Keyring::default();
+        bob.trust("alice".to_string(), &public).unwrap();
+
+        let sealed = alice.seal(b"turn 1");
+        assert_eq!(bob.open(&sealed).unwrap(), b"turn 1");
+    }
+
+    #[test]
+    fn tampered_payload_is_rejected() {
+        let mut alice = Keyring::default();
+        alice.generate("alice".to_string()).unwrap();
+
+        let mut sealed = alice.seal(b"turn 1");
+        sealed[3] ^= 1;
+        assert!(matches!(alice.open(&sealed), Err(Error::InvalidSignature)));
+    }
+
+    #[test]
+    fn untrusted_signers_are_rejected() {
+        let mut alice = Keyring::default();
+        let public = alice.generate("alice".to_string()).unwrap();
+        let mut bob = Keyring::default();
+        bob.trust("alice".to_string(), &public).unwrap();
+
+        let mut mallory = Keyring::default();
+        mallory.generate("mallory".to_string()).unwrap();
+        let sealed = mallory.seal(b"turn 1");
+        assert!(matches!(bob.open(&sealed), Err(Error::UnknownSigner(_))));
+
+        let mut impostor = Keyring::default();
+        impostor.generate("alice".to_string()).unwrap();
+        let sealed = impostor.seal(b"turn 1");
+        assert!(matches!(bob.open(&sealed), Err(Error::InvalidSignature)));
+    }
+
+    #[test]
+    fn unsigned_payload_requires_empty_keyring() {
+        let sealed = Keyring::default().seal(b"turn 1");
+        assert_eq!(Keyring::default().open(&sealed).unwrap(), b"turn 1");
+
+        let mut alice = Keyring::default();
+        alice.generate("alice".to_string()).unwrap();
+        assert!(matches!(alice.open(&sealed), Err(Error::Unsigned)));
+    }
+
+    #[test]
+    fn keyring_text_round_trip() {
+        let mut keyring = Keyring::default();
+        keyring.generate("alice".to_string()).unwrap();
+
+        let parsed = Keyring::parse(&keyring.to_text()).unwrap();
+        assert_eq!(parsed.seal(b"payload"), keyring.seal(b"payload"));
+
+        let public = keyring.generate("alice".to_string()).unwrap();
+        for name in ["", "alice smith", "alice\ntrust", "bob\t"] {
+            let err = keyring.generate(name.to_string());
+            assert!(matches!(err, Err(Error::InvalidPlayer(_))));
+            let err = keyring.trust(name.to_string(), &public);
+            assert!(matches!(err, Err(Error::InvalidPlayer(_))));
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use std::collections::BTreeMap;
+
+use crate::error::Result;
+use crate::serde::{FieldReader, FieldType, Serialize};
+use crate::session::Session;
+
+/// How big a session is once serialized and what it is made of.
+#[derive(Debug)]
+pub struct Stats {
+    /// Serialized size in bytes, excluding the signature envelope.
+    pub size: usize,
+    /// Number of fields of each type, nested fields included.
+    pub fields: BTreeMap<FieldType, usize>,
+    pub entities: usize,
+    /// Actions in the log, whether or not they are still current.
+    pub actions: usize,
+}
+
+impl Stats {
+    /// Counts are taken from the serialized bytes rather than the session
+    /// itself, so they show what is actually written.
+    pub fn of(session: &Session) -> Result<Self> {
+        let bytes = session.serialize();
+        let mut fields = BTreeMap::new();
+        count_fields(&bytes, &mut fields)?;
+        Ok(Self {
+            size: bytes.len(),
+            fields,
+            entities: session.entities().len(),
+            actions: session
+                .log()
+                .iter()
+                .filter(|event| event.action().is_some())
+                .count(),
+        })
+    }
+}
+
+fn count_fields(bytes: &[u8], counts: &mut BTreeMap<FieldType, usize>) -> Result<()> {
+    let mut reader = FieldReader::new(bytes);
+    while !reader.is_empty() {
+        let (field_type, payload) = reader.read_raw()?;
+        *counts.entry(field_type).or_default() += 1;
+        match field_type {
+            FieldType::Action
+            | FieldType::Entity
+            | FieldType::Session
+            | FieldType::Map
+            | FieldType::List => count_fields(payload, counts)?,
+            // Skips the discriminant.
+            FieldType::Enum => count_fields(payload.get(1..).unwrap_or_default(), counts)?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Stats;
+    use crate::actions::{Action, ActionKind};
+    use crate::serde::{FieldType, Serialize};
+    use crate::session::Session;
+    use crate::Entity;
+
+    #[test]
+    fn stats_count_nested_fields() {
+        let mut session = Session::new("game".to_string()).unwrap();
+        session.add_entity(Entity::new("hero".to_string())).unwrap();
+        let action = Action::new(ActionKind::Fight, "hero".to_string()).unwrap();
+        session.apply(action).unwrap();
+
+        let stats = Stats::of(&session).unwrap();
+        assert_eq!(stats.size, session.serialize().len());
+        assert_eq!((stats.entities, stats.actions), (1, 1));
+        assert_eq!(stats.fields[&FieldType::Enum], 2);
+        assert_eq!(stats.fields[&FieldType::Entity], 1);
+        assert_eq!(stats.fields[&FieldType::Action], 1);
+        // Session name, entity name and action target.
+        assert_eq!(stats.fields[&FieldType::Str], 3);
+        // Metadata, tags, log and redo; components are opaque bytes.
+        assert_eq!(stats.fields[&FieldType::List], 4);
+    }
+}
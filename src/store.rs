@@ -0,0 +1,751 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs::{self, File, TryLockError};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use crate::delta::Delta;
+use crate::error::{Error, Result};
+use crate::log::Event;
+use crate::metadata::Metadata;
+use crate::passphrase::Passphrases;
+use crate::serde::{from_bytes, FieldReader, FieldWriter, Serialize};
+use crate::session::{validate_name, Session};
+use crate::signing::Keyring;
+use crate::timestamp::Timestamp;
+use crate::{atomic, crypt};
+
+mod mem;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+pub use mem::MemStore;
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteStore;
+
+const EXTENSION: &str = "session";
+
+/// Suffix of the journal of deltas appended to `<name>.session`.
+const JOURNAL: &str = "delta";
+
+/// Previous versions kept by `save` unless `RELAY_CODE_BACKUPS` says otherwise.
+const DEFAULT_BACKUPS: u32 = 3;
+
+/// What `SessionStore::list` can tell about a session without loading it.
+#[derive(Debug)]
+pub struct SessionInfo {
+    pub name: String,
+    pub size: u64,
+    pub modified: Timestamp,
+}
+
+/// Held while a session is being read, modified and written back. Dropping it
+/// releases the session.
+#[derive(Debug, Default)]
+pub struct SessionLock {
+    _file: Option<File>,
+}
+
+/// Locks session `name` with an advisory lock on `.<name>.lock` in `dir`,
+/// which can never clash with a session file since session names cannot
+/// start with a dot. The lock is released when its holder exits, however
+/// it does. The lock file is left behind; removing it would race with the
+/// next locker.
+pub(crate) fn lock_file(dir: &Path, name: &str) -> Result<SessionLock> {
+    validate_name(name)?;
+    let file = File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(dir.join(format!(".{name}.lock")))?;
+    match file.try_lock() {
+        Ok(()) => {
+            crate::verbose!("locked session {name:?}");
+            Ok(SessionLock { _file: Some(file) })
+        }
+        Err(TryLockError::WouldBlock) => Err(Error::SessionLocked(name.to_string())),
+        Err(TryLockError::Error(err)) => Err(err.into()),
+    }
+}
+
+/// Where sessions live. `Session` only knows how to encode itself; finding,
+/// listing and removing the encoded bytes is up to the store.
+pub trait SessionStore {
+    /// Claims `name` for a load-modify-save cycle, failing with
+    /// `Error::SessionLocked` if another process already has. Callers take
+    /// the lock once around the whole cycle; store methods never lock.
+    fn lock(&self, name: &str) -> Result<SessionLock> {
+        validate_name(name)?;
+        Ok(SessionLock::default())
+    }
+
+    /// Writes `session` under its own name, replacing any previous version.
+    fn save(&self, session: &Session) -> Result<()>;
+
+    fn load(&self, name: &str) -> Result<Session>;
+
+    /// Reads `name` only as far as its metadata. Stores that cannot read
+    /// less than the whole session load it.
+    fn metadata(&self, name: &str) -> Result<Metadata> {
+        Ok(self.load(name)?.metadata())
+    }
+
+    /// Every stored session, sorted by name.
+    fn list(&self) -> Result<Vec<SessionInfo>>;
+
+    fn delete(&self, name: &str) -> Result<()>;
+
+    fn exists(&self, name: &str) -> Result<bool> {
+        match self.load(name) {
+            Ok(_) => Ok(true),
+            Err(Error::SessionNotFound(_)) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Renames a session, updating the name stored inside it.
+    fn rename(&self, old: &str, new: &str) -> Result<()> {
+        let mut session = self.load(old)?;
+        ensure_absent(self, new)?;
+        session.set_name(new.to_string())?;
+        session.save(self)?;
+        self.delete(old)
+    }
+
+    /// Copies a session under a new name. The copy is a new session, so it
+    /// gets fresh timestamps and none of the source's history.
+    fn copy(&self, src: &str, dst: &str) -> Result<()> {
+        let mut session = self.load(src)?.copy_as(dst)?;
+        ensure_absent(self, dst)?;
+        session.save(self)
+    }
+
+    /// Encrypts `name` with `passphrase`, or decrypts it given `None`.
+    /// Callers hold the session lock.
+    fn set_passphrase(&self, _name: &str, _passphrase: Option<&str>) -> Result<()> {
+        Err(Error::EncryptionUnsupported)
+    }
+
+    /// Rewrites `name` in full, folding in anything saved incrementally.
+    /// Stores that always save in full have nothing to compact.
+    fn compact(&self, name: &str) -> Result<()> {
+        validate_name(name)
+    }
+
+    /// Rolls `name` back to its `n`th previous version, where 1 is the most
+    /// recent. Stores that keep no history have nothing to restore.
+    fn restore(&self, name: &str, n: u32) -> Result<()> {
+        Err(Error::BackupNotFound(name.to_string(), n))
+    }
+}
+
+pub(crate) fn ensure_absent(store: &(impl SessionStore + ?Sized), name: &str) -> Result<()> {
+    if store.exists(name)? {
+        return Err(Error::SessionExists(name.to_string()));
+    }
+    Ok(())
+}
+
+/// Keeps each session in `<dir>/<name>.session`, sealed with the keyring,
+/// plus rolling `<name>.session.<n>` backups. A session encrypted with
+/// `set_passphrase` stays encrypted, backups included, until decrypted.
+///
+/// With deltas enabled, saving a session this store read or wrote appends
+/// just the changes to `<name>.session.delta` instead, until that holds
+/// as many deltas as allowed and the next save rewrites the session in
+/// full. Backups are only taken on full rewrites, and encrypted sessions
+/// are always rewritten.
+#[derive(Debug)]
+pub struct FsStore<'a> {
+    dir: PathBuf,
+    keyring: &'a Keyring,
+    passphrases: Option<&'a Passphrases>,
+    deltas: u32,
+    saved: RefCell<BTreeMap<String, Saved>>,
+}
+
+/// What a session looked like when this store last read or wrote it.
+#[derive(Debug)]
+struct Saved {
+    log: Vec<Event>,
+    snapshot: Timestamp,
+    deltas: u32,
+}
+
+impl<'a> FsStore<'a> {
+    pub fn new(dir: PathBuf, keyring: &'a Keyring) -> Self {
+        Self {
+            dir,
+            keyring,
+            passphrases: None,
+            deltas: delta_limit(),
+            saved: RefCell::default(),
+        }
+    }
+
+    /// Deltas appended before a session is rewritten in full, 0 to always
+    /// rewrite. Defaults to `RELAY_CODE_DELTAS`.
+    pub fn with_deltas(mut self, deltas: u32) -> Self {
+        self.deltas = deltas;
+        self
+    }
+
+    /// Allows encrypted sessions, whose passphrases come from `passphrases`.
+    pub fn with_passphrases(mut self, passphrases: &'a Passphrases) -> Self {
+        self.passphrases = Some(passphrases);
+        self
+    }
+
+    fn path(&self, name: &str) -> Result<PathBuf> {
+        validate_name(name)?;
+        Ok(self.dir.join(format!("{name}.{EXTENSION}")))
+    }
+
+    fn journal_path(&self, name: &str) -> Result<PathBuf> {
+        validate_name(name)?;
+        Ok(self.dir.join(format!("{name}.{EXTENSION}.{JOURNAL}")))
+    }
+
+    /// The deltas journaled for `name`, oldest first.
+    fn journal(&self, name: &str) -> Result<Vec<Delta>> {
+        let bytes = match fs::read(self.journal_path(name)?) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(err.into()),
+        };
+        let mut reader = FieldReader::new(&bytes);
+        let mut deltas = vec![];
+        while !reader.is_empty() {
+            let record: &[u8] = reader.read_field()?;
+            deltas.push(from_bytes(self.keyring.open(record)?)?);
+        }
+        Ok(deltas)
+    }
+
+    /// Journals the changes to `session` since this store last saw it,
+    /// returning false if it has to be rewritten in full instead.
+    fn append_delta(&self, session: &Session) -> Result<bool> {
+        let mut saved = self.saved.borrow_mut();
+        let Some(last) = saved.get_mut(session.name()) else {
+            return Ok(false);
+        };
+        if last.deltas >= self.deltas {
+            return Ok(false);
+        }
+        let delta = session.delta(&last.log, last.snapshot);
+        let mut record = vec![];
+        FieldWriter::new(&mut record).write_bytes(&self.keyring.seal(&delta.serialize()));
+
+        let mut journal = File::options()
+            .create(true)
+            .append(true)
+            .open(self.journal_path(session.name())?)?;
+        journal.write_all(&record)?;
+        journal.sync_data()?;
+        crate::verbose!(
+            "appended {} bytes to the journal of {:?}",
+            record.len(),
+            session.name()
+        );
+        last.log = session.log().to_vec();
+        last.deltas += 1;
+        Ok(true)
+    }
+
+    /// Backup files of `name` as `(n, path)` for every `<name>.session.<n>`.
+    fn backups(&self, name: &str) -> Result<Vec<(u32, PathBuf)>> {
+        let prefix = format!("{name}.{EXTENSION}.");
+        let mut backups = vec![];
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let file_name = entry.file_name();
+            let n = file_name
+                .to_str()
+                .and_then(|file_name| file_name.strip_prefix(&prefix))
+                .filter(|n| n.bytes().all(|b| b.is_ascii_digit()))
+                .and_then(|n| n.parse().ok());
+            if let Some(n) = n {
+                backups.push((n, entry.path()));
+            }
+        }
+        backups.sort();
+        Ok(backups)
+    }
+
+    fn read_file(&self, name: &str) -> Result<Vec<u8>> {
+        match fs::read(self.path(name)?) {
+            Ok(bytes) => Ok(bytes),
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                Err(Error::SessionNotFound(name.to_string()))
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Reads `name`, with any journaled deltas applied, along with the
+    /// passphrase it is encrypted with, if any.
+    fn read(&self, name: &str) -> Result<(Session, Option<String>)> {
+        let (mut session, passphrase) = self.decode(name, &self.read_file(name)?)?;
+        let snapshot = session.modified();
+        let mut deltas = 0;
+        if passphrase.is_none() {
+            for delta in self.journal(name)? {
+                // Left over from a rewrite interrupted before it could
+                // remove the journal.
+                if delta.snapshot != snapshot {
+                    break;
+                }
+                session.apply_delta(delta)?;
+                deltas += 1;
+            }
+        }
+        crate::verbose!("loaded session {name:?} with {deltas} journaled deltas");
+        self.remember(&session, snapshot, deltas);
+        Ok((session, passphrase))
+    }
+
+    fn remember(&self, session: &Session, snapshot: Timestamp, deltas: u32) {
+        let saved = Saved {
+            log: session.log().to_vec(),
+            snapshot,
+            deltas,
+        };
+        self.saved
+            .borrow_mut()
+            .insert(session.name().to_string(), saved);
+    }
+
+    fn decode(&self, name: &str, bytes: &[u8]) -> Result<(Session, Option<String>)> {
+        let (payload, passphrase) = self.unseal(name, bytes)?;
+        Ok((from_bytes(&payload)?, passphrase))
+    }
+
+    /// The serialized session inside a stored file, decrypted if need be and
+    /// with its signature checked.
+    fn unseal(&self, name: &str, bytes: &[u8]) -> Result<(Vec<u8>, Option<String>)> {
+        if bytes.is_empty() {
+            return Err(Error::NoEntity);
+        }
+        if !crypt::is_encrypted(bytes) {
+            return Ok((self.keyring.open(bytes)?.to_vec(), None));
+        }
+        let (sealed, passphrase) = self.decrypt(name, bytes)?;
+        Ok((self.keyring.open(&sealed)?.to_vec(), Some(passphrase)))
+    }
+
+    fn decrypt(&self, name: &str, bytes: &[u8]) -> Result<(Vec<u8>, String)> {
+        let passphrases = self
+            .passphrases
+            .ok_or_else(|| Error::Encrypted(name.to_string()))?;
+        let passphrase = passphrases.get(name)?;
+        let sealed = crypt::decrypt(&passphrase, bytes)?;
+        passphrases.confirm(name, &passphrase);
+        Ok((sealed, passphrase))
+    }
+
+    /// The passphrase the stored copy of `name` is encrypted with, if any.
+    fn passphrase(&self, name: &str) -> Result<Option<String>> {
+        let bytes = match fs::read(self.path(name)?) {
+            Ok(bytes) if crypt::is_encrypted(&bytes) => bytes,
+            Ok(_) => return Ok(None),
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        match self.passphrases.and_then(|p| p.confirmed(name)) {
+            Some(passphrase) => Ok(Some(passphrase)),
+            None => Ok(Some(self.decrypt(name, &bytes)?.1)),
+        }
+    }
+
+    fn write(&self, session: &Session, passphrase: Option<&str>) -> Result<()> {
+        let mut bytes = self.keyring.seal(&session.serialize());
+        if let Some(passphrase) = passphrase {
+            bytes = crypt::encrypt(passphrase, &bytes);
+            if let Some(passphrases) = self.passphrases {
+                passphrases.confirm(session.name(), passphrase);
+            }
+        }
+        let path = self.path(session.name())?;
+        rotate_backups(&path, backup_count())?;
+        atomic::write(&path, &bytes)?;
+        crate::verbose!("wrote {} bytes to {}", bytes.len(), path.display());
+        // The new snapshot already holds every delta.
+        remove_if_exists(&self.journal_path(session.name())?)?;
+        self.remember(session, session.modified(), 0);
+        Ok(())
+    }
+}
+
+impl SessionStore for FsStore<'_> {
+    fn lock(&self, name: &str) -> Result<SessionLock> {
+        lock_file(&self.dir, name)
+    }
+
+    fn save(&self, session: &Session) -> Result<()> {
+        let passphrase = self.passphrase(session.name())?;
+        if passphrase.is_none() && self.append_delta(session)? {
+            return Ok(());
+        }
+        self.write(session, passphrase.as_deref())
+    }
+
+    fn load(&self, name: &str) -> Result<Session> {
+        Ok(self.read(name)?.0)
+    }
+
+    /// Journaled deltas can change everything in the metadata, so sessions
+    /// with any are loaded in full.
+    fn metadata(&self, name: &str) -> Result<Metadata> {
+        if self.journal_path(name)?.exists() {
+            return Ok(self.load(name)?.metadata());
+        }
+        let (payload, _) = self.unseal(name, &self.read_file(name)?)?;
+        Metadata::from_session_bytes(&payload)
+    }
+
+    fn list(&self) -> Result<Vec<SessionInfo>> {
+        let mut sessions = vec![];
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != EXTENSION) {
+                continue;
+            }
+            let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            let metadata = fs::metadata(&path)?;
+            if !metadata.is_file() || validate_name(name).is_err() {
+                continue;
+            }
+            let mut info = SessionInfo {
+                name: name.to_string(),
+                size: metadata.len(),
+                modified: metadata.modified()?.try_into()?,
+            };
+            if let Ok(journal) = fs::metadata(self.journal_path(name)?) {
+                info.size += journal.len();
+                info.modified = info.modified.max(journal.modified()?.try_into()?);
+            }
+            sessions.push(info);
+        }
+        sessions.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(sessions)
+    }
+
+    /// Removes the session file along with its journal and any
+    /// `<name>.session.<n>` backups.
+    fn delete(&self, name: &str) -> Result<()> {
+        match fs::remove_file(self.path(name)?) {
+            Ok(()) => {}
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                return Err(Error::SessionNotFound(name.to_string()))
+            }
+            Err(err) => return Err(err.into()),
+        }
+
+        remove_if_exists(&self.journal_path(name)?)?;
+        for (_, path) in self.backups(name)? {
+            fs::remove_file(path)?;
+        }
+        self.saved.borrow_mut().remove(name);
+        Ok(())
+    }
+
+    fn exists(&self, name: &str) -> Result<bool> {
+        Ok(self.path(name)?.exists())
+    }
+
+    /// Carries the backups and any encryption along with the session.
+    fn rename(&self, old: &str, new: &str) -> Result<()> {
+        let (mut session, passphrase) = self.read(old)?;
+        ensure_absent(self, new)?;
+        session.set_name(new.to_string())?;
+        session.touch()?;
+        self.write(&session, passphrase.as_deref())?;
+
+        let new_path = self.path(new)?;
+        for (n, path) in self.backups(old)? {
+            fs::rename(path, backup_path(&new_path, n))?;
+        }
+        fs::remove_file(self.path(old)?)?;
+        remove_if_exists(&self.journal_path(old)?)?;
+        self.saved.borrow_mut().remove(old);
+        Ok(())
+    }
+
+    /// Keeps the copy encrypted with the same passphrase as the original.
+    fn copy(&self, src: &str, dst: &str) -> Result<()> {
+        let (session, passphrase) = self.read(src)?;
+        let mut session = session.copy_as(dst)?;
+        ensure_absent(self, dst)?;
+        session.touch()?;
+        self.write(&session, passphrase.as_deref())
+    }
+
+    /// Rewrites the backups too, so no plain text copy is left behind.
+    fn set_passphrase(&self, name: &str, passphrase: Option<&str>) -> Result<()> {
+        let path = self.path(name)?;
+        if !path.exists() {
+            return Err(Error::SessionNotFound(name.to_string()));
+        }
+        // Journals are never encrypted, so fold it into the snapshot first.
+        self.compact(name)?;
+        let backups = self.backups(name)?.into_iter().map(|(_, path)| path);
+        for path in std::iter::once(path).chain(backups) {
+            let bytes = fs::read(&path)?;
+            let sealed = match crypt::is_encrypted(&bytes) {
+                true => self.decrypt(name, &bytes)?.0,
+                false => bytes,
+            };
+            let bytes = match passphrase {
+                Some(passphrase) => crypt::encrypt(passphrase, &sealed),
+                None => sealed,
+            };
+            atomic::write(&path, &bytes)?;
+        }
+        if let Some(passphrases) = self.passphrases {
+            match passphrase {
+                Some(passphrase) => passphrases.confirm(name, passphrase),
+                None => passphrases.forget(name),
+            }
+        }
+        Ok(())
+    }
+
+    fn compact(&self, name: &str) -> Result<()> {
+        if !self.journal_path(name)?.exists() {
+            return Ok(());
+        }
+        let (session, passphrase) = self.read(name)?;
+        self.write(&session, passphrase.as_deref())
+    }
+
+    /// The version being replaced becomes a backup itself, so a restore can
+    /// be undone by restoring again.
+    fn restore(&self, name: &str, n: u32) -> Result<()> {
+        let path = backup_path(&self.path(name)?, n);
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                return Err(Error::BackupNotFound(name.to_string(), n))
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let (mut session, passphrase) = self.decode(name, &bytes)?;
+        // The backup predates any rename that carried it along.
+        session.set_name(name.to_string())?;
+        session.touch()?;
+        self.write(&session, passphrase.as_deref())
+    }
+}
+
+fn backup_path(path: &Path, n: u32) -> PathBuf {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(format!(".{n}"));
+    PathBuf::from(backup)
+}
+
+fn remove_if_exists(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn delta_limit() -> u32 {
+    std::env::var("RELAY_CODE_DELTAS")
+        .ok()
+        .and_then(|count| count.parse().ok())
+        .unwrap_or(0)
+}
+
+pub(crate) fn backup_count() -> u32 {
+    std::env::var("RELAY_CODE_BACKUPS")
+        .ok()
+        .and_then(|count| count.parse().ok())
+        .unwrap_or(DEFAULT_BACKUPS)
+}
+
+/// Shifts `<path>.1..` up by one, dropping anything past `keep`, and copies
+/// the current file to `<path>.1`. The current file is copied rather than
+/// moved so there is never a moment without it.
+fn rotate_backups(path: &Path, keep: u32) -> Result<()> {
+    if keep == 0 || !path.exists() {
+        return Ok(());
+    }
+    let mut n = keep;
+    while backup_path(path, n + 1).exists() {
+        n += 1;
+    }
+    for n in (keep..=n).rev() {
+        if backup_path(path, n).exists() {
+            fs::remove_file(backup_path(path, n))?;
+        }
+    }
+    for n in (1..keep).rev() {
+        let from = backup_path(path, n);
+        if from.exists() {
+            fs::rename(from, backup_path(path, n + 1))?;
+        }
+    }
+    fs::copy(path, backup_path(path, 1))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FsStore, SessionStore};
+    use crate::crypt;
+    use crate::error::Error;
+    use crate::passphrase::Passphrases;
+    use crate::serde::Serialize;
+    use crate::session::Session;
+    use crate::signing::Keyring;
+    use crate::temp_dir::TempDir;
+    use crate::Entity;
+
+    #[test]
+    fn backups_rotate_and_are_pruned() {
+        let dir = TempDir::new("backups");
+        let path = dir.join("game.session");
+        let read = |n| std::fs::read_to_string(super::backup_path(&path, n)).ok();
+
+        for version in ["v1", "v2", "v3", "v4"] {
+            super::rotate_backups(&path, 2).unwrap();
+            std::fs::write(&path, version).unwrap();
+        }
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "v4");
+        assert_eq!(read(1).as_deref(), Some("v3"));
+        assert_eq!(read(2).as_deref(), Some("v2"));
+        assert_eq!(read(3), None);
+
+        super::rotate_backups(&path, 1).unwrap();
+        assert_eq!(read(1).as_deref(), Some("v4"));
+        assert_eq!(read(2), None);
+    }
+
+    #[test]
+    fn locks_are_exclusive_until_dropped() {
+        let dir = TempDir::new("locks");
+        let keyring = Keyring::default();
+        let store = FsStore::new(dir.to_path_buf(), &keyring);
+
+        let lock = store.lock("game").unwrap();
+        assert!(matches!(store.lock("game"), Err(Error::SessionLocked(_))));
+        let other = store.lock("other").unwrap();
+        drop(lock);
+        store.lock("game").unwrap();
+        drop(other);
+    }
+
+    #[test]
+    fn sessions_persist_by_name() {
+        let dir = TempDir::new("sessions");
+        let keyring = Keyring::default();
+        let store = FsStore::new(dir.to_path_buf(), &keyring);
+
+        let mut session = Session::new("first".to_string()).unwrap();
+        session
+            .add_entity(Entity::new("florp".to_string()))
+            .unwrap();
+        session.save(&store).unwrap();
+        Session::copy(&store, "first", "second").unwrap();
+        store.rename("first", "third").unwrap();
+
+        let names: Vec<_> = store
+            .list()
+            .unwrap()
+            .into_iter()
+            .map(|info| info.name)
+            .collect();
+        assert_eq!(names, ["second", "third"]);
+        let third = store.load("third").unwrap();
+        assert_eq!(third.name(), "third");
+        assert_eq!(third.entities(), session.entities());
+        assert!(matches!(
+            store.load("first"),
+            Err(Error::SessionNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn encrypted_sessions_stay_encrypted() {
+        let dir = TempDir::new("encrypted");
+        let keyring = Keyring::default();
+        let passphrases = Passphrases::default();
+        let store = FsStore::new(dir.to_path_buf(), &keyring).with_passphrases(&passphrases);
+
+        let mut session = Session::new("game".to_string()).unwrap();
+        session.save(&store).unwrap();
+        session.add_entity(Entity::new("hero".to_string())).unwrap();
+        session.save(&store).unwrap();
+        store.set_passphrase("game", Some("hunter2")).unwrap();
+        Session::copy(&store, "game", "copy").unwrap();
+        for file in ["game.session", "game.session.1", "copy.session"] {
+            assert!(crypt::is_encrypted(&std::fs::read(dir.join(file)).unwrap()));
+        }
+
+        let wrong = Passphrases::default();
+        wrong.confirm("game", "hunter3");
+        let locked = FsStore::new(dir.to_path_buf(), &keyring).with_passphrases(&wrong);
+        assert!(matches!(locked.load("game"), Err(Error::WrongPassphrase)));
+        let plain = FsStore::new(dir.to_path_buf(), &keyring);
+        assert!(matches!(plain.load("game"), Err(Error::Encrypted(_))));
+
+        store.set_passphrase("game", None).unwrap();
+        assert_eq!(plain.load("game").unwrap().entities(), session.entities());
+    }
+
+    #[test]
+    fn deltas_are_journaled_until_compacted() {
+        let dir = TempDir::new("deltas");
+        let keyring = Keyring::default();
+        let store = FsStore::new(dir.to_path_buf(), &keyring).with_deltas(2);
+        let journal = dir.join("game.session.delta");
+
+        let mut session = Session::new("game".to_string()).unwrap();
+        session.save(&store).unwrap();
+        let snapshot = std::fs::read(dir.join("game.session")).unwrap();
+        session.add_entity(Entity::new("a".to_string())).unwrap();
+        session.save(&store).unwrap();
+        session.undo().unwrap();
+        session.add_entity(Entity::new("b".to_string())).unwrap();
+        session.add_tag("campaign".to_string()).unwrap();
+        session.save(&store).unwrap();
+        assert_eq!(std::fs::read(dir.join("game.session")).unwrap(), snapshot);
+        assert!(journal.exists());
+
+        let fresh = FsStore::new(dir.to_path_buf(), &keyring).with_deltas(2);
+        assert_eq!(fresh.load("game").unwrap(), session);
+        assert_eq!(fresh.metadata("game").unwrap(), session.metadata());
+
+        // The third save is over the limit and rewrites the snapshot.
+        session.add_entity(Entity::new("c".to_string())).unwrap();
+        session.save(&store).unwrap();
+        assert!(!journal.exists());
+        assert_eq!(fresh.load("game").unwrap(), session);
+    }
+
+    #[test]
+    fn sessions_with_long_logs_save_and_reload() {
+        let dir = TempDir::new("long");
+        let mut keyring = Keyring::default();
+        keyring.generate("alice".to_string()).unwrap();
+        let store = FsStore::new(dir.to_path_buf(), &keyring).with_deltas(0);
+
+        let mut session = Session::new("epic".to_string()).unwrap();
+        session.add_entity(Entity::new("gob".to_string())).unwrap();
+        for n in 0..10_000 {
+            session
+                .say("alice", &format!("turn {n}, and still going"))
+                .unwrap();
+        }
+        session.save(&store).unwrap();
+        assert!(std::fs::metadata(dir.join("epic.session")).unwrap().len() > 500_000);
+        let loaded = store.load("epic").unwrap();
+        assert_eq!(loaded.log().len(), 10_001);
+        assert_eq!(loaded, session);
+        assert_eq!(session.size_hint(), session.serialize().len());
+    }
+}
@@ -0,0 +1,111 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+use super::{SessionInfo, SessionStore};
+use crate::error::{Error, Result};
+use crate::metadata::Metadata;
+use crate::serde::{from_bytes, Serialize};
+use crate::session::Session;
+use crate::timestamp::Timestamp;
+
+/// Keeps sessions in memory, encoded exactly as they would be on disk, for
+/// tests and for embedding without a data directory.
+#[derive(Debug, Default)]
+pub struct MemStore {
+    sessions: RefCell<BTreeMap<String, (Timestamp, Vec<u8>)>>,
+}
+
+impl MemStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SessionStore for MemStore {
+    fn save(&self, session: &Session) -> Result<()> {
+        self.sessions.borrow_mut().insert(
+            session.name().to_string(),
+            (session.modified(), session.serialize()),
+        );
+        Ok(())
+    }
+
+    fn load(&self, name: &str) -> Result<Session> {
+        match self.sessions.borrow().get(name) {
+            Some((_, bytes)) => from_bytes(bytes),
+            None => Err(Error::SessionNotFound(name.to_string())),
+        }
+    }
+
+    fn metadata(&self, name: &str) -> Result<Metadata> {
+        match self.sessions.borrow().get(name) {
+            Some((_, bytes)) => Metadata::from_session_bytes(bytes),
+            None => Err(Error::SessionNotFound(name.to_string())),
+        }
+    }
+
+    fn list(&self) -> Result<Vec<SessionInfo>> {
+        let sessions = self.sessions.borrow();
+        let sessions = sessions
+            .iter()
+            .map(|(name, (modified, bytes))| SessionInfo {
+                name: name.clone(),
+                size: bytes.len() as u64,
+                modified: *modified,
+            });
+        Ok(sessions.collect())
+    }
+
+    fn delete(&self, name: &str) -> Result<()> {
+        match self.sessions.borrow_mut().remove(name) {
+            Some(_) => Ok(()),
+            None => Err(Error::SessionNotFound(name.to_string())),
+        }
+    }
+
+    fn exists(&self, name: &str) -> Result<bool> {
+        Ok(self.sessions.borrow().contains_key(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MemStore;
+    use crate::error::Error;
+    use crate::session::Session;
+    use crate::store::SessionStore;
+    use crate::Entity;
+
+    #[test]
+    fn full_session_flow() {
+        let store = MemStore::new();
+        let mut session = Session::new("game".to_string()).unwrap();
+        session.add_entity(Entity::new("hero".to_string())).unwrap();
+        session.save(&store).unwrap();
+
+        Session::copy(&store, "game", "copy").unwrap();
+        store.rename("game", "renamed").unwrap();
+        assert!(matches!(
+            Session::copy(&store, "copy", "renamed"),
+            Err(Error::SessionExists(_))
+        ));
+
+        let names: Vec<_> = store
+            .list()
+            .unwrap()
+            .into_iter()
+            .map(|info| info.name)
+            .collect();
+        assert_eq!(names, ["copy", "renamed"]);
+        let renamed = store.load("renamed").unwrap();
+        assert_eq!(renamed.name(), "renamed");
+        assert_eq!(renamed.entities(), session.entities());
+
+        store.delete("copy").unwrap();
+        assert!(matches!(store.load("copy"), Err(Error::SessionNotFound(_))));
+        assert!(matches!(
+            store.restore("renamed", 1),
+            Err(Error::BackupNotFound(_, 1))
+        ));
+    }
+}
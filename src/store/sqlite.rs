@@ -0,0 +1,322 @@
+use std::path::{Path, PathBuf};
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use super::{backup_count, lock_file, SessionInfo, SessionLock, SessionStore};
+use crate::error::{Error, Result};
+use crate::metadata::Metadata;
+use crate::serde::{from_bytes, Serialize};
+use crate::session::Session;
+use crate::signing::Keyring;
+use crate::timestamp::Timestamp;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS sessions (
+        name     TEXT PRIMARY KEY,
+        created  INTEGER NOT NULL,
+        modified INTEGER NOT NULL,
+        data     BLOB NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS history (
+        session  TEXT NOT NULL,
+        version  INTEGER NOT NULL,
+        modified INTEGER NOT NULL,
+        data     BLOB NOT NULL,
+        PRIMARY KEY (session, version)
+    );
+";
+
+/// Keeps every session in one database file, each sealed with the keyring
+/// as `FsStore` seals its files. Saving moves the previous version into
+/// `history`, which plays the part of the backup files.
+///
+/// Every operation that touches more than one row runs in a transaction, so
+/// concurrent processes never see half a save or half a rename. Sessions
+/// are locked with the same lock files beside the database as `FsStore`
+/// uses, so that a load-modify-save cycle spans two transactions safely.
+#[derive(Debug)]
+pub struct SqliteStore<'a> {
+    conn: Connection,
+    /// Where the lock files go.
+    dir: PathBuf,
+    keyring: &'a Keyring,
+}
+
+impl<'a> SqliteStore<'a> {
+    /// Opens the database at `path`, creating it and its tables if needed.
+    pub fn open(path: &Path, keyring: &'a Keyring) -> Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(SCHEMA)?;
+        let dir = path.parent().unwrap_or(Path::new(".")).to_path_buf();
+        Ok(Self { conn, dir, keyring })
+    }
+
+    /// The sealed session stored as `name`.
+    fn data(&self, name: &str) -> Result<Vec<u8>> {
+        self.conn
+            .query_row(
+                "SELECT data FROM sessions WHERE name = ?1",
+                params![name],
+                |row| row.get(0),
+            )
+            .optional()?
+            .ok_or_else(|| Error::SessionNotFound(name.to_string()))
+    }
+
+    /// Writes `session` inside an already open transaction.
+    fn write(&self, session: &Session) -> Result<()> {
+        let name = session.name();
+        let version: i64 = self.conn.query_row(
+            "SELECT COALESCE(MAX(version), 0) + 1 FROM history WHERE session = ?1",
+            params![name],
+            |row| row.get(0),
+        )?;
+        let moved = self.conn.execute(
+            "INSERT INTO history (session, version, modified, data)
+             SELECT name, ?2, modified, data FROM sessions WHERE name = ?1",
+            params![name, version],
+        )?;
+        if moved > 0 {
+            self.conn.execute(
+                "DELETE FROM history WHERE session = ?1 AND version <= ?2",
+                params![name, version - i64::from(backup_count())],
+            )?;
+        }
+        self.conn.execute(
+            "INSERT INTO sessions (name, created, modified, data) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (name) DO UPDATE SET
+                 created = excluded.created,
+                 modified = excluded.modified,
+                 data = excluded.data",
+            params![
+                name,
+                millis(session.created())?,
+                millis(session.modified())?,
+                self.keyring.seal(&session.serialize())
+            ],
+        )?;
+        Ok(())
+    }
+}
+
+impl SessionStore for SqliteStore<'_> {
+    fn lock(&self, name: &str) -> Result<SessionLock> {
+        lock_file(&self.dir, name)
+    }
+
+    fn save(&self, session: &Session) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        self.write(session)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn load(&self, name: &str) -> Result<Session> {
+        from_bytes(self.keyring.open(&self.data(name)?)?)
+    }
+
+    fn metadata(&self, name: &str) -> Result<Metadata> {
+        Metadata::from_session_bytes(self.keyring.open(&self.data(name)?)?)
+    }
+
+    fn list(&self) -> Result<Vec<SessionInfo>> {
+        let mut statement = self
+            .conn
+            .prepare("SELECT name, length(data), modified FROM sessions ORDER BY name")?;
+        let rows = statement.query_map([], |row| {
+            Ok((row.get(0)?, row.get::<_, i64>(1)?, row.get::<_, i64>(2)?))
+        })?;
+        let mut sessions = vec![];
+        for row in rows {
+            let (name, size, modified) = row?;
+            let modified = u128::try_from(modified).map_err(|_| Error::TimestampOutOfRange)?;
+            sessions.push(SessionInfo {
+                name,
+                size: size.unsigned_abs(),
+                modified: Timestamp::from_millis(modified),
+            });
+        }
+        Ok(sessions)
+    }
+
+    /// Removes the session along with its history.
+    fn delete(&self, name: &str) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        if tx.execute("DELETE FROM sessions WHERE name = ?1", params![name])? == 0 {
+            return Err(Error::SessionNotFound(name.to_string()));
+        }
+        tx.execute("DELETE FROM history WHERE session = ?1", params![name])?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn exists(&self, name: &str) -> Result<bool> {
+        let found = self
+            .conn
+            .query_row(
+                "SELECT 1 FROM sessions WHERE name = ?1",
+                params![name],
+                |_| Ok(()),
+            )
+            .optional()?;
+        Ok(found.is_some())
+    }
+
+    /// Carries the history along with the session.
+    fn rename(&self, old: &str, new: &str) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        let mut session = self.load(old)?;
+        super::ensure_absent(self, new)?;
+        session.set_name(new.to_string())?;
+        session.touch()?;
+        tx.execute("DELETE FROM sessions WHERE name = ?1", params![old])?;
+        tx.execute(
+            "UPDATE history SET session = ?2 WHERE session = ?1",
+            params![old, new],
+        )?;
+        self.write(&session)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// The version being replaced joins the history, so a restore can be
+    /// undone by restoring again.
+    fn restore(&self, name: &str, n: u32) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        let data: Vec<u8> = tx
+            .query_row(
+                "SELECT data FROM history WHERE session = ?1
+                 ORDER BY version DESC LIMIT 1 OFFSET ?2",
+                params![name, n.saturating_sub(1)],
+                |row| row.get(0),
+            )
+            .optional()?
+            .filter(|_| n > 0)
+            .ok_or_else(|| Error::BackupNotFound(name.to_string(), n))?;
+        let mut session: Session = from_bytes(self.keyring.open(&data)?)?;
+        // The version predates any rename that carried it along.
+        session.set_name(name.to_string())?;
+        session.touch()?;
+        self.write(&session)?;
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+fn millis(timestamp: Timestamp) -> Result<i64> {
+    i64::try_from(timestamp.as_millis()).map_err(|_| Error::TimestampOutOfRange)
+}
+
+#[cfg(test)]
+mod tests {
+    use rusqlite::params;
+
+    use super::SqliteStore;
+    use crate::error::Error;
+    use crate::serde::HEADER_LEN;
+    use crate::session::Session;
+    use crate::signing::Keyring;
+    use crate::store::SessionStore;
+    use crate::temp_dir::TempDir;
+    use crate::Entity;
+
+    #[test]
+    fn sessions_and_history_persist() {
+        let dir = TempDir::new("sqlite");
+        let path = dir.join("relay_code.db");
+        let keyring = Keyring::default();
+        let store = SqliteStore::open(&path, &keyring).unwrap();
+
+        let mut session = Session::new("first".to_string()).unwrap();
+        session.save(&store).unwrap();
+        session
+            .add_entity(Entity::new("florp".to_string()))
+            .unwrap();
+        session.save(&store).unwrap();
+        store.rename("first", "second").unwrap();
+
+        let names: Vec<_> = store
+            .list()
+            .unwrap()
+            .into_iter()
+            .map(|info| info.name)
+            .collect();
+        assert_eq!(names, ["second"]);
+        assert!(matches!(
+            store.load("first"),
+            Err(Error::SessionNotFound(_))
+        ));
+
+        // The rename carried the pre-entity version along as history 1.
+        store.restore("second", 1).unwrap();
+        let restored = store.load("second").unwrap();
+        assert_eq!(restored.name(), "second");
+        assert!(restored.entities().is_empty());
+        assert!(matches!(
+            store.restore("second", 9),
+            Err(Error::BackupNotFound(_, 9))
+        ));
+
+        store.delete("second").unwrap();
+        assert!(store.list().unwrap().is_empty());
+    }
+
+    #[test]
+    fn handles_on_one_database_lock_each_other_out() {
+        let dir = TempDir::new("sqlite");
+        let path = dir.join("relay_code.db");
+        let keyring = Keyring::default();
+        let (mine, theirs) = (
+            SqliteStore::open(&path, &keyring).unwrap(),
+            SqliteStore::open(&path, &keyring).unwrap(),
+        );
+        Session::new("game".to_string())
+            .unwrap()
+            .save(&mine)
+            .unwrap();
+
+        let lock = mine.lock("game").unwrap();
+        let mut session = mine.load("game").unwrap();
+        assert!(matches!(theirs.lock("game"), Err(Error::SessionLocked(_))));
+        theirs.lock("other").unwrap();
+        session
+            .add_entity(Entity::new("troll".to_string()))
+            .unwrap();
+        session.save(&mine).unwrap();
+        drop(lock);
+
+        let _lock = theirs.lock("game").unwrap();
+        assert_eq!(theirs.load("game").unwrap().entities().len(), 1);
+        assert!(matches!(mine.lock("game"), Err(Error::SessionLocked(_))));
+    }
+
+    #[test]
+    fn sessions_are_sealed_with_the_keyring() {
+        let dir = TempDir::new("sealed");
+        let path = dir.join("relay_code.db");
+        let mut alice = Keyring::default();
+        alice.generate("alice".to_string()).unwrap();
+        let store = SqliteStore::open(&path, &alice).unwrap();
+        let mut session = Session::new("game".to_string()).unwrap();
+        session
+            .add_entity(Entity::new("troll".to_string()))
+            .unwrap();
+        session.save(&store).unwrap();
+        session.save(&store).unwrap();
+        assert_eq!(store.load("game").unwrap().entities().len(), 1);
+        assert_eq!(store.metadata("game").unwrap().entities, 1);
+        store.restore("game", 1).unwrap();
+
+        let mut bob = Keyring::default();
+        bob.generate("bob".to_string()).unwrap();
+        let strange = SqliteStore::open(&path, &bob).unwrap();
+        assert!(matches!(strange.load("game"), Err(Error::UnknownSigner(_))));
+        let mut data = store.data("game").unwrap();
+        data[HEADER_LEN + 8] ^= 1;
+        store
+            .conn
+            .execute("UPDATE sessions SET data = ?1", params![data])
+            .unwrap();
+        assert!(matches!(store.load("game"), Err(Error::InvalidSignature)));
+    }
+}
@@ -0,0 +1,159 @@
+use std::fmt::Display;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// How a piece of terminal output is colored, if color is on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    Bold,
+    Dim,
+    Red,
+    Green,
+    Yellow,
+    Cyan,
+}
+
+impl Style {
+    fn code(self) -> &'static str {
+        match self {
+            Self::Bold => "1",
+            Self::Dim => "2",
+            Self::Red => "31",
+            Self::Green => "32",
+            Self::Yellow => "33",
+            Self::Cyan => "36",
+        }
+    }
+}
+
+static COLOR: AtomicBool = AtomicBool::new(false);
+
+/// Turns color on or off for the rest of the process. It starts off.
+pub fn set_color(color: bool) {
+    COLOR.store(color, Ordering::Relaxed);
+}
+
+/// Whether to color output going to a terminal, the first of these that
+/// has a say deciding: `--no-color`, a non-empty `NO_COLOR`, `color` in the
+/// config file, and then whether there is a terminal at all.
+pub fn wants_color(no_color_flag: bool, configured: Option<bool>, terminal: bool) -> bool {
+    if no_color_flag || std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+        return false;
+    }
+    configured.unwrap_or(true) && terminal
+}
+
+/// `text` in `style`, or as it is with color off.
+pub fn paint(text: impl Display, style: Style) -> String {
+    if COLOR.load(Ordering::Relaxed) {
+        format!("\x1b[{}m{text}\x1b[0m", style.code())
+    } else {
+        text.to_string()
+    }
+}
+
+/// Rows lined up in columns under a bold header, two spaces apart.
+#[derive(Debug, Default, Clone)]
+pub struct Table {
+    headers: Vec<String>,
+    /// Whether each column is aligned right, as numbers are.
+    right: Vec<bool>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    /// Columns whose header starts with `>` are aligned right.
+    pub fn new(headers: &[&str]) -> Self {
+        Self {
+            headers: headers
+                .iter()
+                .map(|header| header.trim_start_matches('>').to_string())
+                .collect(),
+            right: headers
+                .iter()
+                .map(|header| header.starts_with('>'))
+                .collect(),
+            rows: vec![],
+        }
+    }
+
+    pub fn row(&mut self, cells: Vec<String>) {
+        self.rows.push(cells);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    fn line(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        cells: &[String],
+        widths: &[usize],
+        header: bool,
+    ) -> std::fmt::Result {
+        let last = cells.len().saturating_sub(1);
+        let mut line = String::new();
+        for (i, cell) in cells.iter().enumerate() {
+            let width = widths[i];
+            let cell = match (self.right[i], i == last) {
+                (true, _) => format!("{cell:>width$}"),
+                (false, false) => format!("{cell:<width$}"),
+                (false, true) => cell.clone(),
+            };
+            if i > 0 {
+                line.push_str("  ");
+            }
+            line.push_str(&cell);
+        }
+        let line = line.trim_end();
+        if header {
+            writeln!(f, "{}", paint(line, Style::Bold))
+        } else {
+            writeln!(f, "{line}")
+        }
+    }
+
+    fn widths(&self) -> Vec<usize> {
+        let mut widths: Vec<_> = self.headers.iter().map(|h| h.chars().count()).collect();
+        for row in &self.rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+        widths
+    }
+}
+
+impl Display for Table {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let widths = self.widths();
+        self.line(f, &self.headers, &widths, true)?;
+        for row in &self.rows {
+            self.line(f, row, &widths, false)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{wants_color, Table};
+
+    #[test]
+    fn tables_line_up_and_color_can_be_refused() {
+        let mut table = Table::new(&["NAME", ">HP", "NOTES"]);
+        table.row(vec![
+            "goblin".to_string(),
+            "7".to_string(),
+            "angry".to_string(),
+        ]);
+        table.row(vec!["orc".to_string(), "12".to_string(), String::new()]);
+        assert_eq!(
+            table.to_string(),
+            "NAME    HP  NOTES\ngoblin   7  angry\norc     12\n"
+        );
+        assert!(!wants_color(true, Some(true), true));
+        assert!(!wants_color(false, Some(false), true));
+        assert!(!wants_color(false, None, false));
+    }
+}
@@ -0,0 +1,67 @@
+use crate::error::Error;
+
+/// The number of single-character insertions, deletions, substitutions and
+/// swaps of neighbours it takes to turn `a` into `b`.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // Rows of the distance matrix from two rows back up to this one.
+    let mut before: Vec<usize> = vec![0; b.len() + 1];
+    let mut last: Vec<usize> = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        let mut row = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            row[j] = (last[j] + 1).min(row[j - 1] + 1).min(last[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(before[j - 2] + 1);
+            }
+        }
+        before = std::mem::replace(&mut last, row);
+    }
+    last[b.len()]
+}
+
+/// The candidate closest to `name`, if any is close enough to be what was
+/// meant: about one edit for every three characters.
+pub fn closest<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let limit = (name.chars().count() / 3).max(1);
+    candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= limit)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// `err` suggesting the candidate closest to `name`, or as it is if none
+/// is close.
+pub fn did_you_mean<'a>(
+    err: Error,
+    name: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> Error {
+    match closest(name, candidates) {
+        Some(candidate) if candidate != name => {
+            Error::DidYouMean(Box::new(err), candidate.to_string())
+        }
+        _ => err,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{closest, edit_distance};
+
+    #[test]
+    fn the_closest_name_is_suggested() {
+        assert_eq!(edit_distance("attack", "attack"), 0);
+        assert_eq!(edit_distance("atack", "attack"), 1);
+        assert_eq!(edit_distance("shwo", "show"), 1);
+        assert_eq!(edit_distance("", "rest"), 4);
+        let commands = ["show", "load", "list", "delete"];
+        assert_eq!(closest("shwo", commands), Some("show"));
+        assert_eq!(closest("delte", commands), Some("delete"));
+        assert_eq!(closest("frobnicate", commands), None);
+    }
+}
@@ -0,0 +1,133 @@
+use crate::error::Result;
+use crate::provider::SessionProvider;
+use crate::turnfile::Merged;
+
+/// What syncing a session with a peer did.
+#[derive(Debug, PartialEq)]
+pub struct Synced {
+    /// The peer's events merged into the local copy.
+    pub received: Merged,
+    /// The local events merged into the peer's copy.
+    pub sent: Merged,
+    /// How many events both copies now begin with, for the next sync to
+    /// start after.
+    pub shared: usize,
+}
+
+/// Brings session `name` in `local` and in `peer` up to date with each
+/// other, sending each the events the other has after their first `from`.
+/// Events that no longer apply are skipped and reported, as `merge` does.
+/// If the copies differ before `from`, the whole logs are exchanged.
+pub fn sync(
+    name: &str,
+    local: &dyn SessionProvider,
+    peer: &dyn SessionProvider,
+    from: usize,
+) -> Result<Synced> {
+    let mut mine = local.events(name, from)?;
+    let mut theirs = peer.events(name, from)?;
+    if mine.base != theirs.base || mine.before != theirs.before {
+        mine = local.events(name, 0)?;
+        theirs = peer.events(name, 0)?;
+    }
+    let received = local.receive(&theirs)?;
+    let sent = peer.receive(&mine)?;
+
+    // Each copy appended what it lacked after its own events, so they may
+    // order the rest differently; only the common beginning is settled.
+    let base = mine.base as usize;
+    let mine = local.events(name, base)?;
+    let theirs = peer.events(name, base)?;
+    let common = mine
+        .events
+        .iter()
+        .zip(&theirs.events)
+        .take_while(|(mine, theirs)| mine == theirs)
+        .count();
+    Ok(Synced {
+        received,
+        sent,
+        shared: base + common,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::sync;
+    use crate::actions::{Action, ActionKind};
+    use crate::error::Error;
+    use crate::players::Player;
+    use crate::provider::{LocalSessions, SessionProvider};
+    use crate::session::Session;
+    use crate::store::{MemStore, SessionStore};
+    use crate::Entity;
+
+    #[test]
+    fn peers_end_up_with_each_others_events() {
+        let (here, there) = (MemStore::new(), MemStore::new());
+        let local = LocalSessions::new(Path::new(""), &here);
+        let peer = LocalSessions::new(Path::new(""), &there);
+        local.create("game").unwrap();
+        peer.create("game").unwrap();
+        let mut session = here.load("game").unwrap();
+        session
+            .add_entity(Entity::new("troll".to_string()))
+            .unwrap();
+        session.add_entity(Entity::new("elf".to_string())).unwrap();
+        session.save(&here).unwrap();
+
+        let synced = sync("game", &local, &peer, 0).unwrap();
+        assert_eq!((synced.received.applied, synced.sent.applied), (0, 2));
+        assert_eq!(synced.shared, 2);
+
+        let fight = Action::new(ActionKind::Fight, "troll".to_string()).unwrap();
+        local.apply("game", fight, false).unwrap();
+        let rest = Action::new(ActionKind::Fight, "elf".to_string()).unwrap();
+        peer.apply("game", rest, false).unwrap();
+        let synced = sync("game", &local, &peer, synced.shared).unwrap();
+        assert_eq!((synced.received.applied, synced.sent.applied), (1, 1));
+        assert!(synced.received.conflicts.is_empty());
+        let (mine, theirs) = (here.load("game").unwrap(), there.load("game").unwrap());
+        assert_eq!(mine.log().len(), 4);
+        assert!(mine.log().iter().all(|event| theirs.log().contains(event)));
+
+        // Nothing new on either side changes nothing.
+        let again = sync("game", &local, &peer, synced.shared).unwrap();
+        assert_eq!((again.received.applied, again.sent.applied), (0, 0));
+    }
+
+    #[test]
+    fn players_only_push_their_own_events() {
+        let (here, there) = (MemStore::new(), MemStore::new());
+        let mut session = Session::new("game".to_string()).unwrap();
+        for player in ["alice", "bob"] {
+            session
+                .add_player(Player::new(player.to_string(), None))
+                .unwrap();
+        }
+        session
+            .add_entity(Entity::new("troll".to_string()))
+            .unwrap();
+        session.save(&here).unwrap();
+        session.save(&there).unwrap();
+        let local = LocalSessions::new(Path::new(""), &here);
+        let peer = LocalSessions::new(Path::new(""), &there);
+        let fight = || Action::new(ActionKind::Fight, "troll".to_string()).unwrap();
+
+        local
+            .by(Some("alice"))
+            .apply("game", fight(), false)
+            .unwrap();
+        let synced = sync("game", &local, &peer.by(Some("alice")), 3).unwrap();
+        assert_eq!(synced.sent.applied, 1);
+
+        local.by(Some("bob")).apply("game", fight(), false).unwrap();
+        let err = sync("game", &local, &peer.by(Some("alice")), synced.shared);
+        assert!(matches!(err, Err(Error::NotSentBy(..))));
+        let err = sync("game", &local, &peer.by(None), synced.shared);
+        assert!(matches!(err, Err(Error::Unauthenticated)));
+        assert_eq!(there.load("game").unwrap().log().len(), 4);
+    }
+}
@@ -0,0 +1,164 @@
+use std::fmt::Display;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::{Error, Result};
+use crate::serde::{Field, FieldWriter, SerializeField};
+
+/// A point in time as milliseconds since the Unix epoch, UTC.
+///
+/// Timestamps are written as a `U128` field. With the `chrono` feature,
+/// `chrono::DateTime<Utc>` reads and writes the same field, so the two are
+/// interchangeable on the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Timestamp(u128);
+
+impl Timestamp {
+    pub fn now() -> Result<Self> {
+        SystemTime::now().try_into()
+    }
+
+    pub fn from_millis(millis: u128) -> Self {
+        Self(millis)
+    }
+
+    pub fn as_millis(self) -> u128 {
+        self.0
+    }
+}
+
+impl TryFrom<SystemTime> for Timestamp {
+    type Error = Error;
+
+    fn try_from(value: SystemTime) -> Result<Self> {
+        Ok(Self(value.duration_since(UNIX_EPOCH)?.as_millis()))
+    }
+}
+
+impl Display for Timestamp {
+    /// Formats as RFC 3339, e.g. `2024-02-01T09:30:00.000Z`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let millis = self.0 % 1000;
+        let secs = self.0 / 1000;
+        let (hour, minute, second) = (secs / 3600 % 24, secs / 60 % 60, secs % 60);
+        let (year, month, day) = civil_from_days(secs / 86_400);
+        write!(
+            f,
+            "{year:04}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}.{millis:03}Z"
+        )
+    }
+}
+
+/// Converts days since 1970-01-01 to a proleptic Gregorian date, following
+/// Howard Hinnant's `civil_from_days`.
+fn civil_from_days(days: u128) -> (u128, u128, u128) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u128::from(month <= 2);
+    (year, month, day)
+}
+
+impl SerializeField for Timestamp {
+    fn serialize_field(&self, writer: &mut FieldWriter<'_>) {
+        writer.write_u128(self.0);
+    }
+}
+
+impl TryFrom<Field<'_>> for Timestamp {
+    type Error = Error;
+
+    fn try_from(value: Field<'_>) -> Result<Self> {
+        u128::try_from(value).map(Self)
+    }
+}
+
+#[cfg(feature = "chrono")]
+mod chrono_impls {
+    use chrono::{DateTime, Utc};
+
+    use super::Timestamp;
+    use crate::error::{Error, Result};
+    use crate::serde::{Field, FieldWriter, SerializeField};
+
+    /// Sub-millisecond precision is dropped and instants before the Unix
+    /// epoch are clamped to it.
+    impl From<DateTime<Utc>> for Timestamp {
+        fn from(value: DateTime<Utc>) -> Self {
+            Self(value.timestamp_millis().max(0) as u128)
+        }
+    }
+
+    impl TryFrom<Timestamp> for DateTime<Utc> {
+        type Error = Error;
+
+        fn try_from(value: Timestamp) -> Result<Self> {
+            i64::try_from(value.0)
+                .ok()
+                .and_then(DateTime::from_timestamp_millis)
+                .ok_or(Error::TimestampOutOfRange)
+        }
+    }
+
+    impl SerializeField for DateTime<Utc> {
+        fn serialize_field(&self, writer: &mut FieldWriter<'_>) {
+            Timestamp::from(*self).serialize_field(writer);
+        }
+    }
+
+    impl TryFrom<Field<'_>> for DateTime<Utc> {
+        type Error = Error;
+
+        fn try_from(value: Field<'_>) -> Result<Self> {
+            Timestamp::try_from(value)?.try_into()
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use chrono::{DateTime, TimeZone, Utc};
+
+        use crate::serde::{FieldReader, FieldWriter};
+        use crate::timestamp::Timestamp;
+
+        #[test]
+        fn datetime_shares_the_timestamp_encoding() {
+            let datetime = Utc.with_ymd_and_hms(2024, 2, 1, 9, 30, 0).unwrap();
+
+            let mut bytes = vec![];
+            FieldWriter::new(&mut bytes).write(&datetime);
+            let mut reader = FieldReader::new(&bytes);
+            let timestamp: Timestamp = reader.read_field().unwrap();
+            assert_eq!(timestamp.to_string(), "2024-02-01T09:30:00.000Z");
+
+            let mut reader = FieldReader::new(&bytes);
+            let actual: DateTime<Utc> = reader.read_field().unwrap();
+            assert_eq!(actual, datetime);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Timestamp;
+
+    #[test]
+    fn formats_as_rfc3339() {
+        assert_eq!(
+            Timestamp::from_millis(0).to_string(),
+            "1970-01-01T00:00:00.000Z"
+        );
+        assert_eq!(
+            Timestamp::from_millis(1_706_779_800_123).to_string(),
+            "2024-02-01T09:30:00.123Z"
+        );
+        assert_eq!(
+            Timestamp::from_millis(951_782_400_000).to_string(),
+            "2000-02-29T00:00:00.000Z"
+        );
+    }
+}
@@ -0,0 +1,172 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use rand_core::{OsRng, RngCore};
+
+use crate::error::{Error, Result};
+use crate::players;
+use crate::signing::{to_hex, write_private};
+
+const FILENAME: &str = "relay_code.tokens";
+
+const TOKEN_LEN: usize = 32;
+
+/// The tokens a server has issued, which clients present to act as the
+/// player each was issued to, or to watch as a spectator. A server that has
+/// issued none lets any client act, anonymously.
+///
+/// Stored as plain text, readable only by its owner, one entry per line:
+///
+/// ```text
+/// token <player> <token hex>
+/// spectator <name> <token hex>
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct Tokens {
+    /// The token of each player.
+    issued: BTreeMap<String, String>,
+    /// The token of each spectator, who may only watch.
+    spectators: BTreeMap<String, String>,
+}
+
+impl Tokens {
+    /// Loads the tokens from `dir`, treating a missing file as none issued.
+    pub fn load(dir: &Path) -> Result<Self> {
+        match fs::read_to_string(dir.join(FILENAME)) {
+            Ok(text) => Self::parse(&text),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn parse(text: &str) -> Result<Self> {
+        let mut tokens = Self::default();
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.split_whitespace();
+            match (parts.next(), parts.next(), parts.next(), parts.next()) {
+                (Some("token"), Some(player), Some(token), None) => {
+                    tokens.issued.insert(player.to_string(), token.to_string());
+                }
+                (Some("spectator"), Some(name), Some(token), None) => {
+                    tokens
+                        .spectators
+                        .insert(name.to_string(), token.to_string());
+                }
+                _ => return Err(Error::InvalidToken),
+            }
+        }
+        Ok(tokens)
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<()> {
+        write_private(&dir.join(FILENAME), &self.to_text())
+    }
+
+    fn to_text(&self) -> String {
+        let players = self
+            .issued
+            .iter()
+            .map(|(player, token)| format!("token {player} {token}\n"));
+        let spectators = self
+            .spectators
+            .iter()
+            .map(|(name, token)| format!("spectator {name} {token}\n"));
+        players.chain(spectators).collect()
+    }
+
+    /// Issues `player` a fresh token, revoking any it had, and returns it.
+    /// Fails if `player` is not a valid player name.
+    pub fn generate(&mut self, player: String) -> Result<String> {
+        players::validate_name(&player)?;
+        self.spectators.remove(&player);
+        let token = fresh();
+        self.issued.insert(player, token.clone());
+        Ok(token)
+    }
+
+    /// As `generate`, but for a spectator, whose token only lets them watch.
+    pub fn generate_spectator(&mut self, name: String) -> Result<String> {
+        players::validate_name(&name)?;
+        self.issued.remove(&name);
+        let token = fresh();
+        self.spectators.insert(name, token.clone());
+        Ok(token)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.issued.is_empty() && self.spectators.is_empty()
+    }
+
+    /// The player `token` was issued to. Every token is compared in full,
+    /// so how long this takes says nothing about how close a guess was.
+    pub fn player(&self, token: &str) -> Option<&str> {
+        find(&self.issued, token)
+    }
+
+    /// The spectator `token` was issued to, compared as `player` does.
+    pub fn spectator(&self, token: &str) -> Option<&str> {
+        find(&self.spectators, token)
+    }
+}
+
+fn fresh() -> String {
+    let mut token = [0; TOKEN_LEN];
+    OsRng.fill_bytes(&mut token);
+    to_hex(&token)
+}
+
+fn find<'a>(issued: &'a BTreeMap<String, String>, token: &str) -> Option<&'a str> {
+    let mut found = None;
+    for (name, issued) in issued {
+        if same(issued.as_bytes(), token.as_bytes()) {
+            found = Some(name.as_str());
+        }
+    }
+    found
+}
+
+fn same(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Tokens;
+    use crate::error::Error;
+
+    #[test]
+    fn tokens_name_their_player_until_replaced() {
+        let mut tokens = Tokens::default();
+        assert!(tokens.is_empty());
+        let old = tokens.generate("alice".to_string()).unwrap();
+        let bob = tokens.generate("bob".to_string()).unwrap();
+        let alice = tokens.generate("alice".to_string()).unwrap();
+        assert_ne!(old, alice);
+        assert_eq!(tokens.player(&alice), Some("alice"));
+        assert_eq!(tokens.player(&bob), Some("bob"));
+        assert_eq!(tokens.player(&old), None);
+        assert_eq!(tokens.player(""), None);
+
+        let carol = tokens.generate_spectator("carol".to_string()).unwrap();
+        assert_eq!(tokens.player(&carol), None);
+        assert_eq!(tokens.spectator(&carol), Some("carol"));
+        assert_eq!(tokens.spectator(&bob), None);
+
+        let copy = Tokens::parse(&tokens.to_text()).unwrap();
+        assert_eq!(copy.player(&bob), Some("bob"));
+        assert_eq!(copy.spectator(&carol), Some("carol"));
+        assert!(Tokens::parse("token alice").is_err());
+
+        for name in ["", "alice smith", "alice\nspectator", "bob\t"] {
+            let err = tokens.generate(name.to_string());
+            assert!(matches!(err, Err(Error::InvalidPlayer(_))));
+            let err = tokens.generate_spectator(name.to_string());
+            assert!(matches!(err, Err(Error::InvalidPlayer(_))));
+        }
+    }
+}
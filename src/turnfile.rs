@@ -0,0 +1,474 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use crate::atomic;
+use crate::error::{Error, Result};
+use crate::log::Event;
+use crate::serde::{from_bytes, Deserialize, FieldReader, FieldWriter, Serialize, HEADER_LEN};
+use crate::session::Session;
+use crate::signing::{self, Keyring, Signer};
+use crate::timestamp::Timestamp;
+
+/// First field of every turn file, so stray files are rejected up front.
+const MAGIC: &str = "relay_code turn";
+
+/// Bumped whenever the layout of the contents changes.
+pub const FORMAT_VERSION: u16 = 1;
+
+/// Where each session's last exchange is remembered.
+const EXCHANGES: &str = "relay_code.exchanges";
+
+/// The events one player added to a session since it was last exchanged,
+/// for play by mail: small enough to attach to an email, and sealed with
+/// the sender's keyring like an archive. The file is three fields:
+///
+/// ```text
+/// Str(MAGIC) U16(FORMAT_VERSION) Bytes(sealed contents)
+/// ```
+///
+/// and the contents, where the event before is left out if there is none
+/// and the events run to the end, one field each rather than nested in a
+/// list. Contents too long for a `u16` length get a long header, as any
+/// field does; see `serde::LONG_HEADER_LEN`:
+///
+/// ```text
+/// Timestamp(exported) Str(session) U32(base) [Enum(event before)] Enum(event)...
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct TurnFile {
+    pub exported: Timestamp,
+    pub session: String,
+    /// How many events the log had before these.
+    pub base: u32,
+    /// The last of those, which the receiver's copy must have too.
+    pub before: Option<Event>,
+    pub events: Vec<Event>,
+}
+
+/// What merging a turn into a copy of its session did.
+#[derive(Debug, Default, PartialEq)]
+pub struct Merged {
+    /// How many events were new to the copy.
+    pub applied: usize,
+    /// Those that no longer applied, each with why.
+    pub conflicts: Vec<String>,
+}
+
+impl TurnFile {
+    /// The events `session` added after its first `base`, failing if there
+    /// are none.
+    pub fn since(session: &Session, base: usize) -> Result<Self> {
+        let turn = Self::after(session, base)?;
+        if turn.events.is_empty() {
+            return Err(Error::NothingToExchange(turn.session));
+        }
+        Ok(turn)
+    }
+
+    /// The events `session` has after its first `base`, which may be none.
+    pub fn after(session: &Session, base: usize) -> Result<Self> {
+        let log = session.log();
+        // Undo can leave fewer events than were exchanged.
+        let base = base.min(log.len());
+        Ok(Self {
+            exported: Timestamp::now()?,
+            session: session.name().to_string(),
+            base: base as u32,
+            before: base.checked_sub(1).map(|last| log[last].clone()),
+            events: log[base..].to_vec(),
+        })
+    }
+
+    /// Applies the events `session` does not already have, returning how
+    /// many. `session` must have every event the sender's copy had before
+    /// them; a change that no longer applies fails the whole turn.
+    pub fn apply_to(&self, session: &mut Session) -> Result<usize> {
+        let mut applied = 0;
+        for event in self.missing_from(session)? {
+            session.record(event.clone())?;
+            applied += 1;
+        }
+        Ok(applied)
+    }
+
+    /// As `apply_to`, but as `Session::merge` does, skipping the events
+    /// that no longer apply rather than failing.
+    pub fn merge_into(&self, session: &mut Session) -> Result<Merged> {
+        let mut merged = Merged::default();
+        for event in self.missing_from(session)? {
+            match session.record(event.clone()) {
+                Ok(_) => merged.applied += 1,
+                Err(reason) => merged.conflicts.push(format!("{event}: {reason}")),
+            }
+        }
+        Ok(merged)
+    }
+
+    /// Fails unless `player` may send every event `session` lacks: their
+    /// own actions, passes and messages, never anyone else's nor the events
+    /// that set a session up.
+    pub fn check_sent_by(&self, session: &Session, player: &str) -> Result<()> {
+        self.check_events(session, player, |actor| actor == Some(player))
+    }
+
+    /// Fails unless `signer` may have sent the events `session` lacks. A
+    /// player on its roster must have signed with the key it has for them,
+    /// if any, and may send only what `check_sent_by` allows. Anyone else
+    /// the keyring trusts keeps the session, and may set it up, but still
+    /// not act for its players. Unsigned turns are only for sessions with
+    /// no players, as there is no telling who sent them.
+    pub fn check_signed_by(&self, session: &Session, signer: Option<&Signer>) -> Result<()> {
+        let Some(signer) = signer else {
+            return match session.roster().is_empty() {
+                true => Ok(()),
+                false => Err(Error::Unsigned),
+            };
+        };
+        let name = signer.player.as_str();
+        let Ok(player) = session.player(name) else {
+            return self.check_events(session, name, |actor| {
+                actor.is_none_or(|actor| actor == name)
+            });
+        };
+        if let Some(key) = &player.key {
+            if signing::public_key(key)? != signer.key {
+                return Err(Error::WrongKey(name.to_string()));
+            }
+        }
+        self.check_sent_by(session, name)
+    }
+
+    fn check_events(
+        &self,
+        session: &Session,
+        player: &str,
+        allowed: impl Fn(Option<&str>) -> bool,
+    ) -> Result<()> {
+        match self
+            .missing_from(session)?
+            .into_iter()
+            .find(|event| !allowed(event.actor()))
+        {
+            Some(event) => Err(Error::NotSentBy(player.to_string(), event.to_string())),
+            None => Ok(()),
+        }
+    }
+
+    /// The events `session` lacks, once it is checked to have every event
+    /// before them. Whatever it added since is kept, as merge keeps it.
+    fn missing_from(&self, session: &Session) -> Result<Vec<&Event>> {
+        let base = self.base as usize;
+        let log = session.log();
+        if log.len() < base {
+            return Err(Error::TurnOutOfOrder(session.name().to_string(), base));
+        }
+        if base.checked_sub(1).map(|last| &log[last]) != self.before.as_ref() {
+            return Err(Error::Diverged(session.name().to_string()));
+        }
+        let mine = &log[base..];
+        Ok(self
+            .events
+            .iter()
+            .filter(|event| !mine.contains(event))
+            .collect())
+    }
+
+    pub fn to_bytes(&self, keyring: &Keyring) -> Vec<u8> {
+        let sealed = keyring.seal(&self.serialize());
+        let mut bytes = Vec::with_capacity(3 * HEADER_LEN + MAGIC.len() + 2 + sealed.len());
+        let mut writer = FieldWriter::new(&mut bytes);
+        writer.write_str(MAGIC);
+        writer.write_u16(FORMAT_VERSION);
+        writer.write_bytes(&sealed);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8], keyring: &Keyring) -> Result<Self> {
+        Self::open(bytes, keyring).map(|(turn, _)| turn)
+    }
+
+    /// As `from_bytes`, also returning who signed the file, if anyone did.
+    pub fn open(bytes: &[u8], keyring: &Keyring) -> Result<(Self, Option<Signer>)> {
+        let mut reader = FieldReader::new(bytes);
+        match reader.read_field::<&str>() {
+            Ok(MAGIC) => {}
+            _ => return Err(Error::NotATurnFile),
+        }
+        let version: u16 = reader.read_field()?;
+        if version != FORMAT_VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+        let sealed: &[u8] = reader.read_field()?;
+        reader.finish()?;
+        let (contents, signer) = keyring.open_signed(sealed)?;
+        Ok((from_bytes(contents)?, signer))
+    }
+}
+
+impl Serialize for TurnFile {
+    fn serialize_to(&self, writer: &mut FieldWriter<'_>) {
+        writer.write(&self.exported);
+        writer.write_str(&self.session);
+        writer.write_u32(self.base);
+        if let Some(before) = &self.before {
+            writer.write(before);
+        }
+        for event in &self.events {
+            writer.write(event);
+        }
+    }
+}
+
+impl Deserialize for TurnFile {
+    fn deserialize(reader: &mut FieldReader<'_>) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let exported = reader.read_field()?;
+        let session = reader.read_field()?;
+        let base = reader.read_field()?;
+        let before = match base {
+            0 => None,
+            _ => Some(reader.read_field()?),
+        };
+        let mut events = vec![];
+        while !reader.is_empty() {
+            events.push(reader.read_field()?);
+        }
+        Ok(Self {
+            exported,
+            session,
+            base,
+            before,
+            events,
+        })
+    }
+}
+
+/// How many events of each session had been exchanged as of the last turn
+/// file exported or applied, kept in the data directory one per line:
+///
+/// ```text
+/// <events> <session>
+/// ```
+#[derive(Debug, Default)]
+pub struct Exchanges {
+    sessions: BTreeMap<String, usize>,
+}
+
+impl Exchanges {
+    /// Loads the exchanges from `dir`, treating a missing file as none.
+    pub fn load(dir: &Path) -> Result<Self> {
+        let text = match fs::read_to_string(dir.join(EXCHANGES)) {
+            Ok(text) => text,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err.into()),
+        };
+        let sessions = text
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                let (events, name) = line.split_once(' ').ok_or(Error::NonCanonical)?;
+                let events = events.parse().map_err(|_| Error::NonCanonical)?;
+                Ok((name.to_string(), events))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { sessions })
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<()> {
+        let text: String = self
+            .sessions
+            .iter()
+            .map(|(name, events)| format!("{events} {name}\n"))
+            .collect();
+        atomic::write(&dir.join(EXCHANGES), text.as_bytes())
+    }
+
+    /// How many of `name`'s events have been exchanged, which is none for
+    /// a session never exchanged.
+    pub fn exchanged(&self, name: &str) -> usize {
+        self.sessions.get(name).copied().unwrap_or(0)
+    }
+
+    /// Remembers that the first `events` of `name`'s have been exchanged.
+    pub fn exchange(&mut self, name: &str, events: usize) {
+        self.sessions.insert(name.to_string(), events);
+    }
+
+    /// Forgets `name`, e.g. once deleted.
+    pub fn forget(&mut self, name: &str) -> bool {
+        self.sessions.remove(name).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TurnFile;
+    use crate::actions::{Action, ActionKind};
+    use crate::error::Error;
+    use crate::players::Player;
+    use crate::session::Session;
+    use crate::signing::{Keyring, Signer};
+    use crate::Entity;
+
+    #[test]
+    fn turns_relay_between_copies() {
+        let mut alice = Keyring::default();
+        let public = alice.generate("alice".to_string()).unwrap();
+        let mut bob = Keyring::default();
+        bob.trust("alice".to_string(), &public).unwrap();
+
+        let mut mine = Session::new("game".to_string()).unwrap();
+        mine.add_entity(Entity::new("troll".to_string())).unwrap();
+        let mut theirs = Session::new("game".to_string()).unwrap();
+        let first = TurnFile::since(&mine, 0).unwrap();
+        assert_eq!(first.apply_to(&mut theirs).unwrap(), 1);
+
+        let fight = Action::new(ActionKind::Fight, "troll".to_string()).unwrap();
+        mine.apply_by("alice", fight).unwrap();
+        let turn = TurnFile::since(&mine, 1).unwrap();
+        assert_eq!(turn.events.len(), 1);
+        let bytes = turn.to_bytes(&alice);
+        let received = TurnFile::from_bytes(&bytes, &bob).unwrap();
+        assert_eq!(received, turn);
+        assert_eq!(received.apply_to(&mut theirs).unwrap(), 1);
+        assert_eq!(theirs.log(), mine.log());
+        // Applying a turn twice changes nothing.
+        assert_eq!(received.apply_to(&mut theirs).unwrap(), 0);
+
+        assert!(matches!(
+            TurnFile::since(&mine, 2),
+            Err(Error::NothingToExchange(_))
+        ));
+        let mut stranger = Session::new("game".to_string()).unwrap();
+        stranger.add_entity(Entity::new("elf".to_string())).unwrap();
+        assert!(matches!(
+            turn.apply_to(&mut stranger),
+            Err(Error::Diverged(_))
+        ));
+        let mut behind = Session::new("game".to_string()).unwrap();
+        assert!(matches!(
+            turn.apply_to(&mut behind),
+            Err(Error::TurnOutOfOrder(..))
+        ));
+        assert!(matches!(
+            TurnFile::from_bytes(b"not a turn", &bob),
+            Err(Error::NotATurnFile)
+        ));
+    }
+
+    /// The events `from` added after its first `base`, as `to` receives
+    /// them from `sender`.
+    fn relay(from: &Session, base: usize, sender: &Keyring, to: &Keyring) -> (TurnFile, Signer) {
+        let bytes = TurnFile::since(from, base).unwrap().to_bytes(sender);
+        let (turn, signer) = TurnFile::open(&bytes, to).unwrap();
+        (turn, signer.unwrap())
+    }
+
+    #[test]
+    fn players_only_send_their_own_events() {
+        let mut keeper = Keyring::default();
+        let keeper_key = keeper.generate("keeper".to_string()).unwrap();
+        let mut alice = Keyring::default();
+        let alice_key = alice.generate("alice".to_string()).unwrap();
+        let mut bob = Keyring::default();
+        let bob_key = bob.generate("bob".to_string()).unwrap();
+        let mut mine = Keyring::default();
+        mine.trust("keeper".to_string(), &keeper_key).unwrap();
+        mine.trust("alice".to_string(), &alice_key).unwrap();
+        mine.trust("bob".to_string(), &bob_key).unwrap();
+
+        let mut theirs = Session::new("game".to_string()).unwrap();
+        theirs
+            .add_player(Player::new("alice".to_string(), Some(alice_key)))
+            .unwrap();
+        theirs
+            .add_player(Player::new("bob".to_string(), None))
+            .unwrap();
+        let mut game = Session::new("game".to_string()).unwrap();
+        TurnFile::after(&theirs, 0)
+            .unwrap()
+            .apply_to(&mut game)
+            .unwrap();
+
+        // Whoever keeps the session sets it up, but plays for no one.
+        theirs.add_entity(Entity::new("troll".to_string())).unwrap();
+        let (setup, signer) = relay(&theirs, 2, &keeper, &mine);
+        setup.check_signed_by(&game, Some(&signer)).unwrap();
+        setup.apply_to(&mut game).unwrap();
+        theirs.say("alice", "hello").unwrap();
+        let (said, signer) = relay(&theirs, 3, &keeper, &mine);
+        assert!(matches!(
+            said.check_signed_by(&game, Some(&signer)),
+            Err(Error::NotSentBy(..))
+        ));
+
+        let (said, signer) = relay(&theirs, 3, &alice, &mine);
+        said.check_signed_by(&game, Some(&signer)).unwrap();
+        let (said, signer) = relay(&theirs, 3, &bob, &mine);
+        assert!(matches!(
+            said.check_signed_by(&game, Some(&signer)),
+            Err(Error::NotSentBy(..))
+        ));
+        theirs.add_entity(Entity::new("elf".to_string())).unwrap();
+        let (setup, signer) = relay(&theirs, 3, &alice, &mine);
+        assert!(matches!(
+            setup.check_signed_by(&game, Some(&signer)),
+            Err(Error::NotSentBy(..))
+        ));
+
+        // A trusted key that is not the one on the roster is refused.
+        let mut impostor = Keyring::default();
+        mine.trust(
+            "alice".to_string(),
+            &impostor.generate("alice".to_string()).unwrap(),
+        )
+        .unwrap();
+        let (said, signer) = relay(&theirs, 3, &impostor, &mine);
+        assert!(matches!(
+            said.check_signed_by(&game, Some(&signer)),
+            Err(Error::WrongKey(_))
+        ));
+
+        // Without keys to trust, unsigned turns open, but are only applied
+        // to sessions no one plays.
+        let bytes = TurnFile::since(&theirs, 3)
+            .unwrap()
+            .to_bytes(&Keyring::default());
+        let (said, signer) = TurnFile::open(&bytes, &Keyring::default()).unwrap();
+        assert_eq!(signer, None);
+        assert!(matches!(
+            said.check_signed_by(&game, None),
+            Err(Error::Unsigned)
+        ));
+        let setup = TurnFile::since(&theirs, 0).unwrap();
+        setup
+            .check_signed_by(&Session::new("game".to_string()).unwrap(), None)
+            .unwrap();
+    }
+
+    #[test]
+    fn turns_longer_than_a_field_length_round_trip() {
+        let mut alice = Keyring::default();
+        let public = alice.generate("alice".to_string()).unwrap();
+        let mut bob = Keyring::default();
+        bob.trust("alice".to_string(), &public).unwrap();
+
+        let mut mine = Session::new("game".to_string()).unwrap();
+        mine.add_player(Player::new("alice".to_string(), None))
+            .unwrap();
+        for turn in 0..2_000 {
+            mine.say("alice", &format!("turn {turn}: still thinking"))
+                .unwrap();
+        }
+        let turn = TurnFile::since(&mine, 1).unwrap();
+        let bytes = turn.to_bytes(&alice);
+        assert!(bytes.len() > usize::from(u16::MAX));
+        let received = TurnFile::from_bytes(&bytes, &bob).unwrap();
+        assert_eq!(received, turn);
+        assert_eq!(received.events.len(), 2_000);
+    }
+}
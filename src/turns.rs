@@ -0,0 +1,224 @@
+use crate::error::{Error, Result};
+use crate::timestamp::Timestamp;
+
+/// Whose turn it is, in a session its players take turns in. While it has
+/// no players, anyone may act at any time.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TurnOrder {
+    /// The players, in the order they take their turns.
+    players: Vec<String>,
+    /// Where in `players` the one to play is.
+    current: usize,
+    /// Turns begun so far, counting the one under way.
+    number: u32,
+    /// Seconds each turn may last, or none for no limit.
+    time: Option<u32>,
+    /// Whether a player who runs out of time drops out of the order, rather
+    /// than just losing their turn.
+    forfeit: bool,
+    /// When the turn under way began, if known; logs from before turns were
+    /// timed do not say.
+    began: Option<Timestamp>,
+}
+
+impl TurnOrder {
+    /// `players` taking turns from the first of them, or no order if none,
+    /// timed as `self` is.
+    pub(crate) fn restart(&self, players: Vec<String>, at: Option<Timestamp>) -> Self {
+        let number = u32::from(!players.is_empty());
+        Self {
+            players,
+            current: 0,
+            number,
+            began: at,
+            ..*self
+        }
+    }
+
+    pub fn players(&self) -> &[String] {
+        &self.players
+    }
+
+    /// The player whose turn it is, if the session has players.
+    pub fn current(&self) -> Option<&str> {
+        self.players.get(self.current).map(String::as_str)
+    }
+
+    pub fn number(&self) -> u32 {
+        self.number
+    }
+
+    /// Seconds each turn may last, if limited.
+    pub fn time(&self) -> Option<u32> {
+        self.time
+    }
+
+    pub fn forfeit(&self) -> bool {
+        self.forfeit
+    }
+
+    /// When the turn under way began, if known.
+    pub(crate) fn began(&self) -> Option<Timestamp> {
+        self.began
+    }
+
+    /// Carries on from turn `number`, as a redacted copy of a session does.
+    pub(crate) fn renumber(&mut self, number: u32) {
+        self.number = number;
+    }
+
+    /// When the player to play runs out of time, if turns are timed.
+    pub fn deadline(&self) -> Option<Timestamp> {
+        self.current()?;
+        let began = self.began?.as_millis();
+        Some(Timestamp::from_millis(
+            began + u128::from(self.time?) * 1000,
+        ))
+    }
+
+    /// Limits each turn to `time` seconds, or none, from `at` for the turn
+    /// under way.
+    pub(crate) fn set_time(&mut self, time: Option<u32>, forfeit: bool, at: Timestamp) {
+        self.time = time;
+        self.forfeit = forfeit;
+        self.began = Some(at);
+    }
+
+    /// Fails unless `player`, if anyone in particular, may act now.
+    pub(crate) fn check(&self, player: Option<&str>) -> Result<()> {
+        match self.current() {
+            Some(current) if player != Some(current) => {
+                Err(Error::NotYourTurn(current.to_string()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Ends `player`'s turn, handing it to the next player, at `at` if known.
+    pub(crate) fn pass(&mut self, player: &str, at: Option<Timestamp>) -> Result<()> {
+        self.check(Some(player))?;
+        self.current = (self.current + 1) % self.players.len().max(1);
+        self.number += 1;
+        self.began = at;
+        Ok(())
+    }
+
+    /// Ends `player`'s turn at `at` for running out of time, dropping them
+    /// from the order if turns are forfeit.
+    pub(crate) fn time_out(&mut self, player: &str, at: Timestamp) -> Result<()> {
+        self.check(Some(player))?;
+        if self.deadline().is_none_or(|deadline| at < deadline) {
+            return Err(Error::IllegalAction(format!("{player} still has time")));
+        }
+        if !self.forfeit {
+            return self.pass(player, Some(at));
+        }
+        self.players.remove(self.current);
+        self.current %= self.players.len().max(1);
+        self.number += u32::from(!self.players.is_empty());
+        self.began = Some(at);
+        Ok(())
+    }
+}
+
+/// `1h 30m`: a number of seconds in its two largest units, dropping the
+/// rest.
+pub fn format_time(secs: u64) -> String {
+    let units = [(86_400, "d"), (3600, "h"), (60, "m"), (1, "s")];
+    let parts: Vec<_> = units
+        .iter()
+        .scan(secs, |left, &(size, unit)| {
+            let count = *left / size;
+            *left %= size;
+            Some((count, unit))
+        })
+        .skip_while(|(count, _)| *count == 0)
+        .take(2)
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, unit)| format!("{count}{unit}"))
+        .collect();
+    match parts.is_empty() {
+        true => "0s".to_string(),
+        false => parts.join(" "),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_time;
+    use crate::actions::{Action, ActionKind};
+    use crate::error::Error;
+    use crate::serde::{from_bytes, Serialize};
+    use crate::session::Session;
+    use crate::timestamp::Timestamp;
+    use crate::Entity;
+
+    #[test]
+    fn players_only_act_in_their_turn() {
+        let mut session = Session::new("game".to_string()).unwrap();
+        session
+            .add_entity(Entity::new("troll".to_string()))
+            .unwrap();
+        let fight = || Action::new(ActionKind::Fight, "troll".to_string()).unwrap();
+        assert!(matches!(
+            session.pass(Some("alice")),
+            Err(Error::NoTurnOrder(_))
+        ));
+        session
+            .set_players(vec!["alice".to_string(), "bob".to_string()])
+            .unwrap();
+        assert_eq!(session.order().current(), Some("alice"));
+
+        session.apply_by("alice", fight()).unwrap();
+        let err = session.apply_by("bob", fight()).unwrap_err();
+        assert_eq!(err.to_string(), "it is alice's turn");
+        assert!(session.apply(fight()).is_err());
+        assert!(session.pass(Some("bob")).is_err());
+        assert_eq!(session.pass(Some("alice")).unwrap(), "bob");
+        session.apply_by("bob", fight()).unwrap();
+        assert_eq!(session.order().number(), 2);
+
+        let copy: Session = from_bytes(&session.serialize()).unwrap();
+        assert_eq!(copy.order(), session.order());
+        session.undo().unwrap();
+        session.undo().unwrap();
+        assert_eq!(session.order().current(), Some("alice"));
+        session.set_players(vec![]).unwrap();
+        session.apply(fight()).unwrap();
+    }
+
+    #[test]
+    fn players_who_run_out_of_time_lose_their_turn() {
+        let mut session = Session::new("game".to_string()).unwrap();
+        let players = ["alice", "bob", "carol"].map(String::from);
+        session.set_players(players.to_vec()).unwrap();
+        assert_eq!(session.order().deadline(), None);
+        session.set_turn_time(Some(60), false).unwrap();
+        let deadline = session.order().deadline().unwrap();
+        let before = Timestamp::from_millis(deadline.as_millis() - 1);
+        assert_eq!(session.time_out(before).unwrap(), None);
+        assert_eq!(
+            session.time_out(deadline).unwrap().as_deref(),
+            Some("alice")
+        );
+        assert_eq!(session.order().current(), Some("bob"));
+        assert!(session.order().deadline().unwrap() > deadline);
+
+        session.set_turn_time(Some(60), true).unwrap();
+        let deadline = session.order().deadline().unwrap();
+        assert_eq!(session.time_out(deadline).unwrap().as_deref(), Some("bob"));
+        assert_eq!(session.order().players(), ["alice", "carol"]);
+        assert_eq!(session.order().current(), Some("carol"));
+        assert_eq!(session.size_hint(), session.serialize().len());
+        let copy: Session = from_bytes(&session.serialize()).unwrap();
+        assert_eq!(copy.order(), session.order());
+
+        session.set_turn_time(None, false).unwrap();
+        assert_eq!(
+            session.time_out(Timestamp::from_millis(u128::MAX)).unwrap(),
+            None
+        );
+        assert_eq!(format_time(5400), "1h 30m");
+        assert_eq!(format_time(86_405), "1d");
+    }
+}
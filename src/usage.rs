@@ -0,0 +1,635 @@
+/// How a subcommand is invoked and what it does, for `--help`.
+pub struct Usage {
+    pub command: &'static str,
+    /// One line per form of the command, without the program name.
+    pub synopsis: &'static [&'static str],
+    pub about: &'static [&'static str],
+}
+
+/// Flags taken by every subcommand, and what each does.
+pub const GLOBAL_FLAGS: &[(&str, &str)] = &[
+    ("--data-dir <dir>", "Store sessions and keys in <dir>"),
+    ("--config <file>", "Read defaults from <file>; see CONFIG"),
+    (
+        "--remote <host:port>",
+        "Use the sessions of a server started with serve",
+    ),
+    (
+        "--token <token>",
+        "Act as the player a serve --gen-token token names",
+    ),
+    (
+        "--ca <file>",
+        "Trust TLS servers certified by the PEM CAs in <file>",
+    ),
+    (
+        "--as <player>",
+        "Act as <player> without a server, not the config's",
+    ),
+    (
+        "--sqlite",
+        "Keep sessions in a SQLite database (sqlite builds)",
+    ),
+    (
+        "--no-keyring",
+        "Prompt for passphrases, not the OS keychain",
+    ),
+    ("--json", "Print JSON rather than text, errors included"),
+    ("--no-color", "Never color output, as NO_COLOR=1 does"),
+    ("-y, --yes", "Answer yes to every confirmation prompt"),
+    ("-v, -vv", "Log what is read and written, or every field"),
+    ("-q, --quiet", "Print no warnings or confirmations"),
+    (
+        "-h, --help",
+        "Show help, for one subcommand if given after it",
+    ),
+];
+
+/// Every subcommand, in the order the help lists them.
+pub const COMMANDS: &[Usage] = &[
+    Usage {
+        command: "new",
+        synopsis: &["new <name> [--template <template>] [--rules <rule set>] [--yes]"],
+        about: &[
+            "Create a new session, optionally starting from",
+            "<data-dir>/templates/<template>.session; asks",
+            "before replacing a session of the same name.",
+            "--rules plays it by standard (the default), skirmish",
+            "rules, won by the last side standing, or race rules,",
+            "won by the first side to a score of 10 or the highest",
+            "after 20 turns; a side's score is its entities' score=",
+            "attributes summed. fog rules are a skirmish in which",
+            "players see only five squares from their entities",
+            "and nothing of others' items or resources",
+        ],
+    },
+    Usage {
+        command: "show",
+        synopsis: &["show [<name>] [--entity <id>]"],
+        about: &[
+            "Show a session's metadata, turn, queued actions and",
+            "entities, or everything about one entity",
+        ],
+    },
+    Usage {
+        command: "load",
+        synopsis: &["load [<name>] [--at <n>]"],
+        about: &["Load a session, as of its first <n> events"],
+    },
+    Usage {
+        command: "log",
+        synopsis: &["log <name>"],
+        about: &["Show every change made to a session"],
+    },
+    Usage {
+        command: "history",
+        synopsis: &["history <name> [--kind <action>] [--entity <name>] [--last <n>]"],
+        about: &[
+            "Show the actions taken and turns resolved, with",
+            "what each did",
+        ],
+    },
+    Usage {
+        command: "stats",
+        synopsis: &["stats <name>"],
+        about: &["Show the size and makeup of a saved session"],
+    },
+    Usage {
+        command: "compact",
+        synopsis: &["compact <name>"],
+        about: &["Fold saved deltas into the session file"],
+    },
+    Usage {
+        command: "export",
+        synopsis: &["export <name> [-o <file>] [--for <player>]"],
+        about: &[
+            "Pack a session into a shareable .relay file. --for",
+            "leaves out what its rules hide from that player",
+        ],
+    },
+    Usage {
+        command: "import",
+        synopsis: &["import <file>"],
+        about: &["Add the session from a .relay file"],
+    },
+    Usage {
+        command: "turn",
+        synopsis: &["turn export <name> [-o <file>]", "turn apply <file>"],
+        about: &[
+            "Play by mail: pack what changed in a session since it",
+            "was last exchanged into a signed .turn file, and",
+            "apply one received from another player",
+        ],
+    },
+    Usage {
+        command: "replay",
+        synopsis: &[
+            "replay export <name> [-o <file>]",
+            "replay play <file> [--step]",
+        ],
+        about: &[
+            "Pack a game into a signed .replay file, with what",
+            "every turn did, for review; play one back turn by",
+            "turn, with --step waiting for Enter after each",
+        ],
+    },
+    Usage {
+        command: "sync",
+        synopsis: &["sync <name> --peer <host:port>"],
+        about: &[
+            "Exchange a session's new events with the same session",
+            "on another player's server, both ways; events that no",
+            "longer apply are skipped as conflicts, as merge does",
+        ],
+    },
+    Usage {
+        command: "doctor",
+        synopsis: &["doctor <file> [-o <file>]"],
+        about: &["Check a session file and salvage what is intact"],
+    },
+    Usage {
+        command: "merge",
+        synopsis: &["merge <base> <mine> <theirs>"],
+        about: &["Replay changes from <theirs> onto <mine>"],
+    },
+    Usage {
+        command: "list",
+        synopsis: &["list [--tag <tag>]..."],
+        about: &["List saved sessions, only those with every <tag>"],
+    },
+    Usage {
+        command: "tag",
+        synopsis: &["tag <name> <tag>..."],
+        about: &["Add tags to a session"],
+    },
+    Usage {
+        command: "untag",
+        synopsis: &["untag <name> <tag>..."],
+        about: &["Remove tags from a session"],
+    },
+    Usage {
+        command: "encrypt",
+        synopsis: &["encrypt <name>"],
+        about: &["Protect a session and its backups with a passphrase"],
+    },
+    Usage {
+        command: "decrypt",
+        synopsis: &["decrypt <name>"],
+        about: &["Remove the passphrase from a session"],
+    },
+    Usage {
+        command: "delete",
+        synopsis: &["delete <name> [--yes]"],
+        about: &["Delete a session and its backups"],
+    },
+    Usage {
+        command: "rename",
+        synopsis: &["rename <old> <new>"],
+        about: &["Rename a session"],
+    },
+    Usage {
+        command: "copy",
+        synopsis: &["copy <src> <dst>"],
+        about: &["Copy a session under a new name"],
+    },
+    Usage {
+        command: "restore",
+        synopsis: &["restore <name> [--backup <n>] [--yes]"],
+        about: &["Roll a session back to a backup (default 1)"],
+    },
+    Usage {
+        command: "entity",
+        synopsis: &[
+            "entity add <name> <entity> [hp=<n>] [attack=<n>] [defense=<n>] [speed=<n>]",
+            "           [ai=aggressive|defensive|wander]",
+            "entity remove <name> <entity>",
+            "entity show <name> [<entity>]",
+            "entity set <name> <entity> <key>=<value>...",
+            "entity get <name> <entity> [<key>]",
+            "entity place <name> <entity> <x> <y>",
+            "entity own <name> <entity> <player>|none",
+        ],
+        about: &[
+            "Add, remove or show the entities in a session; with",
+            "ai= the computer plays one on every resolve. Set",
+            "attaches custom attributes, an empty value removing",
+            "one, and get shows them. Place puts one on the map;",
+            "own gives one to a player, or none",
+        ],
+    },
+    Usage {
+        command: "map",
+        synopsis: &["map <name> <width> <height>"],
+        about: &["Bound the map entities move on"],
+    },
+    Usage {
+        command: "spawn",
+        synopsis: &["spawn <name> <archetype> [<entity>]"],
+        about: &[
+            "Add a copy of an archetype to a session, named",
+            "after it unless <entity> is given",
+        ],
+    },
+    Usage {
+        command: "archetype",
+        synopsis: &[
+            "archetype save <name> <entity> [<archetype>]",
+            "archetype list",
+        ],
+        about: &[
+            "Save an entity as an archetype in",
+            "<data-dir>/archetypes/<archetype>.entity, or list them",
+        ],
+    },
+    Usage {
+        command: "rel",
+        synopsis: &[
+            "rel <name> show <entity>",
+            "rel <name> add|remove <entity> <relation> <entity>",
+        ],
+        about: &[
+            "Show how an entity is related to others, or relate",
+            "two entities as ally, enemy, owns or located-in;",
+            "enemies will not trade",
+        ],
+    },
+    Usage {
+        command: "faction",
+        synopsis: &[
+            "faction <name> show | add <faction>",
+            "faction <name> join <entity> <faction>|none",
+            "faction <name> stance <faction> <faction> hostile|neutral|allied",
+        ],
+        about: &[
+            "Show or define the factions of a session, move an",
+            "entity into a faction or out of its own, or set how",
+            "two factions stand; hostile ones will not trade and",
+            "allies attack each other only when forced",
+        ],
+    },
+    Usage {
+        command: "player",
+        synopsis: &[
+            "player list <name>",
+            "player add <name> <player> [<public key>]",
+            "player remove <name> <player>",
+        ],
+        about: &[
+            "List, add or remove the players of a session; once",
+            "it has any, every action is taken as one of them",
+        ],
+    },
+    Usage {
+        command: "action",
+        synopsis: &[
+            "action [<name>] <action> <target> [<key>=<value>]... [--dry-run] [--force]",
+            "action [<name>] - [--dry-run] [--force]",
+        ],
+        about: &[
+            "Act upon an entity in a session, e.g. move hero",
+            "direction=north steps=2; entities may be given",
+            "by id or name prefix.",
+            "--dry-run shows the outcome without saving it;",
+            "attack by=<entity> --force attacks an ally.",
+            "With - the actions are read from stdin, one per",
+            "line or separated by commas as queue takes them",
+        ],
+    },
+    Usage {
+        command: "queue",
+        synopsis: &["queue <name> <action> <target> [<key>=<value>]... [, <action>...]"],
+        about: &[
+            "Store actions, separated by commas, to apply later;",
+            "a macro name may stand in for several of them",
+        ],
+    },
+    Usage {
+        command: "script",
+        synopsis: &["script <name> <kind> <file>"],
+        about: &[
+            "Define action <kind> in a session by a rhai script",
+            "(scripting builds); <data-dir>/scripts/<kind>.rhai",
+            "is used for kinds a session does not define",
+        ],
+    },
+    Usage {
+        command: "macros",
+        synopsis: &["macros"],
+        about: &["List the macros in <data-dir>/relay_code.macros"],
+    },
+    Usage {
+        command: "resolve",
+        synopsis: &["resolve <name>"],
+        about: &[
+            "Apply every queued action in order and end the turn;",
+            "of actions with the same claim=<thing>, only the one",
+            "with most initiative is applied; ties are rolled for.",
+            "Then the game ends if its rule set's conditions are met",
+        ],
+    },
+    Usage {
+        command: "rules",
+        synopsis: &["rules <name> [max=<n>] [<action>=<turns>]..."],
+        about: &[
+            "Show or change the actions allowed per turn",
+            "and the cooldown of each kind, 0 for none",
+        ],
+    },
+    Usage {
+        command: "require",
+        synopsis: &[
+            "require <name> <action> [hp=<n>|guarding|unguarded|exists=<entity>|after=<action>]...",
+        ],
+        about: &[
+            "Set what must hold before <action> is allowed;",
+            "nothing lifts the requirements",
+        ],
+    },
+    Usage {
+        command: "cost",
+        synopsis: &["cost <name> <action> [ap=<n>] [mana=<n>] [gold=<n>]"],
+        about: &["Set what the target of <action> pays for it"],
+    },
+    Usage {
+        command: "afflict",
+        synopsis: &["afflict <name> <entity> poisoned|shielded|stunned <turns>"],
+        about: &[
+            "Put an entity under an effect for some turns;",
+            "effects stack and tick down on resolve",
+        ],
+    },
+    Usage {
+        command: "xp",
+        synopsis: &["xp <name> <action> <n>"],
+        about: &["Set the xp the target of <action> earns, 0 for none"],
+    },
+    Usage {
+        command: "leveling",
+        synopsis: &["leveling <name> <xp> [hp=<n>] [attack=<n>] [defense=<n>] [speed=<n>]"],
+        about: &[
+            "Level entities up every <xp> xp, 0 for never, adding",
+            "the given stats each time",
+        ],
+    },
+    Usage {
+        command: "grant",
+        synopsis: &["grant <name> <entity> [ap=<n>] [mana=<n>] [gold=<n>]"],
+        about: &["Add to the resources of <entity>"],
+    },
+    Usage {
+        command: "players",
+        synopsis: &["players <name> [<player>...] [--none]"],
+        about: &[
+            "Show or set the players who take turns, in order;",
+            "then only the one to play may act. --none lets",
+            "anyone act at any time again",
+        ],
+    },
+    Usage {
+        command: "pass",
+        synopsis: &["pass [<name>]"],
+        about: &["End your turn, handing it to the next player"],
+    },
+    Usage {
+        command: "deadline",
+        synopsis: &["deadline <name> <time>|none [--forfeit]"],
+        about: &[
+            "Limit each turn to a time such as 90s, 10m, 2h or",
+            "3d, counting the turn under way from now. A player",
+            "who runs out loses their turn, or with --forfeit",
+            "their place in the turn order. none lifts the limit",
+        ],
+    },
+    Usage {
+        command: "check-deadlines",
+        synopsis: &["check-deadlines"],
+        about: &[
+            "End the turn of every player who has run out of",
+            "time; servers do this on their own",
+        ],
+    },
+    Usage {
+        command: "undo",
+        synopsis: &["undo <name>"],
+        about: &["Revert the last change to a session"],
+    },
+    Usage {
+        command: "redo",
+        synopsis: &["redo <name>"],
+        about: &["Reapply the last undone change"],
+    },
+    Usage {
+        command: "completions",
+        synopsis: &["completions bash|zsh|fish|powershell"],
+        about: &[
+            "Print a script completing commands, session names",
+            "and action kinds in the given shell",
+        ],
+    },
+    Usage {
+        command: "complete",
+        synopsis: &["complete commands|sessions|actions"],
+        about: &["List what the completion scripts offer, one per line"],
+    },
+    Usage {
+        command: "serve",
+        synopsis: &[
+            "serve [--bind <address>] [--ws <address>]",
+            "      [--cert <file> --key <file>]",
+            "serve --gen-token <player> [--spectator]",
+        ],
+        about: &[
+            "Host the sessions in the data directory for clients",
+            "to create, act on and fetch over TCP, on",
+            "127.0.0.1:7777 unless e.g. --bind 0.0.0.0:7777, and",
+            "over WebSocket on --ws, if given (websocket builds).",
+            "With a PEM certificate chain and key, both are over",
+            "TLS (tls builds). Clients pass --remote <host:port>,",
+            "or tls://, ws:// or wss://<host:port>, to new, action,",
+            "load, show and list, and --ca unless the system",
+            "trusts the certificate's authority. Once a token",
+            "has been issued with --gen-token, which replaces any",
+            "the player had, clients must pass --token, and their",
+            "actions are logged as the player's. A --spectator's",
+            "token only lets its holder watch",
+        ],
+    },
+    Usage {
+        command: "chat",
+        synopsis: &["chat [<name>] <message>"],
+        about: &[
+            "Tell the other players of a session something. It is",
+            "logged with the session's events, so it travels with",
+            "turn files and sync; with --remote and --token they",
+            "also read it with their next command, or with mail.",
+            "Without a server, it is said as --as <player>, or",
+            "the config's player",
+        ],
+    },
+    Usage {
+        command: "mail",
+        synopsis: &["mail"],
+        about: &[
+            "Read what other players did and said while you were",
+            "away, with --remote and --token. Any command with",
+            "them prints the same first, except with --json or -q",
+        ],
+    },
+    Usage {
+        command: "lobby",
+        synopsis: &[
+            "lobby host <name> [--rules <set>] [--slots <n>]",
+            "lobby list",
+            "lobby join <name>",
+        ],
+        about: &[
+            "Find opponents on the --remote server, with --token:",
+            "host a game for --slots players (2 by default) by",
+            "--rules (standard by default), list the games still",
+            "waiting for players, or join one. The game starts as",
+            "a session once the last slot is taken, its players",
+            "taking turns in the order they joined",
+        ],
+    },
+    Usage {
+        command: "ping",
+        synopsis: &["ping"],
+        about: &[
+            "Check that the --remote server is still answering,",
+            "and how quickly",
+        ],
+    },
+    Usage {
+        command: "proxy",
+        synopsis: &["proxy --upstream <host:port> [--listen <address>]"],
+        about: &[
+            "Relay clients to the server at --upstream, printing",
+            "each message both ways as it passes, for debugging.",
+            "Listens on 127.0.0.1:7778 unless e.g. --listen :7779.",
+            "Only unencrypted TCP can be followed",
+        ],
+    },
+    Usage {
+        command: "watch",
+        synopsis: &["watch [<name>]"],
+        about: &[
+            "Follow a session on the --remote server as a",
+            "spectator, printing its events and chat as they",
+            "happen, until interrupted. The connection can only",
+            "watch, as with a token from --gen-token --spectator",
+        ],
+    },
+    Usage {
+        command: "version",
+        synopsis: &["version"],
+        about: &[
+            "Show the version, git commit, session format and",
+            "features of this build; also --version",
+        ],
+    },
+    Usage {
+        command: "keygen",
+        synopsis: &["keygen [<player>]"],
+        about: &[
+            "Create a signing key for <player>, by default the",
+            "configured player",
+        ],
+    },
+    Usage {
+        command: "trust",
+        synopsis: &["trust <player> <public-key>"],
+        about: &["Accept files signed by <player>"],
+    },
+];
+
+/// The usage of `command`, if there is such a subcommand.
+pub fn usage(command: &str) -> Option<&'static Usage> {
+    COMMANDS.iter().find(|usage| usage.command == command)
+}
+
+/// Where the descriptions start in the help.
+const COLUMN: usize = 20;
+
+/// A synopsis line followed by the description, on the same line when the
+/// synopsis is short enough, as in:
+///
+/// ```text
+///   show [<name>]     | Summarize a session
+/// ```
+pub(crate) fn format(usage: &Usage) -> String {
+    let mut lines = vec![];
+    let mut about = usage.about.iter();
+    for synopsis in usage.synopsis {
+        lines.push(format!("  {synopsis}"));
+    }
+    if let [synopsis] = usage.synopsis {
+        if synopsis.len() + 2 < COLUMN - 1 {
+            let first = about.next().copied().unwrap_or_default();
+            lines[0] = format!("  {synopsis:<width$}| {first}", width = COLUMN - 2);
+        }
+    }
+    for line in about {
+        lines.push(format!("{:COLUMN$}| {line}", ""));
+    }
+    lines.join("\n")
+}
+
+/// The help for every subcommand.
+pub fn help() -> String {
+    let mut help = vec!["USAGE".to_string()];
+    help.push("  relay_code [<flag>...] <command> [<arg>...]".to_string());
+    help.push(String::new());
+    help.push("FLAGS".to_string());
+    for (flag, about) in GLOBAL_FLAGS {
+        help.push(format!("  {flag:<width$}| {about}", width = COLUMN - 2));
+    }
+    help.push(String::new());
+    help.push("COMMANDS".to_string());
+    for usage in COMMANDS {
+        help.push(format(usage));
+    }
+    help.join("\n")
+}
+
+/// The help for `usage` alone, as `relay_code <command> --help` shows it.
+pub fn command_help(usage: &Usage) -> String {
+    let mut help = vec!["USAGE".to_string()];
+    for synopsis in usage.synopsis {
+        help.push(format!("  relay_code {synopsis}"));
+    }
+    help.push(String::new());
+    help.extend(usage.about.iter().map(|line| line.to_string()));
+    help.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{usage, COMMANDS};
+
+    #[test]
+    fn every_command_has_its_own_help() {
+        for (i, command) in COMMANDS.iter().enumerate() {
+            assert!(
+                COMMANDS[..i]
+                    .iter()
+                    .all(|other| other.command != command.command),
+                "{} is listed twice",
+                command.command
+            );
+            assert!(
+                command
+                    .synopsis
+                    .iter()
+                    .all(|synopsis| synopsis.starts_with(command.command)
+                        || synopsis.starts_with(' '))
+            );
+        }
+        let show = super::format(usage("undo").unwrap());
+        assert_eq!(
+            show,
+            "  undo <name>       | Revert the last change to a session"
+        );
+        let help = super::command_help(usage("delete").unwrap());
+        assert!(help.contains("relay_code delete <name> [--yes]"));
+    }
+}
@@ -0,0 +1,158 @@
+use crate::actions::Action;
+use crate::error::{Error, Result};
+use crate::handlers::handler;
+use crate::session::Session;
+
+/// Checks that an action is legal in the current state of a session before
+/// it is applied, so the log only ever holds actions that made sense.
+/// Replaying the log does not check again.
+pub struct Validator<'a> {
+    session: &'a Session,
+}
+
+impl<'a> Validator<'a> {
+    pub fn new(session: &'a Session) -> Self {
+        Self { session }
+    }
+
+    /// Checks that the target exists, rather than being dead, and no effect
+    /// on it blocks the action, then that the session's rule set allows it
+    /// and whatever the handler of its kind requires.
+    pub fn check(&self, action: &Action) -> Result<()> {
+        let target = self.session.entity(action.target()).ok_or_else(|| {
+            match self.session.grave(action.target()) {
+                Some(_) => Error::IllegalAction(format!("{} is dead", action.target())),
+                None => Error::EntityNotFound(action.target().to_string()),
+            }
+        })?;
+        if let Some(effect) = target
+            .effects
+            .iter()
+            .find(|effect| effect.kind.blocks(action.kind()))
+        {
+            return Err(Error::IllegalAction(format!(
+                "{} is {}",
+                target.name, effect.kind
+            )));
+        }
+        self.session.rule_set().check(action, self.session)?;
+        handler(action.kind())
+            .ok_or(Error::UnknownActionKind(action.kind().id()))?
+            .validate(action, self.session)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::actions::{Action, ActionKind, Param};
+    use crate::error::Error;
+    use crate::rules::Prerequisite;
+    use crate::serde::{Deserialize, FieldReader, Serialize};
+    use crate::session::Session;
+    use crate::Entity;
+
+    fn action(kind: ActionKind, target: &str, params: &[(&str, &str)]) -> Action {
+        let params = params
+            .iter()
+            .map(|(key, value)| (key.to_string(), Param::parse(value)))
+            .collect();
+        Action::new(kind, target.to_string())
+            .unwrap()
+            .with_params(params)
+    }
+
+    #[test]
+    fn illegal_actions_change_nothing() {
+        let mut session = Session::new("market".to_string()).unwrap();
+        session
+            .add_entity(Entity::new("alice".to_string()))
+            .unwrap();
+        session.add_entity(Entity::new("bob".to_string())).unwrap();
+        session
+            .apply(action(ActionKind::Rest, "alice", &[]))
+            .unwrap();
+        let before = session.entities().to_vec();
+
+        for (illegal, reason) in [
+            (
+                action(ActionKind::Attack, "bob", &[]),
+                "bob has nothing left to lose",
+            ),
+            (
+                action(
+                    ActionKind::Trade,
+                    "alice",
+                    &[("with", "bob"), ("amount", "2")],
+                ),
+                "alice has only 1 to trade, not 2",
+            ),
+            (
+                action(ActionKind::Trade, "alice", &[("with", "alice")]),
+                "alice cannot trade with itself",
+            ),
+            (
+                action(ActionKind::Trade, "alice", &[]),
+                "trade needs with=<value>",
+            ),
+            (
+                action(ActionKind::Attack, "alice", &[("damage", "0")]),
+                "damage must be from 1 to 255",
+            ),
+        ] {
+            let err = session.apply(illegal).unwrap_err();
+            assert!(matches!(&err, Error::IllegalAction(_)));
+            assert_eq!(err.to_string(), reason);
+        }
+        assert!(matches!(
+            session.apply(action(ActionKind::Say, "carol", &[("text", "hi")])),
+            Err(Error::EntityNotFound(_))
+        ));
+        assert_eq!(session.entities(), before);
+        assert_eq!(session.log().len(), 3);
+
+        let outcome = session
+            .apply(action(ActionKind::Trade, "alice", &[("with", "bob")]))
+            .unwrap();
+        assert_eq!(outcome.messages, ["alice gave 1 to bob"]);
+        assert_eq!(session.entity("bob").unwrap().stats.hp, 1);
+    }
+
+    #[test]
+    fn unmet_prerequisites_are_named() {
+        let mut session = Session::new("market".to_string()).unwrap();
+        session
+            .add_entity(Entity::new("alice".to_string()))
+            .unwrap();
+        session
+            .require(
+                ActionKind::Inspect,
+                vec![
+                    Prerequisite::After(ActionKind::Rest),
+                    Prerequisite::MinHp(2),
+                ],
+            )
+            .unwrap();
+        let bytes = session.serialize();
+        let mut session = Session::deserialize(&mut FieldReader::new(&bytes)).unwrap();
+
+        let err = session
+            .apply(action(ActionKind::Inspect, "alice", &[]))
+            .unwrap_err();
+        assert_eq!(err.to_string(), "inspect requires a rest action first");
+        session
+            .apply(action(ActionKind::Rest, "alice", &[]))
+            .unwrap();
+        let err = session
+            .apply(action(ActionKind::Inspect, "alice", &[]))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "inspect requires the target to have at least 2 hp"
+        );
+
+        session.require(ActionKind::Inspect, vec![]).unwrap();
+        session
+            .apply(action(ActionKind::Inspect, "alice", &[]))
+            .unwrap();
+    }
+}
@@ -0,0 +1,84 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// How much goes to stderr besides errors, from `-q` up to `-vv`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// `-q`: not even warnings, nor messages saying a command worked.
+    Quiet,
+    /// Warnings only.
+    #[default]
+    Normal,
+    /// `-v`: what is being read, written and applied.
+    Verbose,
+    /// `-vv`: every field decoded, down to the bytes.
+    Trace,
+}
+
+impl Level {
+    /// The level one `-v` more gives.
+    pub fn louder(self) -> Self {
+        match self {
+            Self::Quiet | Self::Normal => Self::Verbose,
+            Self::Verbose | Self::Trace => Self::Trace,
+        }
+    }
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Normal as u8);
+
+/// Sets the level for the rest of the process.
+pub fn set_level(level: Level) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn level() -> Level {
+    match LEVEL.load(Ordering::Relaxed) {
+        0 => Level::Quiet,
+        1 => Level::Normal,
+        2 => Level::Verbose,
+        _ => Level::Trace,
+    }
+}
+
+/// Prints to stderr unless `-q` was given.
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        if $crate::verbosity::level() >= $crate::verbosity::Level::Normal {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+/// Prints to stderr with `-v` or more.
+#[macro_export]
+macro_rules! verbose {
+    ($($arg:tt)*) => {
+        if $crate::verbosity::level() >= $crate::verbosity::Level::Verbose {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+/// Prints to stderr with `-vv`.
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        if $crate::verbosity::level() >= $crate::verbosity::Level::Trace {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Level;
+
+    #[test]
+    fn each_v_is_louder_up_to_trace() {
+        assert_eq!(Level::default().louder(), Level::Verbose);
+        assert_eq!(Level::default().louder().louder(), Level::Trace);
+        assert_eq!(Level::Trace.louder(), Level::Trace);
+        assert!(Level::Quiet < Level::Normal);
+    }
+}
@@ -0,0 +1,92 @@
+use std::fmt::Display;
+
+/// What `version` reports: which build a bug report came from, and whether
+/// two players' builds can share sessions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Version {
+    pub version: &'static str,
+    /// The commit built from, when built from a git checkout.
+    pub git_hash: Option<&'static str>,
+    /// The session format version read and written; see `Metadata`.
+    pub session_format: u16,
+    /// The `.relay` file format version read and written; see `Archive`.
+    pub archive_format: u16,
+    /// Optional capabilities compiled in.
+    pub features: Vec<&'static str>,
+}
+
+impl Version {
+    /// This build's.
+    pub fn current() -> Self {
+        let optional = [
+            ("chrono", cfg!(feature = "chrono")),
+            ("sqlite", cfg!(feature = "sqlite")),
+            ("keychain", cfg!(feature = "keychain")),
+            ("scripting", cfg!(feature = "scripting")),
+            ("async-server", cfg!(feature = "async-server")),
+            ("websocket", cfg!(feature = "websocket")),
+            ("tls", cfg!(feature = "tls")),
+            ("verify-canonical", cfg!(feature = "verify-canonical")),
+        ];
+        // Sessions can always be encrypted with a passphrase.
+        let features = std::iter::once("encryption")
+            .chain(
+                optional
+                    .into_iter()
+                    .filter(|(_, on)| *on)
+                    .map(|(name, _)| name),
+            )
+            .collect();
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            git_hash: option_env!("RELAY_CODE_GIT_HASH"),
+            session_format: crate::metadata::FORMAT_VERSION,
+            archive_format: crate::archive::FORMAT_VERSION,
+            features,
+        }
+    }
+}
+
+/// ```text
+/// relay_code 0.1.0 (1a2b3c4)
+/// session format: 14
+/// archive format: 1
+/// features: encryption, sqlite
+/// ```
+impl Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "relay_code {}", self.version)?;
+        if let Some(hash) = self.git_hash {
+            write!(f, " ({hash})")?;
+        }
+        writeln!(f)?;
+        writeln!(f, "session format: {}", self.session_format)?;
+        writeln!(f, "archive format: {}", self.archive_format)?;
+        write!(f, "features: {}", self.features.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Version;
+
+    #[test]
+    fn version_names_formats_and_features() {
+        let version = Version {
+            git_hash: Some("1a2b3c4"),
+            features: vec!["encryption", "sqlite"],
+            ..Version::current()
+        };
+        let text = version.to_string();
+        assert!(text.starts_with(&format!(
+            "relay_code {} (1a2b3c4)\n",
+            env!("CARGO_PKG_VERSION")
+        )));
+        assert!(text.contains(&format!(
+            "session format: {}\n",
+            crate::metadata::FORMAT_VERSION
+        )));
+        assert!(text.ends_with("features: encryption, sqlite"));
+        assert_eq!(Version::current().features[0], "encryption");
+    }
+}
@@ -0,0 +1,152 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+
+use crate::attributes::AttributeValue;
+use crate::session::Session;
+
+/// The attribute an entity's score is kept in, summed over its side.
+pub const SCORE: &str = "score";
+
+/// A way for a game to end, which rule sets declare. Each is checked after
+/// every resolve, in the order declared, until one is met. Sides are
+/// factions, or entities in none; only their living entities count.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Victory {
+    /// The only side with entities left alive wins, once any has died.
+    LastSideStanding,
+    /// The side whose score first reaches this wins.
+    Score(i64),
+    /// After this many turns the side with the highest score wins.
+    TurnLimit(u32),
+}
+
+impl Victory {
+    /// How the game ended, if this condition has been met.
+    pub fn check(&self, session: &Session) -> Option<GameOver> {
+        let sides = sides(session);
+        let over = |winner| GameOver { winner, how: *self };
+        match *self {
+            Self::LastSideStanding => {
+                if session.graveyard().is_empty() || sides.len() != 1 {
+                    return None;
+                }
+                Some(over(sides.into_keys().next()))
+            }
+            Self::Score(target) => {
+                let (leader, score) = leader(&sides)?;
+                (score >= target).then(|| over(leader))
+            }
+            Self::TurnLimit(turns) => {
+                if session.turn().number < turns {
+                    return None;
+                }
+                Some(over(leader(&sides).and_then(|(leader, _)| leader)))
+            }
+        }
+    }
+}
+
+impl Display for Victory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::LastSideStanding => write!(f, "last side standing"),
+            Self::Score(score) => write!(f, "first to a score of {score}"),
+            Self::TurnLimit(turns) => write!(f, "highest score after {turns} turns"),
+        }
+    }
+}
+
+/// How a finished game ended. Rebuilt by replaying the log, like the rest
+/// of the session's state.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameOver {
+    /// The side that won, or none for a draw.
+    pub winner: Option<String>,
+    /// The condition that was met.
+    pub how: Victory,
+}
+
+/// `goblins won, first to a score of 10`.
+impl Display for GameOver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.winner {
+            Some(winner) => write!(f, "{winner} won, {}", self.how),
+            None => write!(f, "a draw, {}", self.how),
+        }
+    }
+}
+
+/// The score of each side with entities left alive.
+fn sides(session: &Session) -> BTreeMap<String, i64> {
+    let mut sides = BTreeMap::new();
+    for entity in session.entities().iter().filter(|e| e.stats.hp > 0) {
+        let side = entity.faction.as_ref().unwrap_or(&entity.name);
+        let score = match entity.attributes.get(SCORE) {
+            Some(AttributeValue::Int(score)) => *score,
+            _ => 0,
+        };
+        *sides.entry(side.clone()).or_insert(0) += score;
+    }
+    sides
+}
+
+/// The side with the highest score and that score, the side being none if
+/// several share it.
+fn leader(sides: &BTreeMap<String, i64>) -> Option<(Option<String>, i64)> {
+    let best = *sides.values().max()?;
+    let mut leaders = sides.iter().filter(|(_, &score)| score == best);
+    let leader = leaders.next().map(|(side, _)| side.clone());
+    match leaders.next() {
+        Some(_) => Some((None, best)),
+        None => Some((leader, best)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::actions::{Action, ActionKind};
+    use crate::attributes::AttributeValue;
+    use crate::error::Error;
+    use crate::session::Session;
+    use crate::Entity;
+
+    #[test]
+    fn races_end_at_a_score_or_a_turn_limit() {
+        let mut session = Session::new("race".to_string()).unwrap();
+        session.play_by("race").unwrap();
+        for name in ["hare", "tortoise"] {
+            let mut entity = Entity::new(name.to_string());
+            entity.stats.hp = 1;
+            session.add_entity(entity).unwrap();
+        }
+        let score = |session: &mut Session, name: &str, score| {
+            session
+                .set_attribute(name, "score".to_string(), AttributeValue::Int(score))
+                .unwrap()
+        };
+        score(&mut session, "hare", 9);
+        session.resolve().unwrap();
+        assert_eq!(session.over(), None);
+        score(&mut session, "hare", 10);
+        let outcome = session.resolve().unwrap();
+        assert_eq!(
+            outcome.messages.last().unwrap(),
+            "hare won, first to a score of 10"
+        );
+        let rest = Action::new(ActionKind::Rest, "tortoise".to_string()).unwrap();
+        assert!(matches!(session.apply(rest), Err(Error::GameOver(_))));
+        assert!(session.resolve().is_err());
+
+        session.undo().unwrap();
+        score(&mut session, "hare", 9);
+        score(&mut session, "tortoise", 9);
+        while session.over().is_none() {
+            session.resolve().unwrap();
+        }
+        assert_eq!(session.turn().number, 20);
+        assert_eq!(
+            session.over().unwrap().to_string(),
+            "a draw, highest score after 20 turns"
+        );
+    }
+}
@@ -0,0 +1,248 @@
+use std::ops::Bound::{Excluded, Unbounded};
+
+use crate::components::Position;
+use crate::error::Result;
+use crate::factions::Stance;
+use crate::log::Event;
+use crate::rules::Rules;
+use crate::session::Session;
+use crate::Entity;
+
+/// What players may see of the entities they do not own, which rule sets
+/// declare. Sessions sent to or exported for a player are stripped of the
+/// rest with `redact`; the server's and the GM's copies keep everything.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Visibility {
+    /// How many squares a player's entities see. Others on the map further
+    /// than that from all of them are hidden; none sees the whole map.
+    pub sight: Option<u32>,
+    /// Whether others' inventories and equipment are hidden.
+    pub hands: bool,
+    /// Whether others' resource pools are hidden.
+    pub resources: bool,
+    /// Attributes only an entity's owner sees, by name.
+    pub secrets: Vec<String>,
+}
+
+impl Visibility {
+    /// Whether players see everything, as by default.
+    pub fn is_open(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Whether players are kept from seeing all of `session`, which they
+    /// are until its game is over.
+    pub fn hides(session: &Session) -> bool {
+        !session.rule_set().visibility().is_open() && session.over().is_none()
+    }
+
+    /// What `player`, whose entities on the map are at `eyes`, may see of
+    /// `entity`, or none if it is out of their sight.
+    fn mask(&self, entity: &Entity, player: Option<&str>, eyes: &[Position]) -> Option<Entity> {
+        if player.is_some() && entity.owner.as_deref() == player {
+            return Some(entity.clone());
+        }
+        if let (Some(sight), Some(position)) = (self.sight, entity.position) {
+            if !eyes.iter().any(|eye| eye.distance(position) <= sight) {
+                return None;
+            }
+        }
+        let mut entity = entity.clone();
+        if self.hands {
+            entity.inventory.clear();
+            entity.equipment.clear();
+        }
+        if self.resources {
+            entity.resources = Default::default();
+        }
+        for secret in &self.secrets {
+            entity.attributes.remove(secret);
+        }
+        Some(entity)
+    }
+}
+
+/// `session` as `player` may see it by its rule set's `Visibility`, or as
+/// anyone may if they are no one in particular, like a spectator, who owns
+/// nothing and so sees the least. Its log could not be redacted without
+/// replaying differently, so the copy is a snapshot: its log sets up what
+/// can be seen as it stands now, ending in `Event::Redacted`. Once the game
+/// is over nothing is hidden, and sessions whose rule set hides nothing
+/// come back whole.
+pub fn redact(session: Session, player: Option<&str>) -> Result<Session> {
+    if !Visibility::hides(&session) {
+        return Ok(session);
+    }
+    let visibility = session.rule_set().visibility();
+    let eyes: Vec<_> = session
+        .entities()
+        .iter()
+        .filter(|entity| player.is_some() && entity.owner.as_deref() == player)
+        .filter_map(|entity| entity.position)
+        .collect();
+    let mut copy = Session::from_parts(
+        session.name().to_string(),
+        session.created(),
+        session.modified(),
+    );
+    for tag in session.tags() {
+        copy.add_tag(tag.clone())?;
+    }
+    if *session.rules() != Rules::default() {
+        copy.record(Event::SetRules(session.rules().clone()))?;
+    }
+    copy.record(Event::PlayBy(session.rule_set().name().to_string()))?;
+    for (name, source) in session.scripts() {
+        copy.record(Event::DefineScript(name.clone(), source.clone()))?;
+    }
+    for (kind, prerequisites) in session.requirements() {
+        copy.record(Event::Require(*kind, prerequisites.clone()))?;
+    }
+    if let Some(map) = session.map() {
+        copy.record(Event::SetMap(*map))?;
+    }
+    for faction in session.factions() {
+        copy.record(Event::DefineFaction(faction.clone()))?;
+    }
+    for a in session.factions() {
+        for b in session
+            .factions()
+            .range::<String, _>((Excluded(a), Unbounded))
+        {
+            let stance = session.stance(a, b);
+            if stance != Stance::Neutral {
+                copy.record(Event::SetStance(a.clone(), b.clone(), stance))?;
+            }
+        }
+    }
+    for player in session.roster() {
+        copy.record(Event::AddPlayer(player.clone()))?;
+    }
+    let order = session.order();
+    if !order.players().is_empty() {
+        copy.record(Event::SetPlayers(order.players().to_vec(), None))?;
+    }
+    while copy.order().current() != order.current() {
+        let current = copy.order().current().unwrap_or_default().to_string();
+        copy.record(Event::Pass(current, None))?;
+    }
+    if let Some(began) = order.began() {
+        copy.record(Event::TurnTime(order.time(), order.forfeit(), began))?;
+    }
+    let entities: Vec<_> = session
+        .entities()
+        .iter()
+        .filter_map(|entity| visibility.mask(entity, player, &eyes))
+        .collect();
+    let seen = |id| entities.iter().any(|entity: &Entity| entity.id == id);
+    let relationships = session
+        .relationships()
+        .iter()
+        .filter(|relationship| seen(relationship.from) && seen(relationship.to));
+    let relationships: Vec<_> = relationships.copied().collect();
+    for entity in entities {
+        copy.record(Event::AddEntity(entity))?;
+    }
+    for relationship in relationships {
+        copy.record(Event::Relate(relationship))?;
+    }
+    for action in session.queued() {
+        let target = session.entity(action.target());
+        if player.is_some() && target.is_some_and(|target| target.owner.as_deref() == player) {
+            copy.record(Event::Queue(action.clone()))?;
+        }
+    }
+    for event in session.log() {
+        if let Event::Say(..) = event {
+            copy.record(event.clone())?;
+        }
+    }
+    copy.record(Event::Redacted(
+        player.unwrap_or_default().to_string(),
+        session.turn().number,
+        order.number(),
+    ))?;
+    Ok(copy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{redact, Visibility};
+    use crate::attributes::AttributeValue;
+    use crate::components::Position;
+    use crate::map::Map;
+    use crate::players::Player;
+    use crate::serde::{from_bytes, Serialize};
+    use crate::session::Session;
+    use crate::Entity;
+
+    #[test]
+    fn players_only_see_what_is_in_sight() {
+        let mut session = Session::new("fog".to_string()).unwrap();
+        session.play_by("fog").unwrap();
+        session
+            .set_map(Map {
+                width: 20,
+                height: 20,
+            })
+            .unwrap();
+        for player in ["alice", "bob"] {
+            let player = Player::new(player.to_string(), None);
+            session.add_player(player).unwrap();
+        }
+        for (name, owner, at) in [
+            ("scout", "alice", Some((0, 0))),
+            ("orc", "bob", Some((4, 5))),
+            ("troll", "bob", Some((15, 15))),
+            ("merchant", "bob", None),
+        ] {
+            let mut entity = Entity::new(name.to_string());
+            entity.stats.hp = 5;
+            entity.resources.gold = 10;
+            entity.owner = Some(owner.to_string());
+            entity.position = at.map(|(x, y)| Position { x, y });
+            session.add_entity(entity).unwrap();
+        }
+        let players = vec!["alice".to_string(), "bob".to_string()];
+        session.set_players(players).unwrap();
+        session.pass(Some("alice")).unwrap();
+        session.resolve().unwrap();
+
+        let seen = redact(session.at(session.log().len()).unwrap(), None).unwrap();
+        let names: Vec<_> = seen.entities().iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["merchant"]);
+        assert_eq!(seen.entity("merchant").unwrap().resources.gold, 0);
+        assert_eq!(seen.redacted(), Some(""));
+
+        let seen = redact(session.at(session.log().len()).unwrap(), Some("alice")).unwrap();
+        let names: Vec<_> = seen.entities().iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["scout", "orc", "merchant"]);
+        assert_eq!(seen.entity("scout").unwrap().resources.gold, 10);
+        assert_eq!(seen.entity("orc").unwrap().resources.gold, 0);
+        assert_eq!(seen.redacted(), Some("alice"));
+        assert_eq!(seen.order(), session.order());
+        assert_eq!(seen.turn().number, session.turn().number);
+        assert_eq!(seen.size_hint(), seen.serialize().len());
+        let copy: Session = from_bytes(&seen.serialize()).unwrap();
+        assert_eq!(copy.entities(), seen.entities());
+        assert_eq!(copy.redacted(), Some("alice"));
+
+        let mut orc = session.entity("orc").unwrap().clone();
+        orc.attributes
+            .insert("plan".to_string(), AttributeValue::Int(1));
+        let secrets = Visibility {
+            secrets: vec!["plan".to_string()],
+            ..Visibility::default()
+        };
+        assert!(secrets
+            .mask(&orc, Some("bob"), &[])
+            .unwrap()
+            .attributes
+            .contains_key("plan"));
+        assert!(secrets
+            .mask(&orc, Some("alice"), &[])
+            .unwrap()
+            .attributes
+            .is_empty());
+    }
+}
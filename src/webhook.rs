@@ -0,0 +1,221 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use crate::actions::Action;
+use crate::error::{Error, Result};
+use crate::json::{Json, ToJson};
+#[cfg(feature = "tls")]
+use crate::protocol::TlsClient;
+use crate::session::Session;
+
+/// How long a webhook may take to connect, and then to answer.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A URL to POST JSON to when something happens in a session, such as a
+/// Slack or Discord incoming webhook, from `webhook = "<url>"` in the
+/// config file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Webhook {
+    secure: bool,
+    host: String,
+    port: u16,
+    /// The path and query, which for most services hold the secret.
+    path: String,
+}
+
+impl Webhook {
+    /// An `http://` or `https://` URL, if `url` is one.
+    pub fn parse(url: &str) -> Option<Self> {
+        let (secure, rest) = match url.split_once("://")? {
+            ("http", rest) => (false, rest),
+            ("https", rest) => (true, rest),
+            _ => return None,
+        };
+        let (authority, path) = match rest.find(['/', '?']) {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) if !authority.ends_with(']') => (host, port.parse().ok()?),
+            _ => (authority, if secure { 443 } else { 80 }),
+        };
+        let valid = !host.is_empty() && !path.contains(char::is_whitespace);
+        valid.then(|| Self {
+            secure,
+            host: host.to_string(),
+            port,
+            path: match path.starts_with('/') {
+                true => path.to_string(),
+                false => format!("/{path}"),
+            },
+        })
+    }
+
+    /// POSTs `payload`, failing unless the answer is a success.
+    pub fn post(&self, payload: &Json) -> Result<()> {
+        let stream = self.connect()?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        let status = match self.secure {
+            #[cfg(feature = "tls")]
+            true => {
+                let host = self.host.trim_start_matches('[').trim_end_matches(']');
+                self.request(TlsClient::new(None)?.connect(host, stream)?, payload)?
+            }
+            #[cfg(not(feature = "tls"))]
+            true => return Err(Error::TlsUnsupported),
+            false => self.request(stream, payload)?,
+        };
+        match status {
+            200..=299 => Ok(()),
+            _ => Err(Error::WebhookRefused(self.host.clone(), status)),
+        }
+    }
+
+    fn connect(&self) -> Result<TcpStream> {
+        let mut last = None;
+        for addr in (self.host.as_str(), self.port).to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, TIMEOUT) {
+                Ok(stream) => return Ok(stream),
+                Err(err) => last = Some(err),
+            }
+        }
+        Err(last
+            .unwrap_or_else(|| std::io::ErrorKind::NotFound.into())
+            .into())
+    }
+
+    /// Sends the request over `stream` and returns the status it is
+    /// answered with.
+    fn request(&self, mut stream: impl Read + Write, payload: &Json) -> Result<u16> {
+        let body = payload.to_string();
+        let host = match (self.secure, self.port) {
+            (true, 443) | (false, 80) => self.host.clone(),
+            (_, port) => format!("{}:{port}", self.host),
+        };
+        let head = format!(
+            "POST {} HTTP/1.1\r\nHost: {host}\r\nUser-Agent: relay_code/{}\r\n\
+             Content-Type: application/json\r\nContent-Length: {}\r\n\
+             Connection: close\r\n\r\n",
+            self.path,
+            env!("CARGO_PKG_VERSION"),
+            body.len()
+        );
+        stream.write_all(head.as_bytes())?;
+        stream.write_all(body.as_bytes())?;
+        stream.flush()?;
+        let mut status = String::new();
+        BufReader::new(stream).read_line(&mut status)?;
+        // As `HTTP/1.1 204 No Content`.
+        status
+            .split_whitespace()
+            .nth(1)
+            .and_then(|code| code.parse().ok())
+            .ok_or(Error::WebhookRefused(self.host.clone(), 0))
+    }
+}
+
+/// POSTs `payload` to every one of `webhooks`, warning of any that fail
+/// rather than failing what they were told of.
+pub fn post_all(webhooks: &[Webhook], payload: &Json) {
+    for webhook in webhooks {
+        if let Err(err) = webhook.post(payload) {
+            crate::warn!("{err}");
+        }
+    }
+}
+
+/// Says that `player`, if anyone in particular, took `action` in
+/// `session`. `text` and `content` are what Slack and Discord show.
+pub fn acted(session: &Session, player: Option<&str>, action: &Action) -> Json {
+    let text = match player {
+        Some(player) => format!("[{}] {player}: {action}", session.name()),
+        None => format!("[{}] {action}", session.name()),
+    };
+    Json::object([
+        ("event", "action".to_json()),
+        ("text", text.to_json()),
+        ("content", text.to_json()),
+        ("session", session.name().to_json()),
+        ("turn", session.turn().number.to_json()),
+        ("player", player.to_json()),
+        ("action", action.to_json()),
+    ])
+}
+
+/// Says that a new turn has begun in `session`, for its players to act in.
+pub fn turn_began(session: &Session) -> Json {
+    let turn = session.turn().number;
+    let players = session.players();
+    let text = match players.is_empty() {
+        true => format!("[{}] turn {turn} has begun", session.name()),
+        false => format!(
+            "[{}] turn {turn} has begun for {}",
+            session.name(),
+            players.join(", ")
+        ),
+    };
+    Json::object([
+        ("event", "turn".to_json()),
+        ("text", text.to_json()),
+        ("content", text.to_json()),
+        ("session", session.name().to_json()),
+        ("turn", turn.to_json()),
+        ("players", players.to_json()),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    use super::{turn_began, Webhook};
+    use crate::error::Error;
+    use crate::session::Session;
+
+    #[test]
+    fn webhooks_are_posted_json() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let mut requests = vec![];
+            for status in ["204 No Content", "404 Not Found"] {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut head = String::new();
+                while !head.ends_with("\r\n\r\n") {
+                    reader.read_line(&mut head).unwrap();
+                }
+                let len = head
+                    .lines()
+                    .find_map(|line| line.strip_prefix("Content-Length: "))
+                    .unwrap();
+                let mut body = vec![0; len.parse().unwrap()];
+                reader.read_exact(&mut body).unwrap();
+                let reply = format!("HTTP/1.1 {status}\r\nContent-Length: 0\r\n\r\n");
+                reader.get_mut().write_all(reply.as_bytes()).unwrap();
+                requests.push((head, String::from_utf8(body).unwrap()));
+            }
+            requests
+        });
+
+        let webhook = Webhook::parse(&format!("http://{addr}/hooks/secret?x=1")).unwrap();
+        let payload = turn_began(&Session::new("game".to_string()).unwrap());
+        webhook.post(&payload).unwrap();
+        let err = webhook.post(&payload).unwrap_err();
+        assert!(matches!(err, Error::WebhookRefused(_, 404)));
+        assert!(!err.to_string().contains("secret"));
+        let requests = server.join().unwrap();
+        assert!(requests[0]
+            .0
+            .starts_with("POST /hooks/secret?x=1 HTTP/1.1\r\n"));
+        assert_eq!(requests[0].1, payload.to_string());
+
+        assert!(Webhook::parse("ftp://example.com").is_none());
+        assert!(Webhook::parse("https://").is_none());
+        assert_eq!(Webhook::parse("https://example.com").unwrap().path, "/");
+    }
+}